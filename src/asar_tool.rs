@@ -0,0 +1,75 @@
+// `robojules asar`, the .asar implementation as a standalone tool
+use crate::logic::{
+    asar::{parse_asar, write_asar, AsarFile, FileTree},
+    download::extract_asar,
+    walk,
+};
+use anyhow::Context;
+use std::{io::Cursor, path::Path};
+
+pub async fn extract(file: &Path, dir: &Path) -> anyhow::Result<()> {
+    let data = tokio::fs::read(file)
+        .await
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let tree = parse_asar(&mut Cursor::new(data)).context("Failed to parse .asar")?;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .context("Failed to create output dir")?;
+
+    let extraction = extract_asar(&tree, dir).await?;
+    for renamed in &extraction.renamed {
+        eprintln!("Extracted {} as {}", renamed.original, renamed.renamed);
+    }
+    for skipped in &extraction.skipped {
+        eprintln!("Skipped {}: {}", skipped.path, skipped.finding().message);
+    }
+    println!(
+        "Extracted {} files to {}",
+        tree.len() - extraction.skipped.len(),
+        dir.display()
+    );
+    Ok(())
+}
+
+pub async fn pack(dir: &Path, file: &Path) -> anyhow::Result<()> {
+    let mut tree = FileTree::new();
+    for entry in walk::walk_dir(dir, &[]).await? {
+        if entry.is_dir || entry.is_unfollowed_symlink() {
+            continue;
+        }
+        let data = tokio::fs::read(&entry.path)
+            .await
+            .with_context(|| format!("Failed to read {}", entry.relative))?;
+        #[cfg(unix)]
+        let executable = {
+            use std::os::unix::fs::PermissionsExt;
+            let metadata = tokio::fs::metadata(&entry.path)
+                .await
+                .with_context(|| format!("Failed to read {}", entry.relative))?;
+            metadata.permissions().mode() & 0o111 != 0
+        };
+        #[cfg(not(unix))]
+        let executable = false;
+
+        tree.insert(
+            entry.relative,
+            AsarFile {
+                data,
+                executable,
+                offset: 0,
+            },
+        );
+    }
+
+    let data = write_asar(&tree)?;
+    tokio::fs::write(file, &data)
+        .await
+        .with_context(|| format!("Failed to write {}", file.display()))?;
+    println!(
+        "Packed {} files into {} ({} bytes)",
+        tree.len(),
+        file.display(),
+        data.len()
+    );
+    Ok(())
+}
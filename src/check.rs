@@ -0,0 +1,365 @@
+// Headless review of a pull request for CI, failing when findings exceed a policy
+use crate::{
+    logic::{
+        app_logic_thread,
+        checks::CheckConclusion,
+        concurrency,
+        config::Config,
+        diff::DiffedExtension,
+        scan::{Finding, FindingCategory, Severity},
+        validate, LogicCommand, LogicResponse,
+    },
+    report::{self, BuildReport, BuildStatus, ExtensionReport, PullRequestReport, Report},
+};
+use anyhow::Context;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    // Any finding in these categories fails the check
+    pub fail_on: Vec<FindingCategory>,
+    // Any finding at or above this severity fails the check
+    pub fail_on_severity: Option<Severity>,
+}
+
+impl Policy {
+    pub fn parse_categories(text: &str) -> anyhow::Result<Vec<FindingCategory>> {
+        text.split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                FindingCategory::parse(id).with_context(|| {
+                    let ids = FindingCategory::ALL
+                        .iter()
+                        .map(|category| category.id())
+                        .collect::<Vec<_>>();
+                    format!(
+                        "Unknown category {}, expected one of {}",
+                        id,
+                        ids.join(", ")
+                    )
+                })
+            })
+            .collect()
+    }
+
+    pub fn violates(&self, finding: &Finding) -> bool {
+        self.fail_on.contains(&finding.category)
+            || self
+                .fail_on_severity
+                .is_some_and(|severity| finding.severity >= severity)
+    }
+}
+
+fn print_finding(finding: &Finding, violation: bool) {
+    let location = match finding.line {
+        Some(line) => format!("{}:{}", finding.path, line),
+        None => finding.path.clone(),
+    };
+    println!(
+        "{} [{:?}] {} {} ({:?}) {}: {}",
+        if violation { "FAIL" } else { "    " },
+        finding.severity,
+        finding.category.id(),
+        finding.rule,
+        finding.origin,
+        location,
+        finding.message
+    );
+}
+
+fn print_extension(ext: &DiffedExtension, policy: &Policy, build: &BuildReport) {
+    println!(
+        "{} ({} -> {})",
+        ext.extension.id, ext.extension.old_commit, ext.extension.new_commit
+    );
+    println!(
+        "     Consistency {}%: {}",
+        ext.consistency.score(),
+        ext.consistency.summary()
+    );
+    if ext.findings.is_empty() {
+        println!("     No findings");
+    }
+    for finding in &ext.findings {
+        print_finding(finding, policy.violates(finding));
+    }
+
+    match build.status {
+        BuildStatus::NotRun => {}
+        BuildStatus::Error => println!(
+            "     Build: {}",
+            build.error.as_deref().unwrap_or("failed to start")
+        ),
+        BuildStatus::Failed => println!("     Build: failed"),
+        BuildStatus::MissingOutput => {
+            println!("     Build: succeeded without producing any output")
+        }
+        BuildStatus::Matches => println!("     Build: matches the artifact"),
+        BuildStatus::Differs => {
+            println!("     Build: {} files differ", build.differing_files.len());
+            for path in &build.differing_files {
+                println!("       {}", path);
+            }
+        }
+    }
+}
+
+// Builds the new source and compares it to the artifact, like the build button in the UI
+fn build(
+    main_tx: &flume::Sender<LogicCommand>,
+    main_rx: &flume::Receiver<LogicResponse>,
+    diffed: &DiffedExtension,
+) -> anyhow::Result<BuildReport> {
+    let temp_dir = diffed
+        .repository_dir
+        .parent()
+        .unwrap_or(&diffed.repository_dir)
+        .to_path_buf();
+    main_tx
+        .send(LogicCommand::BuildLocally {
+            extension_id: diffed.extension.id.clone(),
+            source_dir: diffed.source_diff.new.clone(),
+            artifact_dir: diffed.asar_diff.new.clone(),
+            temp_dir,
+        })
+        .context("Logic thread stopped")?;
+    match main_rx.recv().context("Logic thread stopped")? {
+        LogicResponse::LocalBuild(res) => Ok(BuildReport::new(
+            res.as_ref().map_err(|err| err.to_string()),
+        )),
+        _ => anyhow::bail!("Unexpected response from logic thread"),
+    }
+}
+
+// Where the report goes: `-` replaces the usual text on stdout, anything else is a file
+pub struct Output<'a> {
+    pub json: Option<&'a Path>,
+    pub build: bool,
+}
+
+impl Output<'_> {
+    fn text(&self) -> bool {
+        self.json != Some(Path::new("-"))
+    }
+
+    fn write(&self, report: &Report) -> anyhow::Result<()> {
+        let Some(path) = self.json else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(report).context("Failed to serialize report")?;
+        if self.text() {
+            std::fs::write(path, json)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        } else {
+            println!("{}", json);
+        }
+        Ok(())
+    }
+}
+
+fn review(
+    main_tx: &flume::Sender<LogicCommand>,
+    main_rx: &flume::Receiver<LogicResponse>,
+    diffed: &DiffedExtension,
+    policy: &Policy,
+    output: &Output,
+) -> anyhow::Result<ExtensionReport> {
+    let build = if output.build {
+        build(main_tx, main_rx, diffed)?
+    } else {
+        BuildReport::not_run()
+    };
+    if output.text() {
+        print_extension(diffed, policy, &build);
+    }
+    Ok(ExtensionReport::new(
+        diffed,
+        |finding| policy.violates(finding),
+        build,
+    ))
+}
+
+// Returns whether the pull request passes the policy, publishing the findings as a check run when asked to
+pub fn check(
+    pr: u64,
+    extension: Option<&str>,
+    policy: &Policy,
+    publish: bool,
+    output: &Output,
+) -> anyhow::Result<bool> {
+    let (main_tx, logic_rx) = flume::unbounded::<LogicCommand>();
+    let (logic_tx, main_rx) = flume::unbounded::<LogicResponse>();
+    std::thread::spawn(move || app_logic_thread(logic_rx, logic_tx));
+
+    main_tx
+        .send(LogicCommand::GetPullRequest(pr))
+        .context("Logic thread stopped")?;
+    let update = match main_rx.recv().context("Logic thread stopped")? {
+        LogicResponse::PullRequest(res) => res.map_err(|err| anyhow::anyhow!("{}", err))?,
+        _ => anyhow::bail!("Unexpected response from logic thread"),
+    };
+    let pull_request = PullRequestReport::new(&update);
+
+    let extensions = update
+        .extensions
+        .into_iter()
+        .filter(|ext| extension.is_none_or(|id| ext.id == id))
+        .collect::<Vec<_>>();
+    if extensions.is_empty() {
+        anyhow::bail!("No matching extensions changed in pull request {}", pr);
+    }
+
+    let mut reports = Vec::new();
+    let mut findings = BTreeMap::new();
+    for ext in extensions {
+        main_tx
+            .send(LogicCommand::DownloadExtension {
+                extension: ext,
+                artifact_url: update.artifact_url.clone(),
+                pull_request: update.number,
+                head_sha: update.head_sha.clone(),
+            })
+            .context("Logic thread stopped")?;
+        let diffed = match main_rx.recv().context("Logic thread stopped")? {
+            LogicResponse::ExtensionDownloadComplete(res) => {
+                res.map_err(|err| anyhow::anyhow!("{}", err))?
+            }
+            _ => anyhow::bail!("Unexpected response from logic thread"),
+        };
+        reports.push(review(&main_tx, &main_rx, &diffed, policy, output)?);
+        findings.insert(diffed.extension.id, diffed.findings);
+    }
+
+    let violations = reports
+        .iter()
+        .map(|report| report.violations)
+        .sum::<usize>();
+    if violations > 0 && output.text() {
+        println!("{} findings exceed the policy", violations);
+    }
+
+    let mut check_run_url = None;
+    if publish {
+        let conclusion = if violations > 0 {
+            CheckConclusion::Failure
+        } else {
+            CheckConclusion::from_findings(&findings)
+        };
+        main_tx
+            .send(LogicCommand::PublishCheckRun {
+                head_sha: update.head_sha,
+                findings,
+                conclusion,
+            })
+            .context("Logic thread stopped")?;
+        let check_run = match main_rx.recv().context("Logic thread stopped")? {
+            LogicResponse::CheckRunPublished(res) => {
+                res.map_err(|err| anyhow::anyhow!("{}", err))?
+            }
+            _ => anyhow::bail!("Unexpected response from logic thread"),
+        };
+        if output.text() {
+            println!("Published check run {}", check_run.html_url);
+        }
+        check_run_url = Some(check_run.html_url);
+    }
+
+    output.write(&Report {
+        version: report::VERSION,
+        pull_request: Some(pull_request),
+        passed: violations == 0,
+        violations,
+        extensions: reports,
+        check_run_url,
+    })?;
+    Ok(violations == 0)
+}
+
+// The same scan over an author's working tree and built .asar, before there's a pull request
+pub fn check_local(
+    extension_id: &str,
+    working_dir: &Path,
+    asar_path: &Path,
+    policy: &Policy,
+    output: &Output,
+) -> anyhow::Result<bool> {
+    let (main_tx, logic_rx) = flume::unbounded::<LogicCommand>();
+    let (logic_tx, main_rx) = flume::unbounded::<LogicResponse>();
+    std::thread::spawn(move || app_logic_thread(logic_rx, logic_tx));
+
+    main_tx
+        .send(LogicCommand::ReviewLocal {
+            extension_id: extension_id.to_string(),
+            working_dir: working_dir.to_path_buf(),
+            asar_path: asar_path.to_path_buf(),
+        })
+        .context("Logic thread stopped")?;
+    let diffed = match main_rx.recv().context("Logic thread stopped")? {
+        LogicResponse::ExtensionDownloadComplete(res) => {
+            res.map_err(|err| anyhow::anyhow!("{}", err))?
+        }
+        _ => anyhow::bail!("Unexpected response from logic thread"),
+    };
+
+    let report = review(&main_tx, &main_rx, &diffed, policy, output)?;
+    let violations = report.violations;
+    if violations > 0 && output.text() {
+        println!("{} findings exceed the policy", violations);
+    }
+
+    output.write(&Report {
+        version: report::VERSION,
+        pull_request: None,
+        passed: violations == 0,
+        violations,
+        extensions: vec![report],
+        check_run_url: None,
+    })?;
+    Ok(violations == 0)
+}
+
+// Returns whether every manifest is valid. `exts_dir` is validated in full when given.
+pub fn validate(
+    mut manifests: Vec<PathBuf>,
+    exts_dir: Option<&Path>,
+    fetch: bool,
+) -> anyhow::Result<bool> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    if let Some(dir) = exts_dir {
+        manifests.extend(runtime.block_on(validate::list_manifests(dir))?);
+    }
+    if manifests.is_empty() {
+        anyhow::bail!("No manifests to validate");
+    }
+
+    let limit = Config::load().concurrency.downloads;
+    let results = runtime.block_on(concurrency::run_limited(
+        limit,
+        manifests
+            .iter()
+            .cloned()
+            .map(|path| async move { validate::validate_manifest(&path, fetch).await }),
+    ));
+
+    let mut invalid = 0;
+    for (path, problems) in manifests.iter().zip(results) {
+        if problems.is_empty() {
+            println!("  OK {}", path.display());
+            continue;
+        }
+        invalid += 1;
+        println!("FAIL {}", path.display());
+        for problem in problems {
+            println!("     {}", problem);
+        }
+    }
+
+    if invalid > 0 {
+        println!("{} of {} manifests are invalid", invalid, manifests.len());
+    }
+    Ok(invalid == 0)
+}
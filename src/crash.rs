@@ -0,0 +1,203 @@
+// Crash reports, written by a panic hook with the recent log lines leading up to it
+use crate::logic::config;
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+const MAX_LOG_LINES: usize = 200;
+const ISSUES_URL: &str = "https://github.com/moonlight-mod/robojules/issues/new";
+// GitHub rejects overly long URLs, so only the end of the report goes in the issue body
+const MAX_ISSUE_BODY: usize = 4000;
+
+static LOG_LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+// 0 when no pull request has been fetched
+static PULL_REQUEST: AtomicU64 = AtomicU64::new(0);
+// Reports from panics in this process that the UI hasn't shown yet
+static PENDING: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub path: PathBuf,
+    pub text: String,
+}
+
+impl CrashReport {
+    fn load(path: PathBuf) -> Option<Self> {
+        let text = std::fs::read_to_string(&path).ok()?;
+        Some(Self { path, text })
+    }
+
+    pub fn issue_url(&self) -> String {
+        let title = self
+            .text
+            .lines()
+            .find_map(|line| line.strip_prefix("Panic: "))
+            .unwrap_or("Crash report");
+        let mut start = self.text.len().saturating_sub(MAX_ISSUE_BODY);
+        while !self.text.is_char_boundary(start) {
+            start += 1;
+        }
+        let body = format!("```\n{}\n```", &self.text[start..]);
+        format!(
+            "{}?title={}&body={}",
+            ISSUES_URL,
+            encode_query(&format!("Crash: {}", title)),
+            encode_query(&body)
+        )
+    }
+}
+
+fn encode_query(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn crash_dir() -> PathBuf {
+    config::cache_dir().join("crashes")
+}
+
+// Points at the newest report until it's been shown, so crashes that take the UI down show up on the next launch
+fn pending_path() -> PathBuf {
+    crash_dir().join("pending")
+}
+
+// Keeps our own debug lines for crash reports, whatever RUST_LOG is set to
+struct Logger {
+    inner: env_logger::Logger,
+}
+
+fn is_ours(metadata: &log::Metadata) -> bool {
+    metadata.target().starts_with("robojules") && metadata.level() <= log::Level::Debug
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        is_ours(metadata) || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if is_ours(record.metadata()) {
+            let mut lines = LOG_LINES.lock().unwrap_or_else(|err| err.into_inner());
+            if lines.len() >= MAX_LOG_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(format!(
+                "[{} {}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+fn write_report(info: &std::panic::PanicHookInfo) -> std::io::Result<PathBuf> {
+    let thread = std::thread::current();
+    let pull_request = match PULL_REQUEST.load(Ordering::Relaxed) {
+        0 => "none".to_string(),
+        num => num.to_string(),
+    };
+    // The panic may have happened while logging, so don't wait on the lock
+    let lines = LOG_LINES
+        .try_lock()
+        .map(|lines| lines.iter().cloned().collect::<Vec<_>>().join("\n"))
+        .unwrap_or_default();
+
+    let message = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown".to_string());
+    let location = info
+        .location()
+        .map(|location| location.to_string())
+        .unwrap_or_default();
+
+    let report = format!(
+        "Panic: {}\nLocation: {}\nThread: {}\nVersion: {}\nOS: {} {}\nPull request: {}\n\nBacktrace:\n{}\n\nRecent log lines:\n{}\n",
+        message,
+        location,
+        thread.name().unwrap_or("unnamed"),
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        pull_request,
+        std::backtrace::Backtrace::force_capture(),
+        lines
+    );
+
+    let dir = crash_dir();
+    std::fs::create_dir_all(&dir)?;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let path = dir.join(format!("crash-{}.txt", timestamp));
+    std::fs::write(&path, report)?;
+    std::fs::write(pending_path(), path.to_string_lossy().as_bytes())?;
+    Ok(path)
+}
+
+// Replaces `env_logger::init()`
+pub fn init() {
+    let inner = env_logger::Builder::from_default_env().build();
+    log::set_max_level(inner.filter().max(log::LevelFilter::Debug));
+    if let Err(err) = log::set_boxed_logger(Box::new(Logger { inner })) {
+        eprintln!("Failed to set logger: {}", err);
+    }
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match write_report(info) {
+            Ok(path) => {
+                eprintln!("Crash report written to {}", path.display());
+                *PENDING.lock().unwrap_or_else(|err| err.into_inner()) = Some(path);
+            }
+            Err(err) => eprintln!("Failed to write crash report: {}", err),
+        }
+        default_hook(info);
+    }));
+}
+
+pub fn set_pull_request(num: Option<u64>) {
+    PULL_REQUEST.store(num.unwrap_or(0), Ordering::Relaxed);
+}
+
+// A report from this process, e.g. when the logic thread panicked
+pub fn take_pending() -> Option<CrashReport> {
+    let path = PENDING
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .take()?;
+    let _ = std::fs::remove_file(pending_path());
+    CrashReport::load(path)
+}
+
+// A report from a previous run that crashed before it could be shown
+pub fn take_previous() -> Option<CrashReport> {
+    let pending = pending_path();
+    let path = std::fs::read_to_string(&pending).ok()?;
+    let _ = std::fs::remove_file(&pending);
+    CrashReport::load(PathBuf::from(path.trim()))
+}
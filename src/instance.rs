@@ -0,0 +1,230 @@
+// One window at a time: a second launch hands its pull request to the running instance over a local
+// socket and exits, so two UIs don't save over each other's reviews and settings
+use crate::logic::config::config_dir;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+// The running instance may have taken the lock but not written its port yet
+const CONNECT_ATTEMPTS: usize = 5;
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(200);
+// Requests are tiny, so anything bigger or slower than this isn't another instance
+const MAX_REQUEST_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstanceRequest {
+    // Pull request to open, if the launch was for one
+    pub open: Option<u64>,
+}
+
+// What the UI reads forwarded requests from, shared with the thread that accepts them
+#[derive(Debug, Clone)]
+pub struct InstanceLink {
+    rx: flume::Receiver<InstanceRequest>,
+    // Set once the UI is up, so a hidden window can be shown when a request arrives
+    ctx: Arc<Mutex<Option<egui::Context>>>,
+}
+
+impl InstanceLink {
+    pub fn attach(&self, ctx: &egui::Context) {
+        *self.ctx.lock().unwrap_or_else(|err| err.into_inner()) = Some(ctx.clone());
+    }
+
+    pub fn try_recv(&self) -> Option<InstanceRequest> {
+        self.rx.try_recv().ok()
+    }
+}
+
+// Holds the lock for as long as the UI runs
+pub struct InstanceGuard {
+    _lock: Option<File>,
+    link: InstanceLink,
+}
+
+impl InstanceGuard {
+    pub fn link(&self) -> InstanceLink {
+        self.link.clone()
+    }
+}
+
+pub enum Instance {
+    Running(InstanceGuard),
+    // Another instance took the request, so this one has nothing left to do
+    Forwarded,
+}
+
+fn lock_path() -> PathBuf {
+    config_dir().join("instance.lock")
+}
+
+// Apart from the lock, since a locked file can't be read on Windows
+fn port_path() -> PathBuf {
+    config_dir().join("instance.port")
+}
+
+// A pull request number, `#123`, a link to it on GitHub, or robojules://pr/123
+pub fn parse_link(text: &str) -> Option<u64> {
+    let text = text.trim().trim_end_matches('/');
+    let number = if let Some(rest) = text.strip_prefix("robojules://pr/") {
+        rest
+    } else if let Some(rest) = text
+        .strip_prefix("https://github.com/moonlight-mod/extensions/pull/")
+        .or_else(|| text.strip_prefix("github.com/moonlight-mod/extensions/pull/"))
+    {
+        // e.g. /files or #issuecomment-…
+        rest.split(['/', '#', '?']).next().unwrap_or_default()
+    } else {
+        text.strip_prefix('#').unwrap_or(text)
+    };
+    number.parse().ok().filter(|number| *number > 0)
+}
+
+fn forward(request: &InstanceRequest) -> anyhow::Result<()> {
+    let port = std::fs::read_to_string(port_path()).context("Failed to read instance port")?;
+    let port = port
+        .trim()
+        .parse::<u16>()
+        .context("Invalid instance port")?;
+    let mut stream =
+        TcpStream::connect_timeout(&(Ipv4Addr::LOCALHOST, port).into(), CONNECT_TIMEOUT)
+            .context("Failed to connect to the running instance")?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    if reply.trim() != "ok" {
+        anyhow::bail!("The running instance didn't accept the request");
+    }
+    Ok(())
+}
+
+fn handle(
+    stream: TcpStream,
+    tx: &flume::Sender<InstanceRequest>,
+    ctx: &Mutex<Option<egui::Context>>,
+) {
+    // Connections are accepted one at a time, so one that never sends a line can't hold up the rest
+    if let Err(err) = stream.set_read_timeout(Some(CONNECT_TIMEOUT)) {
+        log::warn!("Failed to set a timeout for another instance: {}", err);
+        return;
+    }
+    let mut reader = BufReader::new((&stream).take(MAX_REQUEST_SIZE));
+    let mut line = String::new();
+    if let Err(err) = reader.read_line(&mut line) {
+        log::warn!("Failed to read from another instance: {}", err);
+        return;
+    }
+    // Anything local can connect, so only a well-formed request does anything
+    let request = match serde_json::from_str::<InstanceRequest>(&line) {
+        Ok(request) => request,
+        Err(err) => {
+            log::warn!(
+                "Ignoring a malformed request from another instance: {}",
+                err
+            );
+            return;
+        }
+    };
+    log::debug!("Request from another instance: {:?}", request);
+    let _ = tx.send(request);
+    if let Some(ctx) = ctx.lock().unwrap_or_else(|err| err.into_inner()).as_ref() {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        ctx.request_repaint();
+    }
+    let _ = (&stream).write_all(b"ok\n");
+}
+
+fn listen(
+    tx: flume::Sender<InstanceRequest>,
+    ctx: Arc<Mutex<Option<egui::Context>>>,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .context("Failed to listen for other instances")?;
+    let port = listener.local_addr()?.port();
+    std::fs::write(port_path(), port.to_string()).context("Failed to write instance port")?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => handle(stream, &tx, &ctx),
+                Err(err) => log::warn!("Failed to accept another instance: {}", err),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn try_lock() -> anyhow::Result<Option<File>> {
+    std::fs::create_dir_all(config_dir()).context("Failed to create config dir")?;
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path())
+        .context("Failed to open instance lock")?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+        Err(std::fs::TryLockError::Error(err)) => Err(err).context("Failed to lock instance lock"),
+    }
+}
+
+// Hands the request to a running instance, or becomes the one others hand theirs to. When the running
+// instance can't be reached this one starts anyway; its temp dirs are locked separately, so the two
+// still don't share downloads.
+pub fn acquire(request: InstanceRequest) -> Instance {
+    let (tx, rx) = flume::unbounded();
+    let ctx = Arc::new(Mutex::new(None));
+    let lock = match try_lock() {
+        Ok(Some(lock)) => {
+            if let Err(err) = listen(tx.clone(), ctx.clone()) {
+                log::warn!("{:?}", err);
+            }
+            Some(lock)
+        }
+        Ok(None) => {
+            let mut attempts = 0;
+            let forwarded = loop {
+                attempts += 1;
+                let res = forward(&request);
+                if res.is_ok() || attempts == CONNECT_ATTEMPTS {
+                    break res;
+                }
+                std::thread::sleep(CONNECT_RETRY_DELAY);
+            };
+            match forwarded {
+                Ok(()) => {
+                    log::info!("Handed over to the running instance");
+                    return Instance::Forwarded;
+                }
+                Err(err) => log::warn!("Starting alongside the running instance: {:?}", err),
+            }
+            None
+        }
+        Err(err) => {
+            log::warn!("{:?}", err);
+            None
+        }
+    };
+
+    // The UI picks up its own launch's request the same way as forwarded ones
+    if request.open.is_some() {
+        let _ = tx.send(request);
+    }
+    Instance::Running(InstanceGuard {
+        _lock: lock,
+        link: InstanceLink { rx, ctx },
+    })
+}
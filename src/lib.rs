@@ -0,0 +1,11 @@
+pub mod asar_tool;
+pub mod check;
+pub mod crash;
+pub mod instance;
+pub mod logic;
+pub mod report;
+pub mod server;
+mod ui;
+pub mod widget;
+
+pub use ui::App;
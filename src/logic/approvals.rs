@@ -0,0 +1,80 @@
+// The last version of each extension the user approved, so later updates can be diffed against it
+use super::{atomic, config::config_dir, diff::ModifiedExtension};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Approval {
+    pub commit: String,
+    pub repository: String,
+    pub pull_request: u64,
+    // Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+// By extension ID
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Approvals(pub BTreeMap<String, Approval>);
+
+impl Approvals {
+    pub fn path() -> PathBuf {
+        config_dir().join("approvals.json")
+    }
+
+    pub fn load() -> Self {
+        let path = Self::path();
+        if !path.exists() {
+            return Self::default();
+        }
+
+        match std::fs::read(&path)
+            .context("Failed to read approvals")
+            .and_then(|data| serde_json::from_slice(&data).context("Failed to parse approvals"))
+        {
+            Ok(approvals) => approvals,
+            Err(err) => {
+                log::warn!("Failed to load approvals: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(config_dir()).context("Failed to create config dir")?;
+        let data = serde_json::to_vec_pretty(self).context("Failed to serialize approvals")?;
+        atomic::write_blocking(&Self::path(), data).context("Failed to write approvals")
+    }
+
+    // The approved commit, if it's neither side of this update and so worth diffing against
+    pub fn previous(&self, ext: &ModifiedExtension) -> Option<&Approval> {
+        self.0.get(&ext.id).filter(|approval| {
+            approval.commit != ext.old_commit && approval.commit != ext.new_commit
+        })
+    }
+}
+
+pub fn approve(ext: &ModifiedExtension, pull_request: u64) -> anyhow::Result<Approval> {
+    let approval = Approval {
+        commit: ext.new_commit.clone(),
+        repository: ext
+            .new_repository
+            .clone()
+            .unwrap_or_else(|| ext.repository.clone()),
+        pull_request,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default(),
+    };
+
+    let mut approvals = Approvals::load();
+    approvals.0.insert(ext.id.clone(), approval.clone());
+    approvals.save()?;
+    Ok(approval)
+}
@@ -0,0 +1,236 @@
+// Reads the extension's .asar out of a CI artifact, whatever kind of archive it came in
+use super::asar::{parse_asar, FileTree};
+use anyhow::Context;
+use std::io::{Cursor, Read};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    Gzip,
+    Zstd,
+}
+
+impl ArchiveKind {
+    // By magic bytes, since the file name isn't always known
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+            Some(ArchiveKind::Zip)
+        } else if data.starts_with(&[0x1f, 0x8b]) {
+            Some(ArchiveKind::Gzip)
+        } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(ArchiveKind::Zstd)
+        } else if data.get(257..262) == Some(b"ustar") {
+            Some(ArchiveKind::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+// Everything decompressed from one artifact counts towards this, so a small archive can't expand
+// into something that fills up memory
+const MAX_DECOMPRESSED_BYTES: u64 = 1024 * 1024 * 1024;
+
+// What's left of MAX_DECOMPRESSED_BYTES, shared by every read out of the same artifact
+struct Budget(u64);
+
+impl Budget {
+    fn new() -> Self {
+        Budget(MAX_DECOMPRESSED_BYTES)
+    }
+
+    fn read(&mut self, reader: impl Read, what: &str) -> anyhow::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        reader
+            .take(self.0 + 1)
+            .read_to_end(&mut data)
+            .with_context(|| format!("Failed to read {}", what))?;
+        if data.len() as u64 > self.0 {
+            anyhow::bail!(
+                "The artifact decompresses to more than {} MiB",
+                MAX_DECOMPRESSED_BYTES / 1024 / 1024
+            );
+        }
+        self.0 -= data.len() as u64;
+        Ok(data)
+    }
+}
+
+// Shown in errors, so an artifact full of .asar files doesn't produce a wall of text
+const MAX_LISTED_CANDIDATES: usize = 10;
+
+fn list_candidates(candidates: &[&str]) -> String {
+    if candidates.is_empty() {
+        return "none".to_string();
+    }
+    let mut list = candidates
+        .iter()
+        .take(MAX_LISTED_CANDIDATES)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if candidates.len() > MAX_LISTED_CANDIDATES {
+        list.push_str(&format!(
+            " and {} more",
+            candidates.len() - MAX_LISTED_CANDIDATES
+        ));
+    }
+    list
+}
+
+// The .asar is usually `<id>.asar` at the root, but may be nested in folders, differ in case,
+// or sit alone in a folder named after the extension
+fn find_asar_entry(names: &[&str], ext_id: &str) -> anyhow::Result<String> {
+    let file_name = format!("{}.asar", ext_id.to_lowercase());
+    let folder = ext_id.to_lowercase();
+    let asars = names
+        .iter()
+        .copied()
+        .filter(|name| !name.ends_with('/') && name.to_lowercase().ends_with(".asar"))
+        .collect::<Vec<_>>();
+
+    let split = |name: &str| {
+        let name = name.to_lowercase();
+        let mut parts = name.rsplit('/');
+        let base = parts.next().unwrap_or_default().to_string();
+        let parent = parts.next().map(str::to_string);
+        (base, parent)
+    };
+    let by_name = asars
+        .iter()
+        .copied()
+        .filter(|name| split(name).0 == file_name)
+        .collect::<Vec<_>>();
+    let matches = if by_name.is_empty() {
+        let in_folder = asars
+            .iter()
+            .copied()
+            .filter(|name| split(name).1.as_deref() == Some(folder.as_str()))
+            .collect::<Vec<_>>();
+        if in_folder.len() > 1 {
+            anyhow::bail!(
+                "Found several .asar files in the {} folder of the artifact: {}",
+                ext_id,
+                list_candidates(&in_folder)
+            );
+        }
+        in_folder
+    } else {
+        by_name
+    };
+
+    match matches.as_slice() {
+        [name] => Ok(name.to_string()),
+        [] => Err(
+            anyhow::Error::new(zip::result::ZipError::FileNotFound).context(format!(
+                "Failed to find {} in the artifact, .asar files in it: {}",
+                file_name,
+                list_candidates(&asars)
+            )),
+        ),
+        _ => {
+            // Prefer the shallowest match, e.g. a root `<id>.asar` over a copy in a subfolder
+            let depth = |name: &&str| name.matches('/').count();
+            let min_depth = matches.iter().map(depth).min().unwrap_or_default();
+            let shallowest = matches
+                .iter()
+                .filter(|name| depth(name) == min_depth)
+                .collect::<Vec<_>>();
+            match shallowest.as_slice() {
+                [name] => Ok(name.to_string()),
+                _ => anyhow::bail!(
+                    "Found several candidates for {} in the artifact: {}",
+                    file_name,
+                    list_candidates(&matches)
+                ),
+            }
+        }
+    }
+}
+
+fn read_zip(data: Vec<u8>, ext_id: &str, budget: &mut Budget) -> anyhow::Result<Vec<u8>> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(data)).context("Failed to open zip")?;
+
+    let names = zip.file_names().collect::<Vec<_>>();
+    let name = find_asar_entry(&names, ext_id)?;
+    log::debug!("Using {} from the artifact", name);
+    let file = zip.by_name(&name).context("Failed to find .asar")?;
+    budget.read(file, ".asar")
+}
+
+// Only .asar entries are kept in memory, the rest are skipped
+fn read_tar(reader: impl Read, ext_id: &str, budget: &mut Budget) -> anyhow::Result<Vec<u8>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut asars = Vec::new();
+    for entry in archive.entries().context("Failed to read tar")? {
+        let entry = entry.context("Failed to read tar entry")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry
+            .path()
+            .context("Failed to read tar entry path")?
+            .to_string_lossy()
+            .trim_start_matches("./")
+            .to_string();
+        if !path.to_lowercase().ends_with(".asar") {
+            continue;
+        }
+        let data = budget.read(entry, &path)?;
+        asars.push((path, data));
+    }
+
+    let names = asars
+        .iter()
+        .map(|(path, _)| path.as_str())
+        .collect::<Vec<_>>();
+    let name = find_asar_entry(&names, ext_id)?;
+    log::debug!("Using {} from the artifact", name);
+    asars
+        .into_iter()
+        .find(|(path, _)| *path == name)
+        .map(|(_, data)| data)
+        .context("Failed to find .asar")
+}
+
+fn read_compressed(
+    data: Vec<u8>,
+    kind: ArchiveKind,
+    ext_id: &str,
+    budget: &mut Budget,
+) -> anyhow::Result<Vec<u8>> {
+    let decompressed = match kind {
+        ArchiveKind::Gzip => {
+            budget.read(flate2::read::GzDecoder::new(Cursor::new(data)), "gzip")?
+        }
+        ArchiveKind::Zstd => {
+            let decoder = zstd::stream::read::Decoder::new(Cursor::new(data))
+                .context("Failed to decompress zstd")?;
+            budget.read(decoder, "zstd")?
+        }
+        ArchiveKind::Zip | ArchiveKind::Tar => unreachable!("{:?} isn't compressed", kind),
+    };
+
+    match ArchiveKind::detect(&decompressed) {
+        Some(ArchiveKind::Tar) => read_tar(Cursor::new(decompressed), ext_id, budget),
+        Some(ArchiveKind::Zip) => read_zip(decompressed, ext_id, budget),
+        // A compressed .asar on its own, with nothing to pick from
+        _ => Ok(decompressed),
+    }
+}
+
+pub async fn get_asar_from_archive(data: Vec<u8>, ext_id: &str) -> anyhow::Result<FileTree> {
+    let kind = ArchiveKind::detect(&data).context("Unknown artifact format")?;
+    log::debug!("Artifact is {:?}", kind);
+    let mut budget = Budget::new();
+    let bytes = match kind {
+        ArchiveKind::Zip => read_zip(data, ext_id, &mut budget)?,
+        ArchiveKind::Tar => read_tar(Cursor::new(data), ext_id, &mut budget)?,
+        ArchiveKind::Gzip | ArchiveKind::Zstd => read_compressed(data, kind, ext_id, &mut budget)?,
+    };
+
+    let mut reader = Cursor::new(bytes);
+    let asar = parse_asar(&mut reader).context("Failed to parse .asar")?;
+    Ok(asar)
+}
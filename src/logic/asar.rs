@@ -1,4 +1,6 @@
 // https://github.com/moonlight-mod/moonlight/blob/main/packages/core/src/asar.ts
+use super::walk::MAX_DEPTH;
+use anyhow::Context;
 use binrw::prelude::*;
 use serde::Deserialize;
 use std::{
@@ -9,9 +11,16 @@ use std::{
 #[derive(Deserialize, Clone)]
 #[serde(untagged)]
 pub enum AsarEntry {
-    Directory { files: HashMap<String, AsarEntry> },
+    Directory {
+        files: HashMap<String, AsarEntry>,
+    },
 
-    File { offset: String, size: usize },
+    File {
+        offset: String,
+        size: usize,
+        #[serde(default)]
+        executable: bool,
+    },
 }
 
 // Why is this required for binrw?
@@ -23,10 +32,36 @@ impl Default for AsarEntry {
     }
 }
 
-pub type FileTree = HashMap<String, Vec<u8>>;
+#[derive(Debug, Clone)]
+pub struct AsarFile {
+    pub data: Vec<u8>,
+    pub executable: bool,
+    // Position in the payload, which is lost once the .asar is extracted
+    pub offset: usize,
+}
+
+pub type FileTree = HashMap<String, AsarFile>;
+
+// Bytes left after the current position, without moving it
+fn remaining(reader: &mut impl Seek) -> std::io::Result<u64> {
+    let position = reader.stream_position()?;
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(position))?;
+    Ok(end.saturating_sub(position))
+}
 
 #[binrw::parser(reader)]
 fn header_json_reader(actual_string_size: u32) -> BinResult<AsarEntry> {
+    // Sizes come from the archive, so they're checked before anything is allocated for them
+    if actual_string_size as u64 > remaining(reader)? {
+        return Err(error(
+            reader,
+            format!(
+                "Header claims {} bytes, more than the archive holds",
+                actual_string_size
+            ),
+        ));
+    }
     let mut buf = Vec::new();
     buf.resize(actual_string_size as usize, 0);
     reader.read_exact(&mut buf)?;
@@ -35,33 +70,66 @@ fn header_json_reader(actual_string_size: u32) -> BinResult<AsarEntry> {
     Ok(root_entry)
 }
 
+// Iterative, so a deeply nested header can't overflow the stack
 fn walk_tree(
-    entry: &AsarEntry,
+    root: &AsarEntry,
     reader: &mut impl BinReaderExt,
     base: usize,
-    output: &mut HashMap<String, Vec<u8>>,
-    path: String,
+    output: &mut FileTree,
 ) -> anyhow::Result<()> {
-    match entry {
-        AsarEntry::Directory { files } => {
-            for (name, entry) in files {
-                let child = if path != "" {
-                    format!("{}/{}", path, name)
-                } else {
-                    name.clone()
-                };
-                walk_tree(entry, reader, base, output, child)?;
+    let archive_size = reader.seek(SeekFrom::End(0))?;
+    // Files don't share data, so together they can't take up more than the payload
+    let mut payload_left = archive_size.saturating_sub(base as u64);
+    let mut stack = vec![(root, String::new(), 0)];
+    while let Some((entry, path, depth)) = stack.pop() {
+        match entry {
+            AsarEntry::Directory { files } => {
+                if depth > MAX_DEPTH {
+                    anyhow::bail!("{} is nested more than {} levels deep", path, MAX_DEPTH);
+                }
+                for (name, entry) in files {
+                    let child = if !path.is_empty() {
+                        format!("{}/{}", path, name)
+                    } else {
+                        name.clone()
+                    };
+                    stack.push((entry, child, depth + 1));
+                }
             }
-        }
 
-        AsarEntry::File { offset, size } => {
-            let offset = offset
-                .parse::<usize>()
-                .map_err(|e| error(reader, e.to_string()))?;
-            reader.seek(SeekFrom::Start(base as u64 + offset as u64))?;
-            let mut data = vec![0; *size];
-            reader.read_exact(&mut data)?;
-            output.insert(path, data);
+            AsarEntry::File {
+                offset,
+                size,
+                executable,
+            } => {
+                let offset = offset
+                    .parse::<usize>()
+                    .map_err(|e| error(reader, e.to_string()))?;
+                let start = base as u64 + offset as u64;
+                if start.saturating_add(*size as u64) > archive_size {
+                    anyhow::bail!(
+                        "{} ({} bytes at {}) runs past the end of the archive",
+                        path,
+                        size,
+                        offset
+                    );
+                }
+                if *size as u64 > payload_left {
+                    anyhow::bail!("Files add up to more than the archive holds");
+                }
+                payload_left -= *size as u64;
+                reader.seek(SeekFrom::Start(start))?;
+                let mut data = vec![0; *size];
+                reader.read_exact(&mut data)?;
+                output.insert(
+                    path,
+                    AsarFile {
+                        data,
+                        executable: *executable,
+                        offset,
+                    },
+                );
+            }
         }
     }
 
@@ -78,7 +146,6 @@ fn file_tree_reader(header_string_size: u32, header_json: AsarEntry) -> BinResul
         // but headerStringStart will always be 8
         8 + header_string_size as usize + 4,
         &mut output,
-        String::new(),
     )
     .map_err(|e| error(reader, e.to_string()))?;
     Ok(output)
@@ -117,3 +184,173 @@ fn error(reader: &mut impl BinReaderExt, message: String) -> binrw::Error {
 pub fn parse_asar<R: Read + Seek>(reader: &mut R) -> anyhow::Result<FileTree> {
     Ok(reader.read_ne::<AsarHeader>()?.file_tree)
 }
+
+// Pickles pad to 4 bytes
+fn align(size: usize) -> usize {
+    size.div_ceil(4) * 4
+}
+
+// Files are stored in path order, and offsets are assigned as they're written
+pub fn write_asar(tree: &FileTree) -> anyhow::Result<Vec<u8>> {
+    let mut paths = tree.keys().collect::<Vec<_>>();
+    paths.sort();
+
+    let mut root = serde_json::Map::new();
+    let mut payload = Vec::new();
+    for path in paths {
+        let file = &tree[path];
+        let (parents, name) = match path.rsplit_once('/') {
+            Some((parents, name)) => (parents.split('/').collect::<Vec<_>>(), name),
+            None => (Vec::new(), path.as_str()),
+        };
+
+        let mut dir = &mut root;
+        for parent in parents {
+            let entry = dir
+                .entry(parent)
+                .or_insert_with(|| serde_json::json!({ "files": {} }));
+            dir = entry
+                .get_mut("files")
+                .and_then(|files| files.as_object_mut())
+                .with_context(|| format!("{} is both a file and a directory", parent))?;
+        }
+
+        let mut entry = serde_json::json!({
+            "offset": payload.len().to_string(),
+            "size": file.data.len(),
+        });
+        if file.executable {
+            entry["executable"] = serde_json::Value::Bool(true);
+        }
+        if dir.insert(name.to_string(), entry).is_some() {
+            anyhow::bail!("{} is both a file and a directory", path);
+        }
+        payload.extend_from_slice(&file.data);
+    }
+
+    let header = serde_json::to_vec(&serde_json::json!({ "files": root }))
+        .context("Failed to serialize header")?;
+    let header_string_size = 4 + align(header.len());
+    let header_size = 4 + header_string_size;
+
+    let mut data = Vec::with_capacity(8 + header_size + payload.len());
+    data.extend_from_slice(&4u32.to_le_bytes());
+    data.extend_from_slice(&(header_size as u32).to_le_bytes());
+    data.extend_from_slice(&(header_string_size as u32).to_le_bytes());
+    data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    data.extend_from_slice(&header);
+    data.resize(8 + header_size, 0);
+    data.extend_from_slice(&payload);
+    Ok(data)
+}
+
+// Whether the files both .asars share are stored in a different order
+pub fn is_reordered(old: &FileTree, new: &FileTree) -> bool {
+    let mut common = old
+        .iter()
+        .filter_map(|(path, file)| Some((file.offset, new.get(path)?.offset)))
+        .collect::<Vec<_>>();
+    common.sort();
+    common.windows(2).any(|pair| pair[0].1 > pair[1].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::paths;
+    use std::io::Cursor;
+
+    // Same layout as write_asar, but with whatever header is given
+    fn raw_asar(header: &str, payload: &[u8]) -> Vec<u8> {
+        let header_string_size = 4 + align(header.len());
+        let mut data = Vec::new();
+        data.extend_from_slice(&4u32.to_le_bytes());
+        data.extend_from_slice(&(4 + header_string_size as u32).to_le_bytes());
+        data.extend_from_slice(&(header_string_size as u32).to_le_bytes());
+        data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        data.extend_from_slice(header.as_bytes());
+        data.resize(12 + header_string_size, 0);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn parse(data: Vec<u8>) -> anyhow::Result<FileTree> {
+        parse_asar(&mut Cursor::new(data))
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut tree = FileTree::new();
+        for (path, data, executable) in [("index.js", "a", false), ("bin/run.sh", "bc", true)] {
+            tree.insert(
+                path.to_string(),
+                AsarFile {
+                    data: data.as_bytes().to_vec(),
+                    executable,
+                    offset: 0,
+                },
+            );
+        }
+        let parsed = parse(write_asar(&tree).unwrap()).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed["index.js"].data, b"a");
+        assert!(!parsed["index.js"].executable);
+        assert_eq!(parsed["bin/run.sh"].data, b"bc");
+        assert!(parsed["bin/run.sh"].executable);
+    }
+
+    // Names are kept as they are, extracting is what refuses them
+    #[test]
+    fn keeps_traversal_names() {
+        let header = r#"{"files":{
+            "..":{"files":{"evil.js":{"offset":"0","size":4}}},
+            "/etc":{"files":{"passwd":{"offset":"4","size":4}}}
+        }}"#;
+        let tree = parse(raw_asar(header, b"evilroot")).unwrap();
+        assert_eq!(tree["../evil.js"].data, b"evil");
+        assert_eq!(tree["/etc/passwd"].data, b"root");
+        assert!(tree.keys().all(|path| paths::is_traversal(path)));
+    }
+
+    #[test]
+    fn rejects_oversized_sizes() {
+        let header = format!(
+            r#"{{"files":{{"a.js":{{"offset":"0","size":{}}}}}}}"#,
+            usize::MAX
+        );
+        assert!(parse(raw_asar(&header, b"data")).is_err());
+
+        let header = r#"{"files":{"a.js":{"offset":"2","size":4}}}"#;
+        assert!(parse(raw_asar(header, b"data")).is_err());
+
+        // Every entry pointing at the same data would multiply it
+        let header = r#"{"files":{"a.js":{"offset":"0","size":4},"b.js":{"offset":"0","size":4}}}"#;
+        assert!(parse(raw_asar(header, b"data")).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert!(parse(raw_asar("{\"files\":", b"")).is_err());
+        assert!(parse(raw_asar(r#"{"files":{"a.js":{"size":4}}}"#, b"data")).is_err());
+        assert!(parse(raw_asar(
+            r#"{"files":{"a.js":{"offset":"x","size":4}}}"#,
+            b"data"
+        ))
+        .is_err());
+        assert!(parse(Vec::new()).is_err());
+
+        let mut data = raw_asar(r#"{"files":{}}"#, b"");
+        data[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse(data).is_err());
+
+        let mut nested = String::new();
+        for _ in 0..=MAX_DEPTH + 1 {
+            nested.push_str(r#"{"files":{"a":"#);
+        }
+        nested.push_str(r#"{"files":{}}"#);
+        for _ in 0..=MAX_DEPTH + 1 {
+            nested.push_str("}}");
+        }
+        assert!(parse(raw_asar(&nested, b"")).is_err());
+    }
+}
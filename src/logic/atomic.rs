@@ -0,0 +1,59 @@
+// Cache and config files are shared with any other running instance, so they're written to a temp file
+// and renamed into place. A reader sees the old file or the new one, never half of each.
+use anyhow::Context;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+// Next to the file, since a rename can't cross filesystems, and per process so two writers don't share it
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(".{}.tmp", std::process::id()));
+    PathBuf::from(temp)
+}
+
+pub async fn write(path: &Path, data: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    let temp = temp_path(path);
+    tokio::fs::write(&temp, data)
+        .await
+        .with_context(|| format!("Failed to write {}", temp.display()))?;
+    if let Err(err) = tokio::fs::rename(&temp, path).await {
+        let _ = tokio::fs::remove_file(&temp).await;
+        return Err(err).with_context(|| format!("Failed to replace {}", path.display()));
+    }
+    Ok(())
+}
+
+fn replace_blocking(temp: &Path, path: &Path) -> anyhow::Result<()> {
+    if let Err(err) = std::fs::rename(temp, path) {
+        let _ = std::fs::remove_file(temp);
+        return Err(err).with_context(|| format!("Failed to replace {}", path.display()));
+    }
+    Ok(())
+}
+
+pub fn write_blocking(path: &Path, data: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    let temp = temp_path(path);
+    std::fs::write(&temp, data).with_context(|| format!("Failed to write {}", temp.display()))?;
+    replace_blocking(&temp, path)
+}
+
+// For files with credentials in them, which only the current user should be able to read. The mode
+// only applies to new files, so a temp file left over from a crash is removed first.
+pub fn write_private_blocking(path: &Path, data: impl AsRef<[u8]>) -> anyhow::Result<()> {
+    let temp = temp_path(path);
+    let _ = std::fs::remove_file(&temp);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&temp)
+        .and_then(|mut file| file.write_all(data.as_ref()))
+        .with_context(|| format!("Failed to write {}", temp.display()))?;
+    replace_blocking(&temp, path)
+}
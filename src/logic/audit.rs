@@ -0,0 +1,139 @@
+// Append-only record of what was fetched and checked out during a session, so reviews can be audited later
+use super::config;
+use anyhow::Context;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    tag = "action",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum AuditAction {
+    PullRequestFetched {
+        number: u64,
+        base_sha: String,
+        head_sha: String,
+        run_id: u64,
+        artifact_url: String,
+        extensions: Vec<String>,
+    },
+    FileDownloaded {
+        url: String,
+        sha256: String,
+        size: usize,
+    },
+    CommitCheckedOut {
+        repository: String,
+        commit: String,
+    },
+    LocalBuild {
+        extension: String,
+        success: bool,
+    },
+    Exported {
+        kind: String,
+        path: PathBuf,
+    },
+    CheckRunPublished {
+        head_sha: String,
+        url: String,
+    },
+    ReviewSubmitted {
+        number: u64,
+        head_sha: String,
+        verdict: String,
+        url: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    // Seconds since the Unix epoch
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub action: AuditAction,
+}
+
+struct Session {
+    entries: Vec<AuditEntry>,
+    // Every entry is also appended here as a JSON line, so a crash doesn't lose the log
+    path: PathBuf,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+static SESSION: LazyLock<Mutex<Session>> = LazyLock::new(|| {
+    Mutex::new(Session {
+        entries: Vec::new(),
+        path: config::cache_dir()
+            .join("audit")
+            // Two instances can start within the same second
+            .join(format!("session-{}-{}.jsonl", now(), std::process::id())),
+    })
+});
+
+fn append(path: &Path, entry: &AuditEntry) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context("Failed to create audit dir")?;
+    }
+    let mut line = serde_json::to_vec(entry).context("Failed to serialize audit entry")?;
+    line.push(b'\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(&line))
+        .context("Failed to append to audit log")
+}
+
+pub fn record(action: AuditAction) {
+    let entry = AuditEntry {
+        timestamp: now(),
+        action,
+    };
+    log::debug!("Audit: {:?}", entry.action);
+
+    let mut session = SESSION.lock().unwrap_or_else(|err| err.into_inner());
+    if let Err(err) = append(&session.path, &entry) {
+        log::warn!("{:?}", err);
+    }
+    session.entries.push(entry);
+}
+
+pub fn record_download(url: &str, data: &[u8]) {
+    record(AuditAction::FileDownloaded {
+        url: url.to_string(),
+        sha256: format!("{:x}", Sha256::digest(data)),
+        size: data.len(),
+    });
+}
+
+pub fn entries() -> Vec<AuditEntry> {
+    let session = SESSION.lock().unwrap_or_else(|err| err.into_inner());
+    session.entries.clone()
+}
+
+pub async fn export(path: &Path) -> anyhow::Result<()> {
+    let data = serde_json::to_vec_pretty(&entries()).context("Failed to serialize audit log")?;
+    tokio::fs::write(path, data)
+        .await
+        .context("Failed to write audit log")?;
+    record(AuditAction::Exported {
+        kind: "audit log".to_string(),
+        path: path.to_path_buf(),
+    });
+    Ok(())
+}
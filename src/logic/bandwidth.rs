@@ -0,0 +1,124 @@
+// Bytes downloaded this session, so reviewers on metered connections can decide whether to prefetch
+use super::LogicResponse;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadKind {
+    Artifact,
+    // Published .asars
+    Asar,
+    // Measured from the repository on disk, which is close to what git transferred
+    Clone,
+    // Manifests, registry metadata and advisories
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub artifacts: u64,
+    pub asars: u64,
+    pub clones: u64,
+    pub other: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.artifacts + self.asars + self.clones + self.other
+    }
+
+    fn add(&mut self, kind: DownloadKind, bytes: u64) {
+        let field = match kind {
+            DownloadKind::Artifact => &mut self.artifacts,
+            DownloadKind::Asar => &mut self.asars,
+            DownloadKind::Clone => &mut self.clones,
+            DownloadKind::Other => &mut self.other,
+        };
+        *field += bytes;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandwidthUsage {
+    pub total: Usage,
+    // By pull request number. Local reviews and lookups outside a pull request only count towards the total.
+    pub pull_requests: BTreeMap<u64, Usage>,
+}
+
+static USAGE: Mutex<BandwidthUsage> = Mutex::new(BandwidthUsage {
+    total: Usage {
+        artifacts: 0,
+        asars: 0,
+        clones: 0,
+        other: 0,
+    },
+    pull_requests: BTreeMap::new(),
+});
+// Bumped on every download, so watchers only send changes
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+pub fn record(pull_request: Option<u64>, kind: DownloadKind, bytes: u64) {
+    let mut usage = USAGE.lock().unwrap_or_else(|err| err.into_inner());
+    usage.total.add(kind, bytes);
+    if let Some(pull_request) = pull_request.filter(|number| *number != 0) {
+        usage
+            .pull_requests
+            .entry(pull_request)
+            .or_default()
+            .add(kind, bytes);
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn usage() -> BandwidthUsage {
+    USAGE.lock().unwrap_or_else(|err| err.into_inner()).clone()
+}
+
+// Sends the usage whenever it changes, until the receiver is gone
+pub async fn watch(tx: flume::Sender<LogicResponse>) {
+    let mut sent = None;
+    loop {
+        let generation = GENERATION.load(Ordering::Relaxed);
+        if sent != Some(generation) {
+            sent = Some(generation);
+            if tx.send(LogicResponse::Bandwidth(usage())).is_err() {
+                return;
+            }
+        }
+        tokio::time::sleep(WATCH_INTERVAL).await;
+    }
+}
+
+// The size of a repository's object store, for measuring clones and fetches
+pub async fn git_size(repo_dir: &Path) -> u64 {
+    fn walk(dir: PathBuf) -> u64 {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return 0;
+        };
+        entries
+            .flatten()
+            .map(|entry| match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => walk(entry.path()),
+                Ok(_) => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+                Err(_) => 0,
+            })
+            .sum()
+    }
+
+    let objects = repo_dir.join(".git").join("objects");
+    tokio::task::spawn_blocking(move || walk(objects))
+        .await
+        .unwrap_or_default()
+}
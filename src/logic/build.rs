@@ -0,0 +1,239 @@
+use super::{
+    config::{BuildConfig, ConcurrencyConfig},
+    diff::{self, Diff},
+    download::copy_recursive,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BuildOutcome {
+    Succeeded,
+    // The install or build command exited with an error
+    Failed,
+    // The commands succeeded, but the output directory wasn't there afterwards
+    MissingOutput,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalBuild {
+    // Output of every step, in order
+    pub log: String,
+    pub outcome: BuildOutcome,
+    // Local build output (old) against the artifact (new), when the build succeeded
+    pub diff: Option<Diff>,
+}
+
+// Proxies that go nowhere, so well-behaved tools can't reach the network after installing.
+// Anything that ignores them still can, only a container actually cuts it off.
+const NO_NETWORK_ENV: &[(&str, &str)] = &[
+    ("HTTP_PROXY", "http://127.0.0.1:9"),
+    ("HTTPS_PROXY", "http://127.0.0.1:9"),
+    ("http_proxy", "http://127.0.0.1:9"),
+    ("https_proxy", "http://127.0.0.1:9"),
+    ("npm_config_offline", "true"),
+];
+
+// The only variables builds get from the reviewer's environment, so tokens and other secrets in
+// it don't reach scripts from the pull request
+const PASSED_ENV: &[&str] = &[
+    "PATH",
+    "LANG",
+    "LC_ALL",
+    "TMPDIR",
+    // Windows can't start much without these
+    "SystemRoot",
+    "windir",
+    "ComSpec",
+    "PATHEXT",
+    "TEMP",
+    "TMP",
+];
+
+// Passed on for installing, when the network is allowed
+const PROXY_ENV: &[&str] = &[
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "no_proxy",
+];
+
+fn step_command(
+    config: &BuildConfig,
+    work_dir: &Path,
+    home_dir: &Path,
+    command: &str,
+    network: bool,
+) -> Command {
+    if !config.container_runtime.trim().is_empty() {
+        let mut cmd = Command::new(config.container_runtime.trim());
+        cmd.arg("run")
+            .arg("--rm")
+            .arg("-v")
+            .arg(format!("{}:/work", work_dir.display()))
+            .arg("-v")
+            .arg(format!("{}:/home/build", home_dir.display()))
+            .args(["-w", "/work", "-e", "HOME=/home/build"]);
+        if !network {
+            cmd.args(["--network", "none"]);
+        }
+        cmd.arg(&config.image).args(["sh", "-c", command]);
+        #[cfg(target_os = "windows")]
+        cmd.creation_flags(super::CREATE_NO_WINDOW);
+        return cmd;
+    }
+
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd.creation_flags(super::CREATE_NO_WINDOW);
+        cmd
+    };
+    #[cfg(not(target_os = "windows"))]
+    let mut cmd = {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+
+    let passed = if network {
+        [PASSED_ENV, PROXY_ENV].concat()
+    } else {
+        PASSED_ENV.to_vec()
+    };
+    cmd.env_clear()
+        .envs(
+            passed
+                .into_iter()
+                .filter_map(|name| Some((name, std::env::var_os(name)?))),
+        )
+        .current_dir(work_dir)
+        .env("HOME", home_dir)
+        .env("USERPROFILE", home_dir)
+        .env("npm_config_cache", home_dir.join(".npm"));
+    if !network {
+        cmd.envs(NO_NETWORK_ENV.iter().copied());
+    }
+    cmd
+}
+
+async fn run_step(
+    config: &BuildConfig,
+    work_dir: &Path,
+    home_dir: &Path,
+    command: &str,
+    network: bool,
+    log: &mut String,
+) -> anyhow::Result<bool> {
+    log.push_str(&format!("$ {}\n", command));
+    let output = step_command(config, work_dir, home_dir, command, network)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {}", command))?;
+
+    log.push_str(&String::from_utf8_lossy(&output.stdout));
+    log.push_str(&String::from_utf8_lossy(&output.stderr));
+    log.push_str(&format!("({})\n\n", output.status));
+    Ok(output.status.success())
+}
+
+// The output directory comes from the settings, so it has to stay inside the copied source
+fn output_dir(
+    config: &BuildConfig,
+    extension_id: &str,
+    work_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let relative = PathBuf::from(config.output_dir.replace("{id}", extension_id));
+    let inside = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !inside {
+        anyhow::bail!(
+            "Build output directory {} isn't inside the source",
+            relative.display()
+        );
+    }
+    Ok(work_dir.join(relative))
+}
+
+pub async fn build_locally(
+    config: &BuildConfig,
+    limits: &ConcurrencyConfig,
+    extension_id: &str,
+    source_dir: &Path,
+    artifact_dir: &Path,
+    temp_dir: &Path,
+) -> anyhow::Result<LocalBuild> {
+    log::debug!("Building {} locally", extension_id);
+
+    let build_dir = temp_dir.join("build");
+    let work_dir = build_dir.join("work");
+    let home_dir = build_dir.join("home");
+    // Checked before anything runs, a bad setting shouldn't cost a whole build
+    let output_dir = output_dir(config, extension_id, &work_dir)?;
+    if build_dir.exists() {
+        tokio::fs::remove_dir_all(&build_dir)
+            .await
+            .context("Failed to remove old build dir")?;
+    }
+    for dir in [&work_dir, &home_dir] {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .context("Failed to create build dir")?;
+    }
+
+    // Build from a copy so the reviewed source never sees build artifacts
+    copy_recursive(source_dir.to_path_buf(), work_dir.clone())
+        .await
+        .context("Failed to copy source")?;
+
+    let mut log = String::new();
+    if config.container_runtime.trim().is_empty() {
+        log.push_str(
+            "No container runtime configured, so the build can still reach the network. \
+             Proxy variables only stop tools that respect them.\n\n",
+        );
+    }
+
+    let success = run_step(
+        config,
+        &work_dir,
+        &home_dir,
+        &config.install_command,
+        true,
+        &mut log,
+    )
+    .await?
+        && run_step(
+            config,
+            &work_dir,
+            &home_dir,
+            &config.build_command,
+            false,
+            &mut log,
+        )
+        .await?;
+
+    let (outcome, diff) = if !success {
+        (BuildOutcome::Failed, None)
+    } else if !output_dir.exists() {
+        log.push_str(&format!(
+            "Build output {} doesn't exist\n",
+            output_dir.display()
+        ));
+        (BuildOutcome::MissingOutput, None)
+    } else {
+        let diff = diff::calculate_diff(&output_dir, artifact_dir, limits)
+            .await
+            .context("Failed to diff build output")?;
+        (BuildOutcome::Succeeded, Some(diff))
+    };
+
+    Ok(LocalBuild { log, outcome, diff })
+}
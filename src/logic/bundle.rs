@@ -0,0 +1,189 @@
+use super::syntax;
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+use tree_sitter::Node;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ModuleChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleChange {
+    pub id: String,
+    pub kind: ModuleChangeKind,
+    pub old_size: usize,
+    pub new_size: usize,
+    // 1-indexed, where the module starts in the new bundle
+    pub new_line: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleDiff {
+    pub changes: Vec<ModuleChange>,
+    pub unchanged: usize,
+}
+
+struct Module<'a> {
+    source: &'a str,
+    line: usize,
+}
+
+// Module factories take (module, exports, require) at most
+fn is_module_factory(node: Node) -> bool {
+    if !matches!(
+        node.kind(),
+        "function" | "function_expression" | "arrow_function"
+    ) {
+        return false;
+    }
+    let params = node
+        .child_by_field_name("parameters")
+        .map(|params| params.named_child_count())
+        .unwrap_or(1);
+    params <= 3
+}
+
+fn module_id(key: Node, source: &str) -> Option<String> {
+    let text = key.utf8_text(source.as_bytes()).ok()?;
+    match key.kind() {
+        "number" => Some(text.to_string()),
+        // Development builds key modules by path instead
+        "string" if text.contains('/') => Some(text.trim_matches(['"', '\'']).to_string()),
+        _ => None,
+    }
+}
+
+// `{ 123: (e, t, n) => {...} }` for webpack 5, `[function (e, t) {...}]` for older builds
+fn module_table<'a>(node: Node, source: &'a str) -> Option<Vec<(String, Module<'a>)>> {
+    let mut modules = Vec::new();
+    let mut cursor = node.walk();
+    let children = node.named_children(&mut cursor).collect::<Vec<_>>();
+
+    match node.kind() {
+        "object" => {
+            for child in &children {
+                if child.kind() != "pair" {
+                    continue;
+                }
+                let (Some(key), Some(value)) = (
+                    child.child_by_field_name("key"),
+                    child.child_by_field_name("value"),
+                ) else {
+                    continue;
+                };
+                if !is_module_factory(value) {
+                    continue;
+                }
+                let Some(id) = module_id(key, source) else {
+                    continue;
+                };
+                modules.push((id, value));
+            }
+        }
+        "array" => {
+            if children.len() < 3 || !children.iter().all(|child| is_module_factory(*child)) {
+                return None;
+            }
+            for (i, child) in children.iter().enumerate() {
+                modules.push((i.to_string(), *child));
+            }
+        }
+        _ => return None,
+    }
+
+    // Plain objects full of methods would otherwise look like module tables
+    if modules.len() < 2 || modules.len() * 2 < children.len() {
+        return None;
+    }
+
+    Some(
+        modules
+            .into_iter()
+            .filter_map(|(id, value)| {
+                let text = value.utf8_text(source.as_bytes()).ok()?;
+                Some((
+                    id,
+                    Module {
+                        source: text,
+                        line: value.start_position().row + 1,
+                    },
+                ))
+            })
+            .collect(),
+    )
+}
+
+fn collect_modules<'a>(node: Node, source: &'a str, modules: &mut BTreeMap<String, Module<'a>>) {
+    if let Some(table) = module_table(node, source) {
+        modules.extend(table);
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_modules(child, source, modules);
+    }
+}
+
+fn find_modules<'a>(path: &Path, source: &'a str) -> BTreeMap<String, Module<'a>> {
+    let mut modules = BTreeMap::new();
+    if let Some(tree) = syntax::parse(path, source) {
+        collect_modules(tree.root_node(), source, &mut modules);
+    }
+    modules
+}
+
+pub fn module_ids(path: &Path, source: &str) -> Vec<String> {
+    find_modules(path, source).into_keys().collect()
+}
+
+// None when neither side looks like a webpack bundle
+pub fn diff_modules(old_path: &Path, old: &str, new_path: &Path, new: &str) -> Option<ModuleDiff> {
+    let old_modules = find_modules(old_path, old);
+    let new_modules = find_modules(new_path, new);
+    if old_modules.is_empty() && new_modules.is_empty() {
+        return None;
+    }
+
+    let mut changes = Vec::new();
+    let mut unchanged = 0;
+
+    for (id, new_module) in &new_modules {
+        let kind = match old_modules.get(id) {
+            None => ModuleChangeKind::Added,
+            Some(old_module) if old_module.source != new_module.source => ModuleChangeKind::Changed,
+            Some(_) => {
+                unchanged += 1;
+                continue;
+            }
+        };
+
+        changes.push(ModuleChange {
+            id: id.clone(),
+            kind,
+            old_size: old_modules
+                .get(id)
+                .map(|module| module.source.len())
+                .unwrap_or_default(),
+            new_size: new_module.source.len(),
+            new_line: Some(new_module.line),
+        });
+    }
+
+    for (id, old_module) in &old_modules {
+        if !new_modules.contains_key(id) {
+            changes.push(ModuleChange {
+                id: id.clone(),
+                kind: ModuleChangeKind::Removed,
+                old_size: old_module.source.len(),
+                new_size: 0,
+                new_line: None,
+            });
+        }
+    }
+
+    Some(ModuleDiff { changes, unchanged })
+}
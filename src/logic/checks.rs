@@ -0,0 +1,165 @@
+// Publishes scan findings as a check run on the pull request, so they show up on GitHub
+use super::{
+    audit::{self, AuditAction},
+    scan::{Finding, FindingCategory, Severity},
+    templates,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const CHECK_NAME: &str = "RoboJules";
+// The Checks API takes at most this many annotations per request
+const MAX_ANNOTATIONS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckConclusion {
+    Success,
+    Neutral,
+    Failure,
+}
+
+impl CheckConclusion {
+    // Without a policy, findings only need a look rather than failing the PR
+    pub fn from_findings(findings: &BTreeMap<String, Vec<Finding>>) -> Self {
+        if findings.values().all(|findings| findings.is_empty()) {
+            CheckConclusion::Success
+        } else {
+            CheckConclusion::Neutral
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckRun {
+    pub id: u64,
+    pub html_url: String,
+}
+
+#[derive(Serialize)]
+struct Annotation {
+    path: String,
+    start_line: usize,
+    end_line: usize,
+    annotation_level: &'static str,
+    title: String,
+    message: String,
+}
+
+// Findings are in the extension's own repository, so they're pinned to its manifest in the PR
+fn annotation(
+    extension_id: &str,
+    finding: &Finding,
+    templates: &BTreeMap<String, String>,
+) -> Annotation {
+    let location = match finding.line {
+        Some(line) => format!("{}:{}", finding.path, line),
+        None => finding.path.clone(),
+    };
+    let details = format!("{:?} {}: {}", finding.origin, location, finding.message);
+    // Check runs are public, so secrets only get the masked form that's already in the message
+    let details = match finding.category {
+        FindingCategory::Secret => details,
+        _ => format!("{}\n\n{}", details, finding.snippet),
+    };
+    Annotation {
+        path: format!("exts/{}.json", extension_id),
+        start_line: 1,
+        end_line: 1,
+        annotation_level: match finding.severity {
+            Severity::Info => "notice",
+            Severity::Warning => "warning",
+            Severity::Danger => "failure",
+        },
+        title: format!("{} ({})", finding.rule, finding.category.id()),
+        // The category's standard wording comes first, since it's what the author reads
+        message: match templates::for_finding(templates, extension_id, finding) {
+            Some(text) => format!("{}\n\n{}", text, details),
+            None => details,
+        },
+    }
+}
+
+fn summary(findings: &BTreeMap<String, Vec<Finding>>) -> String {
+    let mut summary = String::new();
+    for (extension_id, findings) in findings {
+        let count = |severity| {
+            findings
+                .iter()
+                .filter(|finding| finding.severity == severity)
+                .count()
+        };
+        summary.push_str(&format!(
+            "- `{}`: {} danger, {} warning, {} info\n",
+            extension_id,
+            count(Severity::Danger),
+            count(Severity::Warning),
+            count(Severity::Info)
+        ));
+    }
+    summary.push_str("\nAutomated findings are a first pass and don't replace reviewing the PR.");
+    summary
+}
+
+// Needs a token that can write checks, e.g. a GitHub App or Actions' GITHUB_TOKEN
+pub async fn publish(
+    client: &octocrab::Octocrab,
+    head_sha: &str,
+    findings: &BTreeMap<String, Vec<Finding>>,
+    conclusion: CheckConclusion,
+    templates: &BTreeMap<String, String>,
+) -> anyhow::Result<CheckRun> {
+    let annotations = findings
+        .iter()
+        .flat_map(|(extension_id, findings)| {
+            findings
+                .iter()
+                .map(|finding| annotation(extension_id, finding, templates))
+        })
+        .collect::<Vec<_>>();
+    let title = format!("{} findings", annotations.len());
+    let summary = summary(findings);
+    let mut chunks = annotations.chunks(MAX_ANNOTATIONS);
+
+    let body = serde_json::json!({
+        "name": CHECK_NAME,
+        "head_sha": head_sha,
+        "status": "completed",
+        "conclusion": conclusion,
+        "output": {
+            "title": title,
+            "summary": summary,
+            "annotations": chunks.next().unwrap_or_default(),
+        },
+    });
+    let check_run: CheckRun = client
+        .post("/repos/moonlight-mod/extensions/check-runs", Some(&body))
+        .await
+        .context("Failed to create check run")?;
+
+    // Later requests append to the annotations already on the run
+    let route = format!(
+        "/repos/moonlight-mod/extensions/check-runs/{}",
+        check_run.id
+    );
+    for chunk in chunks {
+        let body = serde_json::json!({
+            "output": {
+                "title": title,
+                "summary": summary,
+                "annotations": chunk,
+            },
+        });
+        client
+            .patch::<serde_json::Value, _, _>(&route, Some(&body))
+            .await
+            .context("Failed to add annotations to check run")?;
+    }
+
+    audit::record(AuditAction::CheckRunPublished {
+        head_sha: head_sha.to_string(),
+        url: check_run.html_url.clone(),
+    });
+    Ok(check_run)
+}
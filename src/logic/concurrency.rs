@@ -0,0 +1,29 @@
+use std::{future::Future, sync::Arc};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+// Runs futures with at most `limit` in flight, returning results in the original order
+pub async fn run_limited<T, F>(limit: usize, futures: impl IntoIterator<Item = F>) -> Vec<T>
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(limit.max(1)));
+    let mut set = JoinSet::new();
+    for (i, future) in futures.into_iter().enumerate() {
+        let semaphore = semaphore.clone();
+        set.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            (i, future.await)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(res) = set.join_next().await {
+        match res {
+            Ok(result) => results.push(result),
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
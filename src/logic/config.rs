@@ -0,0 +1,334 @@
+use super::{atomic, templates};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf, sync::RwLock};
+
+// Set from the config, so the default only applies until it's loaded
+static CACHE_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("robojules")
+}
+
+pub fn default_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("robojules")
+}
+
+pub fn cache_dir() -> PathBuf {
+    CACHE_DIR
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+        .unwrap_or_else(default_cache_dir)
+}
+
+pub fn set_cache_dir(config: &Config) {
+    let dir = config.cache_dir.trim();
+    *CACHE_DIR.write().unwrap_or_else(|err| err.into_inner()) =
+        (!dir.is_empty()).then(|| PathBuf::from(dir));
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildConfig {
+    // Local builds run code from the PR, so they're off until explicitly enabled
+    pub enabled: bool,
+    // e.g. `docker` or `podman`; builds run directly on the host when empty
+    pub container_runtime: String,
+    pub image: String,
+    pub install_command: String,
+    pub build_command: String,
+    // Relative to the repository, `{id}` is replaced with the extension ID
+    pub output_dir: String,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            container_runtime: String::new(),
+            image: "node:22".to_string(),
+            install_command: "pnpm install --frozen-lockfile".to_string(),
+            build_command: "pnpm run build".to_string(),
+            output_dir: "dist/{id}".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArtifactConfig {
+    // GitHub only serves artifacts to logged in users, so they're fetched through a mirror.
+    // `{run_id}` and `{artifact}` are replaced with the workflow run ID and artifact name.
+    pub url_template: String,
+    // Sent with artifact downloads only, e.g. a self-hosted proxy's Authorization header
+    pub headers: BTreeMap<String, String>,
+    // Skips the mirror when there's a GitHub token
+    pub direct: bool,
+}
+
+impl Default for ArtifactConfig {
+    fn default() -> Self {
+        Self {
+            url_template:
+                "https://nightly.link/moonlight-mod/extensions/actions/runs/{run_id}/{artifact}.zip"
+                    .to_string(),
+            headers: BTreeMap::new(),
+            direct: true,
+        }
+    }
+}
+
+impl ArtifactConfig {
+    pub fn url(&self, run_id: u64, artifact: &str) -> String {
+        self.url_template
+            .replace("{run_id}", &run_id.to_string())
+            .replace("{artifact}", artifact)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    // Parallel HTTP requests, e.g. registry lookups
+    pub downloads: usize,
+    pub hashing_threads: usize,
+    // Files whose line stats are computed at once
+    pub diff_workers: usize,
+    // Seconds a single file's diff can take before it's shown as plain text instead, also
+    // the limit for each file's line stats
+    pub diff_timeout_secs: u64,
+    // How often favorites' published .asars are refreshed in the background, 0 to never
+    pub dist_refresh_minutes: u64,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        let cpus = std::thread::available_parallelism()
+            .map(|cpus| cpus.get())
+            .unwrap_or(1);
+        Self {
+            downloads: 4,
+            hashing_threads: cpus,
+            diff_workers: cpus,
+            diff_timeout_secs: 15,
+            dist_refresh_minutes: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    // Secret gist the team shares review progress through; syncing is off when empty
+    pub gist_id: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Renderer {
+    #[default]
+    Glow,
+    Wgpu,
+    // OpenGL without hardware acceleration, for GPU drivers that break both of the others
+    Software,
+}
+
+impl Renderer {
+    pub const ALL: [Renderer; 3] = [Renderer::Glow, Renderer::Wgpu, Renderer::Software];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Renderer::Glow => "glow",
+            Renderer::Wgpu => "wgpu",
+            Renderer::Software => "software",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|renderer| renderer.id() == text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Palette {
+    #[default]
+    RedGreen,
+    BlueOrange,
+    MagentaTeal,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 3] = [Palette::RedGreen, Palette::BlueOrange, Palette::MagentaTeal];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::RedGreen => "Red and green",
+            Palette::BlueOrange => "Blue and orange",
+            Palette::MagentaTeal => "Magenta and teal",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 2] = [Theme::Dark, Theme::Light];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+        }
+    }
+}
+
+// Kept out of logs, since configs are printed along with the commands carrying them
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0.is_empty() {
+            f.write_str("\"\"")
+        } else {
+            f.write_str("\"<redacted>\"")
+        }
+    }
+}
+
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 3.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Hosts extensions are expected to talk to; subdomains are allowed too
+    pub allowed_domains: Vec<String>,
+    pub build: BuildConfig,
+    pub artifacts: ArtifactConfig,
+    pub concurrency: ConcurrencyConfig,
+    pub sync: SyncConfig,
+    // Extension IDs listed first in the extension picker and prefetch list
+    pub favorites: Vec<String>,
+    // Read at startup; the others are tried in turn if it fails to start
+    pub renderer: Renderer,
+    // On top of the display's own scale
+    pub ui_scale: f32,
+    // Colors for added and removed content
+    pub palette: Palette,
+    pub theme: Theme,
+    // Closing the window hides it to the tray instead of quitting
+    pub tray: bool,
+    // Used when GITHUB_TOKEN isn't set, before falling back to the gh CLI's login
+    pub github_token: Secret,
+    // Wording for feedback on each finding category, by category ID
+    pub review_templates: BTreeMap<String, String>,
+    // Languages for file extensions the built-in detection gets wrong or doesn't know
+    pub languages: BTreeMap<String, String>,
+    // Downloaded grammars, published .asars and advisories; the platform's cache directory when empty
+    pub cache_dir: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            allowed_domains: [
+                "discord.com",
+                "discordapp.com",
+                "discordapp.net",
+                "discord.gg",
+                "discord.media",
+                "github.com",
+                "githubusercontent.com",
+                "moonlight-mod.github.io",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            build: BuildConfig::default(),
+            artifacts: ArtifactConfig::default(),
+            concurrency: ConcurrencyConfig::default(),
+            sync: SyncConfig::default(),
+            favorites: Vec::new(),
+            renderer: Renderer::default(),
+            ui_scale: 1.0,
+            palette: Palette::default(),
+            theme: Theme::default(),
+            tray: false,
+            github_token: Secret::default(),
+            review_templates: templates::default_templates(),
+            languages: [("svelte", "html"), ("vue", "html"), ("jsonc", "json")]
+                .into_iter()
+                .map(|(ext, name)| (ext.to_string(), name.to_string()))
+                .collect(),
+            cache_dir: String::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        config_dir().join("config.json")
+    }
+
+    pub fn load() -> Self {
+        let path = Self::path();
+        if !path.exists() {
+            return Self::default();
+        }
+
+        match std::fs::read(&path)
+            .context("Failed to read config")
+            .and_then(|data| serde_json::from_slice(&data).context("Failed to parse config"))
+        {
+            Ok(config) => config,
+            Err(err) => {
+                log::warn!("Failed to load config, using defaults: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+
+    // The GitHub token is in here, so only the user can read it
+    pub fn save(&self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(config_dir()).context("Failed to create config dir")?;
+        let data = serde_json::to_vec_pretty(self).context("Failed to serialize config")?;
+        atomic::write_private_blocking(&Self::path(), data).context("Failed to write config")
+    }
+
+    pub fn is_favorite(&self, extension_id: &str) -> bool {
+        self.favorites.iter().any(|id| id == extension_id)
+    }
+
+    pub fn toggle_favorite(&mut self, extension_id: &str) {
+        if self.is_favorite(extension_id) {
+            self.favorites.retain(|id| id != extension_id);
+        } else {
+            self.favorites.push(extension_id.to_string());
+        }
+    }
+
+    pub fn is_domain_allowed(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+        self.allowed_domains.iter().any(|allowed| {
+            let allowed = allowed.trim().to_lowercase();
+            host == allowed || host.ends_with(&format!(".{}", allowed))
+        })
+    }
+}
@@ -0,0 +1,80 @@
+// Published extensions that depend on the one under review, so a risky change's reach is known before approving
+use super::{
+    audit,
+    bandwidth::{self, DownloadKind},
+    download::get_url,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+// Every published extension's manifest, as moonlight itself reads it
+const REPO_URL: &str = "https://moonlight-mod.github.io/extensions-dist/repo.json";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RepoMeta {
+    name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoManifest {
+    id: String,
+    #[serde(default)]
+    meta: RepoMeta,
+    #[serde(default)]
+    dependencies: Vec<String>,
+    #[serde(default)]
+    suggested: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dependent {
+    pub id: String,
+    pub name: String,
+    // Only suggests the extension, so it still loads without it
+    pub optional: bool,
+}
+
+pub async fn find_dependents(
+    client: &octocrab::Octocrab,
+    ext_id: &str,
+) -> anyhow::Result<Vec<Dependent>> {
+    log::debug!("Finding extensions that depend on {}", ext_id);
+    let data = get_url(client, REPO_URL)
+        .await
+        .context("Failed to download extension registry")?;
+    audit::record_download(REPO_URL, &data);
+    bandwidth::record(None, DownloadKind::Other, data.len() as u64);
+    let entries = serde_json::from_slice::<Vec<serde_json::Value>>(&data)
+        .context("Failed to parse extension registry")?;
+
+    // One malformed manifest shouldn't hide everything else
+    let mut dependents = Vec::new();
+    for entry in entries {
+        let manifest = match serde_json::from_value::<RepoManifest>(entry) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                log::warn!("Skipping malformed registry entry: {}", err);
+                continue;
+            }
+        };
+        if manifest.id == ext_id {
+            continue;
+        }
+
+        let required = manifest.dependencies.iter().any(|id| id == ext_id);
+        let suggested = manifest.suggested.iter().any(|id| id == ext_id);
+        if required || suggested {
+            dependents.push(Dependent {
+                name: manifest.meta.name.unwrap_or_else(|| manifest.id.clone()),
+                id: manifest.id,
+                optional: !required,
+            });
+        }
+    }
+
+    // Hard dependencies break outright, so they come first
+    dependents.sort_by(|a, b| a.optional.cmp(&b.optional).then(a.id.cmp(&b.id)));
+    Ok(dependents)
+}
@@ -0,0 +1,567 @@
+use super::{
+    atomic,
+    bandwidth::{self, DownloadKind},
+    concurrency, config,
+    download::{get_url, post_json},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
+
+const LOCKFILES: &[&str] = &["pnpm-lock.yaml", "package-lock.json"];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    pub integrity: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DependencyChangeKind {
+    Added,
+    Removed,
+    Updated,
+    // Same versions, but at least one resolves to different contents
+    IntegrityChanged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyChange {
+    pub name: String,
+    pub kind: DependencyChangeKind,
+    pub old_versions: Vec<String>,
+    pub new_versions: Vec<String>,
+    // Versions on both sides whose integrity hash changed
+    pub integrity_changed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DependencyDiff {
+    // The lockfile name, if the extension has one
+    pub lockfile: Option<String>,
+    pub changes: Vec<DependencyChange>,
+}
+
+impl DependencyDiff {
+    // Every version that wasn't in the old lockfile, as (name, version) pairs
+    pub fn introduced(&self) -> Vec<(String, String)> {
+        self.changes
+            .iter()
+            .filter(|change| change.kind != DependencyChangeKind::Removed)
+            .flat_map(|change| {
+                change
+                    .new_versions
+                    .iter()
+                    .filter(|version| !change.old_versions.contains(version))
+                    .map(|version| (change.name.clone(), version.clone()))
+            })
+            .collect()
+    }
+
+    pub fn added(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.changes
+            .iter()
+            .filter(|change| change.kind == DependencyChangeKind::Added)
+            .filter_map(|change| {
+                change
+                    .new_versions
+                    .last()
+                    .map(|version| (change.name.as_str(), version.as_str()))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyRisk {
+    pub name: String,
+    pub version: String,
+    pub version_age_days: Option<i64>,
+    pub package_age_days: Option<i64>,
+    pub maintainers: usize,
+    // preinstall/install/postinstall scripts that run on `npm install`
+    pub install_scripts: Vec<String>,
+}
+
+impl DependencyRisk {
+    pub fn signals(&self) -> Vec<String> {
+        let mut signals = Vec::new();
+        if self.version_age_days.is_some_and(|days| days < 14) {
+            signals.push("version published in the last two weeks".to_string());
+        }
+        if self.package_age_days.is_some_and(|days| days < 90) {
+            signals.push("package is less than three months old".to_string());
+        }
+        if self.maintainers <= 1 {
+            signals.push("single maintainer".to_string());
+        }
+        for script in &self.install_scripts {
+            signals.push(format!("runs a {} script", script));
+        }
+        signals
+    }
+}
+
+fn parse_pnpm_key(key: &str) -> Option<(String, String)> {
+    let key = key.trim().trim_end_matches(':').trim_matches(['\'', '"']);
+    let key = key.strip_prefix('/').unwrap_or(key);
+    // Drop peer dependency suffixes like `(react@18.0.0)`
+    let key = key.split('(').next().unwrap_or(key);
+    if key.is_empty() {
+        return None;
+    }
+
+    // pnpm v6+ uses name@version, older lockfiles use name/version. The first character is
+    // skipped so a scope's @ isn't taken for the version separator.
+    let first = key.chars().next()?.len_utf8();
+    match key[first..].rfind('@') {
+        Some(i) => Some((
+            key[..first + i].to_string(),
+            key[first + i + 1..].to_string(),
+        )),
+        None => {
+            let (name, version) = key.rsplit_once('/')?;
+            Some((name.to_string(), version.to_string()))
+        }
+    }
+}
+
+pub fn parse_pnpm_lock(text: &str) -> Vec<LockedPackage> {
+    let mut packages = Vec::new();
+    let mut in_packages = false;
+
+    for line in text.lines() {
+        if !line.starts_with(' ') && !line.trim().is_empty() {
+            in_packages = line.trim_end() == "packages:";
+            continue;
+        }
+        if !in_packages {
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        if indent == 2 && line.trim_end().ends_with(':') {
+            if let Some((name, version)) = parse_pnpm_key(line) {
+                packages.push(LockedPackage {
+                    name,
+                    version,
+                    integrity: None,
+                });
+            }
+        } else if let Some(package) = packages.last_mut() {
+            if let Some((_, rest)) = line.split_once("integrity: ") {
+                let integrity = rest.split([',', '}']).next().unwrap_or(rest).trim();
+                package.integrity = Some(integrity.to_string());
+            }
+        }
+    }
+
+    packages
+}
+
+#[derive(Deserialize)]
+struct PackageLockEntry {
+    version: Option<String>,
+    integrity: Option<String>,
+    #[serde(default)]
+    dependencies: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct PackageLock {
+    #[serde(default)]
+    packages: HashMap<String, PackageLockEntry>,
+    #[serde(default)]
+    dependencies: HashMap<String, serde_json::Value>,
+}
+
+fn walk_v1_dependencies(
+    dependencies: &HashMap<String, serde_json::Value>,
+    packages: &mut Vec<LockedPackage>,
+) {
+    for (name, value) in dependencies {
+        let Ok(entry) = serde_json::from_value::<PackageLockEntry>(value.clone()) else {
+            continue;
+        };
+        if let Some(version) = entry.version {
+            packages.push(LockedPackage {
+                name: name.clone(),
+                version,
+                integrity: entry.integrity,
+            });
+        }
+        walk_v1_dependencies(&entry.dependencies, packages);
+    }
+}
+
+pub fn parse_package_lock(text: &str) -> anyhow::Result<Vec<LockedPackage>> {
+    let lock = serde_json::from_str::<PackageLock>(text).context("Failed to parse lockfile")?;
+    let mut packages = Vec::new();
+
+    if !lock.packages.is_empty() {
+        for (path, entry) in lock.packages {
+            // The root project is keyed by an empty string
+            let Some((_, name)) = path.rsplit_once("node_modules/") else {
+                continue;
+            };
+            if let Some(version) = entry.version {
+                packages.push(LockedPackage {
+                    name: name.to_string(),
+                    version,
+                    integrity: entry.integrity,
+                });
+            }
+        }
+    } else {
+        walk_v1_dependencies(&lock.dependencies, &mut packages);
+    }
+
+    Ok(packages)
+}
+
+async fn read_lockfile(dir: &Path, name: &str) -> anyhow::Result<Vec<LockedPackage>> {
+    let path = dir.join(name);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let text = tokio::fs::read_to_string(&path)
+        .await
+        .context("Failed to read lockfile")?;
+    if name == "pnpm-lock.yaml" {
+        Ok(parse_pnpm_lock(&text))
+    } else {
+        parse_package_lock(&text)
+    }
+}
+
+// Integrity hashes by version, by package name
+fn versions_by_name(
+    packages: &[LockedPackage],
+) -> BTreeMap<String, BTreeMap<String, Option<String>>> {
+    let mut map: BTreeMap<String, BTreeMap<String, Option<String>>> = BTreeMap::new();
+    for package in packages {
+        map.entry(package.name.clone())
+            .or_default()
+            .insert(package.version.clone(), package.integrity.clone());
+    }
+    map
+}
+
+pub async fn diff_dependencies(old_dir: &Path, new_dir: &Path) -> anyhow::Result<DependencyDiff> {
+    let Some(lockfile) = LOCKFILES
+        .iter()
+        .find(|name| new_dir.join(name).exists() || old_dir.join(name).exists())
+    else {
+        return Ok(DependencyDiff::default());
+    };
+
+    let old = versions_by_name(&read_lockfile(old_dir, lockfile).await?);
+    let new = versions_by_name(&read_lockfile(new_dir, lockfile).await?);
+
+    let mut changes = Vec::new();
+    let names = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+    for name in names {
+        let old_versions = old.get(name).cloned().unwrap_or_default();
+        let new_versions = new.get(name).cloned().unwrap_or_default();
+        // Only when both sides have a hash, older lockfiles may not record one
+        let integrity_changed = old_versions
+            .iter()
+            .filter(|(version, old_integrity)| {
+                new_versions.get(*version).is_some_and(|new_integrity| {
+                    old_integrity.is_some()
+                        && new_integrity.is_some()
+                        && new_integrity != *old_integrity
+                })
+            })
+            .map(|(version, _)| version.clone())
+            .collect::<Vec<_>>();
+        let kind = match (old_versions.is_empty(), new_versions.is_empty()) {
+            (true, false) => DependencyChangeKind::Added,
+            (false, true) => DependencyChangeKind::Removed,
+            _ if !old_versions.keys().eq(new_versions.keys()) => DependencyChangeKind::Updated,
+            _ if !integrity_changed.is_empty() => DependencyChangeKind::IntegrityChanged,
+            _ => continue,
+        };
+
+        changes.push(DependencyChange {
+            name: name.clone(),
+            kind,
+            old_versions: old_versions.into_keys().collect(),
+            new_versions: new_versions.into_keys().collect(),
+            integrity_changed,
+        });
+    }
+
+    Ok(DependencyDiff {
+        lockfile: Some(lockfile.to_string()),
+        changes,
+    })
+}
+
+#[derive(Deserialize)]
+struct RegistryVersion {
+    #[serde(default)]
+    scripts: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct RegistryPackage {
+    #[serde(default)]
+    time: HashMap<String, String>,
+    #[serde(default)]
+    maintainers: Vec<serde_json::Value>,
+    #[serde(default)]
+    versions: HashMap<String, RegistryVersion>,
+}
+
+// Days since an ISO 8601 timestamp like `2024-10-31T12:00:00.000Z`
+fn days_since(timestamp: &str) -> Option<i64> {
+    let date = timestamp.get(..10)?;
+    let mut parts = date.split('-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+
+    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs() as i64
+        / 86400;
+    Some(now - days)
+}
+
+pub async fn get_dependency_risk(
+    client: &octocrab::Octocrab,
+    name: &str,
+    version: &str,
+) -> anyhow::Result<DependencyRisk> {
+    log::debug!("Fetching registry metadata for {}@{}", name, version);
+
+    // Scoped packages need the slash escaped
+    let url = format!("https://registry.npmjs.org/{}", name.replace('/', "%2F"));
+    let data = get_url(client, &url)
+        .await
+        .context("Failed to fetch registry metadata")?;
+    bandwidth::record(None, DownloadKind::Other, data.len() as u64);
+    let package = serde_json::from_slice::<RegistryPackage>(&data)
+        .context("Failed to parse registry metadata")?;
+
+    let install_scripts = package
+        .versions
+        .get(version)
+        .map(|version| {
+            ["preinstall", "install", "postinstall"]
+                .into_iter()
+                .filter(|script| version.scripts.contains_key(*script))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(DependencyRisk {
+        name: name.to_string(),
+        version: version.to_string(),
+        version_age_days: package.time.get(version).and_then(|time| days_since(time)),
+        package_age_days: package
+            .time
+            .get("created")
+            .and_then(|time| days_since(time)),
+        maintainers: package.maintainers.len(),
+        install_scripts,
+    })
+}
+
+pub async fn get_dependency_risks(
+    client: &octocrab::Octocrab,
+    packages: Vec<(String, String)>,
+    max_downloads: usize,
+) -> anyhow::Result<Vec<DependencyRisk>> {
+    let results = concurrency::run_limited(
+        max_downloads,
+        packages.into_iter().map(|(name, version)| {
+            let client = client.clone();
+            async move {
+                let res = get_dependency_risk(&client, &name, &version).await;
+                (name, version, res)
+            }
+        }),
+    )
+    .await;
+
+    let mut risks = Vec::new();
+    for (name, version, res) in results {
+        match res {
+            Ok(risk) => risks.push(risk),
+            // One unpublished/private package shouldn't hide the rest of the report
+            Err(err) => log::warn!("Failed to check {}@{}: {:?}", name, version, err),
+        }
+    }
+    Ok(risks)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Advisory {
+    pub name: String,
+    pub version: String,
+    pub id: String,
+    pub summary: String,
+    // OpenSSF malicious package reports use MAL- IDs
+    pub malicious: bool,
+}
+
+#[derive(Serialize)]
+struct OsvPackage<'a> {
+    name: &'a str,
+    ecosystem: &'static str,
+}
+
+#[derive(Serialize)]
+struct OsvQuery<'a> {
+    package: OsvPackage<'a>,
+    version: &'a str,
+}
+
+#[derive(Serialize)]
+struct OsvBatchQuery<'a> {
+    queries: Vec<OsvQuery<'a>>,
+}
+
+#[derive(Deserialize)]
+struct OsvVulnId {
+    id: String,
+}
+
+#[derive(Deserialize, Default)]
+struct OsvBatchResult {
+    #[serde(default)]
+    vulns: Vec<OsvVulnId>,
+}
+
+#[derive(Deserialize)]
+struct OsvBatchResponse {
+    results: Vec<OsvBatchResult>,
+}
+
+#[derive(Deserialize)]
+struct OsvVuln {
+    #[serde(default)]
+    summary: String,
+}
+
+fn advisory_cache_path() -> std::path::PathBuf {
+    config::cache_dir().join("osv.json")
+}
+
+// name@version -> advisories, so reopening the same PR doesn't hit OSV again
+async fn load_advisory_cache() -> HashMap<String, Vec<Advisory>> {
+    let Ok(data) = tokio::fs::read(advisory_cache_path()).await else {
+        return HashMap::new();
+    };
+    serde_json::from_slice(&data).unwrap_or_default()
+}
+
+async fn save_advisory_cache(cache: &HashMap<String, Vec<Advisory>>) -> anyhow::Result<()> {
+    tokio::fs::create_dir_all(config::cache_dir())
+        .await
+        .context("Failed to create cache dir")?;
+    atomic::write(&advisory_cache_path(), serde_json::to_vec(cache)?)
+        .await
+        .context("Failed to write advisory cache")
+}
+
+pub async fn check_advisories(
+    client: &octocrab::Octocrab,
+    packages: Vec<(String, String)>,
+    refresh: bool,
+) -> anyhow::Result<Vec<Advisory>> {
+    let mut cache = if refresh {
+        HashMap::new()
+    } else {
+        load_advisory_cache().await
+    };
+
+    let missing = packages
+        .iter()
+        .filter(|(name, version)| !cache.contains_key(&format!("{}@{}", name, version)))
+        .collect::<Vec<_>>();
+
+    if !missing.is_empty() {
+        log::debug!("Querying OSV for {} packages", missing.len());
+        let query = OsvBatchQuery {
+            queries: missing
+                .iter()
+                .map(|(name, version)| OsvQuery {
+                    package: OsvPackage {
+                        name,
+                        ecosystem: "npm",
+                    },
+                    version,
+                })
+                .collect(),
+        };
+        let res = post_json(client, "https://api.osv.dev/v1/querybatch", &query)
+            .await
+            .context("Failed to query OSV")?;
+        let res = serde_json::from_slice::<OsvBatchResponse>(&res)
+            .context("Failed to parse OSV response")?;
+
+        // The batch endpoint only returns IDs, so look up the summaries separately
+        let mut summaries = HashMap::new();
+        for (name, version) in &missing {
+            cache.insert(format!("{}@{}", name, version), Vec::new());
+        }
+        for ((name, version), result) in missing.iter().zip(res.results) {
+            let mut advisories = Vec::new();
+            for vuln in result.vulns {
+                if !summaries.contains_key(&vuln.id) {
+                    let url = format!("https://api.osv.dev/v1/vulns/{}", vuln.id);
+                    let summary = match get_url(client, &url).await {
+                        Ok(data) => {
+                            bandwidth::record(None, DownloadKind::Other, data.len() as u64);
+                            serde_json::from_slice::<OsvVuln>(&data)
+                                .map(|vuln| vuln.summary)
+                                .unwrap_or_default()
+                        }
+                        Err(err) => {
+                            log::warn!("Failed to fetch {}: {:?}", vuln.id, err);
+                            String::new()
+                        }
+                    };
+                    summaries.insert(vuln.id.clone(), summary);
+                }
+
+                advisories.push(Advisory {
+                    name: name.clone(),
+                    version: version.clone(),
+                    summary: summaries[&vuln.id].clone(),
+                    malicious: vuln.id.starts_with("MAL-"),
+                    id: vuln.id,
+                });
+            }
+            cache.insert(format!("{}@{}", name, version), advisories);
+        }
+
+        if let Err(err) = save_advisory_cache(&cache).await {
+            log::warn!("Failed to save advisory cache: {:?}", err);
+        }
+    }
+
+    let mut advisories = packages
+        .iter()
+        .filter_map(|(name, version)| cache.get(&format!("{}@{}", name, version)))
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    advisories.sort_by(|a, b| b.malicious.cmp(&a.malicious));
+    Ok(advisories)
+}
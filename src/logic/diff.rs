@@ -1,46 +1,162 @@
-use super::LogicResult;
+use super::{
+    approvals::Approval,
+    build::LocalBuild,
+    bundle::{self, ModuleDiff},
+    concurrency,
+    config::ConcurrencyConfig,
+    deps::DependencyDiff,
+    downgrade::Downgrade,
+    download::TempDir,
+    error::{check_output, spawn_error},
+    grammars,
+    icons::ImageChange,
+    patches::PatchChange,
+    paths::RenamedPath,
+    policy::ReviewPolicy,
+    pr::{PullRequestCheck, PullRequestLabel},
+    pretty,
+    provenance::Consistency,
+    scan::Finding,
+    syntax::{self, HighlightSpan, OutlineEntry},
+    timing, walk, LogicResult,
+};
 use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::Range,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock,
+    },
+    time::{Duration, Instant},
 };
 use tokio::process::Command;
+use tree_sitter::Tree;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModifiedExtension {
     pub id: String,
     pub repository: String,
+    // Set when the manifest points at a different repository than before
+    #[serde(default)]
+    pub new_repository: Option<String>,
     pub old_commit: String,
     pub new_commit: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PullRequestUpdate {
+    #[serde(default)]
+    pub number: u64,
     pub extensions: Vec<ModifiedExtension>,
     pub artifact_url: String,
+    #[serde(default)]
+    pub head_sha: String,
+    #[serde(default)]
+    pub title: String,
+    // Markdown, often with notes for reviewers
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub labels: Vec<PullRequestLabel>,
+    // Every check on the head commit, not just the workflow that builds the artifact
+    #[serde(default)]
+    pub checks: Vec<PullRequestCheck>,
+    // From the extensions repository's robojules.toml
+    #[serde(default)]
+    pub policy: ReviewPolicy,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DiffedExtension {
+    // Holds the temp dir everything below was extracted to
+    #[serde(skip)]
+    pub(crate) _claim: Arc<TempDir>,
+    pub extension: ModifiedExtension,
+    // The full clone of the extension's repository, for history lookups
+    pub repository_dir: PathBuf,
     pub source_diff: Diff,
     pub asar_diff: Diff,
+    pub findings: Vec<Finding>,
+    pub dependencies: DependencyDiff,
+    // moonlight patch definitions that changed in the source
+    pub patches: Vec<PatchChange>,
+    // Whether the files in the .asar were written in a different order
+    pub asar_reordered: bool,
+    // .asar entries Windows can't create, and the names they were extracted as
+    pub renamed_paths: Vec<RenamedPath>,
+    // The manifest's icon and banner on each side
+    pub images: Vec<ImageChange>,
+    // Filled in once the user builds the extension themselves
+    pub local_build: Option<LocalBuild>,
+    // The new source compared to the last version the user approved, if it's neither side of this PR
+    pub previous_review: Option<PreviousReview>,
+    pub downgrades: Vec<Downgrade>,
+    pub consistency: Consistency,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviousReview {
+    pub approval: Approval,
+    pub diff: Diff,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum FileState {
     Modified,
     Added,
     Removed,
+    // Same contents, but the executable bit changed
+    ModeChanged,
+    // Only in `Diff::full_dir`
+    Unchanged,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct FileStats {
+    pub added_lines: usize,
+    pub removed_lines: usize,
+    // Changed regions, grouped the same way as the hunk view
+    pub hunks: usize,
+    pub old_size: u64,
+    pub new_size: u64,
+    // Whether the file is now executable, if that changed
+    pub executable_change: Option<bool>,
+    // Only differs by build paths, timestamps, or line endings
+    pub build_noise: bool,
+}
+
+impl FileStats {
+    pub fn size_delta(&self) -> i64 {
+        self.new_size as i64 - self.old_size as i64
+    }
+
+    fn add(&mut self, other: &FileStats) {
+        self.added_lines += other.added_lines;
+        self.removed_lines += other.removed_lines;
+        self.hunks += other.hunks;
+        self.old_size += other.old_size;
+        self.new_size += other.new_size;
+    }
 }
 
 pub type Directory = Vec<FilesystemItem>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum FilesystemItem {
     File {
         name: String,
         state: FileState,
+        stats: FileStats,
     },
     Directory {
         name: Option<String>,
@@ -48,42 +164,569 @@ pub enum FilesystemItem {
     },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Diff {
     pub old: PathBuf,
     pub new: PathBuf,
     pub dir: Directory,
+    // Every file, including unchanged ones, for reading the code around a change
+    pub full_dir: Directory,
 }
 
-// path/to/file -> sha256
-pub async fn get_dir_tree(dir: &Path) -> anyhow::Result<HashMap<String, String>> {
-    let mut tree = HashMap::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
 
-    let mut files = tokio::fs::read_dir(dir)
-        .await
-        .context("Failed to read directory")?;
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    // 1-indexed, like every other diff tool
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+    // Byte ranges of `text` that differ from the line it replaced or was replaced by, so a renamed
+    // identifier stands out from the rest of the line
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    // The function/class the hunk is in, if we know the language
+    pub context: Option<String>,
+    pub lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    pub fn header(&self) -> String {
+        let header = format!(
+            "@@ -{},{} +{},{} @@",
+            self.old_start, self.old_len, self.new_start, self.new_len
+        );
+        match &self.context {
+            Some(context) => format!("{} {}", header, context),
+            None => header,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiff {
+    // difftastic's ANSI output
+    pub rendered: String,
+    // Empty for binary files
+    pub hunks: Vec<Hunk>,
+    // Both sides in full, for the side-by-side view. Empty for binary and missing files.
+    pub old: String,
+    pub new: String,
+    // Symbols in the new file
+    pub outline: Vec<OutlineEntry>,
+    // Whether the new file ends with a newline/starts with a BOM, if that changed
+    pub eof_newline_change: Option<bool>,
+    pub bom_change: Option<bool>,
+    // Module table changes, for webpack-style bundles
+    pub modules: Option<ModuleDiff>,
+    // A grammar that could be installed to label hunks and outline the file
+    pub missing_grammar: Option<String>,
+    // Took too long to diff properly, so `rendered` and `hunks` are a plain line diff
+    pub timed_out: bool,
+    // Why difftastic couldn't diff the file, in which case `rendered` is a line diff
+    pub difft_error: Option<String>,
+    // Minified JavaScript laid out one statement per line, so line numbers are the formatted code's
+    pub pretty_printed: bool,
+    // What the side-by-side view draws, worked out once instead of every frame
+    #[serde(skip)]
+    pub whole_file: Option<Hunk>,
+}
+
+impl FileDiff {
+    // Both sides in full as one hunk, with the unchanged lines between hunks filled in from the new file
+    fn whole_file_hunk(&self) -> Option<Hunk> {
+        if self.hunks.is_empty() {
+            return None;
+        }
+        let new_lines = self.new.lines().collect::<Vec<_>>();
+        let mut lines = Vec::new();
+        // The next line of each side that isn't in `lines` yet
+        let (mut old_line, mut new_line) = (1, 1);
+        let unchanged =
+            |lines: &mut Vec<DiffLine>, old_line: &mut usize, new_line: &mut usize, count| {
+                for _ in 0..count {
+                    lines.push(DiffLine {
+                        kind: LineKind::Context,
+                        old_line: Some(*old_line),
+                        new_line: Some(*new_line),
+                        text: new_lines
+                            .get(*new_line - 1)
+                            .copied()
+                            .unwrap_or_default()
+                            .to_string(),
+                        changes: Vec::new(),
+                    });
+                    *old_line += 1;
+                    *new_line += 1;
+                }
+            };
+
+        for hunk in &self.hunks {
+            let first_old = hunk.lines.iter().find_map(|line| line.old_line);
+            let first_new = hunk.lines.iter().find_map(|line| line.new_line);
+            let gap = match (first_old, first_new) {
+                (Some(old), Some(new)) => old
+                    .saturating_sub(old_line)
+                    .min(new.saturating_sub(new_line)),
+                (Some(old), None) => old.saturating_sub(old_line),
+                (None, Some(new)) => new.saturating_sub(new_line),
+                (None, None) => 0,
+            };
+            unchanged(&mut lines, &mut old_line, &mut new_line, gap);
+            for line in &hunk.lines {
+                if let Some(number) = line.old_line {
+                    old_line = number + 1;
+                }
+                if let Some(number) = line.new_line {
+                    new_line = number + 1;
+                }
+                lines.push(line.clone());
+            }
+        }
+        let rest = (new_lines.len() + 1).saturating_sub(new_line);
+        unchanged(&mut lines, &mut old_line, &mut new_line, rest);
+
+        Some(Hunk {
+            old_start: 1,
+            old_len: self.old.lines().count(),
+            new_start: 1,
+            new_len: new_lines.len(),
+            context: None,
+            lines,
+        })
+    }
+
+    // Lines in the new file that were added, or that removed lines sit right before
+    pub fn changed_new_lines(&self) -> Vec<usize> {
+        let mut lines = Vec::new();
+        for hunk in &self.hunks {
+            let mut current = hunk.new_start;
+            for line in &hunk.lines {
+                if let Some(new_line) = line.new_line {
+                    current = new_line;
+                }
+                if line.kind != LineKind::Context {
+                    lines.push(current);
+                }
+            }
+        }
+        lines
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub hash: String,
+    pub executable: bool,
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+// Windows doesn't have an executable bit
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
 
-    while let Some(file) = files.next_entry().await? {
-        let path = file.path();
-        let path_str = path.strip_prefix(dir)?.to_string_lossy().to_string();
+// (path/to/file, full path)
+async fn list_dir_files(dir: &Path) -> anyhow::Result<Vec<(String, PathBuf)>> {
+    let entries = walk::walk_dir(dir, &[]).await?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| !entry.is_dir && !entry.is_unfollowed_symlink())
+        .map(|entry| (entry.relative, entry.path))
+        .collect())
+}
 
-        if path.is_dir() {
-            let children = Box::pin(get_dir_tree(&path)).await?;
-            for (child_path, hash) in children {
-                tree.insert(format!("{}/{}", path_str, child_path), hash);
+fn hash_file(path: &Path) -> anyhow::Result<TreeEntry> {
+    let mut hash = Sha256::new();
+    hash.update(std::fs::read(path)?);
+    let metadata = std::fs::metadata(path)?;
+    Ok(TreeEntry {
+        hash: format!("{:x}", hash.finalize()),
+        executable: is_executable(&metadata),
+    })
+}
+
+// path/to/file -> sha256 + mode
+pub async fn get_dir_tree(
+    dir: &Path,
+    hashing_threads: usize,
+) -> anyhow::Result<HashMap<String, TreeEntry>> {
+    let files = list_dir_files(dir).await?;
+
+    let chunk_size = files.len().div_ceil(hashing_threads.max(1)).max(1);
+    tokio::task::spawn_blocking(move || {
+        std::thread::scope(|scope| {
+            let handles = files
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|(path_str, path)| {
+                                let entry = hash_file(path)
+                                    .with_context(|| format!("Failed to hash {}", path_str))?;
+                                Ok((path_str.clone(), entry))
+                            })
+                            .collect::<anyhow::Result<Vec<_>>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut tree = HashMap::new();
+            for handle in handles {
+                let entries = handle
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("Hashing thread panicked"))??;
+                tree.extend(entries);
             }
+            Ok(tree)
+        })
+    })
+    .await
+    .context("Failed to join hashing threads")?
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectorySummaryEntry {
+    pub name: String,
+    pub is_dir: bool,
+    // None for directories with mixed changes
+    pub state: Option<FileState>,
+    pub changed_files: usize,
+    pub stats: FileStats,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DirectorySummary {
+    pub entries: Vec<DirectorySummaryEntry>,
+    pub total: FileStats,
+}
+
+// Aggregates state and line/size stats for everything below a directory
+fn collect_stats(dir: &Directory, states: &mut Vec<FileState>, stats: &mut FileStats) {
+    for item in dir {
+        match item {
+            FilesystemItem::File {
+                state,
+                stats: file_stats,
+                ..
+            } => {
+                states.push(*state);
+                stats.add(file_stats);
+            }
+            FilesystemItem::Directory { children, .. } => collect_stats(children, states, stats),
+        }
+    }
+}
+
+// Every changed file in the tree, as (path, state) pairs
+pub fn flatten_tree(dir: &Directory) -> Vec<(String, FileState)> {
+    fn walk(dir: &Directory, prefix: &str, output: &mut Vec<(String, FileState)>) {
+        for item in dir {
+            match item {
+                FilesystemItem::File { name, state, .. } => {
+                    output.push((format!("{}{}", prefix, name), *state));
+                }
+                FilesystemItem::Directory { name, children } => {
+                    let prefix = format!("{}{}/", prefix, name.as_deref().unwrap_or_default());
+                    walk(children, &prefix, output);
+                }
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    walk(dir, "", &mut output);
+    output
+}
+
+// Lines (1-indexed) that exist in the new text but not the old one
+pub fn added_lines<'a>(old: &str, new: &'a str) -> Vec<(usize, &'a str)> {
+    let (normalized_old, normalized_new) = normalize_pair(old, new);
+    let diff = similar::TextDiff::from_lines(normalized_old.as_ref(), normalized_new.as_ref());
+    let new_lines = new.lines().collect::<Vec<_>>();
+
+    diff.iter_all_changes()
+        .filter(|change| change.tag() == similar::ChangeTag::Insert)
+        .filter_map(|change| change.new_index())
+        .filter_map(|i| new_lines.get(i).map(|line| (i + 1, *line)))
+        .collect()
+}
+
+pub fn find_file(root: &Directory, path: &str) -> Option<(FileState, FileStats)> {
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (find_directory(root, dir)?, name),
+        None => (root, path),
+    };
+    dir.iter().find_map(|item| match item {
+        FilesystemItem::File {
+            name: file_name,
+            state,
+            stats,
+        } if file_name == name => Some((*state, *stats)),
+        _ => None,
+    })
+}
+
+// The first file in sidebar order that matches, as a full path
+pub fn first_file(
+    root: &Directory,
+    matches: &dyn Fn(FileState, &FileStats) -> bool,
+) -> Option<String> {
+    root.iter().find_map(|item| match item {
+        FilesystemItem::File { name, state, stats } if matches(*state, stats) => Some(name.clone()),
+        FilesystemItem::File { .. } => None,
+        FilesystemItem::Directory { name, children } => {
+            let path = first_file(children, matches)?;
+            Some(match name {
+                Some(name) => format!("{}/{}", name, path),
+                None => path,
+            })
+        }
+    })
+}
+
+static BUILD_PATH_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        // Must start a string or token, so URL paths like `example.com/tmp/...` don't count. Only
+        // path characters are taken after the prefix, so it can't run on into minified code.
+        r#"(^|[\s"'`(=,\[])(?:[A-Za-z]:[\\/]+|/)(?:home|Users|tmp|private|runner|github|builds?|workspace|var|opt)[\\/]+[\w./\\-]+"#,
+    )
+    .unwrap()
+});
+static TIMESTAMP_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\b\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(?::\d{2}(?:\.\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?")
+        .unwrap()
+});
+
+fn strip_build_noise(text: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    let text = BUILD_PATH_REGEX.replace_all(&text, "${1}<path>");
+    TIMESTAMP_REGEX
+        .replace_all(&text, "<timestamp>")
+        .to_string()
+}
+
+// Every changed hunk has to be noise on its own, so one real change can't hide among build paths
+pub fn is_build_noise(old: &[u8], new: &[u8]) -> bool {
+    let (Ok(old), Ok(new)) = (std::str::from_utf8(old), std::str::from_utf8(new)) else {
+        return false;
+    };
+    if old == new {
+        return false;
+    }
+
+    let diff = similar::TextDiff::from_lines(old, new);
+    diff.grouped_ops(0).iter().all(|group| {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            return true;
+        };
+        let old_hunk = diff.old_slices()[first.old_range().start..last.old_range().end].concat();
+        let new_hunk = diff.new_slices()[first.new_range().start..last.new_range().end].concat();
+        strip_build_noise(&old_hunk) == strip_build_noise(&new_hunk)
+    })
+}
+
+fn set_build_noise(dir: &mut Directory, prefix: &str, noise: &HashSet<String>) {
+    for item in dir {
+        match item {
+            FilesystemItem::File { name, stats, .. } => {
+                stats.build_noise = noise.contains(&format!("{}{}", prefix, name));
+            }
+            FilesystemItem::Directory { name, children } => {
+                let prefix = format!("{}{}/", prefix, name.as_deref().unwrap_or_default());
+                set_build_noise(children, &prefix, noise);
+            }
+        }
+    }
+}
+
+// Marks modified files whose changes are just nondeterministic build output
+pub async fn mark_build_noise(diff: &mut Diff) -> usize {
+    let mut noise = HashSet::new();
+    for (path, state) in flatten_tree(&diff.dir) {
+        if state != FileState::Modified {
+            continue;
+        }
+        let old = tokio::fs::read(diff.old.join(&path))
+            .await
+            .unwrap_or_default();
+        let new = tokio::fs::read(diff.new.join(&path))
+            .await
+            .unwrap_or_default();
+        if is_build_noise(&old, &new) {
+            noise.insert(path);
+        }
+    }
+
+    set_build_noise(&mut diff.dir, "", &noise);
+    set_build_noise(&mut diff.full_dir, "", &noise);
+    noise.len()
+}
+
+pub fn find_directory<'a>(root: &'a Directory, path: &str) -> Option<&'a Directory> {
+    let mut current = root;
+    for part in path.split('/').filter(|part| !part.is_empty()) {
+        current = current.iter().find_map(|item| match item {
+            FilesystemItem::Directory { name, children } if name.as_deref() == Some(part) => {
+                Some(children)
+            }
+            _ => None,
+        })?;
+    }
+    Some(current)
+}
+
+pub fn summarize_directory(dir: &Directory) -> DirectorySummary {
+    let mut summary = DirectorySummary::default();
+
+    for item in dir {
+        let entry = match item {
+            FilesystemItem::File { name, state, stats } => DirectorySummaryEntry {
+                name: name.clone(),
+                is_dir: false,
+                state: Some(*state),
+                changed_files: 1,
+                stats: *stats,
+            },
+            FilesystemItem::Directory { name, children } => {
+                let mut states = Vec::new();
+                let mut stats = FileStats::default();
+                collect_stats(children, &mut states, &mut stats);
+
+                let state = states
+                    .first()
+                    .copied()
+                    .filter(|first| states.iter().all(|state| state == first));
+                DirectorySummaryEntry {
+                    name: name.clone().unwrap_or_default(),
+                    is_dir: true,
+                    state,
+                    changed_files: states.len(),
+                    stats,
+                }
+            }
+        };
+
+        summary.total.add(&entry.stats);
+        summary.entries.push(entry);
+    }
+
+    summary
+        .entries
+        .sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    summary
+}
+
+fn has_bom(text: &str) -> bool {
+    text.starts_with('\u{feff}')
+}
+
+// Hides BOM and trailing newline differences from the line diff, so the first/last lines
+// don't show up as changed with no visible difference. They get reported separately.
+fn normalize_pair<'a>(old: &'a str, new: &'a str) -> (Cow<'a, str>, Cow<'a, str>) {
+    let old = old.strip_prefix('\u{feff}').unwrap_or(old);
+    let new = new.strip_prefix('\u{feff}').unwrap_or(new);
+    if old.is_empty() || new.is_empty() || old.ends_with('\n') == new.ends_with('\n') {
+        return (Cow::Borrowed(old), Cow::Borrowed(new));
+    }
+
+    let with_newline = |text: &'a str| {
+        if text.ends_with('\n') {
+            Cow::Borrowed(text)
         } else {
-            let mut hash = Sha256::new();
-            hash.update(tokio::fs::read(&path).await?);
-            tree.insert(path_str, format!("{:x}", hash.finalize()));
+            Cow::Owned(format!("{}\n", text))
         }
+    };
+    (with_newline(old), with_newline(new))
+}
+
+fn count_lines(data: &[u8]) -> usize {
+    match std::str::from_utf8(data) {
+        Ok(text) => text.lines().count(),
+        Err(_) => 0,
     }
+}
+
+// The line diff gets `timeout` like the diff view's does, past that the counts are approximate
+async fn file_stats(
+    old: &Path,
+    new: &Path,
+    state: FileState,
+    timeout: Duration,
+) -> anyhow::Result<FileStats> {
+    let old_data = if state != FileState::Added {
+        tokio::fs::read(old).await.context("Failed to read old file")?
+    } else {
+        Vec::new()
+    };
+    let new_data = if state != FileState::Removed {
+        tokio::fs::read(new).await.context("Failed to read new file")?
+    } else {
+        Vec::new()
+    };
+
+    let (added_lines, removed_lines, hunks) = match state {
+        FileState::Added => (count_lines(&new_data), 0, 1),
+        FileState::Removed => (0, count_lines(&old_data), 1),
+        FileState::ModeChanged | FileState::Unchanged => (0, 0, 0),
+        FileState::Modified => {
+            match (std::str::from_utf8(&old_data), std::str::from_utf8(&new_data)) {
+                (Ok(old_text), Ok(new_text)) => {
+                    let (old_text, new_text) = normalize_pair(old_text, new_text);
+                    let diff = similar::TextDiff::configure()
+                        .timeout(timeout)
+                        .diff_lines(old_text.as_ref(), new_text.as_ref());
+                    let mut added = 0;
+                    let mut removed = 0;
+                    for change in diff.iter_all_changes() {
+                        match change.tag() {
+                            similar::ChangeTag::Insert => added += 1,
+                            similar::ChangeTag::Delete => removed += 1,
+                            similar::ChangeTag::Equal => {}
+                        }
+                    }
+                    (added, removed, diff.grouped_ops(3).len())
+                }
+                // Binary files don't have meaningful line counts
+                _ => (0, 0, 0),
+            }
+        }
+    };
 
-    Ok(tree)
+    Ok(FileStats {
+        added_lines,
+        removed_lines,
+        hunks,
+        old_size: old_data.len() as u64,
+        new_size: new_data.len() as u64,
+        executable_change: None,
+        build_noise: false,
+    })
 }
 
 pub fn unflatten_tree(
-    tree: &HashMap<String, FileState>,
+    tree: &HashMap<String, (FileState, FileStats)>,
     prefix: Option<String>,
 ) -> anyhow::Result<Directory> {
     let mut children: Vec<FilesystemItem> = Vec::new();
@@ -145,10 +788,11 @@ pub fn unflatten_tree(
         } else {
             file.to_string()
         };
-        if let Some(state) = tree.get(&path) {
+        if let Some((state, stats)) = tree.get(&path) {
             children.push(FilesystemItem::File {
                 name: file.to_string(),
-                state: state.clone(),
+                state: *state,
+                stats: *stats,
             });
         }
     }
@@ -156,32 +800,75 @@ pub fn unflatten_tree(
     Ok(children)
 }
 
-pub async fn calculate_diff(old_dir: &Path, new_dir: &Path) -> anyhow::Result<Diff> {
-    let old_tree = get_dir_tree(old_dir).await?;
-    let new_tree = get_dir_tree(new_dir).await?;
+pub async fn calculate_diff(
+    old_dir: &Path,
+    new_dir: &Path,
+    limits: &ConcurrencyConfig,
+) -> anyhow::Result<Diff> {
+    let timer = timing::start(format!("Hash {}", old_dir.display()));
+    let old_tree = get_dir_tree(old_dir, limits.hashing_threads).await?;
+    timer.finish();
+    let timer = timing::start(format!("Hash {}", new_dir.display()));
+    let new_tree = get_dir_tree(new_dir, limits.hashing_threads).await?;
+    timer.finish();
 
-    let mut tree = HashMap::new();
-    for (path, old_hash) in &old_tree {
-        if let Some(new_hash) = new_tree.get(&*path) {
-            if *old_hash != *new_hash {
-                tree.insert(path.clone(), FileState::Modified);
+    let mut states = HashMap::new();
+    for (path, old_entry) in &old_tree {
+        if let Some(new_entry) = new_tree.get(&*path) {
+            if old_entry.hash != new_entry.hash {
+                states.insert(path.clone(), FileState::Modified);
+            } else if old_entry.executable != new_entry.executable {
+                states.insert(path.clone(), FileState::ModeChanged);
             }
         } else {
-            tree.insert(path.clone(), FileState::Removed);
+            states.insert(path.clone(), FileState::Removed);
         }
     }
-    for (path, _) in new_tree {
-        if old_tree.get(&path).is_none() {
-            tree.insert(path, FileState::Added);
+    for path in new_tree.keys() {
+        if old_tree.get(path).is_none() {
+            states.insert(path.clone(), FileState::Added);
         }
     }
 
+    let stats = concurrency::run_limited(
+        limits.diff_workers,
+        states.into_iter().map(|(path, state)| {
+            let old = old_dir.join(&path);
+            let new = new_dir.join(&path);
+            let timeout = Duration::from_secs(limits.diff_timeout_secs);
+            async move {
+                let stats = file_stats(&old, &new, state, timeout)
+                    .await
+                    .with_context(|| format!("Failed to compute stats for {}", path));
+                (path, state, stats)
+            }
+        }),
+    )
+    .await;
+
+    let mut tree = HashMap::new();
+    for (path, state, stats) in stats {
+        let mut stats = stats?;
+        if let (Some(old_entry), Some(new_entry)) = (old_tree.get(&path), new_tree.get(&path)) {
+            if old_entry.executable != new_entry.executable {
+                stats.executable_change = Some(new_entry.executable);
+            }
+        }
+        tree.insert(path, (state, stats));
+    }
+
     let file_tree = unflatten_tree(&tree, None)?;
+    for path in new_tree.keys() {
+        tree.entry(path.clone())
+            .or_insert((FileState::Unchanged, FileStats::default()));
+    }
+    let full_tree = unflatten_tree(&tree, None)?;
 
     Ok(Diff {
         old: old_dir.to_path_buf(),
         new: new_dir.to_path_buf(),
         dir: file_tree,
+        full_dir: full_tree,
     })
 }
 
@@ -200,6 +887,9 @@ pub async fn get_diff_string(old: &Path, new: &Path) -> LogicResult<String> {
 
     let mut cmd = Command::new("difft");
     let cmd = cmd
+        // So a diff that timed out doesn't keep running
+        .kill_on_drop(true)
+        .args(syntax::difft_overrides())
         .arg(old)
         .arg(new)
         .env("DFT_COLOR", "always")
@@ -210,7 +900,473 @@ pub async fn get_diff_string(old: &Path, new: &Path) -> LogicResult<String> {
     #[cfg(target_os = "windows")]
     let cmd = cmd.creation_flags(super::CREATE_NO_WINDOW);
 
-    let stdout = cmd.output().await.context("Failed to run difft")?.stdout;
+    let output = cmd
+        .output()
+        .await
+        .map_err(|err| spawn_error("difft", err))
+        .context("Failed to run difft")?;
+    check_output("difft", "", &output)?;
+    let stdout = output.stdout;
 
     Ok(String::from_utf8(stdout).map_err(|_| anyhow::anyhow!("Invalid UTF-8 in diff"))?)
 }
+
+async fn read_text(path: &Path) -> Option<String> {
+    let data = tokio::fs::read(path).await.ok()?;
+    String::from_utf8(data).ok()
+}
+
+pub fn calculate_hunks(old_path: &Path, old: &str, new_path: &Path, new: &str) -> Vec<Hunk> {
+    let old_tree = syntax::parse(old_path, old);
+    let new_tree = syntax::parse(new_path, new);
+    let diff = similar::TextDiff::from_lines(old, new);
+    let cancelled = AtomicBool::new(false);
+    group_hunks(
+        &diff,
+        old,
+        old_tree.as_ref(),
+        new,
+        new_tree.as_ref(),
+        &cancelled,
+    )
+}
+
+// Hunk labels come from the trees, when there are any. Stops early once `cancelled` is set,
+// since nobody is waiting for the result anymore.
+fn group_hunks(
+    diff: &similar::TextDiff<'_, '_, '_, str>,
+    old: &str,
+    old_tree: Option<&Tree>,
+    new: &str,
+    new_tree: Option<&Tree>,
+    cancelled: &AtomicBool,
+) -> Vec<Hunk> {
+    let mut hunks = Vec::new();
+    for group in diff.grouped_ops(3) {
+        if cancelled.load(Ordering::Relaxed) {
+            return Vec::new();
+        }
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+
+        let mut lines = Vec::new();
+        let mut context = None;
+        for op in &group {
+            for change in diff.iter_changes(op) {
+                let kind = match change.tag() {
+                    similar::ChangeTag::Equal => LineKind::Context,
+                    similar::ChangeTag::Insert => LineKind::Added,
+                    similar::ChangeTag::Delete => LineKind::Removed,
+                };
+
+                // Label the hunk with wherever its first change happened
+                if context.is_none() {
+                    context = match (kind, change.new_index(), change.old_index()) {
+                        (LineKind::Added, Some(line), _) => {
+                            new_tree.and_then(|tree| syntax::enclosing_scope(tree, new, line))
+                        }
+                        (LineKind::Removed, _, Some(line)) => {
+                            old_tree.and_then(|tree| syntax::enclosing_scope(tree, old, line))
+                        }
+                        _ => None,
+                    };
+                }
+
+                lines.push(DiffLine {
+                    kind,
+                    old_line: change.old_index().map(|i| i + 1),
+                    new_line: change.new_index().map(|i| i + 1),
+                    text: change.value().trim_end_matches(['\n', '\r']).to_string(),
+                    changes: Vec::new(),
+                });
+            }
+        }
+
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        hunks.push(Hunk {
+            old_start: old_range.start + 1,
+            old_len: old_range.len(),
+            new_start: new_range.start + 1,
+            new_len: new_range.len(),
+            context,
+            lines,
+        });
+    }
+
+    refine_hunks(&mut hunks);
+    hunks
+}
+
+// Minified lines are left whole, a token diff of them is slow and unreadable anyway
+const MAX_REFINE_LINE: usize = 1000;
+// Past this share of either line changing, the two have little in common and partial highlights are noise
+const MAX_REFINE_CHANGED: f64 = 0.6;
+
+// Runs of identifier characters and of whitespace, and everything else one character at a time, so a
+// renamed identifier is a single change and a changed string literal only marks the words that changed
+fn tokens(text: &str) -> Vec<&str> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Class {
+        Word,
+        Space,
+        Other,
+    }
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' || c == '$' {
+            Class::Word
+        } else if c.is_whitespace() {
+            Class::Space
+        } else {
+            Class::Other
+        }
+    };
+
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+    for (index, c) in text.char_indices() {
+        let current = class(c);
+        if index > 0 && (previous != Some(current) || current == Class::Other) {
+            tokens.push(&text[start..index]);
+            start = index;
+        }
+        previous = Some(current);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+fn merge_ranges(ranges: &mut Vec<Range<usize>>, range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    }
+}
+
+// The changed byte ranges of each line, or None when they're too different for it to help
+fn changed_ranges(old: &str, new: &str) -> Option<(Vec<Range<usize>>, Vec<Range<usize>>)> {
+    if old.len() > MAX_REFINE_LINE || new.len() > MAX_REFINE_LINE {
+        return None;
+    }
+    let old_tokens = tokens(old);
+    let new_tokens = tokens(new);
+    // Where each token starts, plus the end of the line
+    let offsets = |tokens: &[&str]| {
+        let mut offsets = vec![0];
+        for token in tokens {
+            offsets.push(offsets.last().copied().unwrap_or_default() + token.len());
+        }
+        offsets
+    };
+    let (old_offsets, new_offsets) = (offsets(&old_tokens), offsets(&new_tokens));
+
+    let (mut old_ranges, mut new_ranges) = (Vec::new(), Vec::new());
+    for op in similar::capture_diff_slices(similar::Algorithm::Myers, &old_tokens, &new_tokens) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == similar::DiffTag::Equal {
+            continue;
+        }
+        merge_ranges(
+            &mut old_ranges,
+            old_offsets[old_range.start]..old_offsets[old_range.end],
+        );
+        merge_ranges(
+            &mut new_ranges,
+            new_offsets[new_range.start]..new_offsets[new_range.end],
+        );
+    }
+
+    let mostly_changed = |ranges: &[Range<usize>], text: &str| {
+        let changed = ranges.iter().map(|range| range.len()).sum::<usize>();
+        changed as f64 > text.len() as f64 * MAX_REFINE_CHANGED
+    };
+    if mostly_changed(&old_ranges, old) || mostly_changed(&new_ranges, new) {
+        return None;
+    }
+    Some((old_ranges, new_ranges))
+}
+
+// Pairs removed lines with the added lines right after them, the way the split view shows them
+fn refine_hunks(hunks: &mut [Hunk]) {
+    fn refine(lines: &mut [DiffLine], removed: &mut Vec<usize>, added: &mut Vec<usize>) {
+        for (&old, &new) in removed.iter().zip(added.iter()) {
+            if let Some((old_ranges, new_ranges)) =
+                changed_ranges(&lines[old].text, &lines[new].text)
+            {
+                lines[old].changes = old_ranges;
+                lines[new].changes = new_ranges;
+            }
+        }
+        removed.clear();
+        added.clear();
+    }
+
+    for hunk in hunks {
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        for i in 0..hunk.lines.len() {
+            match hunk.lines[i].kind {
+                LineKind::Removed => {
+                    if !added.is_empty() {
+                        refine(&mut hunk.lines, &mut removed, &mut added);
+                    }
+                    removed.push(i);
+                }
+                LineKind::Added => added.push(i),
+                LineKind::Context => refine(&mut hunk.lines, &mut removed, &mut added),
+            }
+        }
+        refine(&mut hunk.lines, &mut removed, &mut added);
+    }
+}
+
+// Gives up on difftastic and the syntax-aware parts after `timeout`, so one pathological file
+// can't hold up everything queued behind it. With `pretty`, minified JavaScript is formatted first.
+pub async fn calculate_file_diff(
+    old: &Path,
+    new: &Path,
+    timeout: Duration,
+    pretty: bool,
+) -> LogicResult<FileDiff> {
+    let timer = timing::start(format!("Diff {}", new.display()));
+    let formatted = if pretty {
+        pretty::pretty_pair(old, new).await.unwrap_or_else(|err| {
+            log::warn!("Failed to pretty-print {}: {:?}", new.display(), err);
+            None
+        })
+    } else {
+        None
+    };
+    let pretty_printed = formatted.is_some();
+    let (old, new) = match &formatted {
+        Some((_, old, new)) => (old.as_path(), new.as_path()),
+        None => (old, new),
+    };
+    let deadline = Instant::now() + timeout;
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let full = full_file_diff(old, new, deadline, cancelled.clone());
+    let res = match tokio::time::timeout(timeout, full).await {
+        Ok(res) => res,
+        Err(_) => {
+            // The blocking part can't be aborted, so it's told to stop at its next check instead
+            cancelled.store(true, Ordering::Relaxed);
+            log::warn!(
+                "Diffing {} took longer than {:?}, falling back to plain text",
+                new.display(),
+                timeout
+            );
+            let mut diff = plain_file_diff(old, new).await;
+            diff.timed_out = true;
+            Ok(diff)
+        }
+    };
+    timer.finish();
+    res.map(|mut diff| {
+        diff.pretty_printed = pretty_printed;
+        diff.whole_file = diff.whole_file_hunk();
+        diff
+    })
+}
+
+async fn read_side(path: &Path) -> Option<String> {
+    if path.exists() {
+        read_text(path).await
+    } else {
+        Some(String::new())
+    }
+}
+
+const PLAIN_DIFF_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Colored with the codes difftastic uses, so it's drawn the same way
+fn colored_line_diff(old_path: &Path, old: &str, new_path: &Path, new: &str) -> String {
+    let text_diff = similar::TextDiff::configure()
+        .algorithm(similar::Algorithm::Patience)
+        .timeout(PLAIN_DIFF_TIMEOUT)
+        .diff_lines(old, new);
+    let unified = text_diff
+        .unified_diff()
+        .header(
+            &old_path.display().to_string(),
+            &new_path.display().to_string(),
+        )
+        .to_string();
+    let mut rendered = String::with_capacity(unified.len());
+    for line in unified.lines() {
+        let color = if line.starts_with("+++") || line.starts_with("---") {
+            None
+        } else if line.starts_with('+') {
+            Some("92")
+        } else if line.starts_with('-') {
+            Some("91")
+        } else if line.starts_with("@@") {
+            Some("94")
+        } else {
+            None
+        };
+        match color {
+            Some(color) => rendered.push_str(&format!("\x1b[{}m{}\x1b[0m\n", color, line)),
+            None => {
+                rendered.push_str(line);
+                rendered.push('\n');
+            }
+        }
+    }
+    rendered
+}
+
+// Only a line diff, with a deadline of its own since even that can be slow on huge files
+async fn plain_file_diff(old: &Path, new: &Path) -> FileDiff {
+    let mut diff = FileDiff {
+        rendered: String::new(),
+        hunks: Vec::new(),
+        old: String::new(),
+        new: String::new(),
+        outline: Vec::new(),
+        eof_newline_change: None,
+        bom_change: None,
+        modules: None,
+        missing_grammar: None,
+        timed_out: false,
+        difft_error: None,
+        pretty_printed: false,
+        whole_file: None,
+    };
+    if let (Some(old_text), Some(new_text)) = (read_side(old).await, read_side(new).await) {
+        {
+            let text_diff = similar::TextDiff::configure()
+                .timeout(PLAIN_DIFF_TIMEOUT)
+                .diff_lines(old_text.as_str(), new_text.as_str());
+            let cancelled = AtomicBool::new(false);
+            diff.hunks = group_hunks(&text_diff, &old_text, None, &new_text, None, &cancelled);
+        }
+        diff.rendered = colored_line_diff(old, &old_text, new, &new_text);
+        diff.old = old_text;
+        diff.new = new_text;
+    }
+    diff
+}
+
+async fn full_file_diff(
+    old: &Path,
+    new: &Path,
+    deadline: Instant,
+    cancelled: Arc<AtomicBool>,
+) -> LogicResult<FileDiff> {
+    let old_text = read_side(old).await;
+    let new_text = read_side(new).await;
+
+    // difftastic missing or failing on the file shouldn't hide the change, a line diff still shows it
+    let (rendered, difft_error) = match get_diff_string(old, new).await {
+        Ok(rendered) => (rendered, None),
+        Err(err) => {
+            log::warn!(
+                "difft failed on {}, falling back to a line diff: {}",
+                new.display(),
+                err
+            );
+            let rendered = match (&old_text, &new_text) {
+                (Some(old_text), Some(new_text)) => colored_line_diff(old, old_text, new, new_text),
+                _ => "(binary file)".to_string(),
+            };
+            (rendered, Some(err.message().to_string()))
+        }
+    };
+
+    let mut diff = FileDiff {
+        rendered,
+        hunks: Vec::new(),
+        old: String::new(),
+        new: String::new(),
+        outline: Vec::new(),
+        eof_newline_change: None,
+        bom_change: None,
+        modules: None,
+        missing_grammar: new
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(syntax::lang_name_from_file_ext)
+            .filter(|name| grammars::is_missing(name))
+            .map(str::to_string),
+        timed_out: false,
+        difft_error,
+        pretty_printed: false,
+        whole_file: None,
+    };
+    if let (Some(old_text), Some(new_text)) = (old_text, new_text) {
+        // Only meaningful when both versions of the file exist
+        if !old_text.is_empty() && !new_text.is_empty() {
+            if old_text.ends_with('\n') != new_text.ends_with('\n') {
+                diff.eof_newline_change = Some(new_text.ends_with('\n'));
+            }
+            if has_bom(&old_text) != has_bom(&new_text) {
+                diff.bom_change = Some(has_bom(&new_text));
+            }
+        }
+
+        // Parsing can't be interrupted, so it runs off the runtime where a timeout can abandon it
+        let (old, new) = (old.to_path_buf(), new.to_path_buf());
+        diff = tokio::task::spawn_blocking(move || {
+            diff.hunks = {
+                let (normalized_old, normalized_new) = normalize_pair(&old_text, &new_text);
+                let old_tree = syntax::parse(&old, &normalized_old);
+                let new_tree = syntax::parse(&new, &normalized_new);
+                let text_diff = similar::TextDiff::configure()
+                    .deadline(deadline)
+                    .diff_lines(normalized_old.as_ref(), normalized_new.as_ref());
+                group_hunks(
+                    &text_diff,
+                    &normalized_old,
+                    old_tree.as_ref(),
+                    &normalized_new,
+                    new_tree.as_ref(),
+                    &cancelled,
+                )
+            };
+            if cancelled.load(Ordering::Relaxed) {
+                return diff;
+            }
+            if let Some(tree) = syntax::parse(&new, &new_text) {
+                diff.outline = syntax::outline(&tree, &new_text, &diff.changed_new_lines());
+            }
+            diff.modules = bundle::diff_modules(&old, &old_text, &new, &new_text);
+            diff.old = old_text;
+            diff.new = new_text;
+            diff
+        })
+        .await
+        .context("Diff task panicked")?;
+    }
+
+    Ok(diff)
+}
+
+// One side of a file in full, for reading the code around a change
+#[derive(Debug, Clone, Serialize)]
+pub struct SingleFile {
+    pub path: PathBuf,
+    pub text: String,
+    // Empty when there's no grammar for the file
+    pub highlights: Vec<HighlightSpan>,
+}
+
+pub async fn highlight_single_file(path: &Path) -> anyhow::Result<SingleFile> {
+    let data = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let text = String::from_utf8(data).map_err(|_| anyhow::anyhow!("Binary file"))?;
+    let highlights = syntax::parse(path, &text)
+        .map(|tree| syntax::highlight(&tree))
+        .unwrap_or_default();
+    Ok(SingleFile {
+        path: path.to_path_buf(),
+        text,
+        highlights,
+    })
+}
@@ -0,0 +1,165 @@
+// Published .asars kept on disk, so the old side of a review doesn't have to be downloaded every time
+use super::{
+    atomic, audit,
+    bandwidth::{self, DownloadKind},
+    config::{cache_dir, Config},
+    download::{get_url, published_asar_url},
+};
+use anyhow::Context;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Long enough to cover downloading every extension in a pull request with one listing
+const LISTING_MAX_AGE: Duration = Duration::from_secs(60);
+
+static LISTING: Mutex<Option<(Instant, HashMap<String, String>)>> = Mutex::new(None);
+
+fn dist_dir() -> PathBuf {
+    cache_dir().join("dist")
+}
+
+fn asar_path(ext_id: &str) -> PathBuf {
+    dist_dir().join(format!("{}.asar", ext_id))
+}
+
+// The git blob SHA the cached copy was downloaded at
+fn sha_path(ext_id: &str) -> PathBuf {
+    dist_dir().join(format!("{}.sha", ext_id))
+}
+
+// Blob SHAs of every published .asar, by extension ID. One request covers all of them, and a
+// cached copy is only used when its SHA still matches, so a publish is never missed.
+async fn published_shas(client: &octocrab::Octocrab) -> anyhow::Result<HashMap<String, String>> {
+    let listing = client
+        .repos("moonlight-mod", "extensions-dist")
+        .get_content()
+        .path("exts")
+        .r#ref("main")
+        .send()
+        .await
+        .context("Failed to list published extensions")?;
+    Ok(listing
+        .items
+        .into_iter()
+        .filter_map(|item| {
+            let ext_id = item.name.strip_suffix(".asar")?.to_string();
+            Some((ext_id, item.sha))
+        })
+        .collect())
+}
+
+// None when the extension isn't published, or the listing failed and the cache can't be trusted
+pub async fn current_sha(client: &octocrab::Octocrab, ext_id: &str) -> Option<String> {
+    {
+        let listing = LISTING.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some((at, shas)) = &*listing {
+            if at.elapsed() < LISTING_MAX_AGE {
+                return shas.get(ext_id).cloned();
+            }
+        }
+    }
+
+    let shas = published_shas(client)
+        .await
+        .inspect_err(|err| log::warn!("{:?}", err))
+        .ok()?;
+    let sha = shas.get(ext_id).cloned();
+    *LISTING.lock().unwrap_or_else(|err| err.into_inner()) = Some((Instant::now(), shas));
+    sha
+}
+
+pub async fn read(ext_id: &str, sha: &str) -> Option<Vec<u8>> {
+    let cached_sha = tokio::fs::read_to_string(sha_path(ext_id)).await.ok()?;
+    if cached_sha.trim() != sha {
+        return None;
+    }
+    tokio::fs::read(asar_path(ext_id)).await.ok()
+}
+
+pub async fn write(ext_id: &str, sha: &str, data: &[u8]) -> anyhow::Result<()> {
+    tokio::fs::create_dir_all(dist_dir())
+        .await
+        .context("Failed to create dist cache dir")?;
+    // The SHA goes last, so a partly written .asar is never trusted
+    let _ = tokio::fs::remove_file(sha_path(ext_id)).await;
+    atomic::write(&asar_path(ext_id), data)
+        .await
+        .context("Failed to write cached .asar")?;
+    atomic::write(&sha_path(ext_id), sha)
+        .await
+        .context("Failed to write cached .asar SHA")
+}
+
+async fn refresh(client: &octocrab::Octocrab, ext_ids: &[String]) -> anyhow::Result<()> {
+    let shas = published_shas(client).await?;
+    *LISTING.lock().unwrap_or_else(|err| err.into_inner()) = Some((Instant::now(), shas.clone()));
+    for ext_id in ext_ids {
+        // Not published yet
+        let Some(sha) = shas.get(ext_id) else {
+            continue;
+        };
+        if read(ext_id, sha).await.is_some() {
+            continue;
+        }
+
+        log::debug!("Refreshing cached .asar for {}", ext_id);
+        let url = published_asar_url(ext_id);
+        let data = get_url(client, &url)
+            .await
+            .with_context(|| format!("Failed to download {}", url))?;
+        audit::record_download(&url, &data);
+        bandwidth::record(None, DownloadKind::Asar, data.len() as u64);
+        write(ext_id, sha, &data).await?;
+    }
+    Ok(())
+}
+
+// Keeps favorites' published .asars current, refreshing again early when a favorite is added
+pub async fn run_refresh(
+    client: octocrab::Octocrab,
+    mut config: tokio::sync::watch::Receiver<Config>,
+) {
+    let mut last: Option<(Instant, Vec<String>)> = None;
+    loop {
+        let (minutes, favorites) = {
+            let config = config.borrow_and_update();
+            (
+                config.concurrency.dist_refresh_minutes,
+                config.favorites.clone(),
+            )
+        };
+        let period = Duration::from_secs(minutes * 60);
+
+        let due = match &last {
+            None => true,
+            Some((at, refreshed)) => {
+                at.elapsed() >= period || favorites.iter().any(|id| !refreshed.contains(id))
+            }
+        };
+        if minutes > 0 && !favorites.is_empty() && due {
+            if let Err(err) = refresh(&client, &favorites).await {
+                log::warn!("Failed to refresh cached .asars: {:?}", err);
+            }
+            last = Some((Instant::now(), favorites));
+        }
+
+        let wait = match (&last, minutes) {
+            // Turned off, so only a config change can start it again
+            (_, 0) => Duration::MAX,
+            (Some((at, _)), _) => period.saturating_sub(at.elapsed()),
+            (None, _) => period,
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            changed = config.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
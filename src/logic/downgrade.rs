@@ -0,0 +1,145 @@
+// Updates that go backwards, which can bring back fixed vulnerabilities and are rarely intentional
+use super::{
+    git::git,
+    scan::{Finding, FindingCategory, Origin, Severity},
+    walk,
+};
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Downgrade {
+    // The new commit is an ancestor of the old one
+    Commit,
+    // The extension's manifest.json version went down
+    Version {
+        path: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl Downgrade {
+    pub fn describe(&self) -> String {
+        match self {
+            Downgrade::Commit => "The new commit is older than the current one".to_string(),
+            Downgrade::Version { old, new, .. } => {
+                format!("The version goes down from {} to {}", old, new)
+            }
+        }
+    }
+
+    pub fn finding(&self, ext_id: &str) -> Finding {
+        let path = match self {
+            Downgrade::Commit => format!("exts/{}.json", ext_id),
+            Downgrade::Version { path, .. } => path.clone(),
+        };
+        Finding {
+            category: FindingCategory::Downgrade,
+            severity: Severity::Danger,
+            rule: "downgrade".to_string(),
+            message: self.describe(),
+            origin: Origin::Source,
+            path,
+            line: None,
+            snippet: String::new(),
+        }
+    }
+}
+
+async fn rev_parse(repository_dir: &Path, rev: &str) -> anyhow::Result<String> {
+    let output = git(repository_dir, &["rev-parse", rev]).await?;
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+// The merge base of an ancestor and its descendant is the ancestor itself
+async fn is_older(repository_dir: &Path, old: &str, new: &str) -> anyhow::Result<bool> {
+    let old = rev_parse(repository_dir, old).await?;
+    let new = rev_parse(repository_dir, new).await?;
+    if old == new {
+        return Ok(false);
+    }
+    let output = git(repository_dir, &["merge-base", &old, &new]).await?;
+    Ok(String::from_utf8_lossy(&output).trim() == new)
+}
+
+// "1.2.10" -> [1, 2, 10], ignoring a leading v and any pre-release or build suffix.
+// Trailing zeros are dropped so 1.2 and 1.2.0 compare equal.
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let mut parts = version
+        .trim()
+        .trim_start_matches('v')
+        .split(['-', '+'])
+        .next()?
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    while parts.last() == Some(&0) {
+        parts.pop();
+    }
+    Some(parts)
+}
+
+// The manifest.json declaring this extension's ID, and its version
+async fn find_version(source_dir: &Path, ext_id: &str) -> Option<(String, String)> {
+    let entries = walk::walk_dir(source_dir, &[".git", "node_modules"])
+        .await
+        .ok()?;
+    for entry in entries {
+        let name = entry.relative.rsplit('/').next().unwrap_or_default();
+        if entry.is_dir || entry.is_unfollowed_symlink() || name != "manifest.json" {
+            continue;
+        }
+        let Ok(data) = tokio::fs::read(&entry.path).await else {
+            continue;
+        };
+        let Ok(manifest) = serde_json::from_slice::<serde_json::Value>(&data) else {
+            continue;
+        };
+        if manifest.get("id").and_then(|id| id.as_str()) != Some(ext_id) {
+            continue;
+        }
+        if let Some(version) = manifest.get("version").and_then(|version| version.as_str()) {
+            return Some((entry.relative, version.to_string()));
+        }
+    }
+    None
+}
+
+pub async fn detect(
+    ext_id: &str,
+    repository_dir: &Path,
+    old_commit: &str,
+    new_commit: &str,
+    old_source_dir: &Path,
+    new_source_dir: &Path,
+) -> Vec<Downgrade> {
+    let mut downgrades = Vec::new();
+    // New extensions don't have an old commit to go back to
+    if old_commit.is_empty() || new_commit.is_empty() {
+        return downgrades;
+    }
+
+    match is_older(repository_dir, old_commit, new_commit).await {
+        Ok(true) => downgrades.push(Downgrade::Commit),
+        Ok(false) => {}
+        Err(err) => log::warn!("Failed to compare commits of {}: {:?}", ext_id, err),
+    }
+
+    let old = find_version(old_source_dir, ext_id).await;
+    let new = find_version(new_source_dir, ext_id).await;
+    if let (Some((_, old)), Some((path, new))) = (old, new) {
+        if let (Some(old_parsed), Some(new_parsed)) = (parse_version(&old), parse_version(&new)) {
+            if new_parsed < old_parsed {
+                downgrades.push(Downgrade::Version { path, old, new });
+            }
+        }
+    }
+
+    downgrades
+}
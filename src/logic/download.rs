@@ -1,177 +1,878 @@
 use super::{
+    archive,
     asar::FileTree,
-    diff::{DiffedExtension, ModifiedExtension},
+    audit::{self, AuditAction},
+    bandwidth::{self, DownloadKind},
+    diff::{Diff, DiffedExtension, ModifiedExtension, PreviousReview},
+    error::HttpStatus,
+    git::{git, git_tracked},
     LogicResult,
 };
-use crate::logic::{asar::parse_asar, diff};
+use crate::logic::{
+    approvals::{Approval, Approvals},
+    asar::{self, parse_asar},
+    config::{ConcurrencyConfig, Config},
+    deps, diff, dist, downgrade,
+    icons::{self, ImageChange},
+    patches,
+    paths::{self, CollisionCheck, RenamedPath, UnsafePath, UnsafeReason},
+    policy::{self, ReviewPolicy},
+    provenance,
+    scan::{self, Finding, FindingCategory, Origin, Severity},
+    shape, space, timing,
+    walk::{self, WalkEntry},
+};
 use anyhow::Context;
 use http_body_util::BodyExt;
 use std::{
-    io::{Cursor, Read},
+    collections::{BTreeMap, HashSet},
+    io::Cursor,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
+    },
 };
 
+fn check_status<B>(url: &str, res: &http::Response<B>) -> anyhow::Result<()> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let rate_limited = status.as_u16() == 429
+        || res
+            .headers()
+            .get("x-ratelimit-remaining")
+            .is_some_and(|remaining| remaining == "0");
+    Err(HttpStatus {
+        url: url.to_string(),
+        status: status.as_u16(),
+        rate_limited,
+    }
+    .into())
+}
+
 pub async fn get_url(client: &octocrab::Octocrab, url: &str) -> anyhow::Result<Vec<u8>> {
     let req = client._get(url).await?;
-    let req = client.follow_location_to_data(req).await?;
-    Ok(req.into_body().collect().await?.to_bytes().to_vec())
+    let res = client.follow_location_to_data(req).await?;
+    check_status(url, &res)?;
+    Ok(res.into_body().collect().await?.to_bytes().to_vec())
 }
 
-pub async fn get_asar_from_zip(zip: Vec<u8>, ext_id: &str) -> anyhow::Result<FileTree> {
-    let mut zip = zip::ZipArchive::new(Cursor::new(zip)).context("Failed to open zip")?;
+fn header_map(headers: &BTreeMap<String, String>) -> anyhow::Result<http::HeaderMap> {
+    let mut map = http::HeaderMap::new();
+    for (name, value) in headers {
+        let name = http::HeaderName::try_from(name.as_str())
+            .with_context(|| format!("Invalid header name {}", name))?;
+        let value = http::HeaderValue::try_from(value.as_str())
+            .with_context(|| format!("Invalid value for header {}", name))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
 
-    let file = zip
-        .by_name(format!("{}.asar", ext_id).as_str())
-        .context("Failed to find .asar")?;
-    let bytes = file
-        .bytes()
-        .collect::<Result<Vec<u8>, _>>()
-        .context("Failed to read .asar")?;
+// Reads the body a frame at a time, reporting how much has arrived on the stage's timer
+pub async fn get_url_tracked(
+    client: &octocrab::Octocrab,
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    timer: &timing::Timer,
+) -> anyhow::Result<Vec<u8>> {
+    let req = client
+        ._get_with_headers(url, Some(header_map(headers)?))
+        .await?;
+    let res = client.follow_location_to_data(req).await?;
+    check_status(url, &res)?;
+    let total_bytes = res
+        .headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse().ok());
 
-    let mut reader = Cursor::new(bytes);
-    let asar = parse_asar(&mut reader).context("Failed to parse .asar")?;
-    Ok(asar)
+    let mut body = res.into_body();
+    let mut data = Vec::new();
+    while let Some(frame) = body.frame().await {
+        if let Ok(chunk) = frame?.into_data() {
+            data.extend_from_slice(&chunk);
+            timer.set_bytes(data.len() as u64, total_bytes);
+        }
+    }
+    Ok(data)
 }
 
-pub async fn extract_asar(asar: &FileTree, dir: &Path) -> anyhow::Result<()> {
-    for (path, data) in asar {
-        let path = dir.join(path);
+pub async fn post_json<T: serde::Serialize>(
+    client: &octocrab::Octocrab,
+    url: &str,
+    body: &T,
+) -> anyhow::Result<Vec<u8>> {
+    let res = client._post(url, Some(body)).await?;
+    check_status(url, &res)?;
+    Ok(res.into_body().collect().await?.to_bytes().to_vec())
+}
+
+#[derive(Debug, Default)]
+pub struct Extraction {
+    // Entries written under a different name
+    pub renamed: Vec<RenamedPath>,
+    // Entries that weren't written at all
+    pub skipped: Vec<UnsafePath>,
+}
+
+// The .asar comes from the PR, so entries are checked before anything is written
+pub async fn extract_asar(asar: &FileTree, dir: &Path) -> anyhow::Result<Extraction> {
+    let dir = paths::extended_length(dir);
+    let mut extraction = Extraction::default();
+    let mut collisions = CollisionCheck::default();
+
+    // Sorted, so the same entry wins a collision every time
+    let mut entries = asar.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    for (original, file) in entries {
+        if paths::is_traversal(original) {
+            log::warn!(
+                "Skipping .asar entry {} outside the extraction dir",
+                original
+            );
+            extraction.skipped.push(UnsafePath {
+                path: original.clone(),
+                reason: UnsafeReason::Traversal,
+            });
+            continue;
+        }
+
+        let relative = paths::sanitize_relative(original);
+        if let Some(reason) = collisions.insert(original, &relative) {
+            log::warn!("Skipping colliding .asar entry {}: {:?}", original, reason);
+            extraction.skipped.push(UnsafePath {
+                path: original.clone(),
+                reason,
+            });
+            continue;
+        }
+        if relative != *original {
+            extraction.renamed.push(RenamedPath {
+                original: original.clone(),
+                renamed: relative.clone(),
+            });
+        }
+
+        let path = paths::join_relative(&dir, &relative);
         let parent = path.parent().context("No parent")?;
         tokio::fs::create_dir_all(parent)
             .await
             .context("Failed to create parent dir")?;
-        tokio::fs::write(&path, data)
+        tokio::fs::write(&path, &file.data)
             .await
             .context("Failed to write file")?;
-    }
-
-    Ok(())
-}
-
-pub async fn copy_recursive(src: PathBuf, dest: PathBuf) -> std::io::Result<()> {
-    let mut files = tokio::fs::read_dir(src).await?;
 
-    while let Some(entry) = files.next_entry().await? {
-        let path = entry.path();
-        let file_name = path.file_name().unwrap();
-        if file_name == ".git" {
-            continue;
+        // Carry the executable flag over so it shows up when diffing the extracted trees
+        #[cfg(unix)]
+        if file.executable {
+            use std::os::unix::fs::PermissionsExt;
+            tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+                .await
+                .context("Failed to mark file as executable")?;
         }
+    }
 
-        let dest = dest.join(file_name);
+    Ok(extraction)
+}
 
-        if path.is_dir() {
-            tokio::fs::create_dir(&dest).await?;
-            Box::pin(copy_recursive(path, dest)).await?;
+// Returns the symlinks that were left out, since copying them would copy what they point at
+pub async fn copy_recursive(src: PathBuf, dest: PathBuf) -> anyhow::Result<Vec<WalkEntry>> {
+    // The walk only yields what's inside `src`, so `dest` itself is made here
+    tokio::fs::create_dir_all(&dest)
+        .await
+        .with_context(|| format!("Failed to create {}", dest.display()))?;
+    let mut symlinks = Vec::new();
+    for entry in walk::walk_dir(&src, &[".git"]).await? {
+        let dest = dest.join(&entry.relative);
+        if entry.is_unfollowed_symlink() {
+            symlinks.push(entry);
+        } else if entry.is_dir {
+            tokio::fs::create_dir(&dest)
+                .await
+                .with_context(|| format!("Failed to create {}", entry.relative))?;
         } else {
-            tokio::fs::copy(&path, &dest).await?;
+            tokio::fs::copy(&entry.path, &dest)
+                .await
+                .with_context(|| format!("Failed to copy {}", entry.relative))?;
         }
     }
 
-    Ok(())
+    Ok(symlinks)
 }
 
-pub async fn checkout_copy(src: PathBuf, dest: PathBuf, commit: &str) -> anyhow::Result<()> {
+pub async fn checkout_copy(
+    src: PathBuf,
+    dest: PathBuf,
+    commit: &str,
+) -> anyhow::Result<Vec<WalkEntry>> {
     log::debug!("Checking out commit {}", commit);
 
-    let mut cmd = tokio::process::Command::new("git");
-    let cmd = cmd.arg("checkout").arg(commit).current_dir(&src);
-    #[cfg(target_os = "windows")]
-    let cmd = cmd.creation_flags(super::CREATE_NO_WINDOW);
-
-    let _ = cmd.output().await.context("Failed to checkout commit")?;
+    // Copying after a failed checkout would diff whatever was checked out before
+    git(&src, &["checkout", commit])
+        .await
+        .context("Failed to checkout commit")?;
 
     copy_recursive(src, dest)
         .await
         .context("Failed to copy files")
 }
 
-pub async fn download_extension(
-    client: &octocrab::Octocrab,
-    ext: &ModifiedExtension,
-    artifact_url: &str,
-) -> LogicResult<DiffedExtension> {
-    log::debug!("Downloading extension {}", ext.id);
+pub fn published_asar_url(ext_id: &str) -> String {
+    format!(
+        "https://github.com/moonlight-mod/extensions-dist/raw/refs/heads/main/exts/{}.asar",
+        ext_id
+    )
+}
 
-    let temp_dir = std::env::temp_dir().join("robojules").join(ext.id.clone());
-    if temp_dir.exists() {
-        tokio::fs::remove_dir_all(&temp_dir)
+// Temp dirs this process holds, so the same dir is never handed out twice
+fn held_dirs() -> &'static Mutex<HashSet<PathBuf>> {
+    static HELD: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    HELD.get_or_init(Default::default)
+}
+
+fn lock_path(dir: &Path) -> PathBuf {
+    let mut lock_path = dir.as_os_str().to_owned();
+    lock_path.push(".lock");
+    PathBuf::from(lock_path)
+}
+
+// None when this process or another instance already holds the dir
+fn try_lock_dir(dir: &Path) -> anyhow::Result<Option<std::fs::File>> {
+    let mut held = held_dirs().lock().unwrap_or_else(|err| err.into_inner());
+    if held.contains(dir) {
+        return Ok(None);
+    }
+
+    let lock_path = lock_path(dir);
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("Failed to open {}", lock_path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {
+            held.insert(dir.to_path_buf());
+            Ok(Some(file))
+        }
+        Err(std::fs::TryLockError::WouldBlock) => Ok(None),
+        Err(std::fs::TryLockError::Error(err)) => Err(err).context("Failed to lock temp dir"),
+    }
+}
+
+// A temp dir claimed by this process, locked against everyone else until it's dropped
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+    // The lock is released when the file is closed
+    _lock: std::fs::File,
+    // Unique dirs are only ever this process's, so nothing is left behind for them
+    remove: bool,
+}
+
+impl TempDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if self.remove {
+            if let Err(err) = std::fs::remove_dir_all(&self.path) {
+                log::warn!("Failed to remove {}: {:?}", self.path.display(), err);
+            }
+            let _ = std::fs::remove_file(lock_path(&self.path));
+        }
+        held_dirs()
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&self.path);
+    }
+}
+
+// Where every download, checkout and build goes
+pub(crate) fn temp_root() -> PathBuf {
+    std::env::temp_dir().join("robojules")
+}
+
+// Wipes whatever an earlier run left in the dir
+async fn prepare_temp_dir(dir: TempDir) -> anyhow::Result<TempDir> {
+    if dir.path.exists() {
+        tokio::fs::remove_dir_all(&dir.path)
             .await
             .context("Failed to remove old temp dir")?;
     }
-    tokio::fs::create_dir_all(&temp_dir)
+    tokio::fs::create_dir_all(&dir.path)
         .await
         .context("Failed to create temp dir")?;
+    Ok(dir)
+}
 
-    let old_asar_dir = temp_dir.join("old_asar");
-    let new_asar_dir = temp_dir.join("new_asar");
-    let source_dir = temp_dir.join("source");
-    let old_source_dir = temp_dir.join("old_source");
-    let new_source_dir = temp_dir.join("new_source");
+// An empty dir in the temp dir named `name`, or a unique one if that's already in use
+pub(crate) async fn claim_temp_dir(name: &str) -> anyhow::Result<TempDir> {
+    let root = temp_root();
+    tokio::fs::create_dir_all(&root)
+        .await
+        .context("Failed to create temp dir")?;
 
-    for dir in vec![
-        &old_asar_dir,
-        &new_asar_dir,
-        &source_dir,
-        &old_source_dir,
-        &new_source_dir,
-    ] {
-        tokio::fs::create_dir(dir)
+    let path = root.join(name);
+    match try_lock_dir(&path)? {
+        Some(lock) => {
+            prepare_temp_dir(TempDir {
+                path,
+                _lock: lock,
+                remove: false,
+            })
             .await
-            .context("Failed to create temp dir")?;
+        }
+        None => {
+            // Another instance, or an earlier download in this one, is still using it
+            log::warn!("{} is in use, working beside it", path.display());
+            claim_unique_temp_dir(name).await
+        }
     }
+}
 
-    log::debug!("Downloading artifact .asar from {}", artifact_url);
-    let artifact_asar = get_url(client, artifact_url)
+// An empty dir nothing else will ever use, removed once it's dropped
+pub(crate) async fn claim_unique_temp_dir(name: &str) -> anyhow::Result<TempDir> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let root = temp_root();
+    tokio::fs::create_dir_all(&root)
         .await
-        .context("Failed to download artifact .asar")?;
-    let artifact_asar = get_asar_from_zip(artifact_asar, &ext.id)
-        .await
-        .context("Failed to parse artifact .asar")?;
-    extract_asar(&artifact_asar, &new_asar_dir)
+        .context("Failed to create temp dir")?;
+
+    let path = root.join(format!(
+        "{}-{}-{}",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let Some(lock) = try_lock_dir(&path)? else {
+        anyhow::bail!("{} is in use", path.display());
+    };
+    prepare_temp_dir(TempDir {
+        path,
+        _lock: lock,
+        remove: true,
+    })
+    .await
+}
+
+// Everything below this is removed first, so stale files from an earlier run can't leak into the diff
+pub(crate) struct Workspace {
+    // Kept by the diffed extension, since it keeps reading from the dir after the download
+    pub claim: Arc<TempDir>,
+    pub temp_dir: PathBuf,
+    pub old_asar_dir: PathBuf,
+    pub new_asar_dir: PathBuf,
+    // The full clone, for history lookups and checking out commits
+    pub source_dir: PathBuf,
+    pub old_source_dir: PathBuf,
+    pub new_source_dir: PathBuf,
+}
+
+impl Workspace {
+    pub async fn create(name: &str) -> anyhow::Result<Self> {
+        let claim = claim_temp_dir(name).await?;
+        let temp_dir = claim.path().to_path_buf();
+        let workspace = Self {
+            claim: Arc::new(claim),
+            old_asar_dir: temp_dir.join("old_asar"),
+            new_asar_dir: temp_dir.join("new_asar"),
+            source_dir: temp_dir.join("source"),
+            old_source_dir: temp_dir.join("old_source"),
+            new_source_dir: temp_dir.join("new_source"),
+            temp_dir,
+        };
+        for dir in [
+            &workspace.old_asar_dir,
+            &workspace.new_asar_dir,
+            &workspace.source_dir,
+            &workspace.old_source_dir,
+            &workspace.new_source_dir,
+        ] {
+            tokio::fs::create_dir(dir)
+                .await
+                .context("Failed to create temp dir")?;
+        }
+        Ok(workspace)
+    }
+}
+
+pub(crate) struct AsarComparison {
+    pub diff: Diff,
+    pub reordered: bool,
+    pub renamed: Vec<RenamedPath>,
+    // Entries of the new .asar that weren't extracted
+    pub skipped: Vec<UnsafePath>,
+    pub images: Vec<ImageChange>,
+}
+
+// `current` is the published .asar, `artifact` the one being reviewed
+pub(crate) async fn compare_asars(
+    ext_id: &str,
+    current: &FileTree,
+    artifact: &FileTree,
+    workspace: &Workspace,
+    config: &Config,
+) -> anyhow::Result<AsarComparison> {
+    let timer = timing::start(format!("{}: extract artifact .asar", ext_id));
+    let artifact_extraction = extract_asar(artifact, &workspace.new_asar_dir)
         .await
         .context("Failed to extract artifact .asar")?;
+    timer.finish();
 
-    let current_asar_url = format!(
-        "https://github.com/moonlight-mod/extensions-dist/raw/refs/heads/main/exts/{}.asar",
-        ext.id
-    );
-    log::debug!("Downloading current .asar from {}", current_asar_url);
-    let current_asar = get_url(client, &current_asar_url).await?;
-    let mut current_asar = Cursor::new(current_asar);
-    let current_asar = parse_asar(&mut current_asar).context("Failed to parse current .asar")?;
-    extract_asar(&current_asar, &old_asar_dir)
+    let timer = timing::start(format!("{}: extract current .asar", ext_id));
+    let current_extraction = extract_asar(current, &workspace.old_asar_dir)
         .await
         .context("Failed to extract current .asar")?;
+    let mut renamed = artifact_extraction.renamed;
+    renamed.extend(current_extraction.renamed);
+    renamed.sort_by(|a, b| a.original.cmp(&b.original));
+    renamed.dedup();
+    timer.finish();
+
+    let timer = timing::start(format!("{}: diff .asar", ext_id));
+    let mut diff = diff::calculate_diff(
+        &workspace.old_asar_dir,
+        &workspace.new_asar_dir,
+        &config.concurrency,
+    )
+    .await
+    .context("Failed to diff .asar")?;
+    let noise = diff::mark_build_noise(&mut diff).await;
+    timer.finish();
+    let reordered = asar::is_reordered(current, artifact);
+    log::debug!(
+        "{} .asar files only differ by build noise, reordered: {}",
+        noise,
+        reordered
+    );
+
+    Ok(AsarComparison {
+        diff,
+        reordered,
+        renamed,
+        skipped: artifact_extraction.skipped,
+        images: icons::compare(current, artifact),
+    })
+}
+
+pub async fn download_extension(
+    client: &octocrab::Octocrab,
+    ext: &ModifiedExtension,
+    artifact_url: &str,
+    pull_request: u64,
+    head_sha: &str,
+    config: &Config,
+) -> LogicResult<DiffedExtension> {
+    log::debug!("Downloading extension {} from #{}", ext.id, pull_request);
 
-    let asar_diff = diff::calculate_diff(&old_asar_dir, &new_asar_dir)
+    // Keyed by pull request and head too, so two pull requests touching the same extension don't
+    // clobber each other
+    let short = |sha: &str| sha.get(..12).unwrap_or(sha).to_string();
+    let name = if pull_request == 0 {
+        // RPC clients that don't say which pull request this is for
+        format!("{}-{}", ext.id, short(&ext.new_commit))
+    } else {
+        format!("pr{}-{}-{}", pull_request, short(head_sha), ext.id)
+    };
+    let workspace = Workspace::create(&name).await?;
+    let source_dir = &workspace.source_dir;
+    let needed = space::estimate(client, artifact_url, &ext.repository).await;
+    space::check(&workspace.temp_dir, needed)?;
+
+    let current_asar_url = published_asar_url(&ext.id);
+    let download_artifact = async {
+        log::debug!("Downloading artifact .asar from {}", artifact_url);
+        let timer = timing::start(format!("{}: download artifact", ext.id));
+        // The mirror's headers aren't for GitHub, which gets the token instead
+        let no_headers = BTreeMap::new();
+        let headers = if artifact_url.starts_with("https://api.github.com/") {
+            &no_headers
+        } else {
+            &config.artifacts.headers
+        };
+        let data = get_url_tracked(client, artifact_url, headers, &timer)
+            .await
+            .context("Failed to download artifact .asar")?;
+        timer.finish();
+        audit::record_download(artifact_url, &data);
+        bandwidth::record(
+            Some(pull_request),
+            DownloadKind::Artifact,
+            data.len() as u64,
+        );
+        anyhow::Ok(data)
+    };
+    let download_current = async {
+        // Kept warm for favorites, and only used while it's still what's published
+        let sha = dist::current_sha(client, &ext.id).await;
+        if let Some(sha) = &sha {
+            if let Some(data) = dist::read(&ext.id, sha).await {
+                log::debug!("Using cached current .asar for {}", ext.id);
+                return Ok(data);
+            }
+        }
+
+        log::debug!("Downloading current .asar from {}", current_asar_url);
+        let timer = timing::start(format!("{}: download current .asar", ext.id));
+        let data = get_url_tracked(client, &current_asar_url, &BTreeMap::new(), &timer)
+            .await
+            .context("Failed to download current .asar")?;
+        timer.finish();
+        audit::record_download(&current_asar_url, &data);
+        bandwidth::record(Some(pull_request), DownloadKind::Asar, data.len() as u64);
+        if let Some(sha) = &sha {
+            if let Err(err) = dist::write(&ext.id, sha, &data).await {
+                log::warn!("{:?}", err);
+            }
+        }
+        anyhow::Ok(data)
+    };
+    let (artifact_asar, current_asar) = if config.concurrency.downloads > 1 {
+        tokio::join!(download_artifact, download_current)
+    } else {
+        (download_artifact.await, download_current.await)
+    };
+    let (artifact_asar, current_asar) = (artifact_asar?, current_asar?);
+
+    let timer = timing::start(format!("{}: parse .asars", ext.id));
+    let artifact_asar = archive::get_asar_from_archive(artifact_asar, &ext.id)
         .await
-        .context("Failed to diff .asar")?;
+        .context("Failed to parse artifact .asar")?;
+    let mut current_asar = Cursor::new(current_asar);
+    let current_asar = parse_asar(&mut current_asar).context("Failed to parse current .asar")?;
+    timer.finish();
+    let asar = compare_asars(&ext.id, &current_asar, &artifact_asar, &workspace, config).await?;
 
     // --branch doesn't work with commit hashes, so let's clone the entire repo and copy files
     log::debug!("Cloning repository {}", ext.repository);
-    let mut cmd = tokio::process::Command::new("git");
-    let cmd = cmd
-        .arg("clone")
-        .arg(ext.repository.clone())
-        .arg(&source_dir);
-    #[cfg(target_os = "windows")]
-    let cmd = cmd.creation_flags(super::CREATE_NO_WINDOW);
+    let timer = timing::start(format!("{}: clone repository", ext.id));
+    // The repository comes from the PR, so it can't be allowed to pass as an option
+    git_tracked(
+        &workspace.temp_dir,
+        &["clone", "--", &ext.repository, "source"],
+        &timer,
+    )
+    .await
+    .context("Failed to clone repository")?;
+
+    // The new commit lives in the new repository if the manifest switched repositories
+    if let Some(new_repository) = &ext.new_repository {
+        log::debug!("Fetching new repository {}", new_repository);
+        git_tracked(
+            source_dir,
+            &["fetch", "--", new_repository, &ext.new_commit],
+            &timer,
+        )
+        .await
+        .context("Failed to fetch new repository")?;
+    }
+    timer.finish();
+    bandwidth::record(
+        Some(pull_request),
+        DownloadKind::Clone,
+        bandwidth::git_size(source_dir).await,
+    );
 
-    let _ = cmd.output().await.context("Failed to clone repository")?;
+    let timer = timing::start(format!("{}: checkout commits", ext.id));
+    let symlinks = checkout_copy(
+        source_dir.clone(),
+        workspace.new_source_dir.clone(),
+        &ext.new_commit,
+    )
+    .await
+    .context("Failed to checkout new commit")?;
+    checkout_copy(
+        source_dir.clone(),
+        workspace.old_source_dir.clone(),
+        &ext.old_commit,
+    )
+    .await
+    .context("Failed to checkout old commit")?;
+    timer.finish();
+    audit::record(AuditAction::CommitCheckedOut {
+        repository: ext.repository.clone(),
+        commit: ext.old_commit.clone(),
+    });
+    audit::record(AuditAction::CommitCheckedOut {
+        repository: ext
+            .new_repository
+            .clone()
+            .unwrap_or_else(|| ext.repository.clone()),
+        commit: ext.new_commit.clone(),
+    });
+    let policy = policy::get_or_default(client).await;
+    analyze(ext, workspace, asar, &symlinks, config, &policy).await
+}
 
-    checkout_copy(source_dir.clone(), new_source_dir.clone(), &ext.new_commit)
+// Shared by pull requests and local development, once both sides are on disk. `symlinks` are the
+// ones in the new source that weren't copied.
+pub(crate) async fn analyze(
+    ext: &ModifiedExtension,
+    workspace: Workspace,
+    asar: AsarComparison,
+    symlinks: &[WalkEntry],
+    config: &Config,
+    policy: &ReviewPolicy,
+) -> LogicResult<DiffedExtension> {
+    let config = &policy.apply(config);
+    let Workspace {
+        claim,
+        temp_dir,
+        source_dir,
+        old_source_dir,
+        new_source_dir,
+        ..
+    } = workspace;
+    let asar_diff = asar.diff;
+
+    let timer = timing::start(format!("{}: diff source", ext.id));
+    let source_diff = diff::calculate_diff(&old_source_dir, &new_source_dir, &config.concurrency)
         .await
-        .context("Failed to checkout new commit")?;
-    checkout_copy(source_dir.clone(), old_source_dir.clone(), &ext.old_commit)
+        .context("Failed to diff source")?;
+    timer.finish();
+
+    let previous_review =
+        diff_previous_review(ext, &source_dir, &temp_dir, &new_source_dir, config).await;
+    let downgrades = downgrade::detect(
+        &ext.id,
+        &source_dir,
+        &ext.old_commit,
+        &ext.new_commit,
+        &old_source_dir,
+        &new_source_dir,
+    )
+    .await;
+
+    let timer = timing::start(format!("{}: diff dependencies and patches", ext.id));
+    let dependencies = deps::diff_dependencies(&old_source_dir, &new_source_dir)
         .await
-        .context("Failed to checkout old commit")?;
-    let source_diff = diff::calculate_diff(&old_source_dir, &new_source_dir)
+        .context("Failed to diff dependencies")?;
+
+    let patches = patches::diff_patches(&source_diff).await;
+    timer.finish();
+
+    let timer = timing::start(format!("{}: scan", ext.id));
+    let rules = scan::load_rules().await;
+    let mut findings = scan::scan_diff(&source_diff, Origin::Source, &rules, config)
         .await
-        .context("Failed to diff source")?;
+        .context("Failed to scan source")?;
+    findings.extend(
+        scan::scan_diff(&asar_diff, Origin::Asar, &rules, config)
+            .await
+            .context("Failed to scan .asar")?,
+    );
+    let (consistency, orphans) = provenance::check_consistency(
+        &source_diff,
+        &asar_diff,
+        &new_source_dir,
+        !dependencies.changes.is_empty(),
+    )
+    .await;
+    findings.extend(orphans);
+    findings.extend(shape::check_shape(ext, &new_source_dir, &asar_diff.new).await);
+    // Only the artifact is new in this PR, the current .asar's entries were skipped quietly
+    findings.extend(asar.skipped.iter().map(UnsafePath::finding));
+    findings.extend(symlinks.iter().filter_map(WalkEntry::escape_finding));
+    if let Some(new_repository) = &ext.new_repository {
+        findings.push(Finding {
+            category: FindingCategory::RepoChanged,
+            severity: Severity::Danger,
+            rule: "repo-changed".to_string(),
+            message: format!(
+                "Manifest now points at {} instead of {}",
+                new_repository, ext.repository
+            ),
+            origin: Origin::Source,
+            path: format!("exts/{}.json", ext.id),
+            line: None,
+            snippet: String::new(),
+        });
+    }
+    findings.extend(
+        downgrades
+            .iter()
+            .map(|downgrade| downgrade.finding(&ext.id)),
+    );
+    // A glob in the policy can't hide anything dangerous
+    findings.retain(|finding| {
+        finding.severity == Severity::Danger || !policy.is_ignored(&finding.path)
+    });
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity));
+    timer.finish();
 
     Ok(DiffedExtension {
+        _claim: claim,
+        extension: ext.clone(),
+        repository_dir: source_dir,
         source_diff,
         asar_diff,
+        findings,
+        dependencies,
+        patches,
+        asar_reordered: asar.reordered,
+        renamed_paths: asar.renamed,
+        images: asar.images,
+        local_build: None,
+        previous_review,
+        downgrades,
+        consistency,
     })
 }
+
+// Checks the approved commit out next to the new source and diffs the two
+async fn diff_since_approval(
+    ext: &ModifiedExtension,
+    approval: &Approval,
+    source_dir: &Path,
+    approved_source_dir: &Path,
+    new_source_dir: &Path,
+    limits: &ConcurrencyConfig,
+) -> anyhow::Result<Diff> {
+    // The extension may have moved since, and the approved commit only lives where it was
+    if approval.repository != ext.repository
+        && ext.new_repository.as_ref() != Some(&approval.repository)
+    {
+        log::debug!("Fetching approved repository {}", approval.repository);
+        git(
+            source_dir,
+            &["fetch", "--", &approval.repository, &approval.commit],
+        )
+        .await
+        .context("Failed to fetch approved repository")?;
+    }
+    // A copy left over from an earlier download would mix its files into the diff
+    if approved_source_dir.exists() {
+        tokio::fs::remove_dir_all(approved_source_dir)
+            .await
+            .context("Failed to remove old approved source")?;
+    }
+    checkout_copy(
+        source_dir.to_path_buf(),
+        approved_source_dir.to_path_buf(),
+        &approval.commit,
+    )
+    .await
+    .context("Failed to checkout approved commit")?;
+    diff::calculate_diff(approved_source_dir, new_source_dir, limits)
+        .await
+        .context("Failed to diff against approved commit")
+}
+
+// Not being able to check out the approved commit (e.g. after a force push) shouldn't fail the whole download
+async fn diff_previous_review(
+    ext: &ModifiedExtension,
+    source_dir: &Path,
+    temp_dir: &Path,
+    new_source_dir: &Path,
+    config: &Config,
+) -> Option<PreviousReview> {
+    let approval = Approvals::load().previous(ext)?.clone();
+    let approved_source_dir = temp_dir.join("approved_source");
+
+    let timer = timing::start(format!("{}: diff since last review", ext.id));
+    let res = diff_since_approval(
+        ext,
+        &approval,
+        source_dir,
+        &approved_source_dir,
+        new_source_dir,
+        &config.concurrency,
+    )
+    .await;
+    timer.finish();
+
+    match res {
+        Ok(diff) => Some(PreviousReview { approval, diff }),
+        Err(err) => {
+            log::warn!("{}: {:?}", ext.id, err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::diff::{FileState, FilesystemItem};
+
+    async fn commit_all(repo: &Path, message: &str) -> String {
+        let identity = ["-c", "user.name=test", "-c", "user.email=test@example.com"];
+        git(repo, &["add", "-A"]).await.unwrap();
+        git(
+            repo,
+            &[&identity[..], &["commit", "-q", "-m", message]].concat(),
+        )
+        .await
+        .unwrap();
+        let head = git(repo, &["rev-parse", "HEAD"]).await.unwrap();
+        String::from_utf8(head).unwrap().trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn diffs_since_approval() {
+        let root =
+            std::env::temp_dir().join(format!("robojules-test-approval-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let source = root.join("source");
+        std::fs::create_dir_all(&source).unwrap();
+        git(&source, &["init", "-q"]).await.unwrap();
+        std::fs::write(source.join("index.ts"), "old\n").unwrap();
+        std::fs::write(source.join("kept.ts"), "same\n").unwrap();
+        let approved = commit_all(&source, "approved").await;
+        std::fs::write(source.join("index.ts"), "new\n").unwrap();
+        let head = commit_all(&source, "new").await;
+
+        let new_source = root.join("new_source");
+        copy_recursive(source.clone(), new_source.clone())
+            .await
+            .unwrap();
+        // Left over from an earlier download, shouldn't show up as removed
+        let approved_source = root.join("approved_source");
+        std::fs::create_dir_all(&approved_source).unwrap();
+        std::fs::write(approved_source.join("stale.ts"), "stale\n").unwrap();
+
+        let repository = "https://github.com/moonlight-mod/test".to_string();
+        let ext = ModifiedExtension {
+            id: "test".to_string(),
+            repository: repository.clone(),
+            new_repository: None,
+            old_commit: approved.clone(),
+            new_commit: head,
+        };
+        let approval = Approval {
+            commit: approved,
+            repository,
+            pull_request: 1,
+            timestamp: 0,
+        };
+        let diff = diff_since_approval(
+            &ext,
+            &approval,
+            &source,
+            &approved_source,
+            &new_source,
+            &ConcurrencyConfig::default(),
+        )
+        .await
+        .unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+
+        let changed = diff
+            .dir
+            .iter()
+            .map(|item| match item {
+                FilesystemItem::File { name, state, .. } => (name.as_str(), *state),
+                FilesystemItem::Directory { .. } => panic!("Unexpected directory"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(changed, [("index.ts", FileState::Modified)]);
+    }
+}
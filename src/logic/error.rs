@@ -0,0 +1,334 @@
+use serde::Serialize;
+use std::{
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+const MAX_ATTEMPTS: usize = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", content = "message")]
+pub enum LogicError {
+    // Connection problems and server errors, usually gone on the next try
+    Network(String),
+    RateLimited(String),
+    // GitHub refused the request without a token, or with the one we have
+    AuthRequired(String),
+    NotFound(String),
+    GitMissing(String),
+    // The artifact .zip or an .asar couldn't be parsed
+    AsarCorrupt(String),
+    DiskFull(String),
+    Other(String),
+}
+
+pub type LogicResult<T> = Result<T, LogicError>;
+
+// Picks the variant for an error's message
+type Classify = fn(String) -> LogicError;
+
+impl LogicError {
+    pub fn message(&self) -> &str {
+        match self {
+            LogicError::Network(message)
+            | LogicError::RateLimited(message)
+            | LogicError::AuthRequired(message)
+            | LogicError::NotFound(message)
+            | LogicError::GitMissing(message)
+            | LogicError::AsarCorrupt(message)
+            | LogicError::DiskFull(message)
+            | LogicError::Other(message) => message,
+        }
+    }
+
+    // Whether trying again later could work
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, LogicError::Network(_) | LogicError::RateLimited(_))
+    }
+
+    // Rate limits won't lift within a few seconds, so only network errors are retried automatically
+    pub fn is_transient(&self) -> bool {
+        matches!(self, LogicError::Network(_))
+    }
+
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            LogicError::Network(_) => Some("Check your internet connection and try again."),
+            LogicError::RateLimited(_) => {
+                Some("GitHub's rate limit was hit. Wait a few minutes before trying again.")
+            }
+            LogicError::AuthRequired(_) => Some(
+                "GitHub needs a token for this. Set GITHUB_TOKEN or log in with `gh auth login`.",
+            ),
+            LogicError::NotFound(_) => Some(
+                "Check the pull request number. The artifact or extension may have been removed.",
+            ),
+            LogicError::GitMissing(_) => Some("Install git and make sure it's on your PATH."),
+            LogicError::AsarCorrupt(_) => Some(
+                "The artifact or published .asar couldn't be read. The CI build may be broken.",
+            ),
+            LogicError::DiskFull(_) => {
+                Some("Free up space, or point TMPDIR (TEMP on Windows) at a drive with more of it.")
+            }
+            LogicError::Other(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LogicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl From<anyhow::Error> for LogicError {
+    fn from(err: anyhow::Error) -> Self {
+        let message = format!("{:?}", err);
+        for cause in err.chain() {
+            if let Some(kind) = classify(cause) {
+                return kind(message);
+            }
+        }
+        LogicError::Other(message)
+    }
+}
+
+impl From<String> for LogicError {
+    fn from(err: String) -> Self {
+        LogicError::Other(err)
+    }
+}
+
+// A non-2xx response from a plain HTTP request
+#[derive(Debug)]
+pub struct HttpStatus {
+    pub url: String,
+    pub status: u16,
+    pub rate_limited: bool,
+}
+
+impl std::fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} returned {}", self.url, self.status)
+    }
+}
+
+impl std::error::Error for HttpStatus {}
+
+#[derive(Debug)]
+pub struct ProgramMissing(pub String);
+
+impl std::fmt::Display for ProgramMissing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} isn't installed or isn't on PATH", self.0)
+    }
+}
+
+impl std::error::Error for ProgramMissing {}
+
+#[derive(Debug)]
+pub struct InsufficientSpace {
+    pub dir: std::path::PathBuf,
+    pub needed: u64,
+    pub available: u64,
+}
+
+impl std::fmt::Display for InsufficientSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const MIB: u64 = 1024 * 1024;
+        write!(
+            f,
+            "Not enough free space in {}: about {} MiB needed, {} MiB available",
+            self.dir.display(),
+            self.needed.div_ceil(MIB),
+            self.available / MIB
+        )
+    }
+}
+
+impl std::error::Error for InsufficientSpace {}
+
+// A program that ran but exited unsuccessfully, with what it printed to stderr
+#[derive(Debug)]
+pub struct ProcessFailed {
+    pub program: String,
+    pub subcommand: String,
+    pub status: std::process::ExitStatus,
+    pub stderr: String,
+}
+
+impl std::fmt::Display for ProcessFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.program)?;
+        if !self.subcommand.is_empty() {
+            write!(f, " {}", self.subcommand)?;
+        }
+        write!(f, " failed ({})", self.status)?;
+        if !self.stderr.is_empty() {
+            write!(f, ": {}", self.stderr)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ProcessFailed {}
+
+pub fn check_output(
+    program: &str,
+    subcommand: &str,
+    output: &std::process::Output,
+) -> anyhow::Result<()> {
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(ProcessFailed {
+        program: program.to_string(),
+        subcommand: subcommand.to_string(),
+        status: output.status,
+        stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    }
+    .into())
+}
+
+// Spawning a process fails with NotFound when the program isn't installed
+pub fn spawn_error(program: &str, err: std::io::Error) -> anyhow::Error {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        ProgramMissing(program.to_string()).into()
+    } else {
+        err.into()
+    }
+}
+
+// Whether requests are sent with a token, since GitHub hides some things from anonymous requests behind a 404
+static AUTHENTICATED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_authenticated(authenticated: bool) {
+    AUTHENTICATED.store(authenticated, Ordering::Relaxed);
+}
+
+pub fn is_authenticated() -> bool {
+    AUTHENTICATED.load(Ordering::Relaxed)
+}
+
+fn classify_status(status: u16) -> Option<Classify> {
+    match status {
+        401 | 403 => Some(LogicError::AuthRequired),
+        404 | 410 => Some(LogicError::NotFound),
+        429 => Some(LogicError::RateLimited),
+        500..=599 => Some(LogicError::Network),
+        _ => None,
+    }
+}
+
+fn classify(cause: &(dyn std::error::Error + 'static)) -> Option<Classify> {
+    if let Some(status) = cause.downcast_ref::<HttpStatus>() {
+        if status.rate_limited {
+            return Some(LogicError::RateLimited);
+        }
+        // Workflow artifacts can only be downloaded with a token
+        if status.status == 404
+            && status.url.contains("/actions/artifacts/")
+            && !AUTHENTICATED.load(Ordering::Relaxed)
+        {
+            return Some(LogicError::AuthRequired);
+        }
+        return classify_status(status.status);
+    }
+
+    if let Some(err) = cause.downcast_ref::<octocrab::Error>() {
+        return match err {
+            octocrab::Error::GitHub { source, .. } => {
+                let status = source.status_code.as_u16();
+                if status == 403 && source.message.to_lowercase().contains("rate limit") {
+                    Some(LogicError::RateLimited)
+                } else {
+                    classify_status(status)
+                }
+            }
+            octocrab::Error::Hyper { .. }
+            | octocrab::Error::Service { .. }
+            | octocrab::Error::Http { .. } => Some(LogicError::Network),
+            _ => None,
+        };
+    }
+
+    if let Some(missing) = cause.downcast_ref::<ProgramMissing>() {
+        return (missing.0 == "git").then_some(LogicError::GitMissing as Classify);
+    }
+
+    // Git only reports why a clone or fetch failed on stderr
+    if let Some(failed) = cause.downcast_ref::<ProcessFailed>() {
+        let stderr = failed.stderr.to_lowercase();
+        if failed.program != "git" {
+            return None;
+        }
+        if stderr.contains("could not resolve host")
+            || stderr.contains("failed to connect")
+            || stderr.contains("connection timed out")
+            || stderr.contains("early eof")
+        {
+            return Some(LogicError::Network);
+        }
+        if stderr.contains("repository not found")
+            || stderr.contains("couldn't find remote ref")
+            || stderr.contains("not our ref")
+        {
+            return Some(LogicError::NotFound);
+        }
+        return None;
+    }
+
+    if cause.downcast_ref::<InsufficientSpace>().is_some() {
+        return Some(LogicError::DiskFull);
+    }
+
+    if cause.downcast_ref::<binrw::Error>().is_some() {
+        return Some(LogicError::AsarCorrupt);
+    }
+    if let Some(err) = cause.downcast_ref::<zip::result::ZipError>() {
+        let kind: Classify = match err {
+            zip::result::ZipError::FileNotFound => LogicError::NotFound,
+            _ => LogicError::AsarCorrupt,
+        };
+        return Some(kind);
+    }
+
+    if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+        use std::io::ErrorKind;
+        // Space the estimate missed, e.g. another download filling the disk at the same time
+        if err.kind() == ErrorKind::StorageFull {
+            return Some(LogicError::DiskFull);
+        }
+        return matches!(
+            err.kind(),
+            ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::TimedOut
+        )
+        .then_some(LogicError::Network as Classify);
+    }
+
+    None
+}
+
+// Runs a request again when it failed with a transient error
+pub async fn with_retries<T, F, Fut>(mut f: F) -> LogicResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = LogicResult<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Err(err) if err.is_transient() && attempt < MAX_ATTEMPTS => {
+                log::warn!("Attempt {} failed, retrying: {}", attempt, err);
+                tokio::time::sleep(RETRY_DELAY * attempt as u32).await;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use super::{
+    error::{check_output, spawn_error},
+    timing::Timer,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::{io::AsyncReadExt, process::Command};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    pub hash: String,
+    pub author: String,
+    pub timestamp: i64,
+    pub summary: String,
+    // The file can be renamed across history, so keep track of what it was called
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlameLine {
+    pub hash: String,
+    pub author: String,
+    pub timestamp: i64,
+    // Whether the line was last touched by a commit in the old..new range
+    pub in_range: bool,
+    pub text: String,
+}
+
+pub async fn git(repo: &Path, args: &[&str]) -> anyhow::Result<Vec<u8>> {
+    let mut cmd = Command::new("git");
+    let cmd = cmd.args(args).current_dir(repo);
+    #[cfg(target_os = "windows")]
+    let cmd = cmd.creation_flags(super::CREATE_NO_WINDOW);
+
+    let output = cmd
+        .output()
+        .await
+        .map_err(|err| spawn_error("git", err))
+        .context("Failed to run git")?;
+    check_output("git", args.first().unwrap_or(&""), &output)?;
+
+    Ok(output.stdout)
+}
+
+// Git's progress lines look like "Receiving objects:  45% (450/1000), 1.20 MiB | 2.00 MiB/s"
+fn parse_progress(line: &str) -> Option<(&str, u64, u64)> {
+    let line = line.trim();
+    let line = line.strip_prefix("remote:").unwrap_or(line).trim();
+    let (phase, rest) = line.split_once(':')?;
+    let counts = rest.split_once('(')?.1.split_once(')')?.0;
+    let (done, total) = counts.split_once('/')?;
+    Some((phase, done.trim().parse().ok()?, total.trim().parse().ok()?))
+}
+
+// For clones and fetches, with git's progress reported on the stage's timer. `args` starts with the
+// subcommand, since `--progress` has to come right after it.
+pub async fn git_tracked(repo: &Path, args: &[&str], timer: &Timer) -> anyhow::Result<()> {
+    let subcommand = args.first().copied().unwrap_or_default();
+    let mut cmd = Command::new("git");
+    let cmd = cmd
+        .arg(subcommand)
+        // Git only reports progress to a terminal unless asked
+        .arg("--progress")
+        .args(args.iter().skip(1))
+        .current_dir(repo)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(target_os = "windows")]
+    let cmd = cmd.creation_flags(super::CREATE_NO_WINDOW);
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|err| spawn_error("git", err))
+        .context("Failed to run git")?;
+    let mut stderr = child.stderr.take().context("Failed to read git's output")?;
+    let mut output = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let read = stderr.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        output.extend_from_slice(&buf[..read]);
+        // Progress is redrawn in place with \r, so the latest complete line is at the end. The
+        // piece after the last separator may still be arriving.
+        let tail = String::from_utf8_lossy(&output[output.len().saturating_sub(512)..]);
+        if let Some((phase, done, total)) =
+            tail.rsplit(['\r', '\n']).skip(1).find_map(parse_progress)
+        {
+            timer.set_items(phase, done, total);
+        }
+    }
+    let status = child.wait().await.context("Failed to run git")?;
+
+    // Errors are easier to read without every progress update before them
+    let stderr = String::from_utf8_lossy(&output)
+        .split(['\r', '\n'])
+        .filter(|line| !line.trim().is_empty() && parse_progress(line).is_none())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let output = std::process::Output {
+        status,
+        stdout: Vec::new(),
+        stderr: stderr.into_bytes(),
+    };
+    check_output("git", subcommand, &output)
+}
+
+pub async fn file_history(repo: &Path, rev: &str, path: &str) -> anyhow::Result<Vec<Revision>> {
+    log::debug!("Getting history of {} at {}", path, rev);
+
+    let output = git(
+        repo,
+        &[
+            "log",
+            "--follow",
+            "--name-only",
+            "--format=%x00%H%x1f%an%x1f%at%x1f%s",
+            "--end-of-options",
+            rev,
+            "--",
+            path,
+        ],
+    )
+    .await
+    .context("Failed to get file history")?;
+    let output = String::from_utf8_lossy(&output);
+
+    let mut revisions = Vec::new();
+    for entry in output.split('\0').filter(|entry| !entry.trim().is_empty()) {
+        let mut lines = entry.lines();
+        let header = lines.next().context("Missing log header")?;
+        let fields = header.split('\x1f').collect::<Vec<_>>();
+        let [hash, author, timestamp, summary] = fields[..] else {
+            anyhow::bail!("Malformed log entry: {}", header);
+        };
+
+        let path = lines
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or(path);
+
+        revisions.push(Revision {
+            hash: hash.to_string(),
+            author: author.to_string(),
+            timestamp: timestamp.parse().unwrap_or_default(),
+            summary: summary.to_string(),
+            path: path.to_string(),
+        });
+    }
+
+    Ok(revisions)
+}
+
+// Writes the file as it was at a revision to disk, so it can be handed to difftastic
+pub async fn export_revision(
+    repo: &Path,
+    revision: &Revision,
+    dest_dir: &Path,
+) -> anyhow::Result<PathBuf> {
+    let data = git(
+        repo,
+        &["show", format!("{}:{}", revision.hash, revision.path).as_str()],
+    )
+    .await
+    .context("Failed to read file at revision")?;
+
+    // Keep the original file name so difftastic can pick the right language
+    let file_name = Path::new(&revision.path)
+        .file_name()
+        .context("Revision path has no file name")?;
+    let dir = dest_dir.join(&revision.hash);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .context("Failed to create revision dir")?;
+
+    let path = dir.join(file_name);
+    tokio::fs::write(&path, data)
+        .await
+        .context("Failed to write revision")?;
+    Ok(path)
+}
+
+pub async fn commits_in_range(
+    repo: &Path,
+    old: &str,
+    new: &str,
+) -> anyhow::Result<HashSet<String>> {
+    let range = format!("{}..{}", old, new);
+    let output = git(repo, &["rev-list", "--end-of-options", range.as_str()])
+        .await
+        .context("Failed to list commits in range")?;
+    Ok(String::from_utf8_lossy(&output)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+pub async fn blame(
+    repo: &Path,
+    old_rev: &str,
+    new_rev: &str,
+    path: &str,
+) -> anyhow::Result<Vec<BlameLine>> {
+    log::debug!("Blaming {} at {}", path, new_rev);
+
+    // blame doesn't take --end-of-options, so a revision that looks like an option is refused here
+    anyhow::ensure!(!new_rev.starts_with('-'), "Invalid revision {:?}", new_rev);
+    let output = git(repo, &["blame", "--porcelain", new_rev, "--", path])
+        .await
+        .context("Failed to blame file")?;
+    let output = String::from_utf8_lossy(&output);
+    let in_range = commits_in_range(repo, old_rev, new_rev).await?;
+
+    // Porcelain output only lists commit details the first time a commit shows up
+    let mut commits: HashMap<String, (String, i64)> = HashMap::new();
+    let mut current_hash = String::new();
+    let mut lines = Vec::new();
+
+    for line in output.lines() {
+        if let Some(text) = line.strip_prefix('\t') {
+            let (author, timestamp) = commits.get(&current_hash).cloned().unwrap_or_default();
+            lines.push(BlameLine {
+                hash: current_hash.clone(),
+                author,
+                timestamp,
+                in_range: in_range.contains(&current_hash),
+                text: text.to_string(),
+            });
+        } else if let Some(author) = line.strip_prefix("author ") {
+            commits.entry(current_hash.clone()).or_default().0 = author.to_string();
+        } else if let Some(time) = line.strip_prefix("author-time ") {
+            commits.entry(current_hash.clone()).or_default().1 = time.parse().unwrap_or_default();
+        } else if let Some(hash) = line.split(' ').next() {
+            if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                current_hash = hash.to_string();
+            }
+        }
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_repo(name: &str) -> PathBuf {
+        let repo =
+            std::env::temp_dir().join(format!("robojules-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&repo);
+        std::fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "-q"]).await.unwrap();
+        std::fs::write(repo.join("index.js"), "a\n").unwrap();
+        git(&repo, &["add", "-A"]).await.unwrap();
+        git(
+            &repo,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-q",
+                "-m",
+                "initial",
+            ],
+        )
+        .await
+        .unwrap();
+        repo
+    }
+
+    // Revisions that look like options have to be read as revisions, and fail as ones
+    #[tokio::test]
+    async fn revisions_arent_options() {
+        let repo = test_repo("git-options").await;
+        let output = repo.join("output");
+        let option = format!("--output={}", output.display());
+
+        assert_eq!(
+            file_history(&repo, "HEAD", "index.js").await.unwrap().len(),
+            1
+        );
+        assert!(file_history(&repo, &option, "index.js").await.is_err());
+        assert!(commits_in_range(&repo, &option, "HEAD").await.is_err());
+        assert!(blame(&repo, "HEAD", &option, "index.js").await.is_err());
+        assert!(blame(&repo, "HEAD", "-p", "index.js").await.is_err());
+        let wrote_output = output.exists();
+        let _ = std::fs::remove_dir_all(&repo);
+        assert!(!wrote_output);
+    }
+}
@@ -0,0 +1,196 @@
+// tree-sitter grammars that aren't built in, downloaded and compiled into the cache dir when the user asks
+use super::{
+    audit::{self, AuditAction},
+    config::cache_dir,
+    git::git,
+};
+use anyhow::Context;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+use tree_sitter::{Language, Parser};
+
+pub struct Grammar {
+    pub name: &'static str,
+    repository: &'static str,
+    // Pinned, since newer grammars can target a tree-sitter ABI this build doesn't support
+    tag: &'static str,
+    // What the tag pointed to when it was pinned, so a moved tag isn't built
+    commit: &'static str,
+}
+
+pub const GRAMMARS: &[Grammar] = &[
+    Grammar {
+        name: "css",
+        repository: "https://github.com/tree-sitter/tree-sitter-css",
+        tag: "v0.23.1",
+        commit: "6a442a3cf461b0ce275339e5afa178693484c927",
+    },
+    Grammar {
+        name: "html",
+        repository: "https://github.com/tree-sitter/tree-sitter-html",
+        tag: "v0.23.2",
+        commit: "cbb91a0ff3621245e890d1c50cc811bffb77a26b",
+    },
+    Grammar {
+        name: "json",
+        repository: "https://github.com/tree-sitter/tree-sitter-json",
+        tag: "v0.24.8",
+        commit: "ee35a6ebefcef0c5c416c0d1ccec7370cfca5a24",
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Grammar> {
+    GRAMMARS.iter().find(|grammar| grammar.name == name)
+}
+
+fn grammar_dir() -> PathBuf {
+    cache_dir().join("grammars")
+}
+
+fn library_path(name: &str) -> PathBuf {
+    grammar_dir().join(format!(
+        "{}{}{}",
+        std::env::consts::DLL_PREFIX,
+        name,
+        std::env::consts::DLL_SUFFIX
+    ))
+}
+
+// None is remembered too, so a missing library isn't looked for on every parse
+fn loaded() -> &'static Mutex<HashMap<&'static str, Option<Language>>> {
+    static LOADED: OnceLock<Mutex<HashMap<&'static str, Option<Language>>>> = OnceLock::new();
+    LOADED.get_or_init(Default::default)
+}
+
+fn load_library(name: &str, path: &Path) -> anyhow::Result<Language> {
+    let library = unsafe { libloading::Library::new(path) }
+        .with_context(|| format!("Failed to load {}", path.display()))?;
+    let symbol = format!("tree_sitter_{}", name);
+    let language = unsafe {
+        let constructor = library
+            .get::<unsafe extern "C" fn() -> *const tree_sitter::ffi::TSLanguage>(symbol.as_bytes())
+            .with_context(|| format!("{} doesn't export {}", path.display(), symbol))?;
+        Language::from_raw(constructor())
+    };
+    Parser::new()
+        .set_language(&language)
+        .context("Grammar was built for an incompatible version of tree-sitter")?;
+
+    // The language points into the library, so it stays loaded until exit
+    std::mem::forget(library);
+    Ok(language)
+}
+
+// A previously installed grammar, if there is one
+pub fn load(name: &str) -> Option<Language> {
+    let grammar = find(name)?;
+    let mut loaded = loaded().lock().unwrap_or_else(|err| err.into_inner());
+    loaded
+        .entry(grammar.name)
+        .or_insert_with(|| {
+            let path = library_path(grammar.name);
+            if !path.exists() {
+                return None;
+            }
+            load_library(grammar.name, &path)
+                .inspect_err(|err| log::warn!("Failed to load {} grammar: {:?}", name, err))
+                .ok()
+        })
+        .clone()
+}
+
+// Whether the language could be parsed after installing its grammar. Never on Windows, where
+// there's no compiler that takes the same flags to count on.
+pub fn is_missing(name: &str) -> bool {
+    !cfg!(target_os = "windows") && find(name).is_some() && load(name).is_none()
+}
+
+#[cfg(target_os = "windows")]
+async fn compile(_src_dir: &Path, _output: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("Compiling grammars isn't supported on Windows")
+}
+
+// Any C compiler that takes GCC's flags works, `CC` picks one other than the default
+#[cfg(not(target_os = "windows"))]
+async fn compile(src_dir: &Path, output: &Path) -> anyhow::Result<()> {
+    use super::error::{check_output, spawn_error};
+
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let mut cmd = tokio::process::Command::new(&compiler);
+    cmd.args(["-shared", "-fPIC", "-O2", "-std=c11", "-I"])
+        .arg(src_dir)
+        .arg(src_dir.join("parser.c"));
+    // Grammars with context-sensitive tokens have a hand-written scanner
+    let scanner = src_dir.join("scanner.c");
+    if scanner.exists() {
+        cmd.arg(scanner);
+    }
+    cmd.arg("-o").arg(output);
+
+    let output = cmd
+        .output()
+        .await
+        .map_err(|err| spawn_error(&compiler, err))
+        .context("Failed to run the C compiler, set CC to use another one")?;
+    check_output(&compiler, "", &output)
+}
+
+pub async fn install(name: &str) -> anyhow::Result<()> {
+    let grammar = find(name).with_context(|| format!("No grammar available for {}", name))?;
+    log::debug!("Installing {} grammar from {}", name, grammar.repository);
+
+    let dir = grammar_dir();
+    let source_dir = dir.join(grammar.name);
+    if source_dir.exists() {
+        tokio::fs::remove_dir_all(&source_dir)
+            .await
+            .context("Failed to remove old grammar source")?;
+    }
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .context("Failed to create grammar dir")?;
+
+    git(
+        &dir,
+        &[
+            "clone",
+            "--depth=1",
+            "--branch",
+            grammar.tag,
+            "--",
+            grammar.repository,
+            grammar.name,
+        ],
+    )
+    .await
+    .context("Failed to download grammar")?;
+    let commit = git(&source_dir, &["rev-parse", "HEAD"])
+        .await
+        .context("Failed to read grammar commit")?;
+    let commit = String::from_utf8_lossy(&commit).trim().to_string();
+    anyhow::ensure!(
+        commit == grammar.commit,
+        "{} points at {} instead of the pinned {}, not building it",
+        grammar.tag,
+        commit,
+        grammar.commit
+    );
+    audit::record(AuditAction::CommitCheckedOut {
+        repository: grammar.repository.to_string(),
+        commit,
+    });
+
+    let path = library_path(grammar.name);
+    compile(&source_dir.join("src"), &path)
+        .await
+        .context("Failed to compile grammar")?;
+    let language = load_library(grammar.name, &path)?;
+    loaded()
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .insert(grammar.name, Some(language));
+    Ok(())
+}
@@ -0,0 +1,170 @@
+// The icon and banner an extension's manifest points at, decoded from each .asar so changes are visible
+use super::asar::FileTree;
+use anyhow::Context;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// Keeps a huge image in a PR from allocating gigabytes
+const MAX_DECODED_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImageKind {
+    Icon,
+    Banner,
+}
+
+impl ImageKind {
+    const ALL: [ImageKind; 2] = [ImageKind::Icon, ImageKind::Banner];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageKind::Icon => "Icon",
+            ImageKind::Banner => "Banner",
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            ImageKind::Icon => "icon",
+            ImageKind::Banner => "banner",
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    // Left out of JSON, RPC clients can read the file from the .asar themselves
+    #[serde(skip)]
+    pub rgba: Vec<u8>,
+}
+
+impl std::fmt::Debug for DecodedImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DecodedImage({}x{})", self.width, self.height)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestImage {
+    // Inside the .asar
+    pub path: String,
+    pub sha256: String,
+    // Why it couldn't be shown, e.g. a format other than PNG or a missing file
+    pub image: Result<DecodedImage, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageChange {
+    pub kind: ImageKind,
+    pub old: Option<ManifestImage>,
+    pub new: Option<ManifestImage>,
+}
+
+impl ImageChange {
+    pub fn changed(&self) -> bool {
+        self.old.as_ref().map(|image| &image.sha256) != self.new.as_ref().map(|image| &image.sha256)
+    }
+}
+
+fn decode_png(data: &[u8]) -> anyhow::Result<DecodedImage> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(
+        png::Transformations::normalize_to_color8() | png::Transformations::ALPHA,
+    );
+    decoder.set_limits(png::Limits {
+        bytes: MAX_DECODED_BYTES,
+    });
+    let mut reader = decoder.read_info().context("Not a valid PNG")?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buf)
+        .context("Failed to decode PNG")?;
+    buf.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        other => anyhow::bail!("Unexpected PNG color type {:?}", other),
+    };
+    Ok(DecodedImage {
+        width: info.width,
+        height: info.height,
+        rgba,
+    })
+}
+
+fn decode(data: &[u8]) -> Result<DecodedImage, String> {
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if data.starts_with(PNG_SIGNATURE) {
+        return decode_png(data).map_err(|err| format!("{:#}", err));
+    }
+    let format = if data.starts_with(b"\xff\xd8\xff") {
+        "JPEG"
+    } else if data.starts_with(b"GIF8") {
+        "GIF"
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(&b"WEBP"[..]) {
+        "WebP"
+    } else if String::from_utf8_lossy(&data[..data.len().min(512)]).contains("<svg") {
+        "SVG"
+    } else {
+        "Unknown format"
+    };
+    Err(format!("{} images can't be previewed", format))
+}
+
+// Relative to the .asar's root, which is where the manifest lives
+fn normalize(path: &str) -> String {
+    path.trim_start_matches("./")
+        .trim_start_matches('/')
+        .to_string()
+}
+
+fn manifest_image(tree: &FileTree, kind: ImageKind) -> Option<ManifestImage> {
+    let manifest = tree.get("manifest.json")?;
+    let manifest = serde_json::from_slice::<serde_json::Value>(&manifest.data).ok()?;
+    let path = manifest
+        .get("meta")
+        .and_then(|meta| meta.get(kind.key()))
+        .or_else(|| manifest.get(kind.key()))
+        .and_then(|path| path.as_str())?;
+    // Hosted elsewhere, so there's nothing in the .asar to show
+    if path.contains("://") {
+        return None;
+    }
+
+    let path = normalize(path);
+    let Some(file) = tree.get(&path) else {
+        return Some(ManifestImage {
+            path,
+            sha256: String::new(),
+            image: Err("Not in the .asar".to_string()),
+        });
+    };
+    Some(ManifestImage {
+        sha256: format!("{:x}", Sha256::digest(&file.data)),
+        image: decode(&file.data),
+        path,
+    })
+}
+
+// `current` is the published .asar, `artifact` the one being reviewed
+pub fn compare(current: &FileTree, artifact: &FileTree) -> Vec<ImageChange> {
+    ImageKind::ALL
+        .into_iter()
+        .map(|kind| ImageChange {
+            kind,
+            old: manifest_image(current, kind),
+            new: manifest_image(artifact, kind),
+        })
+        .filter(|change| change.old.is_some() || change.new.is_some())
+        .collect()
+}
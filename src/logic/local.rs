@@ -0,0 +1,210 @@
+// Reviews an extension author's working tree and .asar against what's published, before there's a PR
+use super::{
+    archive,
+    asar::{parse_asar, FileTree},
+    audit::{self, AuditAction},
+    bandwidth::{self, DownloadKind},
+    config::Config,
+    diff::{DiffedExtension, ModifiedExtension},
+    download::{self, checkout_copy, get_url, published_asar_url, Workspace},
+    error::HttpStatus,
+    git::git,
+    policy,
+    pr::ExtensionManifest,
+    timing, validate, LogicResult,
+};
+use anyhow::Context;
+use std::{io::Cursor, path::Path};
+
+// None when the extension hasn't been published yet
+async fn get_published(
+    client: &octocrab::Octocrab,
+    url: &str,
+    kind: DownloadKind,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    match get_url(client, url).await {
+        Ok(data) => {
+            audit::record_download(url, &data);
+            bandwidth::record(None, kind, data.len() as u64);
+            Ok(Some(data))
+        }
+        Err(err)
+            if err
+                .downcast_ref::<HttpStatus>()
+                .is_some_and(|status| status.status == 404) =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// Built .asars are read directly, anything else is treated like a CI artifact
+async fn read_asar(path: &Path, ext_id: &str) -> anyhow::Result<FileTree> {
+    let data = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    if path.extension().is_some_and(|ext| ext == "asar") {
+        parse_asar(&mut Cursor::new(data)).context("Failed to parse local .asar")
+    } else {
+        archive::get_asar_from_archive(data, ext_id)
+            .await
+            .context("Failed to parse local artifact")
+    }
+}
+
+// Only what git would commit, so node_modules and build output stay out of the diff
+async fn copy_working_tree(working_dir: &Path, dest: &Path) -> anyhow::Result<()> {
+    let files = git(
+        working_dir,
+        &[
+            "ls-files",
+            "-z",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+        ],
+    )
+    .await
+    .context("Failed to list working tree files")?;
+
+    for relative in files
+        .split(|byte| *byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let relative = String::from_utf8_lossy(relative);
+        let src = working_dir.join(relative.as_ref());
+        // Tracked files deleted in the working tree are still listed
+        if !src.is_file() {
+            continue;
+        }
+        let dest = dest.join(relative.as_ref());
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context("Failed to create parent dir")?;
+        }
+        tokio::fs::copy(&src, &dest)
+            .await
+            .with_context(|| format!("Failed to copy {}", relative))?;
+    }
+    Ok(())
+}
+
+pub async fn review_local(
+    client: &octocrab::Octocrab,
+    ext_id: &str,
+    working_dir: &Path,
+    asar_path: &Path,
+    config: &Config,
+) -> LogicResult<DiffedExtension> {
+    log::debug!(
+        "Reviewing {} from {} and {}",
+        ext_id,
+        working_dir.display(),
+        asar_path.display()
+    );
+    // The ID is typed in and ends up in the temp dir's name
+    if !validate::is_valid_id(ext_id) {
+        return Err(anyhow::anyhow!("Invalid extension ID {:?}", ext_id).into());
+    }
+    let workspace = Workspace::create(&format!("{}-local", ext_id)).await?;
+
+    let timer = timing::start(format!("{}: get published version", ext_id));
+    let manifest_url = format!(
+        "https://raw.githubusercontent.com/moonlight-mod/extensions/refs/heads/main/exts/{}.json",
+        ext_id
+    );
+    let manifest = get_published(client, &manifest_url, DownloadKind::Other)
+        .await
+        .context("Failed to download published manifest")?
+        .map(|data| serde_json::from_slice::<ExtensionManifest>(&data))
+        .transpose()
+        .context("Failed to parse published manifest")?;
+    let current_asar = match get_published(client, &published_asar_url(ext_id), DownloadKind::Asar)
+        .await
+        .context("Failed to download current .asar")?
+    {
+        Some(data) => {
+            parse_asar(&mut Cursor::new(data)).context("Failed to parse current .asar")?
+        }
+        None => FileTree::default(),
+    };
+    timer.finish();
+
+    let timer = timing::start(format!("{}: read local .asar", ext_id));
+    let local_asar = read_asar(asar_path, ext_id).await?;
+    timer.finish();
+    let asar =
+        download::compare_asars(ext_id, &current_asar, &local_asar, &workspace, config).await?;
+
+    // Cloning the working directory leaves the author's checkout alone
+    let timer = timing::start(format!("{}: copy working tree", ext_id));
+    let working_dir_arg = working_dir.to_string_lossy();
+    git(
+        &workspace.temp_dir,
+        &["clone", "--no-checkout", &working_dir_arg, "source"],
+    )
+    .await
+    .context("Failed to clone working directory")?;
+    let head = git(working_dir, &["rev-parse", "HEAD"])
+        .await
+        .map(|output| String::from_utf8_lossy(&output).trim().to_string())
+        .unwrap_or_default();
+    copy_working_tree(working_dir, &workspace.new_source_dir).await?;
+    timer.finish();
+
+    let ext = match manifest {
+        Some(manifest) => {
+            let timer = timing::start(format!("{}: checkout published commit", ext_id));
+            // The published commit may only be on the remote, e.g. in a fork
+            if !validate::is_commit_hash(&manifest.commit) {
+                return Err(anyhow::anyhow!(
+                    "Published commit {:?} isn't a commit hash",
+                    manifest.commit
+                )
+                .into());
+            }
+            let before = bandwidth::git_size(&workspace.source_dir).await;
+            git(
+                &workspace.source_dir,
+                &["fetch", "--", &manifest.repository, &manifest.commit],
+            )
+            .await
+            .context("Failed to fetch published commit")?;
+            let fetched = bandwidth::git_size(&workspace.source_dir).await;
+            bandwidth::record(None, DownloadKind::Clone, fetched.saturating_sub(before));
+            checkout_copy(
+                workspace.source_dir.clone(),
+                workspace.old_source_dir.clone(),
+                &manifest.commit,
+            )
+            .await
+            .context("Failed to checkout published commit")?;
+            timer.finish();
+            audit::record(AuditAction::CommitCheckedOut {
+                repository: manifest.repository.clone(),
+                commit: manifest.commit.clone(),
+            });
+
+            ModifiedExtension {
+                id: ext_id.to_string(),
+                repository: manifest.repository,
+                new_repository: None,
+                old_commit: manifest.commit,
+                new_commit: head,
+            }
+        }
+        None => ModifiedExtension {
+            id: ext_id.to_string(),
+            repository: working_dir.display().to_string(),
+            new_repository: None,
+            old_commit: String::new(),
+            new_commit: head,
+        },
+    };
+
+    let policy = policy::get_or_default(client).await;
+    // The working tree is the user's own, so its symlinks are followed
+    download::analyze(&ext, workspace, asar, &[], config, &policy).await
+}
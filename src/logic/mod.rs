@@ -1,99 +1,816 @@
 use anyhow::Context;
-use diff::{DiffedExtension, ModifiedExtension, PullRequestUpdate};
-use std::path::PathBuf;
+use approvals::Approval;
+use audit::{AuditAction, AuditEntry};
+use bandwidth::BandwidthUsage;
+use build::{BuildOutcome, LocalBuild};
+use checks::{CheckConclusion, CheckRun};
+use config::Config;
+use dependents::Dependent;
+use deps::{Advisory, DependencyRisk};
+use diff::{DiffedExtension, FileDiff, ModifiedExtension, PullRequestUpdate, SingleFile};
+use git::{BlameLine, Revision};
+use onboarding::{SelfTestStep, ToolCheck};
+use overview::ExtensionOverview;
+use pr::PullRequestSummary;
+use prefetch::{DiffCache, PrefetchItem, PrefetchRequest};
+use provenance::Provenance;
+use review::{LineComment, ReviewVerdict, SubmittedReview};
+use scan::Finding;
+use serde::{Deserialize, Serialize};
+use session::{ReviewSession, ReviewState, ReviewStatus};
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+use sync::TeamReview;
+use timing::{StageProgress, Timing};
 use tokio::runtime::Runtime;
 
+pub mod approvals;
+pub mod archive;
 pub mod asar;
+pub mod atomic;
+pub mod audit;
+pub mod bandwidth;
+pub mod build;
+pub mod bundle;
+pub mod checks;
+pub mod concurrency;
+pub mod config;
+pub mod dependents;
+pub mod deps;
 pub mod diff;
+pub mod dist;
+pub mod downgrade;
 pub mod download;
+pub mod error;
+pub mod git;
+pub mod grammars;
+pub mod icons;
+pub mod local;
+pub mod onboarding;
+pub mod overview;
+pub mod patches;
+pub mod paths;
+pub mod policy;
 pub mod pr;
+pub mod prefetch;
+pub mod pretty;
+pub mod provenance;
+pub mod review;
+pub mod scan;
+pub mod session;
+pub mod shape;
+pub mod space;
+pub mod sync;
+pub mod syntax;
+pub mod templates;
+pub mod timing;
+pub mod validate;
+pub mod walk;
+pub mod wasm;
 
 pub const CREATE_NO_WINDOW: u32 = 0x08000000;
 
-#[derive(Clone, Debug)]
-pub struct LogicError(String);
-pub type LogicResult<T> = Result<T, LogicError>;
+use error::with_retries;
+pub use error::{LogicError, LogicResult};
 
-impl std::fmt::Display for LogicError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-impl From<anyhow::Error> for LogicError {
-    fn from(err: anyhow::Error) -> Self {
-        Self(format!("{:?}", err))
-    }
-}
-
-impl From<String> for LogicError {
-    fn from(err: String) -> Self {
-        Self(err)
-    }
-}
-
-#[derive(Debug, Clone)]
+// Deserialized from JSON-RPC requests in server mode, as `{ "method": ..., "params": ... }`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(
+    tag = "method",
+    content = "params",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
 pub enum LogicCommand {
     GetPullRequest(u64),
+    // Open pull requests, for picking one without knowing its number
+    ListPullRequests,
+    // Which token requests are sent with, checked against GitHub
+    GetAuthStatus,
     DownloadExtension {
         extension: ModifiedExtension,
         artifact_url: String,
+        // Which pull request and head this is for, so its temp dir is its own
+        #[serde(default)]
+        pull_request: u64,
+        #[serde(default)]
+        head_sha: String,
+    },
+    // What's been downloaded so far, without starting any downloads
+    GetOverview {
+        extensions: Vec<ModifiedExtension>,
+        artifact_url: String,
+    },
+    // Reads from wherever the user points, so it's not exposed over RPC
+    #[serde(skip_deserializing)]
+    ReviewLocal {
+        extension_id: String,
+        working_dir: PathBuf,
+        asar_path: PathBuf,
+    },
+    DiffFile {
+        old: PathBuf,
+        new: PathBuf,
+        // Format minified JavaScript before diffing
+        #[serde(default)]
+        pretty: bool,
+    },
+    // Reads any path, so it's not exposed over RPC
+    #[serde(skip_deserializing)]
+    GetFile(PathBuf),
+    // Runs a C compiler on the downloaded grammar, so it's not exposed over RPC
+    #[serde(skip_deserializing)]
+    InstallGrammar(String),
+    GetFileHistory {
+        repository_dir: PathBuf,
+        rev: String,
+        path: String,
+    },
+    DiffRevisions {
+        repository_dir: PathBuf,
+        old: Revision,
+        new: Revision,
+        // The file whose history this is, which the revisions may have had other names for
+        path: String,
+    },
+    BlameFile {
+        repository_dir: PathBuf,
+        old_rev: String,
+        new_rev: String,
+        path: String,
+    },
+    // Config can enable local builds, which run arbitrary commands, so it's never exposed over RPC
+    #[serde(skip_deserializing)]
+    UpdateConfig(Config),
+    // (name, version) pairs to look up on the npm registry
+    CheckDependencies(Vec<(String, String)>),
+    // Looks up OSV advisories, bypassing the local cache when `refresh` is set
+    CheckAdvisories {
+        packages: Vec<(String, String)>,
+        refresh: bool,
     },
-    DiffFile(PathBuf, PathBuf),
+    // Published extensions that depend on this extension ID
+    GetDependents(String),
+    #[serde(skip_deserializing)]
+    BuildLocally {
+        extension_id: String,
+        source_dir: PathBuf,
+        artifact_dir: PathBuf,
+        temp_dir: PathBuf,
+    },
+    GetProvenance {
+        dist_dir: PathBuf,
+        source_dir: PathBuf,
+        path: String,
+    },
+    // Progress is reported with unprompted `PrefetchStatus` responses, so these aren't exposed over RPC
+    #[serde(skip_deserializing)]
+    Prefetch(Vec<u64>),
+    #[serde(skip_deserializing)]
+    PrefetchOpen,
+    // Session files are read and written wherever the user points, so neither is exposed over RPC
+    #[serde(skip_deserializing)]
+    ExportSession {
+        path: PathBuf,
+        session: ReviewSession,
+    },
+    #[serde(skip_deserializing)]
+    ImportSession(PathBuf),
+    // Saved reviews are what the PR list's progress is derived from, so only the UI writes them
+    #[serde(skip_deserializing)]
+    SaveReviews {
+        pull_request: u64,
+        reviews: BTreeMap<String, ReviewState>,
+    },
+    LoadReviews(u64),
+    // Pull request numbers and the extensions each one touches
+    GetReviewStatuses(BTreeMap<u64, Vec<String>>),
+    // Posts to the PR, so it's only available from the UI and CLI
+    #[serde(skip_deserializing)]
+    PublishCheckRun {
+        head_sha: String,
+        // By extension ID
+        findings: BTreeMap<String, Vec<Finding>>,
+        conclusion: CheckConclusion,
+    },
+    // Publishes to the team's gist, which isn't something RPC clients should do on our behalf
+    #[serde(skip_deserializing)]
+    SyncReview {
+        pull_request: u64,
+        reviews: BTreeMap<String, ReviewState>,
+    },
+    // Remembers the PR's new commit, so the next update can be diffed against it. Later downloads
+    // fetch from the approved repository, so RPC clients can't record approvals.
+    #[serde(skip_deserializing)]
+    ApproveExtension {
+        extension: ModifiedExtension,
+        pull_request: u64,
+    },
+    // Approves or requests changes on GitHub as the user, which RPC clients shouldn't do on their behalf
+    #[serde(skip_deserializing)]
+    SubmitReview {
+        pull_request: u64,
+        // The commit that was reviewed
+        head_sha: String,
+        verdict: ReviewVerdict,
+        body: String,
+        // Queued line comments, by extension ID
+        comments: BTreeMap<String, Vec<LineComment>>,
+    },
+    GetAuditLog,
+    // Written as a JSON array. Not exposed over RPC, since it writes to any path.
+    #[serde(skip_deserializing)]
+    ExportAuditLog(PathBuf),
+    // Starts sending unprompted `Progress` and `Bandwidth` responses, so it's for the UI only
+    #[serde(skip_deserializing)]
+    WatchProgress,
+    // Recent pipeline stage durations, for the diagnostics window
+    GetTimings,
+    ClearTimings,
+    // Bytes downloaded this session
+    GetBandwidth,
+    // Whether git and difft can be run, for the setup wizard. Runs tools on the user's machine, so
+    // neither is exposed over RPC.
+    #[serde(skip_deserializing)]
+    CheckTools,
+    // Goes through a review on a recently merged pull request
+    #[serde(skip_deserializing)]
+    SelfTest,
 }
 
 #[derive(Debug, Clone)]
 pub enum LogicResponse {
     PullRequest(LogicResult<PullRequestUpdate>),
+    PullRequests(LogicResult<Vec<PullRequestSummary>>),
+    AuthStatus(LogicResult<AuthStatus>),
     ExtensionDownloadComplete(LogicResult<DiffedExtension>),
-    FileDiff(LogicResult<String>),
+    Overview(Vec<ExtensionOverview>),
+    FileDiff(LogicResult<FileDiff>),
+    File(LogicResult<SingleFile>),
+    // The language whose grammar was installed
+    GrammarInstalled(LogicResult<String>),
+    // Tagged with the file they were requested for, which may no longer be the selected one
+    FileHistory {
+        path: String,
+        revisions: LogicResult<Vec<Revision>>,
+    },
+    RevisionDiff {
+        path: String,
+        diff: LogicResult<String>,
+    },
+    Blame {
+        path: String,
+        lines: LogicResult<Vec<BlameLine>>,
+    },
+    DependencyRisks(LogicResult<Vec<DependencyRisk>>),
+    Advisories(LogicResult<Vec<Advisory>>),
+    Dependents(LogicResult<Vec<Dependent>>),
+    LocalBuild(LogicResult<LocalBuild>),
+    Provenance(LogicResult<Provenance>),
+    Timings(Vec<Timing>),
+    Progress(Vec<StageProgress>),
+    Bandwidth(BandwidthUsage),
+    AuditLog(Vec<AuditEntry>),
+    PrefetchStatus(Vec<PrefetchItem>),
+    SessionExported(LogicResult<PathBuf>),
+    SessionImported(LogicResult<ReviewSession>),
+    ReviewsSaved(LogicResult<()>),
+    // Empty when nothing was saved for the pull request
+    ReviewsLoaded {
+        pull_request: u64,
+        reviews: BTreeMap<String, ReviewState>,
+    },
+    ReviewStatuses(BTreeMap<u64, ReviewStatus>),
+    ReviewSynced(LogicResult<TeamReview>),
+    CheckRunPublished(LogicResult<CheckRun>),
+    ReviewSubmitted(LogicResult<SubmittedReview>),
+    AuditLogExported(LogicResult<PathBuf>),
+    ExtensionApproved(LogicResult<Approval>),
+    Tools(Vec<ToolCheck>),
+    SelfTest(Vec<SelfTestStep>),
 }
 
-fn build_octocrab() -> anyhow::Result<octocrab::Octocrab> {
-    octocrab::Octocrab::builder()
-        .build()
-        .context("Failed to build Octocrab client")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenSource {
+    // GITHUB_TOKEN
+    Environment,
+    Config,
+    GhCli,
+}
+
+impl TokenSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenSource::Environment => "GITHUB_TOKEN",
+            TokenSource::Config => "the token in the settings",
+            TokenSource::GhCli => "the gh CLI",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthStatus {
+    // None when requests go unauthenticated
+    pub source: Option<TokenSource>,
+    pub login: Option<String>,
+}
+
+// Falls back to the gh CLI's login, so most maintainers are authenticated without setting anything up
+fn gh_token() -> Option<String> {
+    let mut cmd = std::process::Command::new("gh");
+    cmd.args(["auth", "token"]);
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    // Not installed or not logged in are both fine, requests just go unauthenticated
+    let output = cmd.output().ok().filter(|output| output.status.success())?;
+    let token = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    if token.is_empty() {
+        return None;
+    }
+    log::debug!("Using the gh CLI's token");
+    Some(token)
+}
+
+fn build_octocrab(config: &Config) -> anyhow::Result<(octocrab::Octocrab, Option<TokenSource>)> {
+    let mut builder = octocrab::Octocrab::builder();
+    // Raises the rate limit, and is needed for writing, e.g. syncing reviews and downloading artifacts
+    let config_token = config.github_token.0.trim().to_string();
+    let token = std::env::var("GITHUB_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .map(|token| (token, TokenSource::Environment))
+        .or_else(|| (!config_token.is_empty()).then_some((config_token, TokenSource::Config)))
+        .or_else(|| gh_token().map(|token| (token, TokenSource::GhCli)));
+    error::set_authenticated(token.is_some());
+    let source = token.as_ref().map(|(_, source)| *source);
+    if let Some((token, source)) = token {
+        log::debug!("Authenticating with {}", source.name());
+        builder = builder.personal_token(token);
+    }
+    let client = builder.build().context("Failed to build Octocrab client")?;
+    Ok((client, source))
+}
+
+async fn diff_revisions(
+    repository_dir: &std::path::Path,
+    old: &Revision,
+    new: &Revision,
+) -> LogicResult<String> {
+    let history_dir = repository_dir
+        .parent()
+        .context("Repository has no parent dir")?
+        .join("history");
+    let old_path = git::export_revision(repository_dir, old, &history_dir).await?;
+    let new_path = git::export_revision(repository_dir, new, &history_dir).await?;
+    diff::get_diff_string(&old_path, &new_path).await
 }
 
 async fn app_logic_thread_inner(
     rx: flume::Receiver<LogicCommand>,
     tx: flume::Sender<LogicResponse>,
 ) -> anyhow::Result<()> {
-    let client = build_octocrab()?;
+    let mut config = Config::load();
+    config::set_cache_dir(&config);
+    syntax::set_language_map(&config.languages);
+    // The token is only read here, so changing it restarts the logic thread
+    let (client, token_source) = build_octocrab(&config)?;
+    let (config_tx, config_rx) = tokio::sync::watch::channel(config.clone());
+    tokio::spawn(dist::run_refresh(client.clone(), config_rx));
+
+    let cache = DiffCache::default();
+    let (prefetch_tx, prefetch_rx) = flume::unbounded();
+    tokio::spawn(prefetch::run_worker(
+        client.clone(),
+        cache.clone(),
+        prefetch_rx,
+        tx.clone(),
+    ));
 
-    loop {
-        match rx.recv()? {
+    // The receiver only fails once every sender is gone, so there's nobody left to answer
+    while let Ok(command) = rx.recv_async().await {
+        match command {
             LogicCommand::GetPullRequest(num) => {
-                let res = pr::get_pull_request(&client, num).await;
+                let res =
+                    with_retries(|| pr::get_pull_request(&client, num, &config.artifacts)).await;
                 log::debug!("Got pull request: {:?}", res);
                 tx.send(LogicResponse::PullRequest(res))?;
             }
 
+            LogicCommand::GetAuthStatus => {
+                let res = match token_source {
+                    Some(source) => client
+                        .current()
+                        .user()
+                        .await
+                        .context("GitHub rejected the token")
+                        .map(|user| AuthStatus {
+                            source: Some(source),
+                            login: Some(user.login),
+                        })
+                        .map_err(Into::into),
+                    None => Ok(AuthStatus {
+                        source: None,
+                        login: None,
+                    }),
+                };
+                log::debug!("Auth status: {:?}", res);
+                tx.send(LogicResponse::AuthStatus(res))?;
+            }
+
+            LogicCommand::ListPullRequests => {
+                let res = with_retries(|| pr::list_pull_requests(&client)).await;
+                log::debug!("Listed pull requests: {:?}", res.as_ref().map(Vec::len));
+                tx.send(LogicResponse::PullRequests(res))?;
+            }
+
             LogicCommand::DownloadExtension {
                 extension,
                 artifact_url,
+                pull_request,
+                head_sha,
             } => {
-                let res = download::download_extension(&client, &extension, &artifact_url).await;
+                let res = with_retries(|| {
+                    cache.download_extension(
+                        &client,
+                        &extension,
+                        &artifact_url,
+                        pull_request,
+                        &head_sha,
+                        &config,
+                    )
+                })
+                .await;
                 log::debug!("Downloaded extension: {:?}", res);
                 tx.send(LogicResponse::ExtensionDownloadComplete(res))?;
             }
 
-            LogicCommand::DiffFile(old, new) => {
-                let res = diff::get_diff_string(&old, &new).await;
-                log::debug!("Diffed files: {:?}", res);
+            LogicCommand::GetOverview {
+                extensions,
+                artifact_url,
+            } => {
+                let overview = extensions
+                    .iter()
+                    .map(|ext| ExtensionOverview {
+                        id: ext.id.clone(),
+                        state: cache.overview(ext, &artifact_url),
+                    })
+                    .collect();
+                tx.send(LogicResponse::Overview(overview))?;
+            }
+
+            // Answered like a download, so the rest of the review works the same
+            LogicCommand::ReviewLocal {
+                extension_id,
+                working_dir,
+                asar_path,
+            } => {
+                let res =
+                    local::review_local(&client, &extension_id, &working_dir, &asar_path, &config)
+                        .await;
+                log::debug!("Reviewed local extension: {:?}", res);
+                tx.send(LogicResponse::ExtensionDownloadComplete(res))?;
+            }
+
+            LogicCommand::DiffFile { old, new, pretty } => {
+                let timeout = Duration::from_secs(config.concurrency.diff_timeout_secs);
+                let res = diff::calculate_file_diff(&old, &new, timeout, pretty).await;
+                match &res {
+                    Ok(diff) => log::debug!("Diffed {:?}: {} hunks", new, diff.hunks.len()),
+                    Err(err) => log::debug!("Failed to diff {:?}: {:?}", new, err),
+                }
                 tx.send(LogicResponse::FileDiff(res))?;
             }
+
+            LogicCommand::GetFile(path) => {
+                let res = diff::highlight_single_file(&path).await.map_err(Into::into);
+                tx.send(LogicResponse::File(res))?;
+            }
+
+            LogicCommand::InstallGrammar(name) => {
+                let res = grammars::install(&name)
+                    .await
+                    .map(|_| name)
+                    .map_err(Into::into);
+                log::debug!("Installed grammar: {:?}", res);
+                tx.send(LogicResponse::GrammarInstalled(res))?;
+            }
+
+            LogicCommand::GetFileHistory {
+                repository_dir,
+                rev,
+                path,
+            } => {
+                let res = git::file_history(&repository_dir, &rev, &path)
+                    .await
+                    .map_err(Into::into);
+                log::debug!("Got file history: {:?}", res);
+                tx.send(LogicResponse::FileHistory {
+                    path,
+                    revisions: res,
+                })?;
+            }
+
+            LogicCommand::DiffRevisions {
+                repository_dir,
+                old,
+                new,
+                path,
+            } => {
+                let res = diff_revisions(&repository_dir, &old, &new).await;
+                log::debug!("Diffed revisions: {:?}", res);
+                tx.send(LogicResponse::RevisionDiff { path, diff: res })?;
+            }
+
+            LogicCommand::BlameFile {
+                repository_dir,
+                old_rev,
+                new_rev,
+                path,
+            } => {
+                let res = git::blame(&repository_dir, &old_rev, &new_rev, &path)
+                    .await
+                    .map_err(Into::into);
+                log::debug!("Blamed file: {:?}", res.as_ref().map(|lines| lines.len()));
+                tx.send(LogicResponse::Blame { path, lines: res })?;
+            }
+
+            LogicCommand::CheckDependencies(packages) => {
+                let res =
+                    deps::get_dependency_risks(&client, packages, config.concurrency.downloads)
+                        .await
+                        .map_err(Into::into);
+                log::debug!("Checked dependencies: {:?}", res);
+                tx.send(LogicResponse::DependencyRisks(res))?;
+            }
+
+            LogicCommand::CheckAdvisories { packages, refresh } => {
+                let res = with_retries(|| async {
+                    deps::check_advisories(&client, packages.clone(), refresh)
+                        .await
+                        .map_err(Into::into)
+                })
+                .await;
+                log::debug!("Checked advisories: {:?}", res);
+                tx.send(LogicResponse::Advisories(res))?;
+            }
+
+            LogicCommand::GetDependents(extension_id) => {
+                let res = with_retries(|| async {
+                    dependents::find_dependents(&client, &extension_id)
+                        .await
+                        .map_err(Into::into)
+                })
+                .await;
+                log::debug!("Found dependents: {:?}", res);
+                tx.send(LogicResponse::Dependents(res))?;
+            }
+
+            LogicCommand::BuildLocally {
+                extension_id,
+                source_dir,
+                artifact_dir,
+                temp_dir,
+            } => {
+                let res = if config.build.enabled {
+                    build::build_locally(
+                        &config.build,
+                        &config.concurrency,
+                        &extension_id,
+                        &source_dir,
+                        &artifact_dir,
+                        &temp_dir,
+                    )
+                    .await
+                    .map_err(Into::into)
+                } else {
+                    Err("Local builds are disabled".to_string().into())
+                };
+                log::debug!(
+                    "Built locally: {:?}",
+                    res.as_ref().map(|build| build.outcome)
+                );
+                if let Ok(build) = &res {
+                    audit::record(AuditAction::LocalBuild {
+                        extension: extension_id.clone(),
+                        success: build.outcome == BuildOutcome::Succeeded,
+                    });
+                }
+                tx.send(LogicResponse::LocalBuild(res))?;
+            }
+
+            LogicCommand::GetProvenance {
+                dist_dir,
+                source_dir,
+                path,
+            } => {
+                let repo_files = provenance::list_files(&source_dir).await;
+                let res = provenance::find_provenance(&dist_dir, &path, &repo_files).await;
+                log::debug!("Got provenance of {}: {}", path, res.summary());
+                tx.send(LogicResponse::Provenance(Ok(res)))?;
+            }
+
+            LogicCommand::Prefetch(numbers) => {
+                let _ = prefetch_tx.send((PrefetchRequest::PullRequests(numbers), config.clone()));
+            }
+
+            LogicCommand::PrefetchOpen => {
+                let _ = prefetch_tx.send((PrefetchRequest::AllOpen, config.clone()));
+            }
+
+            LogicCommand::ExportSession { path, session } => {
+                let res = session::export(&path, &session)
+                    .await
+                    .map(|_| path)
+                    .map_err(Into::into);
+                log::debug!("Exported session: {:?}", res);
+                if res.is_ok() {
+                    if let Err(err) = session::mark_exported(&session).await {
+                        log::warn!("{:?}", err);
+                    }
+                }
+                tx.send(LogicResponse::SessionExported(res))?;
+            }
+
+            LogicCommand::SaveReviews {
+                pull_request,
+                reviews,
+            } => {
+                let res = session::save_reviews(pull_request, reviews)
+                    .await
+                    .map_err(Into::into);
+                log::debug!("Saved reviews for {}: {:?}", pull_request, res);
+                tx.send(LogicResponse::ReviewsSaved(res))?;
+            }
+
+            LogicCommand::LoadReviews(pull_request) => {
+                let reviews = session::load_saved(pull_request)
+                    .await
+                    .map(|saved| saved.reviews)
+                    .unwrap_or_default();
+                log::debug!(
+                    "Loaded {} saved reviews for {}",
+                    reviews.len(),
+                    pull_request
+                );
+                tx.send(LogicResponse::ReviewsLoaded {
+                    pull_request,
+                    reviews,
+                })?;
+            }
+
+            LogicCommand::GetReviewStatuses(pull_requests) => {
+                let approvals = approvals::Approvals::load();
+                let mut statuses = BTreeMap::new();
+                for (number, extensions) in pull_requests {
+                    let status = session::review_status(number, &extensions, &approvals).await;
+                    statuses.insert(number, status);
+                }
+                tx.send(LogicResponse::ReviewStatuses(statuses))?;
+            }
+
+            LogicCommand::ImportSession(path) => {
+                let res = session::import(&path).await.map_err(Into::into);
+                log::debug!(
+                    "Imported session: {:?}",
+                    res.as_ref()
+                        .map(|session: &ReviewSession| session.pull_request)
+                );
+                tx.send(LogicResponse::SessionImported(res))?;
+            }
+
+            LogicCommand::PublishCheckRun {
+                head_sha,
+                findings,
+                conclusion,
+            } => {
+                let res = checks::publish(
+                    &client,
+                    &head_sha,
+                    &findings,
+                    conclusion,
+                    &config.review_templates,
+                )
+                .await
+                .map_err(Into::into);
+                log::debug!("Published check run: {:?}", res);
+                tx.send(LogicResponse::CheckRunPublished(res))?;
+            }
+
+            LogicCommand::SyncReview {
+                pull_request,
+                reviews,
+            } => {
+                let gist_id = config.sync.gist_id.trim();
+                let res = if gist_id.is_empty() {
+                    Err("No review gist is configured".to_string().into())
+                } else {
+                    with_retries(|| async {
+                        sync::sync(&client, gist_id, pull_request, &reviews)
+                            .await
+                            .map_err(Into::into)
+                    })
+                    .await
+                };
+                log::debug!(
+                    "Synced review: {:?}",
+                    res.as_ref().map(|team: &TeamReview| team.reviewers.len())
+                );
+                tx.send(LogicResponse::ReviewSynced(res))?;
+            }
+
+            LogicCommand::ApproveExtension {
+                extension,
+                pull_request,
+            } => {
+                let res = approvals::approve(&extension, pull_request).map_err(Into::into);
+                log::debug!("Approved {}: {:?}", extension.id, res);
+                tx.send(LogicResponse::ExtensionApproved(res))?;
+            }
+
+            LogicCommand::SubmitReview {
+                pull_request,
+                head_sha,
+                verdict,
+                body,
+                comments,
+            } => {
+                let res =
+                    review::submit(&client, pull_request, &head_sha, verdict, &body, &comments)
+                        .await
+                        .map_err(Into::into);
+                log::debug!("Submitted review: {:?}", res);
+                tx.send(LogicResponse::ReviewSubmitted(res))?;
+            }
+
+            LogicCommand::GetAuditLog => {
+                tx.send(LogicResponse::AuditLog(audit::entries()))?;
+            }
+
+            LogicCommand::ExportAuditLog(path) => {
+                let res = audit::export(&path).await.map(|_| path).map_err(Into::into);
+                log::debug!("Exported audit log: {:?}", res);
+                tx.send(LogicResponse::AuditLogExported(res))?;
+            }
+
+            LogicCommand::WatchProgress => {
+                tokio::spawn(timing::watch(tx.clone()));
+                tokio::spawn(bandwidth::watch(tx.clone()));
+            }
+
+            LogicCommand::GetTimings => {
+                tx.send(LogicResponse::Timings(timing::recent()))?;
+            }
+
+            LogicCommand::ClearTimings => {
+                timing::clear();
+                tx.send(LogicResponse::Timings(Vec::new()))?;
+            }
+
+            LogicCommand::GetBandwidth => {
+                tx.send(LogicResponse::Bandwidth(bandwidth::usage()))?;
+            }
+
+            LogicCommand::CheckTools => {
+                tx.send(LogicResponse::Tools(onboarding::check_tools().await))?;
+            }
+
+            LogicCommand::SelfTest => {
+                tx.send(LogicResponse::SelfTest(
+                    onboarding::self_test(&client).await,
+                ))?;
+            }
+
+            LogicCommand::UpdateConfig(new_config) => {
+                if let Err(err) = new_config.save() {
+                    log::error!("Failed to save config: {:?}", err);
+                }
+                config::set_cache_dir(&new_config);
+                syntax::set_language_map(&new_config.languages);
+                config_tx.send_replace(new_config.clone());
+                config = new_config;
+            }
         }
     }
+
+    Ok(())
 }
 
 // I'm not sure how well Tokio will work if the main thread is blocking, so we do this on a separate thread
 // I could also be wrong, because I didn't test it, lmfao
-pub fn app_logic_thread(rx: flume::Receiver<LogicCommand>, tx: flume::Sender<LogicResponse>) {
-    let runtime = Runtime::new().expect("Unable to create the runtime");
-    runtime.block_on(async move {
-        if let Err(err) = app_logic_thread_inner(rx, tx).await {
-            log::error!("Logic thread error: {:?}", err);
-        }
-    });
+// Returns once the other side hangs up, or with the error that stopped it
+pub fn app_logic_thread(
+    rx: flume::Receiver<LogicCommand>,
+    tx: flume::Sender<LogicResponse>,
+) -> anyhow::Result<()> {
+    let runtime = Runtime::new().context("Unable to create the runtime")?;
+    let res = runtime.block_on(app_logic_thread_inner(rx, tx));
+    if let Err(err) = &res {
+        log::error!("Logic thread error: {:?}", err);
+    }
+    res
 }
@@ -0,0 +1,166 @@
+// Checks for the first-run setup, so a missing tool or a blocked network shows up before the first review
+use super::{
+    diff,
+    download::{claim_unique_temp_dir, get_url},
+    error::spawn_error,
+    git::git,
+    pr::ExtensionManifest,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::future::Future;
+use tokio::process::Command;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolCheck {
+    pub name: String,
+    // The version it reports, or why it couldn't be run
+    pub version: Result<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestStep {
+    pub name: String,
+    // What was found, or why the step failed
+    pub result: Result<String, String>,
+}
+
+async fn tool_version(program: &str) -> anyhow::Result<String> {
+    let mut cmd = Command::new(program);
+    let cmd = cmd.arg("--version");
+    #[cfg(target_os = "windows")]
+    let cmd = cmd.creation_flags(super::CREATE_NO_WINDOW);
+
+    let output = cmd
+        .output()
+        .await
+        .map_err(|err| spawn_error(program, err))
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!("{} exited with {}", program, output.status);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().next().unwrap_or_default().trim().to_string())
+}
+
+// git fetches the extensions' sources and difft diffs them, so neither is optional
+pub async fn check_tools() -> Vec<ToolCheck> {
+    let mut checks = Vec::new();
+    for program in ["git", "difft"] {
+        checks.push(ToolCheck {
+            name: program.to_string(),
+            version: tool_version(program)
+                .await
+                .map_err(|err| format!("{:#}", err)),
+        });
+    }
+    checks
+}
+
+async fn step<F>(steps: &mut Vec<SelfTestStep>, name: &str, run: F) -> Option<String>
+where
+    F: Future<Output = anyhow::Result<String>>,
+{
+    let result = run.await.map_err(|err| format!("{:#}", err));
+    log::debug!("Self-test {}: {:?}", name, result);
+    let ok = result.as_ref().ok().cloned();
+    steps.push(SelfTestStep {
+        name: name.to_string(),
+        result,
+    });
+    ok
+}
+
+// Each step needs the one before it, so this stops at the first failure
+async fn run_steps(client: &octocrab::Octocrab, steps: &mut Vec<SelfTestStep>) -> Option<()> {
+    // The most recently merged pull request, since its manifests are known to be valid
+    let mut merged = None;
+    step(steps, "Find a merged pull request", async {
+        let page = client
+            .pulls("moonlight-mod", "extensions")
+            .list()
+            .state(octocrab::params::State::Closed)
+            .sort(octocrab::params::pulls::Sort::Updated)
+            .direction(octocrab::params::Direction::Descending)
+            .per_page(20)
+            .send()
+            .await
+            .context("Failed to list pull requests")?;
+        let pr = page
+            .items
+            .into_iter()
+            .find(|pr| pr.merged_at.is_some() && pr.merge_commit_sha.is_some())
+            .context("No recently merged pull requests")?;
+        let files = client
+            .pulls("moonlight-mod", "extensions")
+            .list_files(pr.number)
+            .await
+            .context("Failed to list changed files")?
+            .take_items();
+        let path = files
+            .into_iter()
+            .map(|file| file.filename)
+            .find(|path| path.starts_with("exts/"))
+            .context("The pull request doesn't change any manifests")?;
+        let description = format!("#{}", pr.number);
+        merged = Some((pr.merge_commit_sha.unwrap_or_default(), path));
+        Ok(description)
+    })
+    .await?;
+    let (sha, path) = merged?;
+
+    let mut repository = None;
+    step(steps, "Read its manifest", async {
+        let url = format!(
+            "https://raw.githubusercontent.com/moonlight-mod/extensions/{}/{}",
+            sha, path
+        );
+        let data = get_url(client, &url).await?;
+        let manifest = serde_json::from_slice::<ExtensionManifest>(&data)
+            .context("Failed to parse manifest")?;
+        repository = Some(manifest.repository.clone());
+        Ok(format!("{} at {}", path, manifest.commit))
+    })
+    .await?;
+    let repository = repository?;
+
+    step(steps, "Reach the extension's repository", async {
+        let temp = std::env::temp_dir();
+        let refs = git(&temp, &["ls-remote", "--heads", "--", &repository]).await?;
+        let heads = String::from_utf8_lossy(&refs).lines().count();
+        Ok(format!("{} ({} branches)", repository, heads))
+    })
+    .await?;
+
+    step(steps, "Diff two files", async {
+        // Removed once the step is done
+        let dir = claim_unique_temp_dir("self-test").await?;
+        let old = dir.path().join("old.js");
+        let new = dir.path().join("new.js");
+        tokio::fs::write(&old, "const a = 1;\n")
+            .await
+            .context("Failed to write old file")?;
+        tokio::fs::write(&new, "const a = 2;\n")
+            .await
+            .context("Failed to write new file")?;
+        let diff = diff::get_diff_string(&old, &new)
+            .await
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+        if diff.trim().is_empty() {
+            anyhow::bail!("difft didn't find the change");
+        }
+        Ok("difft works".to_string())
+    })
+    .await?;
+
+    Some(())
+}
+
+// Goes through a review's steps on a public pull request, without touching the cache
+pub async fn self_test(client: &octocrab::Octocrab) -> Vec<SelfTestStep> {
+    let mut steps = Vec::new();
+    run_steps(client, &mut steps).await;
+    steps
+}
@@ -0,0 +1,53 @@
+// Where each extension in a pull request stands, so the breadth of a PR is visible before opening one
+use super::{
+    diff::{self, DiffedExtension},
+    scan::Severity,
+};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionStats {
+    pub source_files: usize,
+    pub asar_files: usize,
+    // Across the source diff, the .asar is mostly build output
+    pub added_lines: usize,
+    pub removed_lines: usize,
+    pub findings: usize,
+    pub dangerous_findings: usize,
+    pub downgrades: usize,
+}
+
+impl ExtensionStats {
+    pub fn new(diffed_extension: &DiffedExtension) -> Self {
+        let source = diff::summarize_directory(&diffed_extension.source_diff.dir).total;
+        Self {
+            source_files: diff::flatten_tree(&diffed_extension.source_diff.dir).len(),
+            asar_files: diff::flatten_tree(&diffed_extension.asar_diff.dir).len(),
+            added_lines: source.added_lines,
+            removed_lines: source.removed_lines,
+            findings: diffed_extension.findings.len(),
+            dangerous_findings: diffed_extension
+                .findings
+                .iter()
+                .filter(|finding| finding.severity == Severity::Danger)
+                .count(),
+            downgrades: diffed_extension.downgrades.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", content = "stats", rename_all = "camelCase")]
+pub enum OverviewState {
+    NotDownloaded,
+    Downloading,
+    // Diffing and scanning happen as part of the download, so these are done too
+    Diffed(ExtensionStats),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtensionOverview {
+    pub id: String,
+    pub state: OverviewState,
+}
@@ -0,0 +1,225 @@
+use super::{
+    diff::{self, Diff, FileState},
+    syntax,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+use tree_sitter::Node;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PatchReplacement {
+    pub matcher: String,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Patch {
+    pub path: String,
+    // 1-indexed
+    pub line: usize,
+    pub find: String,
+    pub replacements: Vec<PatchReplacement>,
+    // Anything else on the patch object, like `prerequisite`
+    pub extra: Vec<(String, String)>,
+}
+
+impl Patch {
+    fn same_contents(&self, other: &Patch) -> bool {
+        self.replacements == other.replacements && self.extra == other.extra
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PatchChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchChange {
+    pub find: String,
+    pub kind: PatchChangeKind,
+    pub old: Option<Patch>,
+    pub new: Option<Patch>,
+}
+
+fn text(node: Node, source: &str) -> String {
+    node.utf8_text(source.as_bytes())
+        .unwrap_or_default()
+        .to_string()
+}
+
+// `[...] as ExtensionWebExports["patches"]` and friends
+fn unwrap_expression(mut node: Node) -> Node {
+    while matches!(
+        node.kind(),
+        "as_expression" | "satisfies_expression" | "parenthesized_expression"
+    ) {
+        match node.named_child(0) {
+            Some(child) => node = child,
+            None => break,
+        }
+    }
+    node
+}
+
+fn object_pairs<'a>(node: Node<'a>, source: &str) -> Vec<(String, Node<'a>)> {
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .filter(|child| child.kind() == "pair")
+        .filter_map(|pair| {
+            let key = pair.child_by_field_name("key")?;
+            let value = pair.child_by_field_name("value")?;
+            Some((
+                text(key, source).trim_matches(['"', '\'']).to_string(),
+                value,
+            ))
+        })
+        .collect()
+}
+
+fn parse_replacement(node: Node, source: &str) -> Option<PatchReplacement> {
+    let node = unwrap_expression(node);
+    if node.kind() != "object" {
+        return None;
+    }
+
+    let pairs = object_pairs(node, source);
+    let get = |name: &str| {
+        pairs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| text(*value, source))
+            .unwrap_or_default()
+    };
+    Some(PatchReplacement {
+        matcher: get("match"),
+        replacement: get("replacement"),
+    })
+}
+
+fn parse_patch(node: Node, source: &str, path: &str) -> Option<Patch> {
+    let node = unwrap_expression(node);
+    if node.kind() != "object" {
+        return None;
+    }
+
+    let mut find = None;
+    let mut replacements = Vec::new();
+    let mut extra = Vec::new();
+    for (key, value) in object_pairs(node, source) {
+        match key.as_str() {
+            "find" => find = Some(text(value, source)),
+            "replace" => {
+                let value = unwrap_expression(value);
+                if value.kind() == "array" {
+                    let mut cursor = value.walk();
+                    replacements.extend(
+                        value
+                            .named_children(&mut cursor)
+                            .filter_map(|child| parse_replacement(child, source)),
+                    );
+                } else {
+                    replacements.extend(parse_replacement(value, source));
+                }
+            }
+            _ => extra.push((key, text(value, source))),
+        }
+    }
+
+    Some(Patch {
+        path: path.to_string(),
+        line: node.start_position().row + 1,
+        find: find?,
+        replacements,
+        extra,
+    })
+}
+
+fn collect_patches(node: Node, source: &str, path: &str, patches: &mut Vec<Patch>) {
+    if node.kind() == "variable_declarator" {
+        let name = node.child_by_field_name("name");
+        let value = node.child_by_field_name("value").map(unwrap_expression);
+        if let (Some(name), Some(value)) = (name, value) {
+            if text(name, source) == "patches" && value.kind() == "array" {
+                let mut cursor = value.walk();
+                patches.extend(
+                    value
+                        .named_children(&mut cursor)
+                        .filter_map(|child| parse_patch(child, source, path)),
+                );
+                return;
+            }
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_patches(child, source, path, patches);
+    }
+}
+
+async fn read_patches(dir: &Path, path: &str, patches: &mut Vec<Patch>) {
+    let full_path = dir.join(path);
+    let Ok(source) = tokio::fs::read_to_string(&full_path).await else {
+        return;
+    };
+    if let Some(tree) = syntax::parse(&full_path, &source) {
+        collect_patches(tree.root_node(), &source, path, patches);
+    }
+}
+
+// Patches are keyed by their `find`, numbered so duplicate finds still pair up in order
+fn key_patches(patches: Vec<Patch>) -> BTreeMap<(String, usize), Patch> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    patches
+        .into_iter()
+        .map(|patch| {
+            let count = counts.entry(patch.find.clone()).or_default();
+            *count += 1;
+            ((patch.find.clone(), *count), patch)
+        })
+        .collect()
+}
+
+// Only changed files can have changed patches, so unchanged files are skipped
+pub async fn diff_patches(diff: &Diff) -> Vec<PatchChange> {
+    let mut old_patches = Vec::new();
+    let mut new_patches = Vec::new();
+
+    for (path, state) in diff::flatten_tree(&diff.dir) {
+        if state != FileState::Added {
+            read_patches(&diff.old, &path, &mut old_patches).await;
+        }
+        if state != FileState::Removed {
+            read_patches(&diff.new, &path, &mut new_patches).await;
+        }
+    }
+
+    let mut old_patches = key_patches(old_patches);
+    let mut changes = Vec::new();
+    for (key, new) in key_patches(new_patches) {
+        let (kind, old) = match old_patches.remove(&key) {
+            None => (PatchChangeKind::Added, None),
+            Some(old) if old.same_contents(&new) => continue,
+            Some(old) => (PatchChangeKind::Changed, Some(old)),
+        };
+        changes.push(PatchChange {
+            find: key.0,
+            kind,
+            old,
+            new: Some(new),
+        });
+    }
+    for ((find, _), old) in old_patches {
+        changes.push(PatchChange {
+            find,
+            kind: PatchChangeKind::Removed,
+            old: Some(old),
+            new: None,
+        });
+    }
+
+    changes
+}
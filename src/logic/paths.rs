@@ -0,0 +1,257 @@
+// Turning .asar entry paths into paths we can write, which Windows is much pickier about
+use super::scan::{Finding, FindingCategory, Origin, Severity};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+// Device names Windows reserves in every directory, whatever the extension
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+const INVALID_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+// An entry written under a different name than it has in the .asar
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenamedPath {
+    pub original: String,
+    pub renamed: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum UnsafeReason {
+    // `..`, absolute paths, or drive prefixes
+    Traversal,
+    // Same path as another entry on case-insensitive filesystems
+    CaseCollision(String),
+    // Same path as another entry once renamed for Windows, e.g. `a?.js` and `a_.js`
+    NameCollision(String),
+}
+
+// An entry that wasn't extracted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnsafePath {
+    pub path: String,
+    pub reason: UnsafeReason,
+}
+
+impl UnsafePath {
+    pub fn finding(&self) -> Finding {
+        let (severity, rule, message) = match &self.reason {
+            UnsafeReason::Traversal => (
+                Severity::Danger,
+                "path-traversal",
+                "Entry points outside the .asar, so it wasn't extracted".to_string(),
+            ),
+            UnsafeReason::CaseCollision(other) => (
+                Severity::Warning,
+                "case-collision",
+                format!(
+                    "Entry only differs from {} by case, so it wasn't extracted",
+                    other
+                ),
+            ),
+            UnsafeReason::NameCollision(other) => (
+                Severity::Warning,
+                "name-collision",
+                format!(
+                    "Entry has the same name as {} once made safe for Windows, so it wasn't extracted",
+                    other
+                ),
+            ),
+        };
+        Finding {
+            category: FindingCategory::UnsafePath,
+            severity,
+            rule: rule.to_string(),
+            message,
+            origin: Origin::Asar,
+            path: self.path.clone(),
+            line: None,
+            snippet: String::new(),
+        }
+    }
+}
+
+// Both separators count, since Windows treats `\` as one
+pub fn is_traversal(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    let drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    drive
+        || path.starts_with(['/', '\\'])
+        || path
+            .split(['/', '\\'])
+            .any(|component| component == ".." || component == ".")
+}
+
+// Tracks written paths to catch entries that would overwrite each other on Windows and macOS,
+// by the path they're written to and the name they had in the .asar
+#[derive(Default)]
+pub struct CollisionCheck {
+    seen: HashMap<String, String>,
+}
+
+impl CollisionCheck {
+    // Returns how this entry collides with an earlier one. `relative` is `original` after
+    // `sanitize_relative`, which keeps the components lined up.
+    pub fn insert(&mut self, original: &str, relative: &str) -> Option<UnsafeReason> {
+        // Parent directories collide too, e.g. `Lib/a.js` and `lib/b.js` end up in one folder
+        let mut parent = String::new();
+        let mut original_parent = String::new();
+        for (component, original_component) in relative.split('/').zip(original.split('/')) {
+            if !parent.is_empty() {
+                parent.push('/');
+                original_parent.push('/');
+            }
+            parent.push_str(component);
+            original_parent.push_str(original_component);
+            let key = parent.to_lowercase();
+            match self.seen.get(&key) {
+                // Anything else that ends up here, whether it differs by case or was renamed
+                Some(other) if *other != original_parent => {
+                    let other = other.clone();
+                    return Some(if other.to_lowercase() == original_parent.to_lowercase() {
+                        UnsafeReason::CaseCollision(other)
+                    } else {
+                        UnsafeReason::NameCollision(other)
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    self.seen.insert(key, original_parent.clone());
+                }
+            }
+        }
+        None
+    }
+}
+
+fn is_reserved(component: &str) -> bool {
+    let stem = component.split('.').next().unwrap_or_default();
+    RESERVED_NAMES.contains(&stem.trim_end().to_lowercase().as_str())
+}
+
+fn sanitize_component(component: &str) -> String {
+    let mut sanitized = component
+        .chars()
+        .map(|c| {
+            if c.is_control() || INVALID_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>();
+
+    // `aux.js` -> `aux_.js`
+    if is_reserved(&sanitized) {
+        let stem_len = sanitized.find('.').unwrap_or(sanitized.len());
+        sanitized.insert(stem_len, '_');
+    }
+    // Windows silently drops trailing dots and spaces, so `a.` and `a` would be the same file
+    if sanitized.ends_with(['.', ' ']) {
+        sanitized.push('_');
+    }
+    sanitized
+}
+
+// The path an entry is written to, `/`-separated. Unchanged outside of Windows.
+pub fn sanitize_relative(path: &str) -> String {
+    if !cfg!(windows) {
+        return path.to_string();
+    }
+    path.split('/')
+        .map(sanitize_component)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Pushes each component separately, since extended-length paths don't accept `/`
+pub fn join_relative(dir: &Path, relative: &str) -> PathBuf {
+    let mut path = dir.to_path_buf();
+    for component in relative
+        .split('/')
+        .filter(|component| !component.is_empty())
+    {
+        path.push(component);
+    }
+    path
+}
+
+// Lifts MAX_PATH on Windows with a `\\?\` prefix. Needs an absolute path.
+pub fn extended_length(path: &Path) -> PathBuf {
+    if !cfg!(windows) || !path.is_absolute() {
+        return path.to_path_buf();
+    }
+    let text = path.to_string_lossy();
+    if text.starts_with(r"\\?\") {
+        path.to_path_buf()
+    } else if let Some(unc) = text.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{}", unc))
+    } else {
+        PathBuf::from(format!(r"\\?\{}", text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizes_components() {
+        assert_eq!(sanitize_component("index.js"), "index.js");
+        assert_eq!(sanitize_component("a?b:c.js"), "a_b_c.js");
+        assert_eq!(sanitize_component("aux.js"), "aux_.js");
+        assert_eq!(sanitize_component("CON"), "CON_");
+        assert_eq!(sanitize_component("com1.tar.gz"), "com1_.tar.gz");
+        assert_eq!(sanitize_component("console.js"), "console.js");
+        assert_eq!(sanitize_component("a."), "a._");
+        assert_eq!(sanitize_component("a "), "a _");
+        assert_eq!(sanitize_component("a\u{1}b"), "a_b");
+    }
+
+    #[test]
+    fn detects_traversal() {
+        assert!(is_traversal("../a.js"));
+        assert!(is_traversal("a/../../b.js"));
+        assert!(is_traversal("a\\..\\b.js"));
+        assert!(is_traversal("/etc/passwd"));
+        assert!(is_traversal("\\server\\share"));
+        assert!(is_traversal("C:/a.js"));
+        assert!(is_traversal("./a.js"));
+        assert!(!is_traversal("a/b.js"));
+        assert!(!is_traversal("..a/b.js"));
+    }
+
+    #[test]
+    fn detects_case_collisions() {
+        let mut collisions = CollisionCheck::default();
+        assert_eq!(collisions.insert("Lib/a.js", "Lib/a.js"), None);
+        assert_eq!(collisions.insert("Lib/b.js", "Lib/b.js"), None);
+        assert_eq!(
+            collisions.insert("lib/c.js", "lib/c.js"),
+            Some(UnsafeReason::CaseCollision("Lib".to_string()))
+        );
+        assert_eq!(
+            collisions.insert("Lib/A.js", "Lib/A.js"),
+            Some(UnsafeReason::CaseCollision("Lib/a.js".to_string()))
+        );
+    }
+
+    #[test]
+    fn detects_sanitized_collisions() {
+        let mut collisions = CollisionCheck::default();
+        assert_eq!(collisions.insert("a_.js", "a_.js"), None);
+        assert_eq!(
+            collisions.insert("a?.js", &sanitize_component("a?.js")),
+            Some(UnsafeReason::NameCollision("a_.js".to_string()))
+        );
+        assert_eq!(collisions.insert("aux_/a.js", "aux_/a.js"), None);
+        assert_eq!(
+            collisions.insert("aux/b.js", "aux_/b.js"),
+            Some(UnsafeReason::NameCollision("aux_".to_string()))
+        );
+    }
+}
@@ -0,0 +1,135 @@
+// Review policy kept in the extensions repository as robojules.toml, so maintainers can change it
+// without a new release
+use super::{
+    config::Config,
+    download::get_url,
+    error::HttpStatus,
+    pr::{CiStatus, PullRequestCheck},
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// The default branch rather than the pull request's head, so a pull request can't loosen its own review
+const POLICY_URL: &str =
+    "https://raw.githubusercontent.com/moonlight-mod/extensions/HEAD/robojules.toml";
+const POLICY_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+static POLICY: Mutex<Option<(Instant, ReviewPolicy)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReviewPolicy {
+    // On top of the allowlist in the settings
+    pub allowed_domains: Vec<String>,
+    // Globs for files that only get danger findings, e.g. "**/*.map"
+    pub ignore: Vec<String>,
+    // Check runs that have to pass before approving
+    pub required_checks: Vec<String>,
+}
+
+impl ReviewPolicy {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        toml::from_str(text).context("Failed to parse robojules.toml")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // The settings with the policy's domains allowed too
+    pub fn apply(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        for domain in &self.allowed_domains {
+            if !config.allowed_domains.contains(domain) {
+                config.allowed_domains.push(domain.clone());
+            }
+        }
+        config
+    }
+
+    pub fn is_ignored(&self, path: &str) -> bool {
+        self.ignore
+            .iter()
+            .any(|pattern| glob_match(pattern.as_bytes(), path.as_bytes()))
+    }
+
+    // Required checks that haven't passed, including ones that never ran
+    pub fn missing_checks(&self, checks: &[PullRequestCheck]) -> Vec<String> {
+        self.required_checks
+            .iter()
+            .filter(|required| {
+                !checks
+                    .iter()
+                    .any(|check| check.name == **required && check.status == CiStatus::Success)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+// `*` and `?` stay within a path segment, `**/` matches any number of directories
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob_match(rest, path)
+                || path
+                    .iter()
+                    .position(|c| *c == b'/')
+                    .is_some_and(|slash| glob_match(pattern, &path[slash + 1..]))
+        }
+        [b'*', b'*'] => true,
+        [b'*', rest @ ..] => {
+            glob_match(rest, path)
+                || (path.first().is_some_and(|c| *c != b'/') && glob_match(pattern, &path[1..]))
+        }
+        [b'?', rest @ ..] => {
+            path.first().is_some_and(|c| *c != b'/') && glob_match(rest, &path[1..])
+        }
+        [c, rest @ ..] => path.first() == Some(c) && glob_match(rest, &path[1..]),
+    }
+}
+
+async fn fetch(client: &octocrab::Octocrab) -> anyhow::Result<ReviewPolicy> {
+    match get_url(client, POLICY_URL).await {
+        Ok(data) => {
+            let text = std::str::from_utf8(&data).context("robojules.toml isn't UTF-8")?;
+            ReviewPolicy::parse(text)
+        }
+        // The repository doesn't have one, so the settings are all there is
+        Err(err)
+            if err
+                .downcast_ref::<HttpStatus>()
+                .is_some_and(|status| status.status == 404) =>
+        {
+            Ok(ReviewPolicy::default())
+        }
+        Err(err) => Err(err).context("Failed to download robojules.toml"),
+    }
+}
+
+// Cached for a few minutes, since every extension in a pull request asks for it
+pub async fn get(client: &octocrab::Octocrab) -> anyhow::Result<ReviewPolicy> {
+    if let Some((fetched, policy)) = &*POLICY.lock().unwrap_or_else(|err| err.into_inner()) {
+        if fetched.elapsed() < POLICY_MAX_AGE {
+            return Ok(policy.clone());
+        }
+    }
+
+    let policy = fetch(client).await?;
+    log::debug!("Review policy: {:?}", policy);
+    *POLICY.lock().unwrap_or_else(|err| err.into_inner()) = Some((Instant::now(), policy.clone()));
+    Ok(policy)
+}
+
+// Without the policy, reviews fall back to the settings alone, which is only ever stricter
+pub async fn get_or_default(client: &octocrab::Octocrab) -> ReviewPolicy {
+    get(client).await.unwrap_or_else(|err| {
+        log::warn!("Failed to get the review policy: {:#}", err);
+        ReviewPolicy::default()
+    })
+}
@@ -1,22 +1,28 @@
 use super::{
+    audit::{self, AuditAction},
+    bandwidth::{self, DownloadKind},
+    config::ArtifactConfig,
     diff::{ModifiedExtension, PullRequestUpdate},
-    LogicResult,
+    error, policy, space, timing, LogicResult,
 };
 use crate::logic::download::get_url;
 use anyhow::Context;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Deserialize)]
-struct ExtensionManifest {
-    repository: String,
-    commit: String,
+pub(crate) struct ExtensionManifest {
+    pub repository: String,
+    pub commit: String,
 }
 
 pub async fn get_pull_request(
     client: &octocrab::Octocrab,
     num: u64,
+    artifacts: &ArtifactConfig,
 ) -> LogicResult<PullRequestUpdate> {
     log::debug!("Getting pull request {}", num);
+    let timer = timing::start(format!("Get pull request {}", num));
 
     let pr = client
         .pulls("moonlight-mod", "extensions")
@@ -47,23 +53,31 @@ pub async fn get_pull_request(
                 .trim_start_matches("exts/")
                 .trim_end_matches(".json");
 
-            let old = get_url(client, &old)
+            let old_url = old;
+            let old = get_url(client, &old_url)
                 .await
                 .context("Failed to download old file")?;
+            audit::record_download(&old_url, &old);
+            bandwidth::record(Some(num), DownloadKind::Other, old.len() as u64);
             let old = std::str::from_utf8(&old).context("Failed to parse old file")?;
             let old = serde_json::from_str::<ExtensionManifest>(old)
                 .context("Failed to parse old manifest")?;
 
-            let new = get_url(client, &new)
+            let new_url = new;
+            let new = get_url(client, &new_url)
                 .await
                 .context("Failed to download new file")?;
+            audit::record_download(&new_url, &new);
+            bandwidth::record(Some(num), DownloadKind::Other, new.len() as u64);
             let new = std::str::from_utf8(&new).context("Failed to parse new file")?;
             let new = serde_json::from_str::<ExtensionManifest>(new)
                 .context("Failed to parse new manifest")?;
 
+            let new_repository = (new.repository != old.repository).then_some(new.repository);
             extensions.push(ModifiedExtension {
                 id: ext_id.to_string(),
                 repository: old.repository,
+                new_repository,
                 old_commit: old.commit,
                 new_commit: new.commit,
             });
@@ -88,7 +102,7 @@ pub async fn get_pull_request(
         })
         .context("No run found for PR")?;
 
-    let artifacts = client
+    let run_artifacts = client
         .actions()
         .list_workflow_run_artifacts("moonlight-mod", "extensions", run.id)
         .send()
@@ -97,15 +111,195 @@ pub async fn get_pull_request(
         .value
         .context("No artifacts for run")?
         .take_items();
-    let artifact = artifacts.first().context("No artifacts for run")?;
+    let artifact = run_artifacts.first().context("No artifacts for run")?;
+    // Only context for the reviewer, so the review can go ahead without them
+    let checks = get_checks(client, &pr.head.sha)
+        .await
+        .unwrap_or_else(|err| {
+            log::warn!("Failed to get checks for {}: {:#}", pr.head.sha, err);
+            Vec::new()
+        });
+    let policy = policy::get_or_default(client).await;
+    timer.finish();
+
+    // GitHub only serves the artifact itself to authenticated requests, otherwise it goes through
+    // the mirror. nightly.link is trustworthy, and the default.
+    let artifact_url = if artifacts.direct && error::is_authenticated() {
+        artifact.archive_download_url.to_string()
+    } else {
+        artifacts.url(run.id.into_inner(), &artifact.name)
+    };
+    space::record_artifact_size(&artifact_url, artifact.size_in_bytes as u64);
+    audit::record(AuditAction::PullRequestFetched {
+        number: num,
+        base_sha: pr.base.sha.clone(),
+        head_sha: pr.head.sha.clone(),
+        run_id: run.id.into_inner(),
+        artifact_url: artifact_url.clone(),
+        extensions: extensions.iter().map(|ext| ext.id.clone()).collect(),
+    });
 
     Ok(PullRequestUpdate {
+        number: num,
         extensions,
-        // The actual artifact URL requires you to be authenticated, so we can't use it
-        // nightly.link is trustworthy
-        artifact_url: format!(
-            "https://nightly.link/moonlight-mod/extensions/actions/runs/{}/{}.zip",
-            run.id, artifact.name
-        ),
+        artifact_url,
+        head_sha: pr.head.sha,
+        title: pr.title.unwrap_or_default(),
+        body: pr.body.unwrap_or_default(),
+        author: pr.user.map(|user| user.login).unwrap_or_default(),
+        draft: pr.draft.unwrap_or_default(),
+        labels: pr
+            .labels
+            .unwrap_or_default()
+            .into_iter()
+            .map(|label| PullRequestLabel {
+                name: label.name,
+                color: label.color,
+            })
+            .collect(),
+        checks,
+        policy,
     })
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullRequestLabel {
+    pub name: String,
+    // Hex without the leading #
+    pub color: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequestCheck {
+    pub name: String,
+    pub status: CiStatus,
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CheckRunList {
+    check_runs: Vec<CheckRunItem>,
+}
+
+#[derive(Deserialize)]
+struct CheckRunItem {
+    name: String,
+    status: String,
+    conclusion: Option<String>,
+    html_url: Option<String>,
+}
+
+async fn get_checks(
+    client: &octocrab::Octocrab,
+    head_sha: &str,
+) -> anyhow::Result<Vec<PullRequestCheck>> {
+    let route = format!(
+        "/repos/moonlight-mod/extensions/commits/{}/check-runs?per_page=100",
+        head_sha
+    );
+    let list: CheckRunList = client
+        .get(route, None::<&()>)
+        .await
+        .context("Failed to list check runs")?;
+    let mut checks = list
+        .check_runs
+        .into_iter()
+        .map(|run| PullRequestCheck {
+            status: match (run.status.as_str(), run.conclusion.as_deref()) {
+                ("completed", Some("success" | "neutral" | "skipped")) => CiStatus::Success,
+                ("completed", _) => CiStatus::Failure,
+                _ => CiStatus::Pending,
+            },
+            name: run.name,
+            url: run.html_url,
+        })
+        .collect::<Vec<_>>();
+    checks.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(checks)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CiStatus {
+    Pending,
+    Success,
+    Failure,
+    // No run for the head commit among the recent ones
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequestSummary {
+    pub number: u64,
+    pub title: String,
+    pub author: String,
+    // Seconds since the Unix epoch
+    pub created_at: i64,
+    pub draft: bool,
+    pub ci: CiStatus,
+}
+
+// Open pull requests, newest first, with the status of the workflow that builds their artifacts
+pub async fn list_pull_requests(
+    client: &octocrab::Octocrab,
+) -> LogicResult<Vec<PullRequestSummary>> {
+    log::debug!("Listing open pull requests");
+    let timer = timing::start("List pull requests");
+    let page = client
+        .pulls("moonlight-mod", "extensions")
+        .list()
+        .state(octocrab::params::State::Open)
+        .per_page(100)
+        .send()
+        .await
+        .context("Failed to list open pull requests")?;
+    let prs = client
+        .all_pages(page)
+        .await
+        .context("Failed to list open pull requests")?;
+
+    // One page of recent runs covers the open pull requests without a request for each
+    let runs = client
+        .workflows("moonlight-mod", "extensions")
+        .list_runs("pull_request.yml")
+        .event("pull_request")
+        .per_page(100)
+        .send()
+        .await
+        .context("Getting workflows failed")?
+        .take_items();
+    let mut statuses = HashMap::new();
+    // Newest first, so a rerun wins over the run it replaced
+    for run in &runs {
+        statuses.entry(run.head_sha.as_str()).or_insert_with(|| {
+            match (run.status.as_str(), run.conclusion.as_deref()) {
+                ("completed", Some("success")) => CiStatus::Success,
+                ("completed", _) => CiStatus::Failure,
+                _ => CiStatus::Pending,
+            }
+        });
+    }
+
+    let mut summaries = prs
+        .into_iter()
+        .map(|pr| PullRequestSummary {
+            number: pr.number,
+            ci: statuses
+                .get(pr.head.sha.as_str())
+                .copied()
+                .unwrap_or(CiStatus::Unknown),
+            title: pr.title.unwrap_or_default(),
+            author: pr.user.map(|user| user.login).unwrap_or_default(),
+            created_at: pr
+                .created_at
+                .map(|time| time.timestamp())
+                .unwrap_or_default(),
+            draft: pr.draft.unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+    summaries.sort_by(|a, b| b.number.cmp(&a.number));
+    timer.finish();
+    Ok(summaries)
+}
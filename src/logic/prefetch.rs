@@ -0,0 +1,240 @@
+// Downloads and diffs pull requests in the background, so they open instantly later
+use super::{
+    config::Config,
+    diff::{DiffedExtension, ModifiedExtension},
+    download,
+    overview::{ExtensionStats, OverviewState},
+    pr, LogicResponse, LogicResult,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+type Slot = Arc<tokio::sync::Mutex<Option<DiffedExtension>>>;
+
+// Diffed extensions by commits and artifact, shared by the logic loop and the prefetch worker
+#[derive(Clone, Default)]
+pub struct DiffCache {
+    slots: Arc<Mutex<HashMap<String, Slot>>>,
+}
+
+impl DiffCache {
+    fn slot(&self, ext: &ModifiedExtension, artifact_url: &str) -> Slot {
+        let key = format!(
+            "{}:{}:{}:{}",
+            ext.id, ext.old_commit, ext.new_commit, artifact_url
+        );
+        let mut slots = self.slots.lock().unwrap_or_else(|err| err.into_inner());
+        slots.entry(key).or_default().clone()
+    }
+
+    // Waits for a prefetch of the same extension instead of downloading it twice
+    pub async fn download_extension(
+        &self,
+        client: &octocrab::Octocrab,
+        ext: &ModifiedExtension,
+        artifact_url: &str,
+        pull_request: u64,
+        head_sha: &str,
+        config: &Config,
+    ) -> LogicResult<DiffedExtension> {
+        let slot = self.slot(ext, artifact_url);
+        let mut slot = slot.lock().await;
+        if let Some(diffed) = &*slot {
+            log::debug!("Using prefetched {}", ext.id);
+            return Ok(diffed.clone());
+        }
+
+        let diffed =
+            download::download_extension(client, ext, artifact_url, pull_request, head_sha, config)
+                .await?;
+        *slot = Some(diffed.clone());
+        Ok(diffed)
+    }
+
+    // Without waiting, a held slot means a download is running
+    pub fn overview(&self, ext: &ModifiedExtension, artifact_url: &str) -> OverviewState {
+        let slot = self.slot(ext, artifact_url);
+        let Ok(slot) = slot.try_lock() else {
+            return OverviewState::Downloading;
+        };
+        match &*slot {
+            Some(diffed) => OverviewState::Diffed(ExtensionStats::new(diffed)),
+            None => OverviewState::NotDownloaded,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum PrefetchState {
+    Queued,
+    Running,
+    Done,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefetchItem {
+    pub number: u64,
+    pub state: PrefetchState,
+    // Extensions downloaded so far, out of `total`
+    pub done: usize,
+    pub total: usize,
+    // IDs of the extensions the pull request touches, once it's been fetched
+    pub extensions: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum PrefetchRequest {
+    PullRequests(Vec<u64>),
+    AllOpen,
+}
+
+pub async fn open_pull_requests(client: &octocrab::Octocrab) -> anyhow::Result<Vec<u64>> {
+    let page = client
+        .pulls("moonlight-mod", "extensions")
+        .list()
+        .state(octocrab::params::State::Open)
+        .per_page(100)
+        .send()
+        .await
+        .context("Failed to list open pull requests")?;
+    let prs = client
+        .all_pages(page)
+        .await
+        .context("Failed to list open pull requests")?;
+    Ok(prs.into_iter().map(|pr| pr.number).collect())
+}
+
+struct Worker {
+    client: octocrab::Octocrab,
+    cache: DiffCache,
+    tx: flume::Sender<LogicResponse>,
+    items: Vec<PrefetchItem>,
+    queue: VecDeque<(u64, Config)>,
+}
+
+impl Worker {
+    fn update(&mut self, number: u64, update: impl FnOnce(&mut PrefetchItem)) {
+        if let Some(item) = self.items.iter_mut().find(|item| item.number == number) {
+            update(item);
+        }
+        // Nobody listening just means the UI is gone
+        let _ = self
+            .tx
+            .send(LogicResponse::PrefetchStatus(self.items.clone()));
+    }
+
+    // Finished and failed pull requests are fetched again, in case they changed
+    async fn enqueue(&mut self, request: PrefetchRequest, config: Config) {
+        let numbers = match request {
+            PrefetchRequest::PullRequests(numbers) => numbers,
+            PrefetchRequest::AllOpen => match open_pull_requests(&self.client).await {
+                Ok(numbers) => numbers,
+                Err(err) => {
+                    log::warn!("{:?}", err);
+                    return;
+                }
+            },
+        };
+
+        for number in numbers {
+            match self.items.iter_mut().find(|item| item.number == number) {
+                Some(item)
+                    if matches!(item.state, PrefetchState::Queued | PrefetchState::Running) =>
+                {
+                    continue
+                }
+                Some(item) => {
+                    item.state = PrefetchState::Queued;
+                    item.done = 0;
+                }
+                None => self.items.push(PrefetchItem {
+                    number,
+                    state: PrefetchState::Queued,
+                    done: 0,
+                    total: 0,
+                    extensions: Vec::new(),
+                }),
+            }
+            self.queue.push_back((number, config.clone()));
+        }
+        let _ = self
+            .tx
+            .send(LogicResponse::PrefetchStatus(self.items.clone()));
+    }
+
+    async fn prefetch(&mut self, number: u64, config: &Config) -> anyhow::Result<()> {
+        self.update(number, |item| item.state = PrefetchState::Running);
+        let update = pr::get_pull_request(&self.client, number, &config.artifacts)
+            .await
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+        self.update(number, |item| {
+            item.total = update.extensions.len();
+            item.extensions = update.extensions.iter().map(|ext| ext.id.clone()).collect();
+        });
+
+        for ext in &update.extensions {
+            self.cache
+                .download_extension(
+                    &self.client,
+                    ext,
+                    &update.artifact_url,
+                    number,
+                    &update.head_sha,
+                    config,
+                )
+                .await
+                .map_err(|err| anyhow::anyhow!("{}", err))
+                .with_context(|| format!("Failed to prefetch {}", ext.id))?;
+            self.update(number, |item| item.done += 1);
+        }
+        Ok(())
+    }
+}
+
+// Handles requests one pull request at a time, alongside the logic loop
+pub async fn run_worker(
+    client: octocrab::Octocrab,
+    cache: DiffCache,
+    rx: flume::Receiver<(PrefetchRequest, Config)>,
+    tx: flume::Sender<LogicResponse>,
+) {
+    let mut worker = Worker {
+        client,
+        cache,
+        tx,
+        items: Vec::new(),
+        queue: VecDeque::new(),
+    };
+
+    loop {
+        // Pick up new requests between pull requests, so they show up as queued right away
+        while let Ok((request, config)) = rx.try_recv() {
+            worker.enqueue(request, config).await;
+        }
+
+        let Some((number, config)) = worker.queue.pop_front() else {
+            match rx.recv_async().await {
+                Ok((request, config)) => {
+                    worker.enqueue(request, config).await;
+                    continue;
+                }
+                Err(_) => return,
+            }
+        };
+
+        log::debug!("Prefetching pull request {}", number);
+        let state = match worker.prefetch(number, &config).await {
+            Ok(()) => PrefetchState::Done,
+            Err(err) => {
+                log::warn!("Failed to prefetch {}: {:?}", number, err);
+                PrefetchState::Failed(format!("{:#}", err))
+            }
+        };
+        worker.update(number, |item| item.state = state);
+    }
+}
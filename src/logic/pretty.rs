@@ -0,0 +1,190 @@
+// Lays minified bundles out one statement per line before they're diffed, so a one-character change
+// in the .asar doesn't light up a line ten thousand characters long
+use super::{
+    download::{claim_unique_temp_dir, TempDir},
+    syntax,
+};
+use anyhow::Context;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+// Hand-written code rarely has lines this long, bundles almost always do
+const MINIFIED_LINE_LEN: usize = 500;
+const INDENT: &str = "  ";
+
+// Kept as written, including any newlines inside them
+const ATOMIC_KINDS: [&str; 5] = ["string", "template_string", "regex", "comment", "jsx_text"];
+
+fn looks_minified(text: &str) -> bool {
+    text.lines().any(|line| line.len() > MINIFIED_LINE_LEN)
+}
+
+// The leaves of the tree, walked without recursion since bundles nest deeply
+fn tokens(tree: &tree_sitter::Tree) -> Vec<Range<usize>> {
+    let mut tokens = Vec::new();
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.child_count() == 0 || ATOMIC_KINDS.contains(&node.kind()) {
+            if !node.byte_range().is_empty() {
+                tokens.push(node.byte_range());
+            }
+        } else if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return tokens;
+            }
+        }
+    }
+}
+
+// Tokens that stay on the same line as a closing brace, e.g. `} else {` or `})`
+fn follows_brace(token: &str) -> bool {
+    matches!(
+        token,
+        ")" | "]" | "," | ";" | "." | "?." | "(" | "else" | "catch" | "finally" | "while"
+    )
+}
+
+// Only adds line breaks after `;`, `{` and `}`, where a newline can't change what the code means. The
+// original's line breaks are kept since automatic semicolons and line comments depend on them, and
+// tokens that were apart stay apart. None when the file doesn't parse cleanly, rather than risk moving
+// code around.
+pub fn pretty_print(path: &Path, source: &str) -> Option<String> {
+    let tree = syntax::parse(path, source)?;
+    if tree.root_node().has_error() {
+        return None;
+    }
+    let tokens = tokens(&tree);
+
+    let mut out = String::with_capacity(source.len() + source.len() / 4);
+    let mut indent = 0usize;
+    // Parentheses and brackets open at each brace level, since `;` inside `for (…)` isn't a statement end
+    let mut depths = vec![0usize];
+    let mut line_start = true;
+    let mut newline = false;
+    let mut prev: Option<&Range<usize>> = None;
+
+    for (i, range) in tokens.iter().enumerate() {
+        let token = &source[range.clone()];
+        let next = tokens.get(i + 1).map(|next| &source[next.clone()]);
+
+        if token == "}" {
+            indent = indent.saturating_sub(1);
+            if depths.len() > 1 {
+                depths.pop();
+            }
+            // `{}` stays together
+            if !matches!(prev, Some(prev) if &source[prev.clone()] == "{") {
+                newline = true;
+            }
+        }
+        if let Some(prev) = prev {
+            if source[prev.end..range.start].contains('\n') {
+                newline = true;
+            }
+        }
+        if newline && !line_start {
+            out.push('\n');
+            line_start = true;
+        }
+        newline = false;
+
+        if line_start {
+            out.extend(std::iter::repeat(INDENT).take(indent));
+        } else if prev.is_some_and(|prev| prev.end != range.start) {
+            out.push(' ');
+        }
+        out.push_str(token);
+        line_start = false;
+
+        let depth = depths.last_mut().unwrap();
+        match token {
+            "(" | "[" => *depth += 1,
+            ")" | "]" => *depth = depth.saturating_sub(1),
+            ";" => newline = *depth == 0,
+            "{" => {
+                depths.push(0);
+                indent += 1;
+                newline = next != Some("}");
+            }
+            "}" => newline = !next.is_some_and(follows_brace),
+            _ => {}
+        }
+        prev = Some(range);
+    }
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+    Some(out)
+}
+
+async fn read_side(path: &Path) -> Option<String> {
+    if !path.exists() {
+        return Some(String::new());
+    }
+    let data = tokio::fs::read(path).await.ok()?;
+    String::from_utf8(data).ok()
+}
+
+async fn write_side(dir: &Path, original: &Path, text: Option<String>) -> anyhow::Result<PathBuf> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .context("Failed to create pretty-print dir")?;
+    // Same file name, so the language is still picked by extension
+    let path = dir.join(original.file_name().context("No file name")?);
+    // A missing side stays missing, so the file still shows as added or deleted
+    if let Some(text) = text {
+        tokio::fs::write(&path, text)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(path)
+}
+
+// Formatted copies of a minified JavaScript file's two versions, or None when it isn't one. Both sides
+// are formatted or neither is, so the diff never compares formatted code against minified code.
+// The formatted files are removed once the returned dir is dropped.
+pub async fn pretty_pair(
+    old: &Path,
+    new: &Path,
+) -> anyhow::Result<Option<(TempDir, PathBuf, PathBuf)>> {
+    let is_javascript = new
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(syntax::lang_name_from_file_ext)
+        == Some("javascript");
+    if !is_javascript {
+        return Ok(None);
+    }
+    let (Some(old_text), Some(new_text)) = (read_side(old).await, read_side(new).await) else {
+        return Ok(None);
+    };
+    if !looks_minified(&old_text) && !looks_minified(&new_text) {
+        return Ok(None);
+    }
+    let (old_exists, new_exists) = (old.exists(), new.exists());
+
+    // Parsing a bundle takes a while, so it's kept off the runtime
+    let (old_path, new_path) = (old.to_path_buf(), new.to_path_buf());
+    let formatted = tokio::task::spawn_blocking(move || {
+        let old = pretty_print(&old_path, &old_text)?;
+        let new = pretty_print(&new_path, &new_text)?;
+        Some((old, new))
+    })
+    .await
+    .context("Pretty-print task panicked")?;
+    let Some((old_text, new_text)) = formatted else {
+        log::debug!("{} didn't parse cleanly, diffing it as is", new.display());
+        return Ok(None);
+    };
+
+    let dir = claim_unique_temp_dir("pretty").await?;
+    let old = write_side(&dir.path().join("old"), old, old_exists.then_some(old_text)).await?;
+    let new = write_side(&dir.path().join("new"), new, new_exists.then_some(new_text)).await?;
+    Ok(Some((dir, old, new)))
+}
@@ -0,0 +1,334 @@
+use super::{
+    bundle,
+    diff::{self, Diff, FileState},
+    scan::{Finding, FindingCategory, Origin, Severity},
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum ProvenanceMethod {
+    SourceMap,
+    ModuleTable,
+    FileName,
+}
+
+impl ProvenanceMethod {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::SourceMap => "sourcemap",
+            Self::ModuleTable => "webpack module IDs",
+            Self::FileName => "file name",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Provenance {
+    // Repository files the dist file was plausibly built from
+    pub sources: BTreeSet<String>,
+    // npm packages bundled into it
+    pub dependencies: BTreeSet<String>,
+    // How the above were worked out, strongest first
+    pub methods: BTreeSet<ProvenanceMethod>,
+}
+
+impl Provenance {
+    pub fn is_known(&self) -> bool {
+        !self.sources.is_empty() || !self.dependencies.is_empty()
+    }
+
+    // e.g. "built from src/index.ts + 3 deps"
+    pub fn summary(&self) -> String {
+        if !self.is_known() {
+            return "no known origin".to_string();
+        }
+
+        let mut parts = Vec::new();
+        match self.sources.len() {
+            0 => {}
+            1 => parts.push(self.sources.first().cloned().unwrap_or_default()),
+            n => parts.push(format!("{} source files", n)),
+        }
+        match self.dependencies.len() {
+            0 => {}
+            1 => parts.push("1 dep".to_string()),
+            n => parts.push(format!("{} deps", n)),
+        }
+        format!("built from {}", parts.join(" + "))
+    }
+
+    fn add_source(&mut self, source: &str, repo_files: &[String], method: ProvenanceMethod) {
+        if let Some(package) = package_name(source) {
+            self.dependencies.insert(package);
+            self.methods.insert(method);
+            return;
+        }
+
+        let source = normalize_source(source);
+        if source.is_empty() {
+            return;
+        }
+        let before = self.sources.len();
+        self.sources.extend(
+            repo_files
+                .iter()
+                .filter(|file| matches_repo_file(file, &source))
+                .cloned(),
+        );
+        if self.sources.len() > before {
+            self.methods.insert(method);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SourceMap {
+    #[serde(default)]
+    sources: Vec<String>,
+}
+
+// Every file in the repository, relative and `/`-separated, skipping .git and node_modules
+pub async fn list_files(root: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    let mut stack = vec![PathBuf::new()];
+
+    while let Some(relative) = stack.pop() {
+        let Ok(mut entries) = tokio::fs::read_dir(root.join(&relative)).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name();
+            if name == ".git" || name == "node_modules" {
+                continue;
+            }
+            let path = relative.join(&name);
+            if entry.file_type().await.is_ok_and(|ty| ty.is_dir()) {
+                stack.push(path);
+            } else {
+                files.push(path.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+fn strip_extensions(path: &str) -> &str {
+    let name_start = path.rfind('/').map(|i| i + 1).unwrap_or_default();
+    match path[name_start..].find('.') {
+        // Keep dotfiles intact
+        Some(0) | None => path,
+        Some(i) => &path[..name_start + i],
+    }
+}
+
+// `webpack://ext/./src/index.ts` -> `src/index.ts`
+fn normalize_source(source: &str) -> String {
+    let (source, has_scheme) = match source.split_once("://") {
+        Some((_, rest)) => (rest, true),
+        None => (source, false),
+    };
+    let mut parts = source
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+        .collect::<Vec<_>>();
+    // webpack:// URLs start with the project name
+    if has_scheme && parts.len() > 1 {
+        parts.remove(0);
+    }
+    parts.join("/")
+}
+
+fn package_name(source: &str) -> Option<String> {
+    let rest = &source[source.rfind("node_modules/")? + "node_modules/".len()..];
+    let mut parts = rest.split('/');
+    let first = parts.next()?;
+    if first.starts_with('@') {
+        Some(format!("{}/{}", first, parts.next()?))
+    } else {
+        Some(first.to_string())
+    }
+}
+
+async fn read_sourcemap(dist_dir: &Path, path: &str) -> Option<SourceMap> {
+    let full_path = dist_dir.join(path);
+    let text = tokio::fs::read_to_string(&full_path).await.ok()?;
+    let url = text
+        .lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix("//# sourceMappingURL="))
+        .map(str::trim);
+
+    let data = match url {
+        Some(url) if url.starts_with("data:") => {
+            let encoded = url.split_once("base64,")?.1;
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .ok()?
+        }
+        Some(url) => {
+            let parent = full_path.parent()?;
+            tokio::fs::read(parent.join(url)).await.ok()?
+        }
+        None => tokio::fs::read(dist_dir.join(format!("{}.map", path)))
+            .await
+            .ok()?,
+    };
+
+    serde_json::from_slice(&data).ok()
+}
+
+fn matches_repo_file(repo_file: &str, suffix: &str) -> bool {
+    repo_file == suffix || repo_file.ends_with(&format!("/{}", suffix))
+}
+
+pub async fn find_provenance(dist_dir: &Path, path: &str, repo_files: &[String]) -> Provenance {
+    let mut provenance = Provenance::default();
+
+    // Sourcemaps come from the same build as the file they describe
+    let path = path.strip_suffix(".map").unwrap_or(path);
+
+    if let Some(sourcemap) = read_sourcemap(dist_dir, path).await {
+        for source in &sourcemap.sources {
+            provenance.add_source(source, repo_files, ProvenanceMethod::SourceMap);
+        }
+    }
+
+    // Development builds key their modules by path, e.g. `./src/index.ts`
+    let full_path = dist_dir.join(path);
+    if let Ok(source) = tokio::fs::read_to_string(&full_path).await {
+        for id in bundle::module_ids(&full_path, &source) {
+            if id.contains('/') {
+                provenance.add_source(&id, repo_files, ProvenanceMethod::ModuleTable);
+            }
+        }
+    }
+
+    // Fall back to matching the path without extensions, e.g. `webpackModules/foo.js` -> `src/ext/webpackModules/foo.ts`
+    if provenance.sources.is_empty() {
+        let stem = strip_extensions(path);
+        provenance.sources.extend(
+            repo_files
+                .iter()
+                .filter(|file| matches_repo_file(strip_extensions(file), stem))
+                .cloned(),
+        );
+        if !provenance.sources.is_empty() {
+            provenance.methods.insert(ProvenanceMethod::FileName);
+        }
+    }
+
+    provenance
+}
+
+// How well the .asar changes line up with the source changes, as a smell test before reading either
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Consistency {
+    pub source_files: usize,
+    // Not counting `build_noise`
+    pub asar_files: usize,
+    pub build_noise: usize,
+    // Added or modified dist files whose sources and dependencies didn't change, or that have no
+    // known origin at all
+    pub unexplained: Vec<String>,
+}
+
+impl Consistency {
+    // Percent of the changed dist files that are explained by a source change
+    pub fn score(&self) -> u8 {
+        if self.asar_files == 0 {
+            return 100;
+        }
+        let explained = self.asar_files.saturating_sub(self.unexplained.len());
+        (explained * 100 / self.asar_files) as u8
+    }
+
+    // e.g. "source changed 3 files, .asar changed 212 files, 14 with no corresponding source change"
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "source changed {} files, .asar changed {} files",
+            self.source_files, self.asar_files
+        );
+        if self.build_noise > 0 {
+            summary.push_str(&format!(" (and {} only by build noise)", self.build_noise));
+        }
+        if !self.unexplained.is_empty() {
+            summary.push_str(&format!(
+                ", {} with no corresponding source change",
+                self.unexplained.len()
+            ));
+        }
+        summary
+    }
+}
+
+// Changed dist files that nothing in the repository could have produced are reported as findings,
+// ones whose sources didn't change only count against the consistency score
+pub async fn check_consistency(
+    source_diff: &Diff,
+    asar_diff: &Diff,
+    source_dir: &Path,
+    dependencies_changed: bool,
+) -> (Consistency, Vec<Finding>) {
+    let repo_files = list_files(source_dir).await;
+    let changed_sources = diff::flatten_tree(&source_diff.dir)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect::<BTreeSet<_>>();
+    let mut consistency = Consistency {
+        source_files: changed_sources.len(),
+        ..Default::default()
+    };
+    let mut findings = Vec::new();
+
+    for (path, state) in diff::flatten_tree(&asar_diff.dir) {
+        let noise =
+            diff::find_file(&asar_diff.dir, &path).is_some_and(|(_, stats)| stats.build_noise);
+        if noise {
+            consistency.build_noise += 1;
+            continue;
+        }
+        consistency.asar_files += 1;
+        if !matches!(state, FileState::Added | FileState::Modified) {
+            continue;
+        }
+
+        let provenance = find_provenance(&asar_diff.new, &path, &repo_files).await;
+        if provenance.is_known() {
+            let explained = provenance
+                .sources
+                .iter()
+                .any(|source| changed_sources.contains(source))
+                || (dependencies_changed && !provenance.dependencies.is_empty());
+            if !explained {
+                consistency.unexplained.push(path);
+            }
+            continue;
+        }
+        consistency.unexplained.push(path.clone());
+
+        findings.push(Finding {
+            category: FindingCategory::UnknownOrigin,
+            severity: if state == FileState::Added {
+                Severity::Danger
+            } else {
+                Severity::Warning
+            },
+            rule: "unknown-origin".to_string(),
+            message: "Has no matching file in the source repository".to_string(),
+            origin: Origin::Asar,
+            path,
+            line: None,
+            snippet: String::new(),
+        });
+    }
+
+    (consistency, findings)
+}
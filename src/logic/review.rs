@@ -0,0 +1,170 @@
+// Submitting a review on the pull request, along with the line comments queued while reviewing
+use super::{
+    audit::{self, AuditAction},
+    scan::Origin,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Comment by default, so nothing gets approved without picking it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReviewVerdict {
+    Approve,
+    RequestChanges,
+    #[default]
+    Comment,
+}
+
+impl ReviewVerdict {
+    pub const ALL: [ReviewVerdict; 3] = [
+        ReviewVerdict::Approve,
+        ReviewVerdict::RequestChanges,
+        ReviewVerdict::Comment,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ReviewVerdict::Approve => "Approve",
+            ReviewVerdict::RequestChanges => "Request changes",
+            ReviewVerdict::Comment => "Comment",
+        }
+    }
+
+    fn event(self) -> &'static str {
+        match self {
+            ReviewVerdict::Approve => "APPROVE",
+            ReviewVerdict::RequestChanges => "REQUEST_CHANGES",
+            ReviewVerdict::Comment => "COMMENT",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmittedReview {
+    pub id: u64,
+    pub html_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineComment {
+    pub origin: Origin,
+    pub path: String,
+    // 1-indexed, in the new file
+    pub line: usize,
+    // The line commented on, since the author can't see it in the pull request itself
+    pub quote: String,
+    // The line in the extension's repository, for source files on GitHub
+    pub link: Option<String>,
+    pub body: String,
+}
+
+impl LineComment {
+    fn text(&self) -> String {
+        let location = match self.origin {
+            Origin::Source => format!("`{}` line {}", self.path, self.line),
+            Origin::Asar => format!("`{}` line {} in the .asar", self.path, self.line),
+        };
+        let location = match &self.link {
+            Some(link) => format!("[{}]({})", location, link),
+            None => location,
+        };
+        let quote = self.quote.trim();
+        if quote.is_empty() {
+            format!("{}\n\n{}", location, self.body.trim())
+        } else {
+            format!("{}\n```\n{}\n```\n{}", location, quote, self.body.trim())
+        }
+    }
+}
+
+// The pull request only changes manifests, so comments on an extension's files are pinned to the
+// line of its manifest that moves the commit, which is always part of the diff
+async fn manifest_line(
+    client: &octocrab::Octocrab,
+    head_sha: &str,
+    extension_id: &str,
+) -> anyhow::Result<(String, usize)> {
+    let path = format!("exts/{}.json", extension_id);
+    let content = client
+        .repos("moonlight-mod", "extensions")
+        .get_content()
+        .path(&path)
+        .r#ref(head_sha)
+        .send()
+        .await
+        .with_context(|| format!("Failed to get {}", path))?;
+    let text = content
+        .items
+        .into_iter()
+        .next()
+        .and_then(|item| item.decoded_content())
+        .unwrap_or_default();
+    let line = text
+        .lines()
+        .position(|line| line.contains("\"commit\""))
+        .map(|index| index + 1)
+        .unwrap_or(1);
+    Ok((path, line))
+}
+
+// Pinned to the commit that was reviewed, so a push in the meantime isn't approved by accident
+pub async fn submit(
+    client: &octocrab::Octocrab,
+    num: u64,
+    head_sha: &str,
+    verdict: ReviewVerdict,
+    body: &str,
+    // By extension ID
+    comments: &BTreeMap<String, Vec<LineComment>>,
+) -> anyhow::Result<SubmittedReview> {
+    let comment_count = comments.values().map(Vec::len).sum::<usize>();
+    // GitHub rejects requesting changes or commenting without a body
+    if verdict != ReviewVerdict::Approve && body.trim().is_empty() && comment_count == 0 {
+        anyhow::bail!("{} needs a comment", verdict.name());
+    }
+
+    let mut review_comments = Vec::with_capacity(comment_count);
+    for (extension_id, comments) in comments {
+        if comments.is_empty() {
+            continue;
+        }
+        let (path, line) = manifest_line(client, head_sha, extension_id).await?;
+        for comment in comments {
+            review_comments.push(serde_json::json!({
+                "path": path,
+                "line": line,
+                "side": "RIGHT",
+                "body": comment.text(),
+            }));
+        }
+    }
+
+    log::debug!(
+        "Submitting {:?} review on pull request {} with {} comments",
+        verdict,
+        num,
+        review_comments.len()
+    );
+    let route = format!("/repos/moonlight-mod/extensions/pulls/{}/reviews", num);
+    let request = serde_json::json!({
+        "commit_id": head_sha,
+        "event": verdict.event(),
+        "body": body,
+        "comments": review_comments,
+    });
+    let review: SubmittedReview = client
+        .post(route, Some(&request))
+        .await
+        .context("Failed to submit review")?;
+
+    audit::record(AuditAction::ReviewSubmitted {
+        number: num,
+        head_sha: head_sha.to_string(),
+        verdict: verdict.name().to_string(),
+        url: review.html_url.clone(),
+    });
+    Ok(review)
+}
@@ -0,0 +1,672 @@
+use super::{
+    config::{self, Config},
+    diff::{self, Diff, FileState},
+    syntax, wasm,
+};
+use anyhow::Context;
+use base64::Engine;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::LazyLock,
+};
+use tree_sitter::{Query, QueryCursor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FindingCategory {
+    DangerousApi,
+    NewDomain,
+    NativeBinary,
+    Secret,
+    Wasm,
+    UnknownOrigin,
+    RepoChanged,
+    // .asar entries that escape the extraction dir or collide with another entry, and symlinks
+    // pointing outside the repository
+    UnsafePath,
+    // The new commit or version is older than the current one
+    Downgrade,
+    // The .asar has far more or fewer files, or is far bigger, than the source would build
+    ArtifactShape,
+}
+
+impl FindingCategory {
+    pub const ALL: &[FindingCategory] = &[
+        Self::DangerousApi,
+        Self::NewDomain,
+        Self::NativeBinary,
+        Self::Secret,
+        Self::Wasm,
+        Self::UnknownOrigin,
+        Self::RepoChanged,
+        Self::UnsafePath,
+        Self::Downgrade,
+        Self::ArtifactShape,
+    ];
+
+    // Used by `robojules check --fail-on`
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::DangerousApi => "dangerous-api",
+            Self::NewDomain => "new-domain",
+            Self::NativeBinary => "native-binary",
+            Self::Secret => "secret",
+            Self::Wasm => "wasm",
+            Self::UnknownOrigin => "unknown-origin",
+            Self::RepoChanged => "repo-changed",
+            Self::UnsafePath => "unsafe-path",
+            Self::Downgrade => "downgrade",
+            Self::ArtifactShape => "artifact-shape",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|category| category.id() == text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Danger,
+}
+
+impl Severity {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "info" => Some(Self::Info),
+            "warning" => Some(Self::Warning),
+            "danger" => Some(Self::Danger),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Origin {
+    Source,
+    Asar,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub category: FindingCategory,
+    pub severity: Severity,
+    pub rule: String,
+    pub message: String,
+    pub origin: Origin,
+    pub path: String,
+    // 1-indexed
+    pub line: Option<usize>,
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryRule {
+    pub id: String,
+    pub severity: Severity,
+    pub message: String,
+    pub query: String,
+}
+
+const BUILTIN_RULES: &str = r#"
+; @rule eval danger Calls eval() on a string
+((call_expression function: (identifier) @fn) @match (#eq? @fn "eval"))
+
+; @rule function-constructor danger Constructs a function from a string
+((new_expression constructor: (identifier) @ctor) @match (#eq? @ctor "Function"))
+((call_expression function: (identifier) @fn) @match (#eq? @fn "Function"))
+
+; @rule dynamic-import warning Imports a module from a computed string
+(call_expression
+  function: (import)
+  arguments: (arguments . [(identifier) (member_expression) (binary_expression) (call_expression) (template_string)])) @match
+"#;
+
+pub fn rules_path() -> std::path::PathBuf {
+    config::config_dir().join("rules.scm")
+}
+
+// Rules are tree-sitter queries, each preceded by a `; @rule <id> <severity> <message>` line
+pub fn parse_rules(text: &str) -> anyhow::Result<Vec<QueryRule>> {
+    let mut rules: Vec<QueryRule> = Vec::new();
+
+    for line in text.lines() {
+        if let Some(header) = line.trim().strip_prefix("; @rule ") {
+            let mut parts = header.splitn(3, ' ');
+            let id = parts.next().context("Rule is missing an ID")?;
+            let severity = parts.next().context("Rule is missing a severity")?;
+            let severity = Severity::parse(severity)
+                .with_context(|| format!("Unknown severity {} for rule {}", severity, id))?;
+            let message = parts.next().unwrap_or(id);
+
+            rules.push(QueryRule {
+                id: id.to_string(),
+                severity,
+                message: message.to_string(),
+                query: String::new(),
+            });
+        } else if let Some(rule) = rules.last_mut() {
+            rule.query.push_str(line);
+            rule.query.push('\n');
+        }
+    }
+
+    Ok(rules)
+}
+
+pub async fn load_rules() -> Vec<QueryRule> {
+    let mut rules = parse_rules(BUILTIN_RULES).expect("Built-in rules are invalid");
+
+    let path = rules_path();
+    if path.exists() {
+        match tokio::fs::read_to_string(&path)
+            .await
+            .context("Failed to read rules file")
+            .and_then(|text| parse_rules(&text))
+        {
+            Ok(user_rules) => rules.extend(user_rules),
+            Err(err) => log::warn!("Failed to load {}: {:?}", path.display(), err),
+        }
+    }
+
+    rules
+}
+
+// Queries are compiled per language, since node names differ between grammars
+struct CompiledRules<'a> {
+    rules: &'a [QueryRule],
+    queries: HashMap<&'static str, Vec<(usize, Query)>>,
+}
+
+impl<'a> CompiledRules<'a> {
+    fn new(rules: &'a [QueryRule]) -> Self {
+        Self {
+            rules,
+            queries: HashMap::new(),
+        }
+    }
+
+    fn for_language(&mut self, name: &'static str) -> &[(usize, Query)] {
+        let rules = self.rules;
+        self.queries.entry(name).or_insert_with(|| {
+            let Some(language) = syntax::language(name) else {
+                return Vec::new();
+            };
+            rules
+                .iter()
+                .enumerate()
+                .filter_map(|(i, rule)| match Query::new(&language, &rule.query) {
+                    Ok(query) => Some((i, query)),
+                    Err(err) => {
+                        log::debug!("Rule {} doesn't apply to {}: {}", rule.id, name, err);
+                        None
+                    }
+                })
+                .collect()
+        })
+    }
+}
+
+fn snippet(source: &str, line: usize) -> String {
+    let text = source.lines().nth(line).unwrap_or_default().trim();
+    if text.chars().count() > 200 {
+        format!("{}…", text.chars().take(200).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
+fn run_rules(
+    compiled: &mut CompiledRules,
+    origin: Origin,
+    path: &str,
+    full_path: &Path,
+    old: &str,
+    new: &str,
+    findings: &mut Vec<Finding>,
+) {
+    let Some(lang_name) = full_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(syntax::lang_name_from_file_ext)
+    else {
+        return;
+    };
+    let Some(tree) = syntax::parse(full_path, new) else {
+        return;
+    };
+
+    // Only report things that were introduced by this update
+    let added = diff::added_lines(old, new)
+        .into_iter()
+        .map(|(line, _)| line)
+        .collect::<Vec<_>>();
+    let rules = compiled.rules;
+
+    for (i, query) in compiled.for_language(lang_name) {
+        let rule = &rules[*i];
+        let match_index = query.capture_index_for_name("match");
+
+        let mut cursor = QueryCursor::new();
+        for query_match in cursor.matches(query, tree.root_node(), new.as_bytes()) {
+            let capture = query_match
+                .captures
+                .iter()
+                .find(|capture| Some(capture.index) == match_index)
+                .or(query_match.captures.first());
+            let Some(capture) = capture else {
+                continue;
+            };
+
+            let start = capture.node.start_position().row + 1;
+            let end = capture.node.end_position().row + 1;
+            if !added.iter().any(|line| (start..=end).contains(line)) {
+                continue;
+            }
+
+            findings.push(Finding {
+                category: FindingCategory::DangerousApi,
+                severity: rule.severity,
+                rule: rule.id.clone(),
+                message: rule.message.clone(),
+                origin,
+                path: path.to_string(),
+                line: Some(start),
+                snippet: snippet(new, start - 1),
+            });
+        }
+    }
+}
+
+static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:(?:https?|wss?):)?//([a-z0-9-]+(?:\.[a-z0-9-]+)+)(?::\d+)?").unwrap()
+});
+
+fn looks_like_host(host: &str) -> bool {
+    let labels = host.split('.').collect::<Vec<_>>();
+    let is_ip = labels.len() == 4
+        && labels
+            .iter()
+            .all(|label| !label.is_empty() && label.chars().all(|c| c.is_ascii_digit()));
+    let has_tld = labels
+        .last()
+        .is_some_and(|tld| tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic()));
+    is_ip || has_tld
+}
+
+fn check_domains(
+    config: &Config,
+    origin: Origin,
+    path: &str,
+    old: &str,
+    new: &str,
+    findings: &mut Vec<Finding>,
+) {
+    for (line, text) in diff::added_lines(old, new) {
+        let mut seen = HashSet::new();
+        for captures in URL_REGEX.captures_iter(text) {
+            let host = captures[1].to_lowercase();
+            if !looks_like_host(&host)
+                || config.is_domain_allowed(&host)
+                || !seen.insert(host.clone())
+            {
+                continue;
+            }
+
+            findings.push(Finding {
+                category: FindingCategory::NewDomain,
+                severity: Severity::Warning,
+                rule: "new-domain".to_string(),
+                message: format!("References {}, which isn't on the allowlist", host),
+                origin,
+                path: path.to_string(),
+                line: Some(line),
+                snippet: snippet(new, line - 1),
+            });
+        }
+    }
+}
+
+struct SecretRule {
+    id: &'static str,
+    severity: Severity,
+    message: &'static str,
+    regex: Regex,
+    // Generic patterns match plenty of placeholders, so they also need to look random
+    min_entropy: Option<f64>,
+}
+
+static SECRET_RULES: LazyLock<Vec<SecretRule>> = LazyLock::new(|| {
+    let rule = |id, severity, message, regex: &str, min_entropy| SecretRule {
+        id,
+        severity,
+        message,
+        regex: Regex::new(regex).unwrap(),
+        min_entropy,
+    };
+
+    vec![
+        rule(
+            "discord-token",
+            Severity::Danger,
+            "Contains a Discord token",
+            r"\b([MNO][A-Za-z0-9_-]{23,27}\.[A-Za-z0-9_-]{6}\.[A-Za-z0-9_-]{27,38})\b",
+            None,
+        ),
+        rule(
+            "discord-webhook",
+            Severity::Danger,
+            "Contains a Discord webhook URL",
+            r"(?i)https?://(?:canary\.|ptb\.)?discord(?:app)?\.com/api/webhooks/\d+/([A-Za-z0-9_-]+)",
+            None,
+        ),
+        rule(
+            "github-token",
+            Severity::Danger,
+            "Contains a GitHub token",
+            r"\b((?:gh[pousr]_[A-Za-z0-9]{36,})|(?:github_pat_[A-Za-z0-9_]{60,}))\b",
+            None,
+        ),
+        rule(
+            "aws-access-key",
+            Severity::Danger,
+            "Contains an AWS access key",
+            r"\b((?:AKIA|ASIA)[0-9A-Z]{16})\b",
+            None,
+        ),
+        rule(
+            "google-api-key",
+            Severity::Warning,
+            "Contains a Google API key",
+            r"\b(AIza[0-9A-Za-z_-]{35})\b",
+            None,
+        ),
+        rule(
+            "slack-token",
+            Severity::Danger,
+            "Contains a Slack token",
+            r"\b(xox[abposr]-[A-Za-z0-9-]{10,})\b",
+            None,
+        ),
+        rule(
+            "telegram-bot-token",
+            Severity::Danger,
+            "Contains a Telegram bot token",
+            r"\b(\d{8,10}:[A-Za-z0-9_-]{35})\b",
+            None,
+        ),
+        rule(
+            "generic-secret",
+            Severity::Warning,
+            "Assigns a high-entropy string to a secret-looking name",
+            r#"(?i)(?:api[_-]?key|secret|token|passw(?:or)?d|auth)["']?\s*[:=]\s*["'`]([A-Za-z0-9+/_.=-]{16,})["'`]"#,
+            Some(3.5),
+        ),
+    ]
+});
+
+// Shannon entropy in bits per character
+fn entropy(text: &str) -> f64 {
+    let mut counts = HashMap::new();
+    for c in text.chars() {
+        *counts.entry(c).or_insert(0usize) += 1;
+    }
+    let len = text.chars().count() as f64;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// Keeps just enough of the secret to recognize it, so it doesn't end up on screen in full
+fn mask_secret(secret: &str) -> String {
+    let visible = secret.chars().take(4).collect::<String>();
+    format!(
+        "{}{}",
+        visible,
+        "*".repeat(secret.chars().count().saturating_sub(4).min(16))
+    )
+}
+
+fn check_secrets(origin: Origin, path: &str, old: &str, new: &str, findings: &mut Vec<Finding>) {
+    for (line, text) in diff::added_lines(old, new) {
+        for rule in SECRET_RULES.iter() {
+            for captures in rule.regex.captures_iter(text) {
+                let secret = captures.get(1).unwrap_or_else(|| captures.get(0).unwrap());
+                let secret = secret.as_str();
+                if rule
+                    .min_entropy
+                    .is_some_and(|min_entropy| entropy(secret) < min_entropy)
+                {
+                    continue;
+                }
+
+                findings.push(Finding {
+                    category: FindingCategory::Secret,
+                    severity: rule.severity,
+                    rule: rule.id.to_string(),
+                    message: format!("{} ({})", rule.message, mask_secret(secret)),
+                    origin,
+                    path: path.to_string(),
+                    line: Some(line),
+                    snippet: snippet(&text.replace(secret, &mask_secret(secret)), 0),
+                });
+            }
+        }
+    }
+}
+
+const NATIVE_EXTENSIONS: &[&str] = &["node", "dll", "so", "dylib", "exe"];
+
+fn native_format(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [b'M', b'Z', ..] => Some("Windows executable (PE)"),
+        [0x7f, b'E', b'L', b'F', ..] => Some("ELF executable"),
+        [0xfe, 0xed, 0xfa, 0xce | 0xcf, ..] | [0xce | 0xcf, 0xfa, 0xed, 0xfe, ..] => {
+            Some("Mach-O executable")
+        }
+        [0xca, 0xfe, 0xba, 0xbe, ..] => Some("Mach-O universal binary"),
+        _ => None,
+    }
+}
+
+// Base64 encodings of the headers above, for binaries smuggled inside text files
+const EMBEDDED_SIGNATURES: &[(&str, &str)] = &[
+    ("TVqQAAMAAAAEAAAA", "base64-encoded Windows executable"),
+    ("f0VMRgIB", "base64-encoded ELF executable"),
+    ("f0VMRgEB", "base64-encoded ELF executable"),
+    ("z/rt/g", "base64-encoded Mach-O executable"),
+];
+
+fn check_native(
+    origin: Origin,
+    path: &str,
+    data: &[u8],
+    old: &str,
+    new: &str,
+    findings: &mut Vec<Finding>,
+) {
+    let file_name = path.rsplit('/').next().unwrap_or(path).to_lowercase();
+    // Also catches versioned shared objects like `libfoo.so.1`
+    let native_extension = NATIVE_EXTENSIONS.iter().find(|ext| {
+        file_name.ends_with(&format!(".{}", ext)) || file_name.contains(&format!(".{}.", ext))
+    });
+
+    if let Some(format) = native_format(data) {
+        findings.push(Finding {
+            category: FindingCategory::NativeBinary,
+            severity: Severity::Danger,
+            rule: "native-binary".to_string(),
+            message: format!("Contains a native binary ({})", format),
+            origin,
+            path: path.to_string(),
+            line: None,
+            snippet: String::new(),
+        });
+    } else if let Some(ext) = native_extension {
+        findings.push(Finding {
+            category: FindingCategory::NativeBinary,
+            severity: Severity::Danger,
+            rule: "native-binary".to_string(),
+            message: format!("Has a native module extension (.{})", ext),
+            origin,
+            path: path.to_string(),
+            line: None,
+            snippet: String::new(),
+        });
+    }
+
+    for (line, text) in diff::added_lines(old, new) {
+        for (signature, description) in EMBEDDED_SIGNATURES {
+            if text.contains(signature) {
+                findings.push(Finding {
+                    category: FindingCategory::NativeBinary,
+                    severity: Severity::Danger,
+                    rule: "embedded-binary".to_string(),
+                    message: format!("Contains a {}", description),
+                    origin,
+                    path: path.to_string(),
+                    line: Some(line),
+                    snippet: snippet(new, line - 1),
+                });
+            }
+        }
+    }
+}
+
+// `\0asm` encoded as base64
+static EMBEDDED_WASM_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"AGFzbQ[A-Za-z0-9+/]*={0,2}").unwrap());
+
+fn wasm_finding(
+    rule: &str,
+    description: &str,
+    data: &[u8],
+    origin: Origin,
+    path: &str,
+    line: Option<usize>,
+) -> Finding {
+    let (message, snippet) = match wasm::summarize(data) {
+        Ok(summary) => (
+            format!(
+                "{} ({} bytes, {} imports, {} exports)",
+                description,
+                summary.size,
+                summary.imports.len(),
+                summary.exports.len()
+            ),
+            format!("imports: {}", summary.imports.join(", ")),
+        ),
+        Err(err) => (
+            format!("{} that couldn't be parsed ({})", description, err),
+            String::new(),
+        ),
+    };
+
+    Finding {
+        category: FindingCategory::Wasm,
+        severity: Severity::Warning,
+        rule: rule.to_string(),
+        message,
+        origin,
+        path: path.to_string(),
+        line,
+        snippet,
+    }
+}
+
+fn check_wasm(
+    origin: Origin,
+    path: &str,
+    data: &[u8],
+    old: &str,
+    new: &str,
+    findings: &mut Vec<Finding>,
+) {
+    if data.starts_with(wasm::MAGIC) || path.to_lowercase().ends_with(".wasm") {
+        findings.push(wasm_finding(
+            "wasm",
+            "Contains a WebAssembly module",
+            data,
+            origin,
+            path,
+            None,
+        ));
+        return;
+    }
+
+    for (line, text) in diff::added_lines(old, new) {
+        for embedded in EMBEDDED_WASM_REGEX.find_iter(text) {
+            let Ok(data) = base64::engine::general_purpose::STANDARD.decode(embedded.as_str())
+            else {
+                continue;
+            };
+            findings.push(wasm_finding(
+                "embedded-wasm",
+                "Contains a base64-encoded WebAssembly module",
+                &data,
+                origin,
+                path,
+                Some(line),
+            ));
+        }
+    }
+}
+
+async fn read_bytes(path: &Path) -> Vec<u8> {
+    tokio::fs::read(path).await.unwrap_or_default()
+}
+
+pub async fn scan_diff(
+    diff: &Diff,
+    origin: Origin,
+    rules: &[QueryRule],
+    config: &Config,
+) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut compiled = CompiledRules::new(rules);
+
+    for (path, state) in diff::flatten_tree(&diff.dir) {
+        if !matches!(state, FileState::Added | FileState::Modified) {
+            continue;
+        }
+
+        let old_path = diff.old.join(&path);
+        let new_path = diff.new.join(&path);
+        let old = if state == FileState::Added {
+            Vec::new()
+        } else {
+            read_bytes(&old_path).await
+        };
+        let new_data = read_bytes(&new_path).await;
+        let old = String::from_utf8_lossy(&old);
+        let new = String::from_utf8_lossy(&new_data);
+
+        check_native(origin, &path, &new_data, &old, &new, &mut findings);
+        check_wasm(origin, &path, &new_data, &old, &new, &mut findings);
+
+        run_rules(
+            &mut compiled,
+            origin,
+            &path,
+            &new_path,
+            &old,
+            &new,
+            &mut findings,
+        );
+        check_domains(config, origin, &path, &old, &new, &mut findings);
+        check_secrets(origin, &path, &old, &new, &mut findings);
+    }
+
+    Ok(findings)
+}
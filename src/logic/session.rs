@@ -0,0 +1,366 @@
+// A review in progress, saved to a single file so it can be picked up later or on another machine
+use super::{
+    approvals::Approvals,
+    atomic,
+    audit::{self, AuditAction},
+    config,
+    diff::PullRequestUpdate,
+    review::LineComment,
+    scan::{Finding, Origin},
+    templates,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+// Bumped when the format changes incompatibly
+const SESSION_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChecklistItem {
+    pub text: String,
+    pub done: bool,
+}
+
+pub fn default_checklist() -> Vec<ChecklistItem> {
+    [
+        "Source changes match what the PR says it does",
+        "No new network requests to unexpected domains",
+        "New dependencies look trustworthy",
+        ".asar changes are explained by the source changes",
+        "All findings were looked at",
+    ]
+    .into_iter()
+    .map(|text| ChecklistItem {
+        text: text.to_string(),
+        done: false,
+    })
+    .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TriageStatus {
+    Accepted,
+    NeedsFollowUp,
+    FalsePositive,
+}
+
+impl TriageStatus {
+    pub const ALL: [TriageStatus; 3] = [
+        TriageStatus::Accepted,
+        TriageStatus::NeedsFollowUp,
+        TriageStatus::FalsePositive,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TriageStatus::Accepted => "Accepted",
+            TriageStatus::NeedsFollowUp => "Needs follow-up",
+            TriageStatus::FalsePositive => "False positive",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Triage {
+    pub status: TriageStatus,
+    #[serde(default)]
+    pub note: String,
+}
+
+// A file in the review. The source and the .asar can have the same paths, so which one it's from is
+// part of the key. Written as `Source:path/to/file`, so it can key JSON objects.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileKey {
+    pub origin: Origin,
+    pub path: String,
+}
+
+impl FileKey {
+    pub fn new(origin: Origin, path: &str) -> Self {
+        Self {
+            origin,
+            path: path.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FileKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}:{}", self.origin, self.path)
+    }
+}
+
+impl FromStr for FileKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let (origin, path) = key
+            .split_once(':')
+            .ok_or_else(|| format!("File key {:?} has no origin", key))?;
+        let origin = match origin {
+            "Source" => Origin::Source,
+            "Asar" => Origin::Asar,
+            _ => return Err(format!("Unknown origin in file key {:?}", key)),
+        };
+        Ok(Self::new(origin, path))
+    }
+}
+
+impl Serialize for FileKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FileKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReviewState {
+    pub reviewed: BTreeSet<FileKey>,
+    pub file_notes: BTreeMap<FileKey, String>,
+    pub notes: String,
+    pub checklist: Vec<ChecklistItem>,
+    // Snapshot of the findings when the extension was last downloaded
+    pub findings: Vec<Finding>,
+    // Changed source and .asar files, to tell how far along the review is
+    pub total_files: usize,
+    // Keyed by `finding_key`
+    pub triage: BTreeMap<String, Triage>,
+    // Not posted until the review is submitted
+    pub comments: Vec<LineComment>,
+}
+
+impl Default for ReviewState {
+    fn default() -> Self {
+        Self {
+            reviewed: BTreeSet::new(),
+            file_notes: BTreeMap::new(),
+            notes: String::new(),
+            checklist: default_checklist(),
+            findings: Vec::new(),
+            total_files: 0,
+            triage: BTreeMap::new(),
+            comments: Vec::new(),
+        }
+    }
+}
+
+impl ReviewState {
+    // Leaves out the line, so triage survives code moving around in a later push
+    pub fn finding_key(finding: &Finding) -> String {
+        format!(
+            "{:?}:{}:{}:{}",
+            finding.origin,
+            finding.path,
+            finding.rule,
+            finding.snippet.trim()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewSession {
+    pub version: u32,
+    pub pull_request: u64,
+    pub update: PullRequestUpdate,
+    pub selected_extension: Option<String>,
+    // By extension ID
+    pub reviews: BTreeMap<String, ReviewState>,
+    // Markdown for each extension's author, from the review templates. Only written, imports ignore it.
+    #[serde(default)]
+    pub feedback: BTreeMap<String, String>,
+}
+
+impl ReviewSession {
+    pub fn new(
+        pull_request: u64,
+        update: PullRequestUpdate,
+        selected_extension: Option<String>,
+        reviews: BTreeMap<String, ReviewState>,
+        templates: &BTreeMap<String, String>,
+    ) -> Self {
+        let feedback = reviews
+            .iter()
+            .map(|(id, review)| (id.clone(), templates::feedback(templates, id, review)))
+            .filter(|(_, feedback)| !feedback.is_empty())
+            .collect();
+        Self {
+            version: SESSION_VERSION,
+            pull_request,
+            update,
+            selected_extension,
+            reviews,
+            feedback,
+        }
+    }
+}
+
+pub async fn export(path: &Path, session: &ReviewSession) -> anyhow::Result<()> {
+    let data = serde_json::to_vec_pretty(session).context("Failed to serialize session")?;
+    tokio::fs::write(path, data)
+        .await
+        .context("Failed to write session")?;
+    audit::record(AuditAction::Exported {
+        kind: "review session".to_string(),
+        path: path.to_path_buf(),
+    });
+    Ok(())
+}
+
+pub async fn import(path: &Path) -> anyhow::Result<ReviewSession> {
+    let data = tokio::fs::read(path)
+        .await
+        .context("Failed to read session")?;
+    let session =
+        serde_json::from_slice::<ReviewSession>(&data).context("Failed to parse session")?;
+    if session.version > SESSION_VERSION {
+        anyhow::bail!(
+            "Session was saved by a newer version of RoboJules (format {})",
+            session.version
+        );
+    }
+    Ok(session)
+}
+
+// Saved automatically for each pull request, so reviews survive restarts and the PR list can show progress
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedReview {
+    // By extension ID
+    pub reviews: BTreeMap<String, ReviewState>,
+    // Whether the session was exported as a report
+    pub exported: bool,
+}
+
+fn saved_path(pull_request: u64) -> PathBuf {
+    config::config_dir()
+        .join("reviews")
+        .join(format!("{}.json", pull_request))
+}
+
+pub async fn load_saved(pull_request: u64) -> Option<SavedReview> {
+    let data = tokio::fs::read(saved_path(pull_request)).await.ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(saved) => Some(saved),
+        Err(err) => {
+            log::warn!("Failed to parse saved review {}: {:?}", pull_request, err);
+            None
+        }
+    }
+}
+
+async fn write_saved(pull_request: u64, saved: &SavedReview) -> anyhow::Result<()> {
+    let path = saved_path(pull_request);
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .context("Failed to create reviews dir")?;
+    }
+    let data = serde_json::to_vec_pretty(saved).context("Failed to serialize review")?;
+    atomic::write(&path, data)
+        .await
+        .context("Failed to write review")
+}
+
+pub async fn save_reviews(
+    pull_request: u64,
+    reviews: BTreeMap<String, ReviewState>,
+) -> anyhow::Result<()> {
+    let mut saved = load_saved(pull_request).await.unwrap_or_default();
+    saved.reviews = reviews;
+    write_saved(pull_request, &saved).await
+}
+
+pub async fn mark_exported(session: &ReviewSession) -> anyhow::Result<()> {
+    write_saved(
+        session.pull_request,
+        &SavedReview {
+            reviews: session.reviews.clone(),
+            exported: true,
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "percent", rename_all = "camelCase")]
+pub enum ReviewStatus {
+    NotStarted,
+    // Percent of the changed files marked as reviewed
+    InProgress(u8),
+    Exported,
+    // Every extension the pull request touches was approved from it
+    Approved,
+}
+
+impl ReviewStatus {
+    pub fn name(&self) -> String {
+        match self {
+            ReviewStatus::NotStarted => "Not started".to_string(),
+            ReviewStatus::InProgress(percent) => format!("In progress {}%", percent),
+            ReviewStatus::Exported => "Report exported".to_string(),
+            ReviewStatus::Approved => "Approved".to_string(),
+        }
+    }
+}
+
+pub async fn review_status(
+    pull_request: u64,
+    extensions: &[String],
+    approvals: &Approvals,
+) -> ReviewStatus {
+    let approved = !extensions.is_empty()
+        && extensions.iter().all(|id| {
+            approvals
+                .0
+                .get(id)
+                .is_some_and(|approval| approval.pull_request == pull_request)
+        });
+    if approved {
+        return ReviewStatus::Approved;
+    }
+
+    let Some(saved) = load_saved(pull_request).await else {
+        return ReviewStatus::NotStarted;
+    };
+    if saved.exported {
+        return ReviewStatus::Exported;
+    }
+
+    let reviewed = saved
+        .reviews
+        .values()
+        .map(|review| review.reviewed.len())
+        .sum::<usize>();
+    let total = saved
+        .reviews
+        .values()
+        .map(|review| review.total_files)
+        .sum::<usize>();
+    let started = reviewed > 0
+        || saved.reviews.values().any(|review| {
+            !review.notes.is_empty()
+                || !review.file_notes.is_empty()
+                || !review.triage.is_empty()
+                || review.checklist.iter().any(|item| item.done)
+        });
+    if !started {
+        return ReviewStatus::NotStarted;
+    }
+    let percent = (reviewed * 100).checked_div(total).unwrap_or(0).min(100);
+    ReviewStatus::InProgress(percent as u8)
+}
@@ -0,0 +1,165 @@
+// Compares the new .asar against what the extension's source layout would build, to catch an artifact
+// that clearly came from somewhere else (a whole node_modules, another extension, an empty build)
+use super::{
+    diff::ModifiedExtension,
+    provenance::list_files,
+    scan::{Finding, FindingCategory, Origin, Severity},
+};
+use std::path::{Path, PathBuf};
+
+// Source maps and split chunks can easily double the file count, ten times as many is another build
+const COUNT_FACTOR: usize = 10;
+// Below this many files apart it's not worth a warning, whatever the ratio
+const COUNT_SLACK: usize = 20;
+// Bundled dependencies legitimately make the output much bigger than the source
+const SIZE_FACTOR: u64 = 100;
+const SIZE_SLACK: u64 = 10 * 1024 * 1024;
+
+const CODE_EXTENSIONS: [&str; 6] = ["ts", "tsx", "mts", "js", "jsx", "mjs"];
+const ENTRY_POINTS: [&str; 3] = ["index", "node", "host"];
+
+fn is_code(path: &str) -> bool {
+    path.rsplit_once('.')
+        .is_some_and(|(_, ext)| CODE_EXTENSIONS.contains(&ext))
+}
+
+fn stem(path: &str) -> &str {
+    path.rsplit_once('.').map_or(path, |(stem, _)| stem)
+}
+
+// The extension's directory in its repository, found by the id in its manifest. A manifest at the root
+// doesn't count, since everything else in the repository would be taken for the extension's files.
+async fn find_extension_dir(source_dir: &Path, files: &[String], id: &str) -> Option<String> {
+    for file in files {
+        let Some((dir, "manifest.json")) = file.rsplit_once('/') else {
+            continue;
+        };
+        let Ok(data) = tokio::fs::read(source_dir.join(file)).await else {
+            continue;
+        };
+        let manifest_id = serde_json::from_slice::<serde_json::Value>(&data)
+            .ok()
+            .and_then(|manifest| manifest.get("id")?.as_str().map(str::to_string));
+        if manifest_id.as_deref() == Some(id) {
+            return Some(dir.to_string());
+        }
+    }
+    None
+}
+
+// Entry points and webpack modules are each built to one file, other code is bundled into them, and
+// everything that isn't code (the manifest, styles, images) is copied as is
+fn expected_outputs(files: &[&str]) -> usize {
+    files
+        .iter()
+        .filter(|file| {
+            if file.ends_with(".d.ts") {
+                return false;
+            }
+            if !is_code(file) {
+                return true;
+            }
+            match file.split_once('/') {
+                None => ENTRY_POINTS.contains(&stem(file)),
+                Some(("webpackModules", rest)) => {
+                    !rest.contains('/') || stem(rest).ends_with("/index")
+                }
+                _ => false,
+            }
+        })
+        .count()
+}
+
+async fn total_size(root: &Path, files: impl Iterator<Item = PathBuf>) -> u64 {
+    let mut total = 0;
+    for file in files {
+        if let Ok(metadata) = tokio::fs::metadata(root.join(file)).await {
+            total += metadata.len();
+        }
+    }
+    total
+}
+
+fn finding(ext: &ModifiedExtension, rule: &str, message: String) -> Finding {
+    Finding {
+        category: FindingCategory::ArtifactShape,
+        severity: Severity::Warning,
+        rule: rule.to_string(),
+        message,
+        origin: Origin::Asar,
+        path: format!("{}.asar", ext.id),
+        line: None,
+        snippet: String::new(),
+    }
+}
+
+// Only orders of magnitude are flagged, the layout can't say exactly what a build produces. Nothing is
+// reported when the extension's directory can't be found in its repository.
+pub async fn check_shape(
+    ext: &ModifiedExtension,
+    source_dir: &Path,
+    asar_dir: &Path,
+) -> Vec<Finding> {
+    let source_files = list_files(source_dir).await;
+    let Some(ext_dir) = find_extension_dir(source_dir, &source_files, &ext.id).await else {
+        log::debug!("No manifest for {} in its repository", ext.id);
+        return Vec::new();
+    };
+    let ext_files = source_files
+        .iter()
+        .filter_map(|file| file.strip_prefix(&ext_dir)?.strip_prefix('/'))
+        .collect::<Vec<_>>();
+    let asar_files = list_files(asar_dir).await;
+
+    let expected = expected_outputs(&ext_files).max(1);
+    let actual = asar_files.len();
+    let mut findings = Vec::new();
+    if actual >= expected * COUNT_FACTOR && actual - expected >= COUNT_SLACK {
+        findings.push(finding(
+            ext,
+            "too-many-files",
+            format!(
+                "The .asar has {} files, but the source layout would build about {}",
+                actual, expected
+            ),
+        ));
+    } else if actual * COUNT_FACTOR <= expected && expected - actual >= COUNT_SLACK {
+        findings.push(finding(
+            ext,
+            "too-few-files",
+            format!(
+                "The .asar has {} files, but the source layout would build about {}",
+                actual, expected
+            ),
+        ));
+    }
+
+    let source_size = total_size(
+        &source_dir.join(&ext_dir),
+        ext_files.iter().map(PathBuf::from),
+    )
+    .await;
+    let asar_size = total_size(asar_dir, asar_files.iter().map(PathBuf::from)).await;
+    log::debug!(
+        "{}: {} files ({} bytes) in the .asar, about {} expected from {} bytes of source",
+        ext.id,
+        actual,
+        asar_size,
+        expected,
+        source_size
+    );
+    if asar_size >= source_size * SIZE_FACTOR && asar_size - source_size >= SIZE_SLACK {
+        findings.push(finding(
+            ext,
+            "too-large",
+            format!(
+                "The .asar is {} KiB, over {} times the {} KiB of the extension's source",
+                asar_size / 1024,
+                SIZE_FACTOR,
+                source_size / 1024
+            ),
+        ));
+    }
+
+    findings
+}
@@ -0,0 +1,88 @@
+// Checked before a download, so running out of space fails up front instead of halfway through extracting
+use super::error::InsufficientSpace;
+use anyhow::Context;
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+// The artifact .zip is extracted to an .asar, and both .asars are extracted next to it
+const ARTIFACT_EXPANSION: u64 = 4;
+// The clone, plus a checkout of the old and new commits
+const CLONE_EXPANSION: u64 = 3;
+// For the published .asar and everything that can't be estimated
+const MARGIN: u64 = 256 * 1024 * 1024;
+
+// By artifact URL, from when the pull request was fetched
+static ARTIFACT_SIZES: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+
+pub fn record_artifact_size(url: &str, size: u64) {
+    ARTIFACT_SIZES
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(url.to_string(), size);
+}
+
+fn artifact_size(url: &str) -> u64 {
+    ARTIFACT_SIZES
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_ref()
+        .and_then(|sizes| sizes.get(url).copied())
+        .unwrap_or_default()
+}
+
+// Only known for GitHub repositories, where the API reports it
+async fn repository_size(client: &octocrab::Octocrab, repository: &str) -> anyhow::Result<u64> {
+    let Some(path) = repository
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .strip_prefix("https://github.com/")
+    else {
+        return Ok(0);
+    };
+    let Some((owner, name)) = path.split_once('/') else {
+        return Ok(0);
+    };
+    let repo = client
+        .repos(owner, name)
+        .get()
+        .await
+        .with_context(|| format!("Failed to get {}", repository))?;
+    // In KiB
+    Ok(repo.size.unwrap_or_default() as u64 * 1024)
+}
+
+pub async fn estimate(client: &octocrab::Octocrab, artifact_url: &str, repository: &str) -> u64 {
+    let clone = repository_size(client, repository)
+        .await
+        .unwrap_or_else(|err| {
+            log::warn!("Failed to get the size of {}: {:#}", repository, err);
+            0
+        });
+    artifact_size(artifact_url) * ARTIFACT_EXPANSION + clone * CLONE_EXPANSION + MARGIN
+}
+
+// Not knowing the free space doesn't stop the download, it'd only fail the way it used to
+pub fn check(dir: &Path, needed: u64) -> anyhow::Result<()> {
+    let available = match fs4::available_space(dir) {
+        Ok(available) => available,
+        Err(err) => {
+            log::warn!("Failed to get free space in {}: {}", dir.display(), err);
+            return Ok(());
+        }
+    };
+    log::debug!(
+        "{} bytes needed, {} available in {}",
+        needed,
+        available,
+        dir.display()
+    );
+    if available < needed {
+        return Err(InsufficientSpace {
+            dir: dir.to_path_buf(),
+            needed,
+            available,
+        }
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+// Review progress shared through a gist, so maintainers splitting a big PR can see each other's work
+use super::session::{FileKey, ReviewState};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SharedExtensionReview {
+    pub reviewed: BTreeSet<FileKey>,
+    pub file_notes: BTreeMap<FileKey, String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReviewerState {
+    // Seconds since the Unix epoch
+    pub updated: u64,
+    // By extension ID
+    pub extensions: BTreeMap<String, SharedExtensionReview>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TeamReview {
+    // Who we're authenticated as, so the UI can tell our own entry apart
+    pub login: String,
+    pub reviewers: BTreeMap<String, ReviewerState>,
+}
+
+impl TeamReview {
+    // Other reviewers who marked a file as reviewed
+    pub fn reviewed_by_others(&self, extension_id: &str, key: &FileKey) -> Vec<&str> {
+        self.reviewers
+            .iter()
+            .filter(|(login, _)| **login != self.login)
+            .filter(|(_, reviewer)| {
+                reviewer
+                    .extensions
+                    .get(extension_id)
+                    .is_some_and(|review| review.reviewed.contains(key))
+            })
+            .map(|(login, _)| login.as_str())
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct Gist {
+    files: BTreeMap<String, GistFile>,
+}
+
+#[derive(Deserialize)]
+struct GistFile {
+    content: Option<String>,
+}
+
+// One file per reviewer, so syncs at the same time never write over each other
+fn file_prefix(pull_request: u64) -> String {
+    format!("robojules-pr-{}-", pull_request)
+}
+
+fn file_name(pull_request: u64, login: &str) -> String {
+    format!("{}{}.json", file_prefix(pull_request), login)
+}
+
+// Before there was a file per reviewer, everyone shared one
+fn shared_file_name(pull_request: u64) -> String {
+    format!("robojules-pr-{}.json", pull_request)
+}
+
+// Someone's broken file shouldn't keep everyone else from syncing
+fn parse_content<T: serde::de::DeserializeOwned>(name: &str, file: &GistFile) -> Option<T> {
+    let content = file.content.as_ref()?;
+    match serde_json::from_str(content) {
+        Ok(parsed) => Some(parsed),
+        Err(err) => {
+            log::warn!("Failed to parse {} in the review gist: {:?}", name, err);
+            None
+        }
+    }
+}
+
+fn read_reviewers(gist: &Gist, pull_request: u64) -> BTreeMap<String, ReviewerState> {
+    let prefix = file_prefix(pull_request);
+    let mut reviewers = gist
+        .files
+        .iter()
+        .filter_map(|(name, file)| {
+            let login = name.strip_prefix(&prefix)?.strip_suffix(".json")?;
+            Some((login.to_string(), parse_content(name, file)?))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let shared_name = shared_file_name(pull_request);
+    if let Some(shared) = gist
+        .files
+        .get(&shared_name)
+        .and_then(|file| parse_content::<BTreeMap<String, ReviewerState>>(&shared_name, file))
+    {
+        for (login, reviewer) in shared {
+            reviewers.entry(login).or_insert(reviewer);
+        }
+    }
+    reviewers
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+pub async fn sync(
+    client: &octocrab::Octocrab,
+    gist_id: &str,
+    pull_request: u64,
+    reviews: &BTreeMap<String, ReviewState>,
+) -> anyhow::Result<TeamReview> {
+    let login = client
+        .current()
+        .user()
+        .await
+        .context("Syncing reviews needs a GitHub token")?
+        .login;
+
+    let route = format!("/gists/{}", gist_id);
+    let gist: Gist = client
+        .get(&route, None::<&()>)
+        .await
+        .context("Failed to fetch review gist")?;
+    let mut reviewers = read_reviewers(&gist, pull_request);
+
+    let extensions = reviews
+        .iter()
+        .map(|(id, review)| {
+            let shared = SharedExtensionReview {
+                reviewed: review.reviewed.clone(),
+                file_notes: review.file_notes.clone(),
+            };
+            (id.clone(), shared)
+        })
+        .collect();
+    let own = ReviewerState {
+        updated: now(),
+        extensions,
+    };
+
+    let content =
+        serde_json::to_string_pretty(&own).context("Failed to serialize shared review")?;
+    let body = serde_json::json!({
+        "files": { file_name(pull_request, &login): { "content": content } }
+    });
+    client
+        .patch::<serde_json::Value, _, _>(&route, Some(&body))
+        .await
+        .context("Failed to update review gist")?;
+
+    reviewers.insert(login.clone(), own);
+    Ok(TeamReview { login, reviewers })
+}
@@ -0,0 +1,356 @@
+use super::grammars;
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path, sync::RwLock};
+use tree_sitter::{Language, Node, Parser, Point, Tree};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum OutlineKind {
+    Function,
+    Class,
+    Export,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineEntry {
+    pub kind: OutlineKind,
+    pub name: String,
+    pub depth: usize,
+    // 1-indexed, inclusive
+    pub start_line: usize,
+    pub end_line: usize,
+    // Whether any changed line falls inside this entry
+    pub changed: bool,
+}
+
+// Every language name tree-sitter can parse, built in or through `grammars`
+pub const LANGUAGES: &[&str] = &["javascript", "typescript", "tsx", "css", "html", "json"];
+
+// From the config, by lowercase file extension without the dot. Values outside `LANGUAGES` are
+// only given to difftastic.
+static LANGUAGE_MAP: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+pub fn normalize_ext(ext: &str) -> String {
+    ext.trim().trim_start_matches('.').to_lowercase()
+}
+
+pub fn set_language_map(map: &BTreeMap<String, String>) {
+    let map = map
+        .iter()
+        .map(|(ext, name)| (normalize_ext(ext), name.trim().to_string()))
+        .filter(|(ext, name)| !ext.is_empty() && !name.is_empty())
+        .collect();
+    *LANGUAGE_MAP.write().unwrap_or_else(|err| err.into_inner()) = map;
+}
+
+// difftastic's name for a language, so one mapping covers both
+fn difft_name(name: &str) -> &str {
+    match name {
+        "javascript" => "JavaScript",
+        "typescript" => "TypeScript",
+        "tsx" => "TypeScript TSX",
+        "css" => "CSS",
+        "html" => "HTML",
+        "json" => "JSON",
+        other => other,
+    }
+}
+
+// `--override` arguments for difftastic, one per mapping
+pub fn difft_overrides() -> Vec<String> {
+    LANGUAGE_MAP
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .iter()
+        .map(|(ext, name)| format!("--override=*.{}:{}", ext, difft_name(name)))
+        .collect()
+}
+
+// The config's mapping wins, so a wrong guess can be fixed without a new release
+pub fn lang_name_from_file_ext(ext: &str) -> Option<&'static str> {
+    let mapped = LANGUAGE_MAP
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(&normalize_ext(ext))
+        .cloned();
+    if let Some(name) = mapped {
+        let name = name.to_lowercase();
+        return LANGUAGES.iter().copied().find(|known| *known == name);
+    }
+
+    match ext {
+        "js" | "mjs" | "cjs" | "jsx" => Some("javascript"),
+        "ts" | "mts" | "cts" => Some("typescript"),
+        "tsx" => Some("tsx"),
+        // Not built in, see `grammars`
+        "css" => Some("css"),
+        "html" | "htm" => Some("html"),
+        "json" => Some("json"),
+        _ => None,
+    }
+}
+
+pub fn language(name: &str) -> Option<Language> {
+    match name {
+        "javascript" => Some(tree_sitter_javascript::LANGUAGE.into()),
+        "typescript" => Some(tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into()),
+        "tsx" => Some(tree_sitter_typescript::LANGUAGE_TSX.into()),
+        _ => grammars::load(name),
+    }
+}
+
+pub fn language_for_path(path: &Path) -> Option<Language> {
+    let ext = path.extension()?.to_str()?;
+    language(lang_name_from_file_ext(ext)?)
+}
+
+pub fn parse(path: &Path, source: &str) -> Option<Tree> {
+    let language = language_for_path(path)?;
+    let mut parser = Parser::new();
+    parser.set_language(&language).ok()?;
+    parser.parse(source, None)
+}
+
+fn node_name(node: Node, source: &str) -> Option<String> {
+    if let Some(name) = node.child_by_field_name("name") {
+        return name.utf8_text(source.as_bytes()).ok().map(str::to_string);
+    }
+
+    // Anonymous functions get their name from whatever they're assigned to
+    let parent = node.parent()?;
+    let name = match parent.kind() {
+        "variable_declarator" => parent.child_by_field_name("name"),
+        "pair" => parent.child_by_field_name("key"),
+        "assignment_expression" => parent.child_by_field_name("left"),
+        "public_field_definition" | "field_definition" => parent
+            .child_by_field_name("name")
+            .or_else(|| parent.child_by_field_name("property")),
+        _ => None,
+    }?;
+    name.utf8_text(source.as_bytes()).ok().map(str::to_string)
+}
+
+pub fn scope_label(node: Node, source: &str) -> Option<String> {
+    match node.kind() {
+        "function_declaration"
+        | "generator_function_declaration"
+        | "function_expression"
+        | "function"
+        | "arrow_function"
+        | "method_definition" => Some(format!(
+            "fn {}()",
+            node_name(node, source).unwrap_or_else(|| "<anonymous>".to_string())
+        )),
+        "class_declaration" | "class" | "abstract_class_declaration" => Some(format!(
+            "class {}",
+            node_name(node, source).unwrap_or_else(|| "<anonymous>".to_string())
+        )),
+        // CSS
+        "rule_set" => node
+            .child_by_field_name("selectors")
+            .or_else(|| node.named_child(0))
+            .and_then(|selectors| selectors.utf8_text(source.as_bytes()).ok())
+            .map(|selectors| selectors.split_whitespace().collect::<Vec<_>>().join(" ")),
+        "media_statement" | "supports_statement" | "keyframes_statement" => node
+            .utf8_text(source.as_bytes())
+            .ok()
+            .and_then(|text| text.split('{').next())
+            .map(|text| text.trim().to_string()),
+        // HTML
+        "element" => node
+            .named_child(0)
+            .filter(|tag| tag.kind() == "start_tag")
+            .and_then(|tag| tag.named_child(0))
+            .and_then(|name| name.utf8_text(source.as_bytes()).ok())
+            .map(|name| format!("<{}>", name)),
+        // JSON, whose root is a `document` rather than JS's `program`
+        "pair" if is_json(node) => node
+            .child_by_field_name("key")
+            .and_then(|key| key.utf8_text(source.as_bytes()).ok())
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+fn is_json(mut node: Node) -> bool {
+    while let Some(parent) = node.parent() {
+        node = parent;
+    }
+    node.kind() == "document"
+}
+
+// Label for the functions/classes surrounding a line, outermost first (e.g. `class Foo > fn bar()`)
+pub fn enclosing_scope(tree: &Tree, source: &str, line: usize) -> Option<String> {
+    let text = source.lines().nth(line)?;
+    let column = text.len() - text.trim_start().len();
+    let point = Point::new(line, column);
+    let mut node = tree.root_node().descendant_for_point_range(point, point)?;
+
+    let mut labels = Vec::new();
+    loop {
+        if let Some(label) = scope_label(node, source) {
+            labels.push(label);
+        }
+        match node.parent() {
+            Some(parent) => node = parent,
+            None => break,
+        }
+    }
+
+    if labels.is_empty() {
+        return None;
+    }
+    labels.reverse();
+    Some(labels.join(" > "))
+}
+
+fn outline_kind(node: Node) -> Option<OutlineKind> {
+    match node.kind() {
+        "function_declaration"
+        | "generator_function_declaration"
+        | "function_expression"
+        | "function"
+        | "arrow_function"
+        | "method_definition" => Some(OutlineKind::Function),
+        "class_declaration" | "class" | "abstract_class_declaration" => Some(OutlineKind::Class),
+        _ => None,
+    }
+}
+
+fn walk_outline(node: Node, source: &str, depth: usize, entries: &mut Vec<OutlineEntry>) {
+    let mut child_depth = depth;
+
+    if let Some(kind) = outline_kind(node) {
+        // Skip anonymous callbacks, they'd just be noise in the outline
+        if let Some(name) = node_name(node, source) {
+            entries.push(OutlineEntry {
+                kind,
+                name,
+                depth,
+                start_line: node.start_position().row + 1,
+                end_line: node.end_position().row + 1,
+                changed: false,
+            });
+            child_depth += 1;
+        }
+    } else if node.kind() == "export_statement" {
+        // Exported variables (e.g. `export const patches = [...]`) don't show up as functions
+        if let Some(declaration) = node.child_by_field_name("declaration") {
+            if matches!(
+                declaration.kind(),
+                "lexical_declaration" | "variable_declaration"
+            ) {
+                let mut cursor = declaration.walk();
+                for declarator in declaration.named_children(&mut cursor) {
+                    let Some(name) = declarator.child_by_field_name("name") else {
+                        continue;
+                    };
+                    let Ok(name) = name.utf8_text(source.as_bytes()) else {
+                        continue;
+                    };
+                    entries.push(OutlineEntry {
+                        kind: OutlineKind::Export,
+                        name: name.to_string(),
+                        depth,
+                        start_line: declarator.start_position().row + 1,
+                        end_line: declarator.end_position().row + 1,
+                        changed: false,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        walk_outline(child, source, child_depth, entries);
+    }
+}
+
+pub fn outline(tree: &Tree, source: &str, changed_lines: &[usize]) -> Vec<OutlineEntry> {
+    let mut entries = Vec::new();
+    walk_outline(tree.root_node(), source, 0, &mut entries);
+
+    for entry in &mut entries {
+        entry.changed = changed_lines
+            .iter()
+            .any(|line| (entry.start_line..=entry.end_line).contains(line));
+    }
+
+    entries
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum HighlightKind {
+    Comment,
+    String,
+    Number,
+    Keyword,
+    Type,
+    Property,
+}
+
+// Byte range into the source
+#[derive(Debug, Clone, Serialize)]
+pub struct HighlightSpan {
+    pub start: usize,
+    pub end: usize,
+    pub kind: HighlightKind,
+}
+
+fn highlight_kind(node: Node) -> Option<HighlightKind> {
+    let kind = node.kind();
+    if kind.contains("comment") {
+        return Some(HighlightKind::Comment);
+    }
+    // Punctuation is anonymous too, only words are keywords
+    if !node.is_named() {
+        return kind
+            .chars()
+            .all(|c| c.is_ascii_alphabetic())
+            .then_some(HighlightKind::Keyword);
+    }
+    match kind {
+        "string"
+        | "template_string"
+        | "regex"
+        | "string_value"
+        | "attribute_value"
+        | "quoted_attribute_value" => Some(HighlightKind::String),
+        "number" | "integer_value" | "float_value" | "color_value" => Some(HighlightKind::Number),
+        "true" | "false" | "null" | "undefined" | "this" | "super" | "tag_name" => {
+            Some(HighlightKind::Keyword)
+        }
+        "type_identifier" | "predefined_type" | "class_selector" | "id_selector" => {
+            Some(HighlightKind::Type)
+        }
+        "property_identifier"
+        | "property_name"
+        | "attribute_name"
+        | "shorthand_property_identifier" => Some(HighlightKind::Property),
+        _ => None,
+    }
+}
+
+fn walk_highlights(node: Node, spans: &mut Vec<HighlightSpan>) {
+    // Strings and comments are colored as a whole, including any interpolation inside
+    if let Some(kind) = highlight_kind(node) {
+        spans.push(HighlightSpan {
+            start: node.start_byte(),
+            end: node.end_byte(),
+            kind,
+        });
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        walk_highlights(child, spans);
+    }
+}
+
+// Non-overlapping and in order, for coloring a whole file
+pub fn highlight(tree: &Tree) -> Vec<HighlightSpan> {
+    let mut spans = Vec::new();
+    walk_highlights(tree.root_node(), &mut spans);
+    spans
+}
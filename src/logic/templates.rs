@@ -0,0 +1,133 @@
+// Standard wording for each finding category, so feedback to extension authors reads the same
+// whoever reviewed the pull request
+use super::{
+    scan::{Finding, FindingCategory, Origin},
+    session::{ReviewState, TriageStatus},
+};
+use std::collections::BTreeMap;
+
+pub const PLACEHOLDERS: &str = "{extension}, {path}, {line}, {rule}, {message} and {snippet}";
+
+pub fn default_template(category: FindingCategory) -> &'static str {
+    match category {
+        FindingCategory::DangerousApi => {
+            "`{path}` uses `{rule}` ({message}). Please explain why it's needed, or use a safer \
+             alternative."
+        }
+        FindingCategory::NewDomain => {
+            "`{path}` now talks to a new external domain: `{snippet}`. What is it used for, and \
+             is it mentioned in the extension's description?"
+        }
+        FindingCategory::NativeBinary => {
+            "`{path}` is a native binary. Extensions can't ship prebuilt code we can't review, \
+             please build it from source in the repository or remove it."
+        }
+        FindingCategory::Secret => {
+            "`{path}` looks like it contains a secret ({message}). Please revoke it and remove it \
+             from the extension."
+        }
+        FindingCategory::Wasm => {
+            "`{path}` contains WebAssembly. Please link the source it's built from and how to \
+             reproduce the build."
+        }
+        FindingCategory::UnknownOrigin => {
+            "`{path}` in the .asar isn't explained by the source changes. Please check that the \
+             build is reproducible from the commit in the manifest."
+        }
+        FindingCategory::RepoChanged => {
+            "The extension's repository changed ({message}). Please confirm the move was \
+             intended."
+        }
+        FindingCategory::UnsafePath => {
+            "The .asar has an unsafe entry, `{path}` ({message}). Please rebuild it with the \
+             standard tooling."
+        }
+        FindingCategory::Downgrade => {
+            "This update goes backwards ({message}). Please make sure the manifest points at the \
+             latest commit."
+        }
+        FindingCategory::ArtifactShape => {
+            "The .asar doesn't look like a build of the extension's source ({message}). Please \
+             check that the artifact was built from the commit in the manifest."
+        }
+    }
+}
+
+// By finding category ID
+pub fn default_templates() -> BTreeMap<String, String> {
+    FindingCategory::ALL
+        .iter()
+        .map(|category| {
+            (
+                category.id().to_string(),
+                default_template(*category).to_string(),
+            )
+        })
+        .collect()
+}
+
+pub fn render(template: &str, extension_id: &str, finding: &Finding) -> String {
+    let line = finding
+        .line
+        .map(|line| line.to_string())
+        .unwrap_or_default();
+    template
+        .replace("{extension}", extension_id)
+        .replace("{path}", &finding.path)
+        .replace("{line}", &line)
+        .replace("{rule}", &finding.rule)
+        .replace("{message}", &finding.message)
+        .replace("{snippet}", finding.snippet.trim())
+}
+
+// None when the category's template was cleared. Categories missing from an older config get the default.
+pub fn for_finding(
+    templates: &BTreeMap<String, String>,
+    extension_id: &str,
+    finding: &Finding,
+) -> Option<String> {
+    let template = templates
+        .get(finding.category.id())
+        .map(String::as_str)
+        .unwrap_or_else(|| default_template(finding.category))
+        .trim();
+    (!template.is_empty()).then(|| render(template, extension_id, finding))
+}
+
+// A Markdown comment for the extension's author, leaving out findings triaged as false positives
+pub fn feedback(
+    templates: &BTreeMap<String, String>,
+    extension_id: &str,
+    review: &ReviewState,
+) -> String {
+    let mut lines = Vec::new();
+    for finding in &review.findings {
+        let triage = review.triage.get(&ReviewState::finding_key(finding));
+        if triage.is_some_and(|triage| triage.status == TriageStatus::FalsePositive) {
+            continue;
+        }
+        let Some(text) = for_finding(templates, extension_id, finding) else {
+            continue;
+        };
+        // Different findings often render the same, e.g. one domain used in several places
+        let line = match finding.origin {
+            Origin::Source => format!("- {}", text),
+            Origin::Asar => format!("- (.asar) {}", text),
+        };
+        if lines.contains(&line) {
+            continue;
+        }
+        lines.push(line);
+        if let Some(note) = triage
+            .map(|triage| triage.note.trim())
+            .filter(|note| !note.is_empty())
+        {
+            lines.push(format!("  {}", note));
+        }
+    }
+
+    if lines.is_empty() {
+        return String::new();
+    }
+    format!("### `{}`\n\n{}\n", extension_id, lines.join("\n"))
+}
@@ -0,0 +1,185 @@
+// Durations of pipeline stages, for the diagnostics window and debug logs
+use super::LogicResponse;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// Enough for a few extensions' worth of stages and file diffs
+const MAX_TIMINGS: usize = 500;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+static TIMINGS: Mutex<VecDeque<Timing>> = Mutex::new(VecDeque::new());
+// Stages that haven't finished yet, for the UI's busy indicators
+static ACTIVE: Mutex<Vec<ActiveStage>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+struct ActiveStage {
+    id: u64,
+    stage: String,
+    start: Instant,
+    bytes: Option<u64>,
+    total_bytes: Option<u64>,
+    items: Option<ItemProgress>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StageProgress {
+    pub stage: String,
+    pub elapsed: Duration,
+    // Only known for downloads; the total is missing when the server doesn't send a length
+    pub bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    // For stages that count something other than bytes, like git's objects
+    pub items: Option<ItemProgress>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemProgress {
+    // e.g. "Receiving objects"
+    pub phase: String,
+    pub done: u64,
+    pub total: u64,
+}
+
+impl StageProgress {
+    // None when there's nothing to compare against, e.g. a download without a length
+    pub fn fraction(&self) -> Option<f32> {
+        match (self.bytes, self.total_bytes, &self.items) {
+            (Some(bytes), Some(total), _) if total > 0 => Some(bytes as f32 / total as f32),
+            (_, _, Some(items)) if items.total > 0 => Some(items.done as f32 / items.total as f32),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Timing {
+    pub stage: String,
+    pub duration: Duration,
+    // Whether the stage returned early, e.g. because of an error
+    pub incomplete: bool,
+}
+
+// Records how long it lived when dropped, so early returns through `?` are still counted
+pub struct Timer {
+    id: u64,
+    stage: String,
+    start: Instant,
+    finished: bool,
+}
+
+pub fn start(stage: impl Into<String>) -> Timer {
+    let timer = Timer {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        stage: stage.into(),
+        start: Instant::now(),
+        finished: false,
+    };
+    active().push(ActiveStage {
+        id: timer.id,
+        stage: timer.stage.clone(),
+        start: timer.start,
+        bytes: None,
+        total_bytes: None,
+        items: None,
+    });
+    timer
+}
+
+fn active() -> std::sync::MutexGuard<'static, Vec<ActiveStage>> {
+    ACTIVE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl Timer {
+    pub fn finish(mut self) {
+        self.finished = true;
+    }
+
+    pub fn set_bytes(&self, bytes: u64, total_bytes: Option<u64>) {
+        if let Some(stage) = active().iter_mut().find(|stage| stage.id == self.id) {
+            stage.bytes = Some(bytes);
+            stage.total_bytes = total_bytes;
+        }
+    }
+
+    pub fn set_items(&self, phase: &str, done: u64, total: u64) {
+        if let Some(stage) = active().iter_mut().find(|stage| stage.id == self.id) {
+            stage.items = Some(ItemProgress {
+                phase: phase.to_string(),
+                done,
+                total,
+            });
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let duration = self.start.elapsed();
+        active().retain(|stage| stage.id != self.id);
+        log::debug!(
+            "{} took {:?}{}",
+            self.stage,
+            duration,
+            if self.finished { "" } else { " (incomplete)" }
+        );
+
+        let mut timings = TIMINGS.lock().unwrap_or_else(|err| err.into_inner());
+        if timings.len() >= MAX_TIMINGS {
+            timings.pop_front();
+        }
+        timings.push_back(Timing {
+            stage: std::mem::take(&mut self.stage),
+            duration,
+            incomplete: !self.finished,
+        });
+    }
+}
+
+// Oldest first, so concurrent stages keep their order between updates
+pub fn in_progress() -> Vec<StageProgress> {
+    active()
+        .iter()
+        .map(|stage| StageProgress {
+            stage: stage.stage.clone(),
+            elapsed: stage.start.elapsed(),
+            bytes: stage.bytes,
+            total_bytes: stage.total_bytes,
+            items: stage.items.clone(),
+        })
+        .collect()
+}
+
+// Sends what's in progress until the receiver is gone, and once more when it all finishes
+pub async fn watch(tx: flume::Sender<LogicResponse>) {
+    let mut was_idle = true;
+    loop {
+        tokio::time::sleep(PROGRESS_INTERVAL).await;
+        let stages = in_progress();
+        if stages.is_empty() && was_idle {
+            continue;
+        }
+        was_idle = stages.is_empty();
+        if tx.send(LogicResponse::Progress(stages)).is_err() {
+            return;
+        }
+    }
+}
+
+pub fn recent() -> Vec<Timing> {
+    let timings = TIMINGS.lock().unwrap_or_else(|err| err.into_inner());
+    timings.iter().cloned().collect()
+}
+
+pub fn clear() {
+    TIMINGS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clear();
+}
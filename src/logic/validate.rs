@@ -0,0 +1,204 @@
+// Checks manifests in the extensions repository, so CI can catch broken ones before review
+use super::{download::claim_unique_temp_dir, git::git};
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+pub(crate) fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub(crate) fn is_commit_hash(commit: &str) -> bool {
+    commit.len() == 40 && commit.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Shape of exts/<id>.json, as problems rather than the first parse error
+fn check_schema(path: &Path, data: &[u8]) -> (Vec<String>, Option<(String, String)>) {
+    let mut problems = Vec::new();
+
+    let id = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if path.extension().is_none_or(|ext| ext != "json") {
+        problems.push("Manifest isn't a .json file".to_string());
+    }
+    if !is_valid_id(&id) {
+        problems.push(format!(
+            "{} isn't a valid extension ID, use letters, digits, - and _",
+            id
+        ));
+    }
+
+    let value = match serde_json::from_slice::<serde_json::Value>(data) {
+        Ok(value) => value,
+        Err(err) => {
+            problems.push(format!("Invalid JSON: {}", err));
+            return (problems, None);
+        }
+    };
+    let Some(object) = value.as_object() else {
+        problems.push("Manifest isn't a JSON object".to_string());
+        return (problems, None);
+    };
+
+    let field = |name: &str, problems: &mut Vec<String>| match object.get(name) {
+        Some(serde_json::Value::String(value)) => Some(value.clone()),
+        Some(_) => {
+            problems.push(format!("{} isn't a string", name));
+            None
+        }
+        None => {
+            problems.push(format!("Missing {}", name));
+            None
+        }
+    };
+    let repository = field("repository", &mut problems);
+    let commit = field("commit", &mut problems);
+
+    if let Some(repository) = &repository {
+        if !repository.starts_with("https://") {
+            problems.push(format!("Repository {} isn't an https:// URL", repository));
+        }
+    }
+    if let Some(commit) = &commit {
+        if !is_commit_hash(commit) {
+            problems.push(format!("Commit {} isn't a full 40 character hash", commit));
+        }
+    }
+
+    match (repository, commit) {
+        (Some(repository), Some(commit)) if problems.is_empty() => {
+            (problems, Some((repository, commit)))
+        }
+        _ => (problems, None),
+    }
+}
+
+// Fetches just the one commit into a scratch repository, which fails if it was force pushed away.
+// Manifests can share a commit, so each check gets its own dir, removed once it's done.
+async fn check_reachable(repository: &str, commit: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(is_commit_hash(commit), "Invalid commit {:?}", commit);
+    let dir = claim_unique_temp_dir(&format!("validate-{}", commit)).await?;
+
+    git(dir.path(), &["init", "--bare", "--quiet"]).await?;
+    git(
+        dir.path(),
+        &["fetch", "--depth=1", "--quiet", "--", repository, commit],
+    )
+    .await
+    .with_context(|| format!("Commit {} isn't reachable in {}", commit, repository))?;
+    Ok(())
+}
+
+// Problems with the manifest, empty when it's fine
+pub async fn validate_manifest(path: &Path, fetch: bool) -> Vec<String> {
+    let data = match tokio::fs::read(path).await {
+        Ok(data) => data,
+        Err(err) => return vec![format!("Failed to read: {}", err)],
+    };
+
+    let (mut problems, source) = check_schema(path, &data);
+    if let Some((repository, commit)) = source.filter(|_| fetch) {
+        if let Err(err) = check_reachable(&repository, &commit).await {
+            problems.push(format!("{:#}", err));
+        }
+    }
+    problems
+}
+
+// Every manifest in the extensions repository's exts dir
+pub async fn list_manifests(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut read_dir = tokio::fs::read_dir(dir)
+        .await
+        .with_context(|| format!("Failed to read {}", dir.display()))?;
+    let mut paths = Vec::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMIT: &str = "0123456789abcdef0123456789ABCDEF01234567";
+
+    #[test]
+    fn checks_ids() {
+        assert!(is_valid_id("moonbase"));
+        assert!(is_valid_id("my-ext_2"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id("../ext"));
+        assert!(!is_valid_id("a/b"));
+        assert!(!is_valid_id("ext.json"));
+        assert!(!is_valid_id("-ext "));
+    }
+
+    #[test]
+    fn checks_commit_hashes() {
+        assert!(is_commit_hash(COMMIT));
+        assert!(!is_commit_hash(&COMMIT[1..]));
+        assert!(!is_commit_hash(&format!("{}0", COMMIT)));
+        assert!(!is_commit_hash("HEAD"));
+        assert!(!is_commit_hash("--output=/tmp/a"));
+        assert!(!is_commit_hash(&format!("-{}", &COMMIT[1..])));
+        assert!(!is_commit_hash(&format!("{}g", &COMMIT[1..])));
+    }
+
+    #[test]
+    fn accepts_valid_manifests() {
+        let data = format!(
+            r#"{{"repository":"https://github.com/moonlight-mod/ext","commit":"{}"}}"#,
+            COMMIT
+        );
+        let (problems, source) = check_schema(Path::new("exts/ext.json"), data.as_bytes());
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(
+            source,
+            Some((
+                "https://github.com/moonlight-mod/ext".to_string(),
+                COMMIT.to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn reports_invalid_manifests() {
+        let problems = |path: &str, data: &str| {
+            let (problems, source) = check_schema(Path::new(path), data.as_bytes());
+            assert_eq!(source, None);
+            problems.len()
+        };
+        let valid = format!(
+            r#"{{"repository":"https://github.com/moonlight-mod/ext","commit":"{}"}}"#,
+            COMMIT
+        );
+        assert_eq!(problems("exts/e x.json", &valid), 1);
+        assert_eq!(problems("exts/ext.txt", &valid), 1);
+        assert_eq!(problems("exts/ext.json", "{"), 1);
+        assert_eq!(problems("exts/ext.json", "[]"), 1);
+        assert_eq!(problems("exts/ext.json", "{}"), 2);
+        assert_eq!(
+            problems(
+                "exts/ext.json",
+                r#"{"repository":"--upload-pack=touch /tmp/a","commit":"main"}"#
+            ),
+            2
+        );
+        assert_eq!(
+            problems(
+                "exts/ext.json",
+                r#"{"repository":1,"commit":"0123456789abcdef0123456789abcdef0123456"}"#
+            ),
+            2
+        );
+    }
+}
@@ -0,0 +1,141 @@
+// Directory walking that's safe on adversarial trees: no recursion, symlink cycles, or escapes
+use super::{
+    paths,
+    scan::{Finding, FindingCategory, Origin, Severity},
+};
+use anyhow::Context;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+// Far deeper than any real extension, but keeps pathological trees from running forever
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Symlink {
+    // As written in the link
+    pub target: PathBuf,
+    // Dangling links and links outside the root aren't followed
+    pub followed: bool,
+    pub escapes: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    // path/to/file, relative to the root
+    pub relative: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub symlink: Option<Symlink>,
+}
+
+impl WalkEntry {
+    // Reading these would read whatever they point at, so they're listed but left alone
+    pub fn is_unfollowed_symlink(&self) -> bool {
+        self.symlink.as_ref().is_some_and(|link| !link.followed)
+    }
+
+    pub fn escape_finding(&self) -> Option<Finding> {
+        let link = self.symlink.as_ref().filter(|link| link.escapes)?;
+        Some(Finding {
+            category: FindingCategory::UnsafePath,
+            severity: Severity::Warning,
+            rule: "symlink-escape".to_string(),
+            message: format!(
+                "Symlink points outside the repository, at {}",
+                link.target.display()
+            ),
+            origin: Origin::Source,
+            path: self.relative.clone(),
+            line: None,
+            snippet: String::new(),
+        })
+    }
+}
+
+// Symlinks are only followed when they stay inside the root, and each directory is visited once.
+// Directories come before their contents. Symlinks that aren't followed are still listed.
+pub async fn walk_dir(root: &Path, skip_names: &[&str]) -> anyhow::Result<Vec<WalkEntry>> {
+    let root_canonical = tokio::fs::canonicalize(root)
+        .await
+        .with_context(|| format!("Failed to resolve {}", root.display()))?;
+    let mut visited = HashSet::from([root_canonical.clone()]);
+    let mut stack = vec![(paths::extended_length(root), String::new(), 0)];
+    let mut entries = Vec::new();
+
+    while let Some((dir, prefix, depth)) = stack.pop() {
+        if depth > MAX_DEPTH {
+            anyhow::bail!("{} is nested more than {} levels deep", prefix, MAX_DEPTH);
+        }
+
+        let mut read_dir = tokio::fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if skip_names.contains(&name.as_str()) {
+                continue;
+            }
+            let path = entry.path();
+            let relative = if prefix.is_empty() {
+                name
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+
+            let file_type = entry.file_type().await?;
+            let mut symlink = None;
+            let is_dir = if file_type.is_symlink() {
+                let target = tokio::fs::read_link(&path)
+                    .await
+                    .with_context(|| format!("Failed to read symlink {}", relative))?;
+                let (followed, escapes) = match tokio::fs::canonicalize(&path).await {
+                    Ok(resolved) if resolved.starts_with(&root_canonical) => (true, false),
+                    Ok(_) => {
+                        log::warn!("Not following symlink {} outside the tree", relative);
+                        (false, true)
+                    }
+                    Err(_) => {
+                        log::warn!("Not following dangling symlink {}", relative);
+                        (false, false)
+                    }
+                };
+                let is_dir = followed
+                    && tokio::fs::metadata(&path)
+                        .await
+                        .is_ok_and(|metadata| metadata.is_dir());
+                symlink = Some(Symlink {
+                    target,
+                    followed,
+                    escapes,
+                });
+                is_dir
+            } else {
+                file_type.is_dir()
+            };
+
+            if is_dir {
+                let canonical = tokio::fs::canonicalize(&path)
+                    .await
+                    .with_context(|| format!("Failed to resolve {}", relative))?;
+                if !visited.insert(canonical) {
+                    log::warn!(
+                        "Skipping {}, it was already visited through a symlink",
+                        relative
+                    );
+                    continue;
+                }
+                stack.push((path.clone(), relative.clone(), depth + 1));
+            }
+            entries.push(WalkEntry {
+                relative,
+                path,
+                is_dir,
+                symlink,
+            });
+        }
+    }
+
+    Ok(entries)
+}
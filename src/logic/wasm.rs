@@ -0,0 +1,150 @@
+use anyhow::Context;
+
+pub const MAGIC: &[u8] = b"\0asm";
+
+#[derive(Debug, Clone, Default)]
+pub struct WasmSummary {
+    pub size: usize,
+    // `module.name`, with the kind for anything that isn't a function
+    pub imports: Vec<String>,
+    pub exports: Vec<String>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> anyhow::Result<u8> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .context("Unexpected end of module")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).context("Length overflow")?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .context("Unexpected end of module")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn leb_u32(&mut self) -> anyhow::Result<u32> {
+        let mut result = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            result |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        anyhow::bail!("Invalid LEB128 integer")
+    }
+
+    fn name(&mut self) -> anyhow::Result<String> {
+        let len = self.leb_u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).to_string())
+    }
+
+    fn limits(&mut self) -> anyhow::Result<()> {
+        let flags = self.byte()?;
+        self.leb_u32()?;
+        if flags & 1 != 0 {
+            self.leb_u32()?;
+        }
+        Ok(())
+    }
+}
+
+fn kind_name(kind: u8) -> &'static str {
+    match kind {
+        0 => "func",
+        1 => "table",
+        2 => "memory",
+        3 => "global",
+        4 => "tag",
+        _ => "unknown",
+    }
+}
+
+fn format_entry(module: Option<&str>, name: &str, kind: u8) -> String {
+    let name = match module {
+        Some(module) => format!("{}.{}", module, name),
+        None => name.to_string(),
+    };
+    if kind == 0 {
+        name
+    } else {
+        format!("{} ({})", name, kind_name(kind))
+    }
+}
+
+// Only reads the import and export sections, which is enough to tell what the module can reach
+pub fn summarize(data: &[u8]) -> anyhow::Result<WasmSummary> {
+    if !data.starts_with(MAGIC) || data.len() < 8 {
+        anyhow::bail!("Not a WebAssembly module");
+    }
+
+    let mut summary = WasmSummary {
+        size: data.len(),
+        ..Default::default()
+    };
+    let mut reader = Reader { data, pos: 8 };
+
+    while reader.pos < data.len() {
+        let id = reader.byte()?;
+        let len = reader.leb_u32()? as usize;
+        let mut section = Reader {
+            data: reader.bytes(len)?,
+            pos: 0,
+        };
+
+        match id {
+            2 => {
+                for _ in 0..section.leb_u32()? {
+                    let module = section.name()?;
+                    let name = section.name()?;
+                    let kind = section.byte()?;
+                    match kind {
+                        0 | 4 => {
+                            if kind == 4 {
+                                section.byte()?;
+                            }
+                            section.leb_u32()?;
+                        }
+                        1 => {
+                            section.byte()?;
+                            section.limits()?;
+                        }
+                        2 => section.limits()?,
+                        3 => {
+                            section.byte()?;
+                            section.byte()?;
+                        }
+                        _ => anyhow::bail!("Unknown import kind {}", kind),
+                    }
+                    summary
+                        .imports
+                        .push(format_entry(Some(&module), &name, kind));
+                }
+            }
+            7 => {
+                for _ in 0..section.leb_u32()? {
+                    let name = section.name()?;
+                    let kind = section.byte()?;
+                    section.leb_u32()?;
+                    summary.exports.push(format_entry(None, &name, kind));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(summary)
+}
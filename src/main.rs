@@ -1,21 +1,276 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-pub mod logic;
-mod ui;
+use clap::{Parser, Subcommand};
+use robojules::{
+    check::{Output, Policy},
+    instance::{self, Instance, InstanceLink, InstanceRequest},
+    logic::{
+        config::{Config, Renderer},
+        scan::Severity,
+    },
+    App,
+};
+use std::path::PathBuf;
 
-fn main() -> eframe::Result {
-    env_logger::init();
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Renderer for the UI (glow, wgpu, software), overriding the one in the settings
+    #[arg(long)]
+    renderer: Option<String>,
+    /// Pull request to open, as a number, a GitHub link or a robojules://pr/ link. Handed to the
+    /// running window if there is one.
+    #[arg(long)]
+    open: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Expose the review pipeline over JSON-RPC on a local port instead of opening the UI
+    Serve {
+        #[arg(long, default_value_t = 7878)]
+        port: u16,
+    },
+    /// Review a pull request without the UI, exiting nonzero when findings exceed the policy
+    Check {
+        pr: u64,
+        /// Only check this extension
+        #[arg(long)]
+        extension: Option<String>,
+        /// Comma-separated finding categories that fail the check, e.g. dangerous-api,new-domain,repo-changed
+        #[arg(long, default_value = "")]
+        fail_on: String,
+        /// Fail on any finding at or above this severity (info, warning, danger)
+        #[arg(long)]
+        fail_on_severity: Option<String>,
+        /// Post the findings to the PR as a check run, using GITHUB_TOKEN
+        #[arg(long)]
+        publish: bool,
+        /// Build each extension and compare the output to the artifact, if builds are enabled in the settings
+        #[arg(long)]
+        build: bool,
+        /// Write the results as JSON to this file, or to stdout instead of the usual text with `-`
+        #[arg(long, num_args = 0..=1, default_missing_value = "-")]
+        json: Option<PathBuf>,
+    },
+    /// Check extension manifests and that their commits can be fetched, exiting nonzero if any are invalid
+    Validate {
+        manifests: Vec<PathBuf>,
+        /// Validate every manifest in exts/
+        #[arg(long)]
+        all: bool,
+        /// Only check the manifests' contents, without fetching their commits
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Work with .asar files directly
+    Asar {
+        #[command(subcommand)]
+        command: AsarCommand,
+    },
+    /// Scan a local working tree and built .asar against the published extension, like `check`
+    CheckLocal {
+        extension: String,
+        working_dir: PathBuf,
+        /// The built .asar, or a CI artifact containing it
+        asar: PathBuf,
+        #[arg(long, default_value = "")]
+        fail_on: String,
+        #[arg(long)]
+        fail_on_severity: Option<String>,
+        #[arg(long)]
+        build: bool,
+        #[arg(long, num_args = 0..=1, default_missing_value = "-")]
+        json: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AsarCommand {
+    /// Extract an .asar into a directory, skipping entries that would escape it
+    Extract { file: PathBuf, dir: PathBuf },
+    /// Pack a directory into an .asar
+    Pack { dir: PathBuf, file: PathBuf },
+}
 
-    let native_options = eframe::NativeOptions {
+// Exit codes for `check`, `check-local` and `validate`, so CI can tell a failed policy from a failed run
+const EXIT_POLICY_FAILED: i32 = 1;
+const EXIT_ERROR: i32 = 2;
+
+fn native_options(renderer: Renderer) -> eframe::NativeOptions {
+    let mut native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default(),
         ..Default::default()
     };
+    match renderer {
+        Renderer::Glow => native_options.renderer = eframe::Renderer::Glow,
+        Renderer::Wgpu => native_options.renderer = eframe::Renderer::Wgpu,
+        Renderer::Software => {
+            native_options.renderer = eframe::Renderer::Glow;
+            native_options.hardware_acceleration = eframe::HardwareAcceleration::Off;
+        }
+    }
+    native_options
+}
+
+// Failures to create a graphics context, as opposed to the app failing once it's running
+fn is_renderer_error(err: &eframe::Error) -> bool {
+    matches!(
+        err,
+        eframe::Error::Glutin(_)
+            | eframe::Error::NoGlutinConfigs(..)
+            | eframe::Error::OpenGL(_)
+            | eframe::Error::Wgpu(_)
+    )
+}
+
+fn run_ui(renderer: Option<Renderer>, link: InstanceLink) -> eframe::Result {
+    let preferred = renderer.unwrap_or_else(|| Config::load().renderer);
+    let fallbacks = Renderer::ALL
+        .into_iter()
+        .filter(|renderer| *renderer != preferred);
+
+    let mut last_err = None;
+    for renderer in std::iter::once(preferred).chain(fallbacks) {
+        log::debug!("Starting the UI with the {} renderer", renderer.id());
+        let link = link.clone();
+        match eframe::run_native(
+            "RoboJules",
+            native_options(renderer),
+            Box::new(move |cc| Ok(Box::new(App::new(cc).with_instance(&cc.egui_ctx, link)))),
+        ) {
+            Ok(()) => return Ok(()),
+            Err(err) if is_renderer_error(&err) => {
+                log::warn!("The {} renderer failed to start: {}", renderer.id(), err);
+                last_err = Some(err);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    match last_err {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+fn parse_policy(fail_on: &str, fail_on_severity: Option<&str>) -> anyhow::Result<Policy> {
+    let fail_on_severity = fail_on_severity
+        .map(|text| {
+            Severity::parse(text).ok_or_else(|| anyhow::anyhow!("Unknown severity {}", text))
+        })
+        .transpose()?;
+    Ok(Policy {
+        fail_on: Policy::parse_categories(fail_on)?,
+        fail_on_severity,
+    })
+}
+
+fn exit_with_check(res: anyhow::Result<bool>) {
+    match res {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(EXIT_POLICY_FAILED),
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    robojules::crash::init();
+    let args = Args::parse();
 
-    eframe::run_native(
-        "RoboJules",
-        native_options,
-        Box::new(|cc| Ok(Box::new(ui::App::new(cc)))),
-    )?;
+    match args.command {
+        Some(Command::Serve { port }) => {
+            let runtime = tokio::runtime::Runtime::new()?;
+            runtime.block_on(robojules::server::serve(port))?;
+        }
+        Some(Command::Check {
+            pr,
+            extension,
+            fail_on,
+            fail_on_severity,
+            publish,
+            build,
+            json,
+        }) => {
+            let policy = parse_policy(&fail_on, fail_on_severity.as_deref());
+            let output = Output {
+                json: json.as_deref(),
+                build,
+            };
+            exit_with_check(policy.and_then(|policy| {
+                robojules::check::check(pr, extension.as_deref(), &policy, publish, &output)
+            }));
+        }
+        Some(Command::Validate {
+            manifests,
+            all,
+            offline,
+        }) => {
+            let exts_dir = all.then(|| PathBuf::from("exts"));
+            exit_with_check(robojules::check::validate(
+                manifests,
+                exts_dir.as_deref(),
+                !offline,
+            ));
+        }
+        Some(Command::Asar { command }) => {
+            let runtime = tokio::runtime::Runtime::new()?;
+            match command {
+                AsarCommand::Extract { file, dir } => {
+                    runtime.block_on(robojules::asar_tool::extract(&file, &dir))?
+                }
+                AsarCommand::Pack { dir, file } => {
+                    runtime.block_on(robojules::asar_tool::pack(&dir, &file))?
+                }
+            }
+        }
+        Some(Command::CheckLocal {
+            extension,
+            working_dir,
+            asar,
+            fail_on,
+            fail_on_severity,
+            build,
+            json,
+        }) => {
+            let policy = parse_policy(&fail_on, fail_on_severity.as_deref());
+            let output = Output {
+                json: json.as_deref(),
+                build,
+            };
+            exit_with_check(policy.and_then(|policy| {
+                robojules::check::check_local(&extension, &working_dir, &asar, &policy, &output)
+            }));
+        }
+        None => {
+            let renderer = args
+                .renderer
+                .as_deref()
+                .map(|text| {
+                    Renderer::parse(text)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown renderer {}", text))
+                })
+                .transpose()?;
+            let open = args
+                .open
+                .as_deref()
+                .map(|link| {
+                    instance::parse_link(link)
+                        .ok_or_else(|| anyhow::anyhow!("Not a pull request: {}", link))
+                })
+                .transpose()?;
+            let guard = match instance::acquire(InstanceRequest { open }) {
+                Instance::Running(guard) => guard,
+                Instance::Forwarded => return Ok(()),
+            };
+            run_ui(renderer, guard.link()).map_err(|err| anyhow::anyhow!("{}", err))?
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,256 @@
+// The JSON written by `check --json`, kept separate from the logic types so their changes don't break bots
+use crate::logic::{
+    build::{BuildOutcome, LocalBuild},
+    diff::{DiffedExtension, Directory, FileState, FilesystemItem, PullRequestUpdate},
+    scan::{Finding, Origin, Severity},
+};
+use serde::Serialize;
+
+// Bumped when a field is removed or changes meaning, new fields don't bump it
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub version: u32,
+    // None for `check-local`
+    pub pull_request: Option<PullRequestReport>,
+    pub passed: bool,
+    pub violations: usize,
+    pub extensions: Vec<ExtensionReport>,
+    pub check_run_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequestReport {
+    pub number: u64,
+    pub head_sha: String,
+    pub title: String,
+    pub author: String,
+    pub artifact_url: String,
+}
+
+impl PullRequestReport {
+    pub fn new(update: &PullRequestUpdate) -> Self {
+        Self {
+            number: update.number,
+            head_sha: update.head_sha.clone(),
+            title: update.title.clone(),
+            author: update.author.clone(),
+            artifact_url: update.artifact_url.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionReport {
+    pub id: String,
+    pub repository: String,
+    pub old_commit: String,
+    pub new_commit: String,
+    pub violations: usize,
+    // Percent of changed .asar files explained by a source or dependency change
+    pub consistency_score: u8,
+    pub unexplained_files: Vec<String>,
+    pub files: Vec<FileReport>,
+    pub findings: Vec<FindingReport>,
+    pub build: BuildReport,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileReport {
+    // "source" or "asar"
+    pub origin: &'static str,
+    pub path: String,
+    // "modified", "added", "removed" or "modeChanged"
+    pub state: &'static str,
+    pub added_lines: usize,
+    pub removed_lines: usize,
+    pub build_noise: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindingReport {
+    pub category: &'static str,
+    // "info", "warning" or "danger"
+    pub severity: &'static str,
+    pub rule: String,
+    pub message: String,
+    pub origin: &'static str,
+    pub path: String,
+    pub line: Option<usize>,
+    // Whether this finding fails the policy
+    pub violation: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BuildStatus {
+    // Not asked for with `--build`
+    NotRun,
+    // The build couldn't be started, see `error`
+    Error,
+    Failed,
+    // The build succeeded, but the configured output directory is missing
+    MissingOutput,
+    // The build output is the same as the artifact
+    Matches,
+    Differs,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildReport {
+    pub status: BuildStatus,
+    // Files that differ between the local build and the artifact, ignoring build noise
+    pub differing_files: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl BuildReport {
+    pub fn not_run() -> Self {
+        Self {
+            status: BuildStatus::NotRun,
+            differing_files: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn new(res: Result<&LocalBuild, String>) -> Self {
+        let build = match res {
+            Ok(build) => build,
+            Err(err) => {
+                return Self {
+                    status: BuildStatus::Error,
+                    differing_files: Vec::new(),
+                    error: Some(err),
+                }
+            }
+        };
+        let diff = match (build.outcome, &build.diff) {
+            (BuildOutcome::Succeeded, Some(diff)) => diff,
+            (BuildOutcome::MissingOutput, _) => {
+                return Self {
+                    status: BuildStatus::MissingOutput,
+                    differing_files: Vec::new(),
+                    error: None,
+                }
+            }
+            _ => {
+                return Self {
+                    status: BuildStatus::Failed,
+                    differing_files: Vec::new(),
+                    error: None,
+                }
+            }
+        };
+
+        let differing_files = file_reports(origin_id(Origin::Asar), &diff.dir)
+            .into_iter()
+            .filter(|file| !file.build_noise)
+            .map(|file| file.path)
+            .collect::<Vec<_>>();
+        Self {
+            status: if differing_files.is_empty() {
+                BuildStatus::Matches
+            } else {
+                BuildStatus::Differs
+            },
+            differing_files,
+            error: None,
+        }
+    }
+}
+
+fn origin_id(origin: Origin) -> &'static str {
+    match origin {
+        Origin::Source => "source",
+        Origin::Asar => "asar",
+    }
+}
+
+fn severity_id(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Info => "info",
+        Severity::Warning => "warning",
+        Severity::Danger => "danger",
+    }
+}
+
+fn state_id(state: FileState) -> &'static str {
+    match state {
+        FileState::Modified => "modified",
+        FileState::Added => "added",
+        FileState::Removed => "removed",
+        FileState::ModeChanged => "modeChanged",
+        FileState::Unchanged => "unchanged",
+    }
+}
+
+fn file_reports(origin: &'static str, dir: &Directory) -> Vec<FileReport> {
+    fn walk(origin: &'static str, dir: &Directory, prefix: &str, output: &mut Vec<FileReport>) {
+        for item in dir {
+            match item {
+                FilesystemItem::File { name, state, stats } => output.push(FileReport {
+                    origin,
+                    path: format!("{}{}", prefix, name),
+                    state: state_id(*state),
+                    added_lines: stats.added_lines,
+                    removed_lines: stats.removed_lines,
+                    build_noise: stats.build_noise,
+                }),
+                FilesystemItem::Directory { name, children } => {
+                    let prefix = format!("{}{}/", prefix, name.as_deref().unwrap_or_default());
+                    walk(origin, children, &prefix, output);
+                }
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    walk(origin, dir, "", &mut output);
+    output
+}
+
+impl ExtensionReport {
+    pub fn new(
+        diffed: &DiffedExtension,
+        violates: impl Fn(&Finding) -> bool,
+        build: BuildReport,
+    ) -> Self {
+        let mut files = file_reports(origin_id(Origin::Source), &diffed.source_diff.dir);
+        files.extend(file_reports(origin_id(Origin::Asar), &diffed.asar_diff.dir));
+        let findings = diffed
+            .findings
+            .iter()
+            .map(|finding| FindingReport {
+                category: finding.category.id(),
+                severity: severity_id(finding.severity),
+                rule: finding.rule.clone(),
+                message: finding.message.clone(),
+                origin: origin_id(finding.origin),
+                path: finding.path.clone(),
+                line: finding.line,
+                violation: violates(finding),
+            })
+            .collect::<Vec<_>>();
+
+        let ext = &diffed.extension;
+        Self {
+            id: ext.id.clone(),
+            repository: ext.new_repository.clone().unwrap_or(ext.repository.clone()),
+            old_commit: ext.old_commit.clone(),
+            new_commit: ext.new_commit.clone(),
+            violations: findings.iter().filter(|finding| finding.violation).count(),
+            consistency_score: diffed.consistency.score(),
+            unexplained_files: diffed.consistency.unexplained.clone(),
+            files,
+            findings,
+            build,
+        }
+    }
+}
@@ -0,0 +1,443 @@
+// Newline-delimited JSON-RPC 2.0 over a local TCP socket, driving the same logic thread as the UI
+use crate::logic::{
+    app_logic_thread, atomic, config, download, validate, LogicCommand, LogicError, LogicResponse,
+    LogicResult,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const SERVER_ERROR: i64 = -32000;
+const UNAUTHORIZED: i64 = -32001;
+// Requests are a few kilobytes at most, so a line longer than this isn't one. Checked before the
+// token, so a client that never authenticates can't make us buffer without end.
+const MAX_LINE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct ErrorObject {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorObject>,
+}
+
+impl Response {
+    fn result(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    fn error(id: Value, code: i64, message: String) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(ErrorObject {
+                code,
+                message,
+                data: None,
+            }),
+        }
+    }
+
+    // Includes the error kind so clients can decide whether to retry
+    fn logic_error(id: Value, err: LogicError) -> Self {
+        let data = serde_json::json!({
+            "kind": err,
+            "retryable": err.is_retryable(),
+            "hint": err.hint(),
+        });
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(ErrorObject {
+                code: SERVER_ERROR,
+                message: err.to_string(),
+                data: Some(data),
+            }),
+        }
+    }
+}
+
+fn to_json<T: Serialize>(res: LogicResult<T>) -> LogicResult<Value> {
+    res.and_then(|value| {
+        serde_json::to_value(value).map_err(|err| LogicError::from(err.to_string()))
+    })
+}
+
+impl LogicResponse {
+    pub fn into_json(self) -> LogicResult<Value> {
+        match self {
+            LogicResponse::PullRequest(res) => to_json(res),
+            LogicResponse::PullRequests(res) => to_json(res),
+            LogicResponse::AuthStatus(res) => to_json(res),
+            LogicResponse::ExtensionDownloadComplete(res) => to_json(res),
+            LogicResponse::Overview(overview) => to_json(Ok(overview)),
+            LogicResponse::FileDiff(res) => to_json(res),
+            LogicResponse::File(res) => to_json(res),
+            LogicResponse::GrammarInstalled(res) => to_json(res),
+            LogicResponse::FileHistory { revisions, .. } => to_json(revisions),
+            LogicResponse::RevisionDiff { diff, .. } => to_json(diff),
+            LogicResponse::Blame { lines, .. } => to_json(lines),
+            LogicResponse::DependencyRisks(res) => to_json(res),
+            LogicResponse::Advisories(res) => to_json(res),
+            LogicResponse::Dependents(res) => to_json(res),
+            LogicResponse::LocalBuild(res) => to_json(res),
+            LogicResponse::Provenance(res) => to_json(res),
+            LogicResponse::Timings(timings) => to_json(Ok(timings)),
+            LogicResponse::Progress(stages) => to_json(Ok(stages)),
+            LogicResponse::Bandwidth(usage) => to_json(Ok(usage)),
+            LogicResponse::AuditLog(entries) => to_json(Ok(entries)),
+            LogicResponse::PrefetchStatus(items) => to_json(Ok(items)),
+            LogicResponse::SessionExported(res) => to_json(res),
+            LogicResponse::SessionImported(res) => to_json(res),
+            LogicResponse::ReviewsSaved(res) => to_json(res),
+            LogicResponse::ReviewsLoaded { reviews, .. } => to_json(Ok(reviews)),
+            LogicResponse::ReviewStatuses(statuses) => to_json(Ok(statuses)),
+            LogicResponse::ReviewSynced(res) => to_json(res),
+            LogicResponse::CheckRunPublished(res) => to_json(res),
+            LogicResponse::ReviewSubmitted(res) => to_json(res),
+            LogicResponse::AuditLogExported(res) => to_json(res),
+            LogicResponse::ExtensionApproved(res) => to_json(res),
+            LogicResponse::Tools(checks) => to_json(Ok(checks)),
+            LogicResponse::SelfTest(steps) => to_json(Ok(steps)),
+        }
+    }
+}
+
+// The logic thread answers commands in order, so one request is in flight at a time
+struct Logic {
+    tx: flume::Sender<LogicCommand>,
+    rx: flume::Receiver<LogicResponse>,
+}
+
+impl Logic {
+    async fn call(&self, command: LogicCommand) -> anyhow::Result<LogicResult<Value>> {
+        self.tx
+            .send_async(command)
+            .await
+            .context("Logic thread stopped")?;
+        let response = self.rx.recv_async().await.context("Logic thread stopped")?;
+        Ok(response.into_json())
+    }
+}
+
+// Clients read this to authenticate, so only the current user's processes can drive RoboJules
+fn token_path() -> PathBuf {
+    config::config_dir().join("rpc-token")
+}
+
+fn new_token() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).context("Failed to generate RPC token")?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Paths from clients have to be in the temp dir, where everything RoboJules downloads goes.
+// Files that were added or removed only exist on one side, so the closest existing parent is
+// what gets checked.
+fn is_in_temp_root(path: &Path, root: &Path) -> bool {
+    !path
+        .components()
+        .any(|component| component == Component::ParentDir)
+        && path
+            .ancestors()
+            .find_map(|dir| std::fs::canonicalize(dir).ok())
+            .is_some_and(|dir| dir.starts_with(root))
+}
+
+fn check_command(command: &LogicCommand) -> Result<(), String> {
+    let paths: Vec<PathBuf> = match command {
+        LogicCommand::DiffFile { old, new, .. } => vec![old.clone(), new.clone()],
+        // Revisions go on git's command line, where anything else could be read as an option
+        LogicCommand::GetFileHistory {
+            repository_dir,
+            rev,
+            ..
+        } => {
+            if !validate::is_commit_hash(rev) {
+                return Err("Revisions need full commit hashes".to_string());
+            }
+            vec![repository_dir.clone()]
+        }
+        LogicCommand::BlameFile {
+            repository_dir,
+            old_rev,
+            new_rev,
+            ..
+        } => {
+            if ![old_rev, new_rev]
+                .iter()
+                .all(|rev| validate::is_commit_hash(rev))
+            {
+                return Err("Revisions need full commit hashes".to_string());
+            }
+            vec![repository_dir.clone()]
+        }
+        // Revisions are checked out to dirs named after their hashes
+        LogicCommand::DiffRevisions {
+            repository_dir,
+            old,
+            new,
+            ..
+        } => {
+            if ![old, new]
+                .iter()
+                .all(|rev| validate::is_commit_hash(&rev.hash))
+            {
+                return Err("Revisions need full commit hashes".to_string());
+            }
+            vec![repository_dir.clone()]
+        }
+        LogicCommand::GetProvenance {
+            dist_dir,
+            source_dir,
+            path,
+        } => vec![dist_dir.join(path), source_dir.clone()],
+        // The ID and head end up in temp dir names, and the repository is cloned
+        LogicCommand::DownloadExtension {
+            extension,
+            artifact_url,
+            head_sha,
+            ..
+        } => {
+            if !validate::is_valid_id(&extension.id) {
+                return Err(format!("Invalid extension ID {:?}", extension.id));
+            }
+            if !head_sha.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("Invalid head {:?}", head_sha));
+            }
+            let urls = [
+                Some(&extension.repository),
+                extension.new_repository.as_ref(),
+            ];
+            if !urls
+                .into_iter()
+                .flatten()
+                .chain([artifact_url])
+                .all(|url| url.starts_with("https://"))
+            {
+                return Err("Repositories and artifacts have to be HTTPS URLs".to_string());
+            }
+            Vec::new()
+        }
+        _ => Vec::new(),
+    };
+    if paths.is_empty() {
+        return Ok(());
+    }
+
+    let root = std::fs::canonicalize(download::temp_root())
+        .map_err(|_| "Nothing has been downloaded yet".to_string())?;
+    match paths.iter().find(|path| !is_in_temp_root(path, &root)) {
+        Some(path) => Err(format!("{} isn't in {}", path.display(), root.display())),
+        None => Ok(()),
+    }
+}
+
+async fn handle_request(logic: &Mutex<Logic>, request: Request) -> Response {
+    let command = serde_json::json!({ "method": request.method, "params": request.params });
+    let command = match serde_json::from_value::<LogicCommand>(command) {
+        Ok(command) => command,
+        Err(err) => return Response::error(request.id, INVALID_REQUEST, err.to_string()),
+    };
+    if let Err(err) = check_command(&command) {
+        return Response::error(request.id, INVALID_REQUEST, err);
+    }
+
+    log::debug!("RPC request {}: {:?}", request.id, command);
+    match logic.lock().await.call(command).await {
+        Ok(Ok(result)) => Response::result(request.id, result),
+        Ok(Err(err)) => Response::logic_error(request.id, err),
+        Err(err) => Response::error(request.id, SERVER_ERROR, format!("{:?}", err)),
+    }
+}
+
+async fn handle_connection(
+    logic: Arc<Mutex<Logic>>,
+    token: Arc<String>,
+    stream: TcpStream,
+) -> anyhow::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut authenticated = false;
+
+    loop {
+        let mut line = Vec::new();
+        let read = (&mut reader)
+            .take(MAX_LINE_BYTES)
+            .read_until(b'\n', &mut line)
+            .await?;
+        if read == 0 {
+            break;
+        }
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+
+        // Whatever sent a broken or overlong line or the wrong token isn't a client worth
+        // talking to further
+        let request = if read as u64 == MAX_LINE_BYTES && !line.ends_with(b"\n") {
+            Err(format!(
+                "Requests can't be longer than {} bytes",
+                MAX_LINE_BYTES
+            ))
+        } else {
+            serde_json::from_slice::<Request>(&line).map_err(|err| err.to_string())
+        };
+        let (response, close) = match request {
+            Err(err) => (Response::error(Value::Null, PARSE_ERROR, err), true),
+            Ok(request) if request.method == "authenticate" => {
+                authenticated = request.params.as_str() == Some(token.as_str());
+                if authenticated {
+                    (Response::result(request.id, Value::Bool(true)), false)
+                } else {
+                    let message = "Wrong token".to_string();
+                    (Response::error(request.id, UNAUTHORIZED, message), true)
+                }
+            }
+            Ok(request) if !authenticated => {
+                let message = format!("Authenticate with the token in {}", token_path().display());
+                (Response::error(request.id, UNAUTHORIZED, message), true)
+            }
+            Ok(request) => (handle_request(&logic, request).await, false),
+        };
+
+        let mut data = serde_json::to_vec(&response)?;
+        data.push(b'\n');
+        writer.write_all(&data).await?;
+        if close {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn serve(port: u16) -> anyhow::Result<()> {
+    let (main_tx, logic_rx) = flume::unbounded::<LogicCommand>();
+    let (logic_tx, main_rx) = flume::unbounded::<LogicResponse>();
+    std::thread::spawn(move || app_logic_thread(logic_rx, logic_tx));
+    let logic = Arc::new(Mutex::new(Logic {
+        tx: main_tx,
+        rx: main_rx,
+    }));
+
+    let token = Arc::new(new_token()?);
+    let path = token_path();
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .context("Failed to create config dir")?;
+    }
+    atomic::write_private_blocking(&path, token.as_str())?;
+    log::info!("RPC token written to {}", path.display());
+
+    // Only listen locally, anything that can connect can make RoboJules download and diff things
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .with_context(|| format!("Failed to listen on port {}", port))?;
+    log::info!("Listening for JSON-RPC on 127.0.0.1:{}", port);
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        log::debug!("RPC connection from {}", addr);
+        let logic = logic.clone();
+        let token = token.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(logic, token, stream).await {
+                log::warn!("RPC connection error: {:?}", err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn command(method: &str, params: Value) -> LogicCommand {
+        serde_json::from_value(serde_json::json!({ "method": method, "params": params })).unwrap()
+    }
+
+    #[test]
+    fn rejects_option_like_revisions() {
+        for rev in ["--output=/tmp/a", "-p", "HEAD", "main"] {
+            let history = command(
+                "getFileHistory",
+                serde_json::json!({ "repositoryDir": "/tmp", "rev": rev, "path": "index.js" }),
+            );
+            assert_eq!(
+                check_command(&history),
+                Err("Revisions need full commit hashes".to_string())
+            );
+
+            for (old, new) in [(rev, COMMIT), (COMMIT, rev)] {
+                let blame = command(
+                    "blameFile",
+                    serde_json::json!({
+                        "repositoryDir": "/tmp",
+                        "oldRev": old,
+                        "newRev": new,
+                        "path": "index.js",
+                    }),
+                );
+                assert_eq!(
+                    check_command(&blame),
+                    Err("Revisions need full commit hashes".to_string())
+                );
+            }
+        }
+    }
+
+    // Hashes get past the revision check, and on to where the repository is
+    #[test]
+    fn checks_repository_dir_after_revisions() {
+        let history = command(
+            "getFileHistory",
+            serde_json::json!({ "repositoryDir": "/", "rev": COMMIT, "path": "index.js" }),
+        );
+        assert_ne!(
+            check_command(&history),
+            Err("Revisions need full commit hashes".to_string())
+        );
+        assert!(check_command(&history).is_err());
+    }
+}
@@ -1,37 +1,107 @@
-use crate::logic::diff::{Diff, Directory, FileState, FilesystemItem};
+use super::{
+    palette::DiffColors,
+    state::{AsyncState, DiffDisplay},
+};
+use crate::{
+    logic::{
+        audit::{AuditAction, AuditEntry},
+        bandwidth::BandwidthUsage,
+        bundle::{ModuleChangeKind, ModuleDiff},
+        dependents::Dependent,
+        deps::{Advisory, DependencyChange, DependencyChangeKind, DependencyRisk},
+        diff::{
+            Diff, Directory, DirectorySummary, FileDiff, FileState, FileStats, FilesystemItem,
+            PullRequestUpdate, SingleFile,
+        },
+        git::BlameLine,
+        icons::{DecodedImage, ImageChange, ImageKind, ManifestImage},
+        overview::{ExtensionOverview, ExtensionStats, OverviewState},
+        patches::{Patch, PatchChange, PatchChangeKind},
+        paths::RenamedPath,
+        policy::ReviewPolicy,
+        pr::{CiStatus, PullRequestSummary},
+        prefetch::{PrefetchItem, PrefetchState},
+        provenance::{Consistency, Provenance},
+        scan::{Finding, Origin, Severity},
+        session::{FileKey, ReviewState, ReviewStatus, Triage, TriageStatus},
+        sync::TeamReview,
+        syntax::{HighlightKind, OutlineEntry, OutlineKind},
+        timing::{StageProgress, Timing},
+        AuthStatus, LogicError,
+    },
+    widget::{self, CommentTarget, DiffLayout, DiffStyle, DiffView},
+};
 use anstyle_parse::{DefaultCharAccumulator, Params, Parser, Perform};
 use egui::{text::LayoutJob, FontId};
+use std::collections::BTreeMap;
 
 fn draw_dir(
     ui: &mut egui::Ui,
     current_file: &mut Option<String>,
+    current_dir: &mut Option<String>,
     root: Option<String>,
     folder: &Directory,
+    hide_noise: bool,
+    review_marker: &dyn Fn(&str) -> String,
 ) -> bool {
     let mut modified = false;
 
     for item in folder {
         match item {
-            FilesystemItem::File { name, state } => {
+            FilesystemItem::File { name, state, stats } => {
+                if hide_noise && stats.build_noise {
+                    continue;
+                }
+
                 let selected = current_file.as_deref() == Some(&name);
                 let state_text = match state {
                     FileState::Added => "+",
                     FileState::Removed => "-",
                     FileState::Modified => "~",
+                    FileState::ModeChanged => "*",
+                    FileState::Unchanged => " ",
+                };
+                let mode_text = match stats.executable_change {
+                    Some(true) => " (+x)",
+                    Some(false) => " (-x)",
+                    None => "",
+                };
+                let noise_text = if stats.build_noise { " (noise)" } else { "" };
+                // Added and removed files are one big change, so only count for modified ones
+                let hunks_text = match (state, stats.hunks) {
+                    (FileState::Modified, 1) => " (1 hunk)".to_string(),
+                    (FileState::Modified, hunks) if hunks > 1 => format!(" ({} hunks)", hunks),
+                    _ => String::new(),
                 };
-                let state_name = format!("{} {}", state_text, name);
 
                 let full_path = if let Some(ref root) = root {
                     format!("{}/{}", root, name)
                 } else {
                     name.clone()
                 };
+                let reviewed_text = review_marker(&full_path);
+                let state_name = format!(
+                    "{} {}{}{}{}{}",
+                    state_text, name, hunks_text, mode_text, noise_text, reviewed_text
+                );
 
                 ui.push_id(full_path.clone(), |ui| {
                     let old_wrap_mode = ui.style().wrap_mode;
                     ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
-                    if ui.selectable_label(selected, state_name.clone()).clicked() {
+                    let colors = DiffColors::get(ui.ctx());
+                    let label = egui::RichText::new(state_name);
+                    let label = match state {
+                        _ if stats.build_noise => label.weak(),
+                        FileState::Added => label.color(colors.added),
+                        FileState::Removed => label.color(colors.removed),
+                        FileState::Modified | FileState::ModeChanged => {
+                            label.color(colors.modified)
+                        }
+                        FileState::Unchanged => label.weak(),
+                    };
+                    if ui.selectable_label(selected, label).clicked() {
                         *current_file = Some(full_path);
+                        *current_dir = None;
                         modified = true;
                     }
                     ui.style_mut().wrap_mode = old_wrap_mode;
@@ -46,12 +116,31 @@ fn draw_dir(
                     name.to_string()
                 };
 
+                // In the full tree, directories without changes fade into the background
+                let header = egui::RichText::new(format!("{}/", name));
+                let header = if contains_changes(children) {
+                    header
+                } else {
+                    header.weak()
+                };
                 ui.push_id(full_path.clone(), |ui| {
-                    ui.collapsing(format!("{}/", name), |ui| {
-                        if draw_dir(ui, current_file, Some(full_path), children) {
+                    let response = egui::CollapsingHeader::new(header).show(ui, |ui| {
+                        if draw_dir(
+                            ui,
+                            current_file,
+                            current_dir,
+                            Some(full_path.clone()),
+                            children,
+                            hide_noise,
+                            review_marker,
+                        ) {
                             modified = true;
                         }
                     });
+                    if response.header_response.clicked() {
+                        *current_dir = Some(full_path.clone());
+                        *current_file = None;
+                    }
                 });
             }
         }
@@ -60,24 +149,377 @@ fn draw_dir(
     modified
 }
 
+fn contains_changes(dir: &Directory) -> bool {
+    dir.iter().any(|item| match item {
+        FilesystemItem::File { state, .. } => *state != FileState::Unchanged,
+        FilesystemItem::Directory { children, .. } => contains_changes(children),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn draw_diffed_extension_sidebar(
     ui: &mut egui::Ui,
     current_file: &mut Option<String>,
+    current_dir: &mut Option<String>,
     diff: &Diff,
+    full_tree: bool,
+    hide_noise: bool,
+    review_marker: &dyn Fn(&str) -> String,
 ) -> bool {
     let mut modified = false;
     ui.vertical(|ui| {
-        modified = draw_dir(ui, current_file, None, &diff.dir);
+        modified = draw_dir(
+            ui,
+            current_file,
+            current_dir,
+            None,
+            if full_tree { &diff.full_dir } else { &diff.dir },
+            hide_noise,
+            review_marker,
+        );
     });
     modified
 }
 
+pub fn draw_renamed_paths(ui: &mut egui::Ui, renamed: &[RenamedPath]) {
+    egui::CollapsingHeader::new(format!("Renamed files ({})", renamed.len())).show(ui, |ui| {
+        ui.label("These names can't be created on Windows, so they're shown as:");
+        for path in renamed {
+            ui.monospace(format!("{} -> {}", path.original, path.renamed));
+        }
+    });
+}
+
+pub fn format_age(timestamp: i64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default();
+    let age = (now - timestamp).max(0);
+
+    match age {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", age / 60),
+        3600..86400 => format!("{}h ago", age / 3600),
+        86400..2592000 => format!("{}d ago", age / 86400),
+        2592000..31536000 => format!("{}mo ago", age / 2592000),
+        _ => format!("{}y ago", age / 31536000),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+fn format_size_delta(delta: i64) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(delta.unsigned_abs()))
+}
+
+pub fn draw_bandwidth(ui: &mut egui::Ui, bandwidth: &BandwidthUsage) {
+    let total = bandwidth.total;
+    ui.label(format!(
+        "Downloaded {} this session",
+        format_size(total.total())
+    ))
+    .on_hover_ui(|ui| {
+        // Local reviews and dependency lookups aren't tied to a pull request
+        let mut other = total;
+        for usage in bandwidth.pull_requests.values() {
+            other.artifacts = other.artifacts.saturating_sub(usage.artifacts);
+            other.asars = other.asars.saturating_sub(usage.asars);
+            other.clones = other.clones.saturating_sub(usage.clones);
+            other.other = other.other.saturating_sub(usage.other);
+        }
+        let rows = bandwidth
+            .pull_requests
+            .iter()
+            .map(|(number, usage)| (format!("#{}", number), *usage))
+            .chain((other.total() > 0).then(|| ("Other".to_string(), other)));
+
+        egui::Grid::new("bandwidth").striped(true).show(ui, |ui| {
+            for header in ["", "Artifacts", ".asars", "Clones", "Other", "Total"] {
+                ui.strong(header);
+            }
+            ui.end_row();
+            for (name, usage) in rows {
+                ui.label(name);
+                for bytes in [
+                    usage.artifacts,
+                    usage.asars,
+                    usage.clones,
+                    usage.other,
+                    usage.total(),
+                ] {
+                    ui.label(format_size(bytes));
+                }
+                ui.end_row();
+            }
+        });
+    });
+}
+
+fn describe_stage(stage: &StageProgress) -> String {
+    let mut text = format!("{} ({:.1}s", stage.stage, stage.elapsed.as_secs_f32());
+    match (stage.bytes, stage.total_bytes) {
+        (Some(bytes), Some(total)) => {
+            text += &format!(", {} of {}", format_size(bytes), format_size(total))
+        }
+        (Some(bytes), None) => text += &format!(", {}", format_size(bytes)),
+        _ => {}
+    }
+    if let Some(items) = &stage.items {
+        text += &format!(", {} {}/{}", items.phase, items.done, items.total);
+    }
+    text.push(')');
+    text
+}
+
+// A bar for each stage that knows how far along it is, for long downloads and clones
+pub fn draw_progress(ui: &mut egui::Ui, stages: &[StageProgress]) {
+    if stages.iter().all(|stage| stage.fraction().is_none()) {
+        draw_busy(ui, stages);
+        return;
+    }
+    for stage in stages {
+        match stage.fraction() {
+            Some(fraction) => {
+                ui.add(egui::ProgressBar::new(fraction).text(describe_stage(stage)));
+            }
+            None => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.weak(describe_stage(stage));
+                });
+            }
+        }
+    }
+}
+
+// A spinner that says what it's waiting on, on hover and in a line that expands to every stage
+pub fn draw_busy(ui: &mut egui::Ui, stages: &[StageProgress]) {
+    let spinner = ui.spinner();
+    let Some(latest) = stages.last() else {
+        return;
+    };
+    let details = stages
+        .iter()
+        .map(describe_stage)
+        .collect::<Vec<_>>()
+        .join("\n");
+    spinner.on_hover_text(details);
+    egui::CollapsingHeader::new(describe_stage(latest))
+        .id_salt("busy_details")
+        .show(ui, |ui| {
+            for stage in stages {
+                ui.weak(describe_stage(stage));
+            }
+        });
+}
+
+fn stats_row(ui: &mut egui::Ui, stats: &FileStats) {
+    let colors = DiffColors::get(ui.ctx());
+    ui.colored_label(colors.added, format!("+{}", stats.added_lines));
+    ui.colored_label(colors.removed, format!("-{}", stats.removed_lines));
+    ui.label(format_size_delta(stats.size_delta()));
+}
+
+pub fn draw_mode_change(ui: &mut egui::Ui, stats: &FileStats) {
+    match stats.executable_change {
+        Some(true) => {
+            ui.colored_label(egui::Color32::LIGHT_YELLOW, "This file is now executable.");
+        }
+        Some(false) => {
+            ui.colored_label(
+                egui::Color32::LIGHT_YELLOW,
+                "This file is no longer executable.",
+            );
+        }
+        None => {}
+    }
+}
+
+pub fn draw_pr_header(
+    ui: &mut egui::Ui,
+    update: &PullRequestUpdate,
+    cache: &mut egui_commonmark::CommonMarkCache,
+) {
+    // Sessions saved before descriptions were fetched don't have one
+    if update.title.is_empty() {
+        return;
+    }
+    let title = egui::RichText::new(format!("#{} {}", update.number, update.title)).heading();
+    egui::CollapsingHeader::new(title)
+        .id_salt("pr_header")
+        .default_open(true)
+        .show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                if update.draft {
+                    ui.weak("Draft");
+                }
+                if !update.author.is_empty() {
+                    ui.label(format!("by {}", update.author));
+                }
+                for label in &update.labels {
+                    let color = egui::Color32::from_hex(&format!("#{}", label.color))
+                        .unwrap_or(egui::Color32::GRAY);
+                    ui.label(egui::RichText::new(&label.name).color(color));
+                }
+            });
+
+            if !update.checks.is_empty() {
+                ui.horizontal_wrapped(|ui| {
+                    for check in &update.checks {
+                        ci_label(ui, check.status);
+                        match &check.url {
+                            Some(url) => ui.hyperlink_to(&check.name, url),
+                            None => ui.label(&check.name),
+                        };
+                    }
+                });
+            }
+
+            if !update.policy.is_empty() {
+                draw_policy(ui, &update.policy);
+            }
+
+            egui::CollapsingHeader::new("Description")
+                .default_open(true)
+                .show(ui, |ui| {
+                    if update.body.trim().is_empty() {
+                        ui.weak("No description.");
+                        return;
+                    }
+                    egui::ScrollArea::vertical()
+                        .id_salt("pr_body")
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            egui_commonmark::CommonMarkViewer::new().show(ui, cache, &update.body);
+                        });
+                });
+        });
+    ui.separator();
+}
+
+fn draw_policy(ui: &mut egui::Ui, policy: &ReviewPolicy) {
+    egui::CollapsingHeader::new("Review policy")
+        .id_salt("review_policy")
+        .show(ui, |ui| {
+            ui.weak("From robojules.toml in the extensions repository");
+            let sections = [
+                ("Allowed domains", &policy.allowed_domains),
+                ("Ignored files", &policy.ignore),
+                ("Required checks", &policy.required_checks),
+            ];
+            for (name, values) in sections {
+                if !values.is_empty() {
+                    ui.label(format!("{}: {}", name, values.join(", ")));
+                }
+            }
+        });
+}
+
+fn ci_label(ui: &mut egui::Ui, status: CiStatus) -> egui::Response {
+    match status {
+        CiStatus::Success => ui.colored_label(egui::Color32::GREEN, "✔"),
+        CiStatus::Failure => ui.colored_label(egui::Color32::RED, "✖"),
+        CiStatus::Pending => ui.colored_label(egui::Color32::YELLOW, "⏳"),
+        CiStatus::Unknown => ui.weak("?"),
+    }
+}
+
+pub fn draw_whitespace_changes(ui: &mut egui::Ui, diff: &FileDiff) {
+    let notices = [
+        match diff.eof_newline_change {
+            Some(true) => Some("Newline added at end of file."),
+            Some(false) => Some("Newline removed at end of file."),
+            None => None,
+        },
+        match diff.bom_change {
+            Some(true) => Some("Byte order mark added."),
+            Some(false) => Some("Byte order mark removed."),
+            None => None,
+        },
+    ];
+
+    for notice in notices.into_iter().flatten() {
+        ui.colored_label(egui::Color32::LIGHT_YELLOW, notice);
+    }
+    if diff.timed_out {
+        ui.colored_label(
+            egui::Color32::LIGHT_YELLOW,
+            "Diff timed out, showing a line diff. The timeout can be raised in the settings.",
+        );
+    }
+    if diff.pretty_printed {
+        ui.colored_label(
+            egui::Color32::LIGHT_YELLOW,
+            "Minified code was formatted for diffing, line numbers refer to the formatted code.",
+        );
+    }
+    if let Some(err) = &diff.difft_error {
+        ui.colored_label(
+            egui::Color32::LIGHT_YELLOW,
+            "difftastic couldn't diff this file, showing a line diff.",
+        )
+        .on_hover_text(err);
+    }
+}
+
+pub fn draw_directory_summary(ui: &mut egui::Ui, path: &str, summary: &DirectorySummary) {
+    ui.heading(format!("{}/", path));
+    ui.horizontal(|ui| {
+        ui.label("Total:");
+        stats_row(ui, &summary.total);
+    });
+    ui.separator();
+
+    egui::Grid::new("directory_summary")
+        .striped(true)
+        .num_columns(6)
+        .show(ui, |ui| {
+            ui.strong("Name");
+            ui.strong("State");
+            ui.strong("Files");
+            ui.strong("Added");
+            ui.strong("Removed");
+            ui.strong("Size");
+            ui.end_row();
+
+            for entry in &summary.entries {
+                if entry.is_dir {
+                    ui.label(format!("{}/", entry.name));
+                } else {
+                    ui.label(&entry.name);
+                }
+                ui.label(match entry.state {
+                    Some(FileState::Added) => "Added",
+                    Some(FileState::Removed) => "Removed",
+                    Some(FileState::Modified) => "Modified",
+                    Some(FileState::ModeChanged) => "Mode changed",
+                    Some(FileState::Unchanged) => "Unchanged",
+                    None => "Mixed",
+                });
+                ui.label(entry.changed_files.to_string());
+                stats_row(ui, &entry.stats);
+                ui.end_row();
+            }
+        });
+}
+
 struct AnsiDrawer {
     layout_job: LayoutJob,
     buf: String,
 
     current_color: Option<egui::Color32>,
     underline: bool,
+    colors: DiffColors,
 }
 
 impl Perform for AnsiDrawer {
@@ -119,10 +561,10 @@ impl Perform for AnsiDrawer {
                     self.current_color = None;
                 }
                 91 => {
-                    self.current_color = Some(egui::Color32::RED);
+                    self.current_color = Some(self.colors.removed);
                 }
                 92 => {
-                    self.current_color = Some(egui::Color32::GREEN);
+                    self.current_color = Some(self.colors.added);
                 }
                 93 => {
                     self.current_color = Some(egui::Color32::LIGHT_YELLOW);
@@ -146,7 +588,7 @@ impl Perform for AnsiDrawer {
 }
 
 impl AnsiDrawer {
-    fn new() -> Self {
+    fn new(colors: DiffColors) -> Self {
         let mut layout_job = LayoutJob::default();
         layout_job.break_on_newline = true;
         Self {
@@ -155,6 +597,7 @@ impl AnsiDrawer {
 
             current_color: None,
             underline: false,
+            colors,
         }
     }
 
@@ -178,17 +621,1401 @@ impl AnsiDrawer {
         self.buf.clear();
     }
 
-    fn draw(mut self, ui: &mut egui::Ui) -> egui::Response {
+    fn finish(mut self) -> LayoutJob {
         self.draw_text();
-        ui.add(egui::Label::new(self.layout_job).wrap_mode(egui::TextWrapMode::Extend))
+        self.layout_job
     }
 }
 
-pub fn ansi(ui: &mut egui::Ui, text: &str) {
-    let mut drawer = AnsiDrawer::new();
+// difftastic's red and green are replaced with the palette's colors
+pub fn ansi_layout_job(text: &str, colors: DiffColors) -> LayoutJob {
+    let mut drawer = AnsiDrawer::new(colors);
     let mut state_machine = Parser::<DefaultCharAccumulator>::new();
     for byte in text.bytes() {
         state_machine.advance(&mut drawer, byte);
     }
-    drawer.draw(ui);
+    drawer.finish()
+}
+
+pub fn ansi(ui: &mut egui::Ui, text: &str, wrap: bool) {
+    let mut layout_job = ansi_layout_job(text, DiffColors::get(ui.ctx()));
+    widget::underline_urls(&mut layout_job);
+    layout_job.wrap.max_width = if wrap {
+        ui.available_width()
+    } else {
+        f32::INFINITY
+    };
+    let galley = ui.fonts(|fonts| fonts.layout_job(layout_job));
+    let response = ui.add(egui::Label::new(galley.clone()).selectable(true));
+    widget::link_urls(ui, &response, &galley);
+    response.context_menu(|ui| {
+        if ui.button("Copy all").clicked() {
+            ui.ctx().copy_text(galley.job.text.clone());
+            ui.close_menu();
+        }
+    });
+}
+
+// Returns the line to scroll to if an entry was clicked
+pub fn draw_outline(ui: &mut egui::Ui, outline: &[OutlineEntry]) -> Option<usize> {
+    if outline.is_empty() {
+        ui.label("No symbols found.");
+        return None;
+    }
+
+    let mut clicked = None;
+    for (i, entry) in outline.iter().enumerate() {
+        let kind = match entry.kind {
+            OutlineKind::Function => "fn",
+            OutlineKind::Class => "class",
+            OutlineKind::Export => "export",
+        };
+        let marker = if entry.changed { "● " } else { "" };
+        let text = egui::RichText::new(format!(
+            "{}{}{} {}",
+            "  ".repeat(entry.depth),
+            marker,
+            kind,
+            entry.name
+        ))
+        .monospace();
+        let text = if entry.changed {
+            text.color(egui::Color32::LIGHT_YELLOW)
+        } else {
+            text
+        };
+
+        ui.push_id(i, |ui| {
+            let response = ui
+                .selectable_label(false, text)
+                .on_hover_text(format!("Lines {}-{}", entry.start_line, entry.end_line));
+            if response.clicked() {
+                clicked = Some(entry.start_line);
+            }
+        });
+    }
+
+    clicked
+}
+
+// Returns the scroll offset after drawing. Wrapped lines never need horizontal scrolling.
+// `comment_target` is set when a line is picked to comment on, None when commenting isn't possible
+#[allow(clippy::too_many_arguments)]
+pub fn draw_file_diff(
+    ui: &mut egui::Ui,
+    diff: &FileDiff,
+    display: &mut DiffDisplay,
+    layout: DiffLayout,
+    scroll_to_line: &mut Option<usize>,
+    restore_offset: Option<egui::Vec2>,
+    wrap: bool,
+    comment_target: Option<&mut Option<CommentTarget>>,
+) -> egui::Vec2 {
+    // Split rows can't wrap without falling out of line
+    let wrap = match display {
+        DiffDisplay::Difftastic => wrap,
+        DiffDisplay::SideBySide => false,
+        DiffDisplay::Hunks | DiffDisplay::Modules => wrap && layout != DiffLayout::Split,
+    };
+    let mut scroll_area = egui::ScrollArea::new([!wrap, true]).auto_shrink(false);
+    if let Some(offset) = restore_offset {
+        scroll_area = scroll_area.scroll_offset(offset);
+    }
+    scroll_area
+        .show(ui, |ui| match display {
+            DiffDisplay::Difftastic => ansi(ui, &diff.rendered, wrap),
+            DiffDisplay::Hunks => draw_hunks(
+                ui,
+                diff,
+                layout,
+                scroll_to_line.take(),
+                wrap,
+                comment_target,
+            ),
+            DiffDisplay::Modules => match &diff.modules {
+                Some(modules) => {
+                    if let Some(line) = draw_module_diff(ui, modules) {
+                        // Only the hunk view knows where lines are
+                        *display = DiffDisplay::Hunks;
+                        *scroll_to_line = Some(line);
+                    }
+                }
+                None => draw_hunks(ui, diff, layout, None, wrap, comment_target),
+            },
+            DiffDisplay::SideBySide => {
+                draw_side_by_side(ui, diff, scroll_to_line.take(), comment_target)
+            }
+        })
+        .state
+        .offset
+}
+
+pub fn diff_style(ui: &egui::Ui, wrap: bool) -> DiffStyle {
+    let colors = DiffColors::get(ui.ctx());
+    DiffStyle {
+        wrap,
+        added_color: colors.added,
+        removed_color: colors.removed,
+        ..Default::default()
+    }
+}
+
+pub fn draw_hunks(
+    ui: &mut egui::Ui,
+    diff: &FileDiff,
+    layout: DiffLayout,
+    scroll_to_line: Option<usize>,
+    wrap: bool,
+    comment_target: Option<&mut Option<CommentTarget>>,
+) {
+    let mut view = DiffView::from_file_diff(diff)
+        .layout(layout)
+        .scroll_to_line(scroll_to_line)
+        .style(diff_style(ui, wrap));
+    if let Some(target) = comment_target {
+        view = view.comment_target(target);
+    }
+    ui.add(view);
+}
+
+// One galley for both columns, so they always scroll together
+pub fn draw_side_by_side(
+    ui: &mut egui::Ui,
+    diff: &FileDiff,
+    scroll_to_line: Option<usize>,
+    comment_target: Option<&mut Option<CommentTarget>>,
+) {
+    let mut view = DiffView::new(diff.whole_file.as_slice())
+        .layout(DiffLayout::Split)
+        .scroll_to_line(scroll_to_line)
+        .style(side_by_side_style(ui));
+    if let Some(target) = comment_target {
+        view = view.comment_target(target);
+    }
+    ui.add(view);
+}
+
+// There's only the one hunk, so its header would just be noise
+pub fn side_by_side_style(ui: &egui::Ui) -> DiffStyle {
+    DiffStyle {
+        show_headers: false,
+        ..diff_style(ui, false)
+    }
+}
+
+pub fn draw_diff_layout(ui: &mut egui::Ui, id: impl std::hash::Hash, layout: &mut DiffLayout) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(layout.name())
+        .show_ui(ui, |ui| {
+            for option in DiffLayout::ALL {
+                ui.selectable_value(layout, option, option.name());
+            }
+        });
+}
+
+fn highlight_color(kind: HighlightKind, dark_mode: bool) -> egui::Color32 {
+    let (dark, light) = match kind {
+        HighlightKind::Comment => ((120, 120, 120), (110, 110, 110)),
+        HighlightKind::String => ((206, 145, 120), (163, 21, 21)),
+        HighlightKind::Number => ((181, 206, 168), (9, 134, 88)),
+        HighlightKind::Keyword => ((86, 156, 214), (0, 0, 255)),
+        HighlightKind::Type => ((78, 201, 176), (38, 127, 153)),
+        HighlightKind::Property => ((156, 220, 254), (0, 16, 128)),
+    };
+    let (r, g, b) = if dark_mode { dark } else { light };
+    egui::Color32::from_rgb(r, g, b)
+}
+
+pub fn single_file_layout_job(ui: &egui::Ui, file: &SingleFile, wrap: bool) -> LayoutJob {
+    let font_id = FontId::monospace(14.);
+    let dim = ui.visuals().weak_text_color();
+    let text_color = ui.visuals().text_color();
+    let dark_mode = ui.visuals().dark_mode;
+
+    // Splits the text into runs of one color, with the line number before each line
+    let mut layout_job = LayoutJob::default();
+    let mut line = 0;
+    let mut line_start = true;
+    let mut append = |layout_job: &mut LayoutJob, text: &str, color| {
+        for piece in text.split_inclusive('\n') {
+            if line_start {
+                line += 1;
+                layout_job.append(
+                    &format!("{:>5} | ", line),
+                    0.,
+                    egui::TextFormat::simple(font_id.clone(), dim),
+                );
+            }
+            layout_job.append(piece, 0., egui::TextFormat::simple(font_id.clone(), color));
+            line_start = piece.ends_with('\n');
+        }
+    };
+    let mut pos = 0;
+    for span in &file.highlights {
+        if span.start < pos {
+            continue;
+        }
+        append(&mut layout_job, &file.text[pos..span.start], text_color);
+        append(
+            &mut layout_job,
+            &file.text[span.start..span.end],
+            highlight_color(span.kind, dark_mode),
+        );
+        pos = span.end;
+    }
+    append(&mut layout_job, &file.text[pos..], text_color);
+
+    layout_job.wrap.max_width = if wrap {
+        ui.available_width()
+    } else {
+        f32::INFINITY
+    };
+    layout_job
+}
+
+pub fn draw_single_file(ui: &mut egui::Ui, file: &SingleFile, wrap: bool) {
+    egui::ScrollArea::new([!wrap, true])
+        .auto_shrink(false)
+        .show(ui, |ui| {
+            let layout_job = single_file_layout_job(ui, file, wrap);
+            let response = ui.add(egui::Label::new(layout_job).selectable(true));
+            // Without the line numbers, so it pastes as the file
+            response.context_menu(|ui| {
+                if ui.button("Copy all").clicked() {
+                    ui.ctx().copy_text(file.text.clone());
+                    ui.close_menu();
+                }
+            });
+        });
+}
+
+pub fn draw_blame(ui: &mut egui::Ui, lines: &[BlameLine]) {
+    let font_id = FontId::monospace(14.);
+    let dim = ui.visuals().weak_text_color();
+    let in_range_color = egui::Color32::LIGHT_YELLOW;
+
+    let mut layout_job = LayoutJob::default();
+    let mut previous_hash = None;
+    for (i, line) in lines.iter().enumerate() {
+        // Only label the first line of each run of lines from the same commit
+        let gutter = if previous_hash != Some(&line.hash) {
+            let author = line.author.chars().take(16).collect::<String>();
+            format!(
+                "{} {:<16} {:>8} ",
+                &line.hash[..line.hash.len().min(8)],
+                author,
+                format_age(line.timestamp)
+            )
+        } else {
+            format!("{:34} ", "")
+        };
+        previous_hash = Some(&line.hash);
+
+        let gutter_color = if line.in_range { in_range_color } else { dim };
+        layout_job.append(
+            &gutter,
+            0.,
+            egui::TextFormat::simple(font_id.clone(), gutter_color),
+        );
+        layout_job.append(
+            &format!("{:>5} | ", i + 1),
+            0.,
+            egui::TextFormat::simple(font_id.clone(), dim),
+        );
+        layout_job.append(
+            &format!("{}\n", line.text),
+            0.,
+            egui::TextFormat::simple(font_id.clone(), ui.visuals().text_color()),
+        );
+    }
+
+    ui.label("Highlighted lines were last changed by a commit between the old and new commits.");
+    ui.add(
+        egui::Label::new(layout_job)
+            .wrap_mode(egui::TextWrapMode::Extend)
+            .selectable(true),
+    );
+}
+
+// Nearest-neighbor downscaling to fit the GPU's texture size limit, which egui panics on exceeding.
+// Previews are drawn far smaller than that anyway.
+fn fit_image(image: &DecodedImage, max_side: usize) -> egui::ColorImage {
+    let (width, height) = (image.width as usize, image.height as usize);
+    if width <= max_side && height <= max_side {
+        return egui::ColorImage::from_rgba_unmultiplied([width, height], &image.rgba);
+    }
+    let scale = max_side as f64 / width.max(height) as f64;
+    let scaled_width = ((width as f64 * scale) as usize).clamp(1, max_side);
+    let scaled_height = ((height as f64 * scale) as usize).clamp(1, max_side);
+    let mut rgba = Vec::with_capacity(scaled_width * scaled_height * 4);
+    for y in 0..scaled_height {
+        let source_y = y * height / scaled_height;
+        for x in 0..scaled_width {
+            let source_x = x * width / scaled_width;
+            let i = (source_y * width + source_x) * 4;
+            rgba.extend_from_slice(&image.rgba[i..i + 4]);
+        }
+    }
+    egui::ColorImage::from_rgba_unmultiplied([scaled_width, scaled_height], &rgba)
+}
+
+// Uploaded once and kept in egui's memory, keyed by the image's hash
+fn image_texture(ui: &egui::Ui, sha256: &str, image: &DecodedImage) -> egui::TextureHandle {
+    let id = egui::Id::new(("manifest_image", sha256));
+    if let Some(texture) = ui.data(|data| data.get_temp::<egui::TextureHandle>(id)) {
+        return texture;
+    }
+    let max_side = ui.ctx().input(|input| input.max_texture_side);
+    let texture = ui.ctx().load_texture(
+        format!("manifest_image_{}", sha256),
+        fit_image(image, max_side),
+        egui::TextureOptions::LINEAR,
+    );
+    ui.data_mut(|data| data.insert_temp(id, texture.clone()));
+    texture
+}
+
+fn draw_manifest_image(ui: &mut egui::Ui, kind: ImageKind, image: Option<&ManifestImage>) {
+    let Some(image) = image else {
+        ui.weak("None");
+        return;
+    };
+    let max_size = match kind {
+        ImageKind::Icon => egui::vec2(64., 64.),
+        ImageKind::Banner => egui::vec2(240., 120.),
+    };
+    ui.vertical(|ui| match &image.image {
+        Ok(decoded) => {
+            let texture = image_texture(ui, &image.sha256, decoded);
+            ui.add(
+                egui::Image::new(egui::load::SizedTexture::from_handle(&texture))
+                    .max_size(max_size),
+            )
+            .on_hover_text(format!(
+                "{}\n{}x{}",
+                image.path, decoded.width, decoded.height
+            ));
+        }
+        Err(err) => {
+            ui.weak(err).on_hover_text(&image.path);
+        }
+    });
+}
+
+pub fn draw_manifest_images(ui: &mut egui::Ui, images: &[ImageChange]) {
+    for change in images {
+        ui.horizontal(|ui| {
+            if !change.changed() {
+                ui.label(change.kind.name());
+                draw_manifest_image(ui, change.kind, change.new.as_ref());
+                return;
+            }
+            ui.colored_label(
+                egui::Color32::LIGHT_YELLOW,
+                format!("{} changed", change.kind.name()),
+            );
+            draw_manifest_image(ui, change.kind, change.old.as_ref());
+            ui.label("→");
+            draw_manifest_image(ui, change.kind, change.new.as_ref());
+        });
+    }
+}
+
+pub fn draw_consistency(ui: &mut egui::Ui, consistency: &Consistency) {
+    let score = consistency.score();
+    let color = match score {
+        90.. => egui::Color32::GREEN,
+        50..90 => egui::Color32::LIGHT_YELLOW,
+        _ => egui::Color32::RED,
+    };
+    let mut hover = consistency.summary();
+    if !consistency.unexplained.is_empty() {
+        // Long lists are mostly noise past the first few
+        let mut paths = consistency
+            .unexplained
+            .iter()
+            .take(20)
+            .cloned()
+            .collect::<Vec<_>>();
+        if consistency.unexplained.len() > paths.len() {
+            paths.push(format!(
+                "and {} more",
+                consistency.unexplained.len() - paths.len()
+            ));
+        }
+        hover.push_str(&format!(
+            "\n\nChanged without a corresponding source change:\n{}",
+            paths.join("\n")
+        ));
+    }
+    ui.colored_label(color, format!("Consistency {}%", score))
+        .on_hover_text(hover);
+}
+
+pub fn severity_color(ui: &egui::Ui, severity: Severity) -> egui::Color32 {
+    match severity {
+        Severity::Danger => egui::Color32::RED,
+        Severity::Warning => egui::Color32::LIGHT_YELLOW,
+        Severity::Info => ui.visuals().weak_text_color(),
+    }
+}
+
+// Returns the index of the finding that was clicked
+pub fn draw_findings(
+    ui: &mut egui::Ui,
+    findings: &[Finding],
+    triage: &mut BTreeMap<String, Triage>,
+) -> Option<usize> {
+    if findings.is_empty() {
+        ui.label("Nothing found.");
+        return None;
+    }
+
+    let keys = findings
+        .iter()
+        .map(ReviewState::finding_key)
+        .collect::<Vec<_>>();
+    let count = |status| {
+        keys.iter()
+            .filter(|key| {
+                triage
+                    .get(*key)
+                    .is_some_and(|triage| triage.status == status)
+            })
+            .count()
+    };
+    let untriaged = keys.iter().filter(|key| !triage.contains_key(*key)).count();
+    ui.label(format!(
+        "{} untriaged, {} need follow-up, {} accepted, {} false positives",
+        untriaged,
+        count(TriageStatus::NeedsFollowUp),
+        count(TriageStatus::Accepted),
+        count(TriageStatus::FalsePositive)
+    ));
+
+    let mut clicked = None;
+    egui::Grid::new("findings_grid")
+        .striped(true)
+        .num_columns(6)
+        .show(ui, |ui| {
+            for (i, finding) in findings.iter().enumerate() {
+                let severity = match finding.severity {
+                    Severity::Danger => "danger",
+                    Severity::Warning => "warning",
+                    Severity::Info => "info",
+                };
+                ui.colored_label(severity_color(ui, finding.severity), severity);
+
+                let origin = match finding.origin {
+                    Origin::Source => "source",
+                    Origin::Asar => ".asar",
+                };
+                let location = match finding.line {
+                    Some(line) => format!("{}: {}:{}", origin, finding.path, line),
+                    None => format!("{}: {}", origin, finding.path),
+                };
+                if ui.link(location).clicked() {
+                    clicked = Some(i);
+                }
+
+                ui.label(format!("[{}] {}", finding.rule, finding.message));
+                ui.monospace(&finding.snippet);
+                draw_triage(ui, i, triage, &keys[i]);
+                ui.end_row();
+            }
+        });
+
+    clicked
+}
+
+fn draw_triage(ui: &mut egui::Ui, i: usize, triage: &mut BTreeMap<String, Triage>, key: &str) {
+    let mut status = triage.get(key).map(|triage| triage.status);
+    egui::ComboBox::from_id_salt(("triage", i))
+        .selected_text(status.map_or("Untriaged", TriageStatus::name))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut status, None, "Untriaged");
+            for option in TriageStatus::ALL {
+                ui.selectable_value(&mut status, Some(option), option.name());
+            }
+        });
+    match status {
+        Some(status) => {
+            let entry = triage.entry(key.to_string()).or_insert_with(|| Triage {
+                status,
+                note: String::new(),
+            });
+            entry.status = status;
+            ui.add(
+                egui::TextEdit::singleline(&mut entry.note)
+                    .hint_text("Note")
+                    .desired_width(200.),
+            );
+        }
+        None => {
+            triage.remove(key);
+            ui.label("");
+        }
+    }
+}
+
+pub fn draw_module_diff(ui: &mut egui::Ui, modules: &ModuleDiff) -> Option<usize> {
+    let mut clicked = None;
+
+    ui.label(format!(
+        "{} modules changed, {} unchanged",
+        modules.changes.len(),
+        modules.unchanged
+    ));
+    ui.separator();
+
+    egui::Grid::new("module_diff")
+        .striped(true)
+        .num_columns(3)
+        .show(ui, |ui| {
+            ui.strong("Module");
+            ui.strong("Change");
+            ui.strong("Size");
+            ui.end_row();
+
+            for change in &modules.changes {
+                match change.new_line {
+                    Some(line) => {
+                        if ui
+                            .link(egui::RichText::new(&change.id).monospace())
+                            .clicked()
+                        {
+                            clicked = Some(line);
+                        }
+                    }
+                    None => {
+                        ui.monospace(&change.id);
+                    }
+                }
+                match change.kind {
+                    ModuleChangeKind::Added => {
+                        ui.colored_label(DiffColors::get(ui.ctx()).added, "Added");
+                    }
+                    ModuleChangeKind::Removed => {
+                        ui.colored_label(DiffColors::get(ui.ctx()).removed, "Removed");
+                    }
+                    ModuleChangeKind::Changed => {
+                        ui.label("Changed");
+                    }
+                }
+                ui.label(format!(
+                    "{} bytes ({})",
+                    change.new_size,
+                    format_size_delta(change.new_size as i64 - change.old_size as i64)
+                ));
+                ui.end_row();
+            }
+        });
+
+    clicked
+}
+
+pub fn draw_dependents(ui: &mut egui::Ui, dependents: &[Dependent]) {
+    if dependents.is_empty() {
+        ui.label("No published extensions depend on this one.");
+        return;
+    }
+
+    egui::Grid::new("dependents")
+        .striped(true)
+        .num_columns(3)
+        .show(ui, |ui| {
+            ui.strong("Extension");
+            ui.strong("ID");
+            ui.strong("Dependency");
+            ui.end_row();
+
+            for dependent in dependents {
+                ui.label(&dependent.name);
+                ui.monospace(&dependent.id);
+                if dependent.optional {
+                    ui.label("Suggested");
+                } else {
+                    ui.colored_label(egui::Color32::LIGHT_YELLOW, "Required")
+                        .on_hover_text("This extension won't load without the one under review");
+                }
+                ui.end_row();
+            }
+        });
+}
+
+pub fn draw_advisories(ui: &mut egui::Ui, advisories: &[Advisory]) {
+    if advisories.is_empty() {
+        return;
+    }
+
+    let malicious = advisories.iter().any(|advisory| advisory.malicious);
+    let (color, title) = if malicious {
+        (
+            egui::Color32::RED,
+            "Known malicious package versions were introduced - do not merge without checking",
+        )
+    } else {
+        (
+            egui::Color32::LIGHT_YELLOW,
+            "Introduced package versions have known advisories",
+        )
+    };
+
+    egui::Frame::group(ui.style())
+        .stroke(egui::Stroke::new(2.0, color))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.colored_label(color, egui::RichText::new(title).strong());
+            for advisory in advisories {
+                ui.horizontal(|ui| {
+                    let id_color = if advisory.malicious {
+                        egui::Color32::RED
+                    } else {
+                        egui::Color32::LIGHT_YELLOW
+                    };
+                    ui.hyperlink_to(
+                        egui::RichText::new(&advisory.id).color(id_color),
+                        format!("https://osv.dev/vulnerability/{}", advisory.id),
+                    );
+                    ui.monospace(format!("{}@{}", advisory.name, advisory.version));
+                    ui.label(&advisory.summary);
+                });
+            }
+        });
+}
+
+pub fn draw_dependency_changes(
+    ui: &mut egui::Ui,
+    changes: &[DependencyChange],
+    advisories: &[Advisory],
+) {
+    if changes.is_empty() {
+        ui.label("No dependency changes.");
+        return;
+    }
+
+    egui::Grid::new("dependency_changes")
+        .striped(true)
+        .num_columns(4)
+        .show(ui, |ui| {
+            ui.strong("Package");
+            ui.strong("Change");
+            ui.strong("Versions");
+            ui.strong("Integrity");
+            ui.end_row();
+
+            for change in changes {
+                let flagged = advisories
+                    .iter()
+                    .filter(|advisory| advisory.name == change.name)
+                    .collect::<Vec<_>>();
+                if flagged.iter().any(|advisory| advisory.malicious) {
+                    ui.colored_label(egui::Color32::RED, format!("⚠ {}", change.name));
+                } else if !flagged.is_empty() {
+                    ui.colored_label(egui::Color32::LIGHT_YELLOW, format!("⚠ {}", change.name));
+                } else {
+                    ui.monospace(&change.name);
+                }
+                match change.kind {
+                    DependencyChangeKind::Added => {
+                        ui.colored_label(DiffColors::get(ui.ctx()).added, "Added");
+                    }
+                    DependencyChangeKind::Removed => {
+                        ui.colored_label(DiffColors::get(ui.ctx()).removed, "Removed");
+                    }
+                    DependencyChangeKind::Updated => {
+                        ui.label("Updated");
+                    }
+                    DependencyChangeKind::IntegrityChanged => {
+                        ui.colored_label(egui::Color32::RED, "Contents changed");
+                    }
+                }
+                ui.monospace(format!(
+                    "{} → {}",
+                    change.old_versions.join(", "),
+                    change.new_versions.join(", ")
+                ));
+                if change.integrity_changed.is_empty() {
+                    ui.label("");
+                } else {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("⚠ {} changed", change.integrity_changed.join(", ")),
+                    )
+                    .on_hover_text(
+                        "The same version now resolves to different contents, \
+                         which a registry doesn't allow for published packages",
+                    );
+                }
+                ui.end_row();
+            }
+        });
+}
+
+pub fn draw_dependency_risks(ui: &mut egui::Ui, risks: &[DependencyRisk]) {
+    let format_days = |days: Option<i64>| {
+        days.map(|days| format!("{}d", days))
+            .unwrap_or_else(|| "?".to_string())
+    };
+
+    egui::Grid::new("dependency_risks")
+        .striped(true)
+        .num_columns(5)
+        .show(ui, |ui| {
+            ui.strong("Package");
+            ui.strong("Version age");
+            ui.strong("Package age");
+            ui.strong("Maintainers");
+            ui.strong("Risk signals");
+            ui.end_row();
+
+            for risk in risks {
+                ui.monospace(format!("{}@{}", risk.name, risk.version));
+                ui.label(format_days(risk.version_age_days));
+                ui.label(format_days(risk.package_age_days));
+                ui.label(risk.maintainers.to_string());
+
+                let signals = risk.signals();
+                if signals.is_empty() {
+                    ui.label("None");
+                } else {
+                    ui.colored_label(egui::Color32::LIGHT_YELLOW, signals.join(", "));
+                }
+                ui.end_row();
+            }
+        });
+}
+
+// Only new patches link anywhere, since the file view scrolls by new line numbers
+fn draw_patch(
+    ui: &mut egui::Ui,
+    patch: &Patch,
+    color: egui::Color32,
+    is_new: bool,
+) -> Option<(String, usize)> {
+    let mut clicked = None;
+    let location = format!("{}:{}", patch.path, patch.line);
+    if !is_new {
+        ui.label(location);
+    } else if ui.link(location).clicked() {
+        clicked = Some((patch.path.clone(), patch.line));
+    }
+
+    egui::Grid::new(("patch", &patch.path, patch.line, is_new))
+        .num_columns(2)
+        .show(ui, |ui| {
+            for replacement in &patch.replacements {
+                ui.label("match");
+                ui.colored_label(color, egui::RichText::new(&replacement.matcher).monospace());
+                ui.end_row();
+                ui.label("replacement");
+                ui.colored_label(
+                    color,
+                    egui::RichText::new(&replacement.replacement).monospace(),
+                );
+                ui.end_row();
+            }
+            for (key, value) in &patch.extra {
+                ui.label(key);
+                ui.monospace(value);
+                ui.end_row();
+            }
+        });
+
+    clicked
+}
+
+// Returns the location of a patch if one was clicked
+pub fn draw_patch_changes(ui: &mut egui::Ui, changes: &[PatchChange]) -> Option<(String, usize)> {
+    if changes.is_empty() {
+        ui.label("No patch changes.");
+        return None;
+    }
+
+    let mut clicked = None;
+    for change in changes {
+        ui.group(|ui| {
+            ui.set_width(ui.available_width());
+            ui.horizontal(|ui| {
+                match change.kind {
+                    PatchChangeKind::Added => {
+                        ui.colored_label(DiffColors::get(ui.ctx()).added, "Added");
+                    }
+                    PatchChangeKind::Removed => {
+                        ui.colored_label(DiffColors::get(ui.ctx()).removed, "Removed");
+                    }
+                    PatchChangeKind::Changed => {
+                        ui.label("Changed");
+                    }
+                }
+                ui.strong("find:");
+                ui.monospace(&change.find);
+            });
+
+            if let Some(old) = &change.old {
+                ui.label("Old:");
+                if let Some(source) = draw_patch(ui, old, egui::Color32::LIGHT_RED, false) {
+                    clicked = Some(source);
+                }
+            }
+            if let Some(new) = &change.new {
+                ui.label("New:");
+                if let Some(source) = draw_patch(ui, new, egui::Color32::LIGHT_GREEN, true) {
+                    clicked = Some(source);
+                }
+            }
+        });
+    }
+
+    clicked
+}
+
+// Returns a source file if one was clicked
+pub fn draw_provenance(ui: &mut egui::Ui, provenance: &Provenance) -> Option<String> {
+    let mut clicked = None;
+
+    if provenance.methods.is_empty() {
+        ui.colored_label(
+            egui::Color32::LIGHT_YELLOW,
+            "Nothing in the repository looks like it produced this file.",
+        );
+        return None;
+    }
+
+    ui.label(format!(
+        "Matched by {}",
+        provenance
+            .methods
+            .iter()
+            .map(|method| method.label())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    for source in &provenance.sources {
+        if ui.link(egui::RichText::new(source).monospace()).clicked() {
+            clicked = Some(source.clone());
+        }
+    }
+    if !provenance.dependencies.is_empty() {
+        ui.label(format!(
+            "Bundled packages: {}",
+            provenance
+                .dependencies
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    clicked
+}
+
+// Newest first, with slow stages highlighted
+pub fn draw_timings(ui: &mut egui::Ui, timings: &[Timing]) {
+    if timings.is_empty() {
+        ui.label("Nothing has been timed yet.");
+        return;
+    }
+
+    egui::ScrollArea::vertical()
+        .auto_shrink(false)
+        .show(ui, |ui| {
+            egui::Grid::new("timings").striped(true).show(ui, |ui| {
+                for timing in timings.iter().rev() {
+                    let secs = timing.duration.as_secs_f64();
+                    let color = if secs >= 10. {
+                        egui::Color32::RED
+                    } else if secs >= 1. {
+                        egui::Color32::LIGHT_YELLOW
+                    } else {
+                        ui.visuals().text_color()
+                    };
+                    ui.colored_label(color, format!("{:.1} ms", secs * 1000.));
+                    ui.label(&timing.stage);
+                    if timing.incomplete {
+                        ui.weak("(incomplete)");
+                    } else {
+                        ui.label("");
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+// Returns whether "Try again" was clicked
+pub fn draw_error(ui: &mut egui::Ui, err: &LogicError) -> bool {
+    draw_error_frame(ui, err, err.is_retryable())
+}
+
+// For results that have nowhere else to show a failure. Only requests sent through
+// `AsyncState::request` can be tried again, and only when the error might go away.
+pub fn draw_error_banner<T>(ui: &mut egui::Ui, state: &AsyncState<T>) -> bool {
+    match &state.error {
+        Some(err) => draw_error_frame(ui, err, state.command.is_some() && err.is_retryable()),
+        None => false,
+    }
+}
+
+fn draw_error_frame(ui: &mut egui::Ui, err: &LogicError, retryable: bool) -> bool {
+    let mut retry = false;
+    egui::Frame::group(ui.style())
+        .stroke(egui::Stroke::new(1.0, egui::Color32::RED))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.colored_label(
+                egui::Color32::RED,
+                err.hint().unwrap_or("Something went wrong."),
+            );
+            ui.collapsing("Details", |ui| {
+                ui.monospace(err.message());
+            });
+            if retryable {
+                retry = ui.button("Try again").clicked();
+            }
+        });
+    retry
+}
+
+pub fn draw_audit_log(ui: &mut egui::Ui, entries: &[AuditEntry]) {
+    if entries.is_empty() {
+        ui.label("Nothing has happened yet this session.");
+        return;
+    }
+
+    egui::ScrollArea::vertical()
+        .auto_shrink(false)
+        .show(ui, |ui| {
+            egui::Grid::new("audit_log").striped(true).show(ui, |ui| {
+                for entry in entries {
+                    ui.monospace(entry.timestamp.to_string());
+                    let text = match &entry.action {
+                        AuditAction::PullRequestFetched {
+                            number,
+                            head_sha,
+                            artifact_url,
+                            ..
+                        } => format!("Fetched PR #{} at {} ({})", number, head_sha, artifact_url),
+                        AuditAction::FileDownloaded { url, sha256, size } => {
+                            format!("Downloaded {} ({} bytes, sha256 {})", url, size, sha256)
+                        }
+                        AuditAction::CommitCheckedOut { repository, commit } => {
+                            format!("Checked out {} from {}", commit, repository)
+                        }
+                        AuditAction::LocalBuild { extension, success } => format!(
+                            "Built {} locally ({})",
+                            extension,
+                            if *success { "succeeded" } else { "failed" }
+                        ),
+                        AuditAction::Exported { kind, path } => {
+                            format!("Exported {} to {}", kind, path.display())
+                        }
+                        AuditAction::CheckRunPublished { head_sha, url } => {
+                            format!("Published findings for {} to {}", head_sha, url)
+                        }
+                        AuditAction::ReviewSubmitted {
+                            number,
+                            head_sha,
+                            verdict,
+                            url,
+                        } => format!(
+                            "Submitted \"{}\" on #{} at {} ({})",
+                            verdict, number, head_sha, url
+                        ),
+                    };
+                    ui.label(text);
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+// What was clicked in the extension overview
+pub enum OverviewAction {
+    Open(String),
+    ToggleFavorite(String),
+}
+
+fn review_progress(review: Option<&ReviewState>) -> String {
+    let Some(review) = review.filter(|review| !review.reviewed.is_empty()) else {
+        return "Not started".to_string();
+    };
+    if review.total_files == 0 {
+        return format!("{} files reviewed", review.reviewed.len());
+    }
+    format!(
+        "{}%",
+        (review.reviewed.len() * 100 / review.total_files).min(100)
+    )
+}
+
+pub fn draw_extension_overview(
+    ui: &mut egui::Ui,
+    overview: &[ExtensionOverview],
+    reviews: &BTreeMap<String, ReviewState>,
+    favorites: &[String],
+    open_enabled: bool,
+) -> Option<OverviewAction> {
+    let mut action = None;
+    let mut total = ExtensionStats::default();
+    let mut diffed = 0;
+
+    egui::Grid::new("extension_overview")
+        .striped(true)
+        .num_columns(7)
+        .show(ui, |ui| {
+            ui.label("");
+            ui.strong("Extension");
+            ui.strong("Status");
+            ui.strong("Changes");
+            ui.strong("Findings");
+            ui.strong("Reviewed");
+            ui.label("");
+            ui.end_row();
+
+            for ext in overview {
+                let favorite = favorites.contains(&ext.id);
+                if ui
+                    .selectable_label(favorite, if favorite { "★" } else { "☆" })
+                    .on_hover_text("Favorite extensions are listed first")
+                    .clicked()
+                {
+                    action = Some(OverviewAction::ToggleFavorite(ext.id.clone()));
+                }
+                ui.monospace(&ext.id);
+
+                match &ext.state {
+                    OverviewState::NotDownloaded => {
+                        ui.weak("Not downloaded");
+                        ui.label("");
+                        ui.label("");
+                    }
+                    OverviewState::Downloading => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("Downloading");
+                        });
+                        ui.label("");
+                        ui.label("");
+                    }
+                    OverviewState::Diffed(stats) => {
+                        diffed += 1;
+                        total.source_files += stats.source_files;
+                        total.asar_files += stats.asar_files;
+                        total.added_lines += stats.added_lines;
+                        total.removed_lines += stats.removed_lines;
+                        total.findings += stats.findings;
+                        total.dangerous_findings += stats.dangerous_findings;
+                        total.downgrades += stats.downgrades;
+
+                        ui.colored_label(egui::Color32::GREEN, "Diffed and scanned");
+                        draw_overview_changes(ui, stats);
+                        draw_overview_findings(ui, stats);
+                    }
+                }
+
+                ui.label(review_progress(reviews.get(&ext.id)));
+                if ui
+                    .add_enabled(open_enabled, egui::Button::new("Open"))
+                    .clicked()
+                {
+                    action = Some(OverviewAction::Open(ext.id.clone()));
+                }
+                ui.end_row();
+            }
+
+            if overview.len() > 1 {
+                ui.label("");
+                ui.strong("Total");
+                ui.label(format!("{}/{} diffed", diffed, overview.len()));
+                draw_overview_changes(ui, &total);
+                draw_overview_findings(ui, &total);
+                ui.label("");
+                ui.label("");
+                ui.end_row();
+            }
+        });
+
+    action
+}
+
+fn draw_overview_changes(ui: &mut egui::Ui, stats: &ExtensionStats) {
+    let colors = DiffColors::get(ui.ctx());
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "{} source, {} .asar files",
+            stats.source_files, stats.asar_files
+        ));
+        ui.colored_label(colors.added, format!("+{}", stats.added_lines));
+        ui.colored_label(colors.removed, format!("-{}", stats.removed_lines));
+    });
+}
+
+fn draw_overview_findings(ui: &mut egui::Ui, stats: &ExtensionStats) {
+    ui.horizontal(|ui| {
+        if stats.dangerous_findings > 0 {
+            ui.colored_label(
+                egui::Color32::RED,
+                format!(
+                    "{} ({} dangerous)",
+                    stats.findings, stats.dangerous_findings
+                ),
+            );
+        } else {
+            ui.label(stats.findings.to_string());
+        }
+        if stats.downgrades > 0 {
+            ui.colored_label(egui::Color32::RED, "⚠ Downgrade");
+        }
+    });
+}
+
+pub fn draw_auth_status(ui: &mut egui::Ui, auth: &AsyncState<AuthStatus>) {
+    if auth.working {
+        ui.spinner();
+        return;
+    }
+    if let Some(err) = &auth.error {
+        ui.colored_label(egui::Color32::RED, "GitHub token rejected")
+            .on_hover_text(err.message());
+        return;
+    }
+    let Some(status) = &auth.value else {
+        return;
+    };
+    match (status.source, &status.login) {
+        (Some(source), Some(login)) => {
+            ui.colored_label(egui::Color32::GREEN, format!("Logged in as {}", login))
+                .on_hover_text(format!("Using {}", source.name()));
+        }
+        _ => {
+            ui.weak("Not logged in").on_hover_text(
+                "Requests are rate limited and artifacts go through the mirror. \
+                 Add a token in the settings, set GITHUB_TOKEN, or log in with the gh CLI.",
+            );
+        }
+    }
+}
+
+// Returns the pull request to open, if one was clicked
+pub fn draw_pull_request_list(
+    ui: &mut egui::Ui,
+    pull_requests: &[PullRequestSummary],
+) -> Option<u64> {
+    if pull_requests.is_empty() {
+        ui.label("No open pull requests.");
+        return None;
+    }
+
+    let mut open = None;
+    egui::Grid::new("pull_requests")
+        .striped(true)
+        .show(ui, |ui| {
+            for pr in pull_requests {
+                if ui
+                    .link(format!("#{}", pr.number))
+                    .on_hover_text("Fetch this pull request")
+                    .clicked()
+                {
+                    open = Some(pr.number);
+                }
+                ui.horizontal(|ui| {
+                    if pr.draft {
+                        ui.weak("Draft");
+                    }
+                    ui.label(&pr.title);
+                });
+                ui.label(&pr.author);
+                ui.weak(format_age(pr.created_at));
+                match pr.ci {
+                    CiStatus::Success => ui.colored_label(egui::Color32::GREEN, "CI passed"),
+                    CiStatus::Failure => ui
+                        .colored_label(egui::Color32::RED, "CI failed")
+                        .on_hover_text("There's no artifact to review until it passes"),
+                    CiStatus::Pending => ui.colored_label(egui::Color32::YELLOW, "CI running"),
+                    CiStatus::Unknown => ui.weak("No CI run"),
+                };
+                ui.end_row();
+            }
+        });
+    open
+}
+
+pub fn draw_prefetch_items(
+    ui: &mut egui::Ui,
+    items: &[PrefetchItem],
+    favorites: &[String],
+    statuses: &BTreeMap<u64, ReviewStatus>,
+) -> Option<u64> {
+    if items.is_empty() {
+        ui.label("Nothing queued.");
+        return None;
+    }
+
+    // Pull requests touching favorites go first, otherwise keep the queue order
+    let starred = |item: &PrefetchItem| {
+        item.extensions
+            .iter()
+            .filter(|id| favorites.contains(id))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let mut items = items
+        .iter()
+        .map(|item| (item, starred(item)))
+        .collect::<Vec<_>>();
+    items.sort_by_key(|(_, starred)| starred.is_empty());
+
+    let mut open = None;
+    egui::ScrollArea::vertical()
+        .auto_shrink(false)
+        .show(ui, |ui| {
+            egui::Grid::new("prefetch").striped(true).show(ui, |ui| {
+                for (item, starred) in &items {
+                    ui.monospace(format!("#{}", item.number));
+                    if starred.is_empty() {
+                        ui.label("");
+                    } else {
+                        ui.label(format!("★ {}", starred.join(", ")));
+                    }
+                    match &item.state {
+                        PrefetchState::Queued => {
+                            ui.weak("Queued");
+                        }
+                        PrefetchState::Running => {
+                            ui.horizontal(|ui| {
+                                ui.spinner();
+                                ui.label(format!("{}/{} extensions", item.done, item.total));
+                            });
+                        }
+                        PrefetchState::Done => {
+                            ui.colored_label(egui::Color32::GREEN, "Ready");
+                        }
+                        PrefetchState::Failed(err) => {
+                            ui.colored_label(egui::Color32::RED, "Failed")
+                                .on_hover_text(err);
+                        }
+                    }
+                    let status = statuses
+                        .get(&item.number)
+                        .copied()
+                        .unwrap_or(ReviewStatus::NotStarted);
+                    match status {
+                        ReviewStatus::NotStarted => ui.weak(status.name()),
+                        ReviewStatus::InProgress(_) => ui.label(status.name()),
+                        ReviewStatus::Exported | ReviewStatus::Approved => {
+                            ui.colored_label(egui::Color32::GREEN, status.name())
+                        }
+                    };
+                    if ui.button("Open").clicked() {
+                        open = Some(item.number);
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    open
+}
+
+// Returns a file to open, from its key
+pub fn draw_team_review(ui: &mut egui::Ui, team: &TeamReview, extension_id: &str) {
+    egui::Grid::new("team_review").striped(true).show(ui, |ui| {
+        for (login, reviewer) in &team.reviewers {
+            if *login == team.login {
+                ui.strong(format!("{} (you)", login));
+            } else {
+                ui.label(login);
+            }
+            let review = reviewer.extensions.get(extension_id);
+            ui.label(format!(
+                "{} files reviewed",
+                review
+                    .map(|review| review.reviewed.len())
+                    .unwrap_or_default()
+            ));
+            ui.weak(format_age(reviewer.updated as i64));
+            ui.end_row();
+
+            for (key, note) in review.iter().flat_map(|review| &review.file_notes) {
+                ui.label("");
+                ui.monospace(key.to_string());
+                ui.label(note);
+                ui.end_row();
+            }
+        }
+    });
+}
+
+pub fn draw_review(ui: &mut egui::Ui, review: &mut ReviewState) -> Option<FileKey> {
+    ui.heading("Checklist");
+    for item in &mut review.checklist {
+        ui.checkbox(&mut item.done, &item.text);
+    }
+    ui.separator();
+
+    ui.heading("Notes");
+    ui.add(
+        egui::TextEdit::multiline(&mut review.notes)
+            .desired_rows(4)
+            .desired_width(f32::INFINITY),
+    );
+    ui.separator();
+
+    let untriaged = review
+        .findings
+        .iter()
+        .filter(|finding| {
+            !review
+                .triage
+                .contains_key(&ReviewState::finding_key(finding))
+        })
+        .count();
+    ui.label(format!(
+        "{} files reviewed, {} findings when downloaded, {} untriaged",
+        review.reviewed.len(),
+        review.findings.len(),
+        untriaged
+    ));
+
+    let mut open = None;
+    // What's left to chase up before the review is done
+    let follow_ups = review
+        .findings
+        .iter()
+        .filter_map(|finding| {
+            let triage = review.triage.get(&ReviewState::finding_key(finding))?;
+            (triage.status == TriageStatus::NeedsFollowUp).then_some((finding, triage))
+        })
+        .collect::<Vec<_>>();
+    if !follow_ups.is_empty() {
+        ui.heading("Findings to follow up");
+        egui::Grid::new("follow_ups").striped(true).show(ui, |ui| {
+            for (finding, triage) in follow_ups {
+                let location = match finding.line {
+                    Some(line) => format!("{}:{}", finding.path, line),
+                    None => finding.path.clone(),
+                };
+                if ui.link(location).clicked() {
+                    open = Some(FileKey::new(finding.origin, &finding.path));
+                }
+                ui.label(format!("[{}] {}", finding.rule, finding.message));
+                ui.label(&triage.note);
+                ui.end_row();
+            }
+        });
+    }
+    if !review.file_notes.is_empty() {
+        ui.heading("File notes");
+        egui::Grid::new("file_notes").striped(true).show(ui, |ui| {
+            for (key, note) in &review.file_notes {
+                if ui.link(key.to_string()).clicked() {
+                    open = Some(key.clone());
+                }
+                ui.label(note);
+                ui.end_row();
+            }
+        });
+    }
+    open
 }
@@ -0,0 +1,206 @@
+use anyhow::Context;
+use egui::{text::LayoutJob, Color32};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Png,
+    Svg,
+}
+
+impl ExportFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG",
+            ExportFormat::Svg => "SVG",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Svg => "svg",
+        }
+    }
+}
+
+// Keeps a long file from allocating hundreds of megabytes of pixels
+const MAX_DIMENSION: usize = 4096;
+const PADDING: usize = 8;
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hex(color: Color32) -> String {
+    let [r, g, b, _] = color.to_srgba_unmultiplied();
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+// Text stays text, so the SVG can be searched and scales without blurring
+pub fn svg(job: &LayoutJob, background: Color32) -> String {
+    let font_size = job
+        .sections
+        .iter()
+        .map(|section| section.format.font_id.size)
+        .fold(14., f32::max);
+    let line_height = font_size * 1.25;
+    let char_width = font_size * 0.6;
+
+    // Each line is a list of (color, text) runs
+    let mut lines: Vec<Vec<(Color32, &str)>> = vec![Vec::new()];
+    for section in &job.sections {
+        let text = &job.text[section.byte_range.clone()];
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(Vec::new());
+            }
+            if !part.is_empty() {
+                lines.last_mut().unwrap().push((section.format.color, part));
+            }
+        }
+    }
+
+    let columns = lines
+        .iter()
+        .map(|line| line.iter().map(|(_, text)| text.chars().count()).sum())
+        .max()
+        .unwrap_or(0);
+    let width = columns as f32 * char_width + PADDING as f32 * 2.;
+    let height = lines.len() as f32 * line_height + PADDING as f32 * 2.;
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    let _ = writeln!(
+        out,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        hex(background)
+    );
+    let _ = writeln!(
+        out,
+        r#"<g font-family="monospace" font-size="{font_size}" xml:space="preserve">"#
+    );
+    for (i, line) in lines.iter().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let y = PADDING as f32 + (i as f32 + 1.) * line_height - (line_height - font_size);
+        let _ = write!(out, r#"<text x="{}" y="{y}">"#, PADDING);
+        for (color, text) in line {
+            let _ = write!(
+                out,
+                r#"<tspan fill="{}">{}</tspan>"#,
+                hex(*color),
+                escape(text)
+            );
+        }
+        out.push_str("</text>\n");
+    }
+    out.push_str("</g>\n</svg>\n");
+    out
+}
+
+// Lays the job out with the UI's fonts and rasterizes the glyph meshes on the CPU, so the
+// export looks like the screen without needing an offscreen GPU target. Runs off the UI thread,
+// so the atlas is read under the same lock as the layout that might have added glyphs to it.
+pub fn png(
+    ctx: &egui::Context,
+    mut job: LayoutJob,
+    background: Color32,
+) -> anyhow::Result<Vec<u8>> {
+    job.wrap.max_width = f32::INFINITY;
+    let (galley, atlas) = ctx.fonts(|fonts| (fonts.layout_job(job), fonts.image()));
+    let ppp = ctx.pixels_per_point();
+
+    let size = galley.size() * ppp;
+    let width = size.x.ceil() as usize + PADDING * 2;
+    let height = size.y.ceil() as usize + PADDING * 2;
+    anyhow::ensure!(
+        width <= MAX_DIMENSION && height <= MAX_DIMENSION,
+        "Diff is too large to export as an image ({}x{} pixels)",
+        width,
+        height
+    );
+
+    let bg = egui::Rgba::from(background);
+    let mut pixels = vec![[bg.r(), bg.g(), bg.b()]; width * height];
+    let [atlas_width, atlas_height] = atlas.size;
+    let offset = PADDING as f32;
+
+    for row in &galley.rows {
+        let mesh = &row.visuals.mesh;
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+            let [pa, pb, pc] = [a, b, c].map(|v| v.pos * ppp + egui::vec2(offset, offset));
+            let area = (pb - pa).x * (pc - pa).y - (pb - pa).y * (pc - pa).x;
+            if area.abs() < f32::EPSILON {
+                continue;
+            }
+            let color = egui::Rgba::from(a.color);
+
+            let min_x = pa.x.min(pb.x).min(pc.x).floor().max(0.) as usize;
+            let min_y = pa.y.min(pb.y).min(pc.y).floor().max(0.) as usize;
+            let max_x = (pa.x.max(pb.x).max(pc.x).ceil() as usize).min(width);
+            let max_y = (pa.y.max(pb.y).max(pc.y).ceil() as usize).min(height);
+
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    let p = egui::pos2(x as f32 + 0.5, y as f32 + 0.5);
+                    // Barycentric weights; all share the sign of `area` when inside
+                    let wa = ((pb - p).x * (pc - p).y - (pb - p).y * (pc - p).x) / area;
+                    let wb = ((pc - p).x * (pa - p).y - (pc - p).y * (pa - p).x) / area;
+                    let wc = 1. - wa - wb;
+                    if wa < 0. || wb < 0. || wc < 0. {
+                        continue;
+                    }
+
+                    let uv = a.uv.to_vec2() * wa + b.uv.to_vec2() * wb + c.uv.to_vec2() * wc;
+                    let u = ((uv.x * atlas_width as f32) as usize).min(atlas_width - 1);
+                    let v = ((uv.y * atlas_height as f32) as usize).min(atlas_height - 1);
+                    let coverage = atlas.pixels[v * atlas_width + u];
+
+                    // Vertex colors are premultiplied
+                    let pixel = &mut pixels[y * width + x];
+                    let alpha = color.a() * coverage;
+                    pixel[0] = color.r() * coverage + pixel[0] * (1. - alpha);
+                    pixel[1] = color.g() * coverage + pixel[1] * (1. - alpha);
+                    pixel[2] = color.b() * coverage + pixel[2] * (1. - alpha);
+                }
+            }
+        }
+    }
+
+    let data = pixels
+        .into_iter()
+        .flat_map(|[r, g, b]| {
+            let color = Color32::from(egui::Rgba::from_rgb(r, g, b));
+            [color.r(), color.g(), color.b(), 255]
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .context("Failed to write PNG header")?;
+    writer
+        .write_image_data(&data)
+        .context("Failed to write PNG data")?;
+    writer.finish().context("Failed to finish PNG")?;
+    Ok(out)
+}
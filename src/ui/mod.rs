@@ -1,51 +1,750 @@
-use crate::logic::{app_logic_thread, LogicCommand, LogicResponse};
-use state::{AppState, ViewType};
-use std::time::Duration;
+use crate::logic::{
+    app_logic_thread,
+    build::BuildOutcome,
+    checks::CheckConclusion,
+    config::{Config, Theme, MAX_UI_SCALE, MIN_UI_SCALE},
+    diff::{self, Diff, DiffedExtension, FileState, FileStats},
+    overview::{ExtensionOverview, OverviewState},
+    prefetch::{PrefetchItem, PrefetchState},
+    review::{LineComment, ReviewVerdict},
+    scan::{Finding, Origin},
+    session::{self, FileKey, ReviewSession, ReviewState},
+    templates, LogicCommand, LogicError, LogicResponse, LogicResult,
+};
+use crate::{crash, instance::InstanceLink, widget};
+use anyhow::Context;
+use components::OverviewAction;
+use export::ExportFormat;
+use onboarding::OnboardingAction;
+use palette::DiffColors;
+use state::{
+    AppState, DiffDisplay, DiscardedExtension, ExtensionPage, FileTab, HistoryState, PoppedOutDiff,
+    RawSide, ViewType,
+};
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+#[cfg(feature = "tray")]
+use tray::{Tray, TrayCommand};
 
 mod components;
+mod export;
+mod onboarding;
+mod palette;
+mod quick_open;
+mod settings;
 mod state;
+#[cfg(feature = "tray")]
+mod tray;
+
+type LogicThread = std::thread::JoinHandle<anyhow::Result<()>>;
+
+// Seconds between checks for unsaved review changes
+const AUTOSAVE_INTERVAL: f64 = 5.0;
 
 #[derive(Debug)]
 pub struct App {
     tx: flume::Sender<LogicCommand>,
     rx: flume::Receiver<LogicResponse>,
+    logic_thread: Option<LogicThread>,
     state: AppState,
+    // Present while the tray setting is on, with why it couldn't be created if it failed
+    #[cfg(feature = "tray")]
+    tray: Option<Result<Tray, String>>,
+    // Set from the tray menu, so closing the window quits instead of hiding it
+    #[cfg(feature = "tray")]
+    quitting: bool,
+    // Requests from later launches, when this is the running instance
+    instance: Option<InstanceLink>,
+}
+
+// A dead logic thread is picked up by `check_logic_thread`, so failed sends are only logged
+fn send(tx: &flume::Sender<LogicCommand>, command: LogicCommand) {
+    if let Err(err) = tx.send(command) {
+        log::warn!("Logic thread is gone, dropping {:?}", err.into_inner());
+    }
+}
+
+fn visuals(theme: Theme) -> egui::Visuals {
+    match theme {
+        Theme::Dark => egui::Visuals::dark(),
+        Theme::Light => egui::Visuals::light(),
+    }
+}
+
+fn spawn_logic_thread() -> (
+    flume::Sender<LogicCommand>,
+    flume::Receiver<LogicResponse>,
+    LogicThread,
+) {
+    let (main_tx, logic_rx) = flume::unbounded::<LogicCommand>();
+    let (logic_tx, main_rx) = flume::unbounded::<LogicResponse>();
+    let thread = std::thread::spawn(move || app_logic_thread(logic_rx, logic_tx));
+    send(&main_tx, LogicCommand::WatchProgress);
+    send(&main_tx, LogicCommand::GetAuthStatus);
+    (main_tx, main_rx, thread)
 }
 
 impl App {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        cc.egui_ctx.set_visuals(egui::Visuals::dark());
+        let config = Config::load();
+        cc.egui_ctx.set_visuals(visuals(config.theme));
+        // Nothing has been saved yet, so this is the first run
+        let first_run = !Config::path().exists();
 
-        let (main_tx, logic_rx) = flume::unbounded::<LogicCommand>();
-        let (logic_tx, main_rx) = flume::unbounded::<LogicResponse>();
-        std::thread::spawn(move || app_logic_thread(logic_rx, logic_tx));
+        let (tx, rx, logic_thread) = spawn_logic_thread();
 
-        let state = AppState::default();
+        let mut state = AppState {
+            config,
+            crash: crash::take_previous(),
+            audit_export_path: dirs::download_dir()
+                .or_else(dirs::home_dir)
+                .unwrap_or_default()
+                .join("robojules-audit.json")
+                .to_string_lossy()
+                .to_string(),
+            session_path: dirs::download_dir()
+                .or_else(dirs::home_dir)
+                .unwrap_or_default()
+                .join("robojules-review.json")
+                .to_string_lossy()
+                .to_string(),
+            ..Default::default()
+        };
+        state.auth.start();
+        if first_run {
+            state.onboarding.show(&state.config);
+            state
+                .onboarding
+                .tools
+                .request(&tx, LogicCommand::CheckTools);
+        }
 
         App {
-            tx: main_tx,
-            rx: main_rx,
+            tx,
+            rx,
+            logic_thread: Some(logic_thread),
             state,
+            #[cfg(feature = "tray")]
+            tray: None,
+            #[cfg(feature = "tray")]
+            quitting: false,
+            instance: None,
+        }
+    }
+
+    pub fn with_instance(mut self, ctx: &egui::Context, link: InstanceLink) -> Self {
+        link.attach(ctx);
+        self.instance = Some(link);
+        self
+    }
+
+    // The window was already shown by the thread that took the request
+    fn handle_instance_requests(&mut self) {
+        let requests =
+            std::iter::from_fn(|| self.instance.as_ref()?.try_recv()).collect::<Vec<_>>();
+        for request in requests {
+            if let Some(number) = request.open {
+                self.open_pull_request(number);
+            }
+        }
+    }
+
+    #[cfg(feature = "tray")]
+    fn update_tray(&mut self, ctx: &egui::Context) {
+        if self.state.config.tray != self.tray.is_some() {
+            self.tray = self.state.config.tray.then(|| {
+                Tray::new(ctx)
+                    .inspect_err(|err| log::warn!("Failed to create tray icon: {:?}", err))
+                    .map_err(|err| format!("{:#}", err))
+            });
+        }
+        // Without an icon to bring it back, closing the window quits as usual
+        let Some(Ok(tray)) = &self.tray else {
+            return;
+        };
+
+        // Hidden instead of closed, the logic thread keeps prefetching in the meantime
+        if !self.quitting && ctx.input(|input| input.viewport().close_requested()) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+        }
+
+        let commands = std::iter::from_fn(|| tray.try_recv()).collect::<Vec<_>>();
+        for command in commands {
+            match command {
+                TrayCommand::Show => {}
+                TrayCommand::OpenReady => self.open_ready_review(),
+                TrayCommand::Quit => {
+                    self.quitting = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            }
+        }
+    }
+
+    // The first prefetched pull request that's neither exported nor approved yet
+    #[cfg(feature = "tray")]
+    fn open_ready_review(&mut self) {
+        let ready = self.state.prefetch.iter().find(|item| {
+            matches!(item.state, PrefetchState::Done)
+                && !matches!(
+                    self.state.review_statuses.get(&item.number),
+                    Some(session::ReviewStatus::Exported | session::ReviewStatus::Approved)
+                )
+        });
+        if let Some(number) = ready.map(|item| item.number) {
+            self.open_pull_request(number);
+        }
+    }
+
+    fn open_pull_request(&mut self, number: u64) {
+        self.state.pull_request_id = number;
+        self.state.selected_extension = None;
+        self.state.diffed_extension.clear();
+        self.fetch_pull_request();
+    }
+
+    fn check_logic_thread(&mut self) {
+        if !self
+            .logic_thread
+            .as_ref()
+            .is_some_and(|thread| thread.is_finished())
+        {
+            return;
+        }
+
+        let reason = match self.logic_thread.take().map(|thread| thread.join()) {
+            Some(Ok(Ok(()))) => "The backend stopped unexpectedly".to_string(),
+            Some(Ok(Err(err))) => format!("The backend stopped: {:?}", err),
+            Some(Err(_)) | None => "The backend crashed".to_string(),
+        };
+        log::error!("{}", reason);
+        self.state.backend_error = Some(reason);
+        self.state.interrupt_requests();
+    }
+
+    fn prompt_for_auth<T>(&mut self, res: &LogicResult<T>) {
+        if let Err(err @ LogicError::AuthRequired(_)) = res {
+            self.state.auth_prompt = Some(err.clone());
+        }
+    }
+
+    fn draw_auth_prompt(&mut self, ctx: &egui::Context) {
+        let Some(err) = &self.state.auth_prompt else {
+            return;
+        };
+
+        let mut open = true;
+        let mut reconnect = false;
+        egui::Window::new("GitHub token needed")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(
+                    "GitHub refused a request because it wasn't authenticated, \
+                     or the token doesn't have access.",
+                );
+                ui.label(
+                    "Log in with the GitHub CLI (`gh auth login`), or create a token and \
+                     paste it into the settings or set it as GITHUB_TOKEN.",
+                );
+                ui.collapsing("Details", |ui| {
+                    ui.monospace(err.message());
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Create a token").clicked() {
+                        ui.ctx().open_url(egui::OpenUrl::new_tab(
+                            "https://github.com/settings/tokens/new?description=RoboJules",
+                        ));
+                    }
+                    reconnect = ui
+                        .button("I've logged in")
+                        .on_hover_text("Pick up the gh CLI's token without restarting")
+                        .clicked();
+                });
+            });
+
+        if reconnect {
+            // The client reads credentials once, when the logic thread starts
+            self.restart_logic_thread();
+            self.state.auth_prompt = None;
+        } else if !open {
+            self.state.auth_prompt = None;
         }
     }
 
+    fn restart_logic_thread(&mut self) {
+        log::info!("Restarting logic thread");
+        let (tx, rx, logic_thread) = spawn_logic_thread();
+        self.tx = tx;
+        self.rx = rx;
+        self.logic_thread = Some(logic_thread);
+        self.state.backend_error = None;
+        self.state.auth.start();
+        // The new thread loads the saved config, which may be behind if saving failed
+        send(
+            &self.tx,
+            LogicCommand::UpdateConfig(self.state.config.clone()),
+        );
+    }
+
     fn handle_messages(&mut self) {
         while let Ok(msg) = self.rx.try_recv() {
             match msg {
                 LogicResponse::PullRequest(res) => {
+                    self.prompt_for_auth(&res);
                     self.state.pull_request_update.set(res);
+                    self.state.overview.clear();
+                    self.refresh_overview();
+                }
+                LogicResponse::PullRequests(res) => {
+                    self.prompt_for_auth(&res);
+                    self.state.pull_requests.set(res);
+                }
+                LogicResponse::AuthStatus(res) => {
+                    self.state.auth.set(res);
+                }
+                LogicResponse::Overview(overview) => {
+                    self.state.overview = overview;
                 }
                 LogicResponse::ExtensionDownloadComplete(res) => {
+                    self.prompt_for_auth(&res);
+                    if let Ok(diffed_extension) = &res {
+                        let review = self.state.review(&diffed_extension.extension.id);
+                        review.findings = diffed_extension.findings.clone();
+                        review.total_files = diff::flatten_tree(&diffed_extension.source_diff.dir)
+                            .len()
+                            + diff::flatten_tree(&diffed_extension.asar_diff.dir).len();
+                    }
                     self.state.diffed_extension.set(res);
+                    self.refresh_overview();
+                    self.state.scroll_offsets.clear();
+                    self.state.page = ExtensionPage::Files;
+                    self.state.dependency_risks.clear();
+                    self.state.advisories.clear();
+                    self.state.dependents.clear();
+                    self.state.building.clear();
+                    self.state.approval.clear();
+                    // Shown before approving, so it's fetched with the extension rather than on demand
+                    if let Some(diffed_extension) = &self.state.diffed_extension.value {
+                        send(
+                            &self.tx,
+                            LogicCommand::GetDependents(diffed_extension.extension.id.clone()),
+                        );
+                        self.state.dependents.start();
+                    }
+                    if matches!(self.state.view_type, ViewType::Build | ViewType::Approved) {
+                        self.state.view_type = ViewType::Source;
+                    }
+                    if let Some(diffed_extension) = self.state.diffed_extension.value.take() {
+                        self.open_first_file(&diffed_extension);
+                        self.state.diffed_extension.value = Some(diffed_extension);
+                    }
+                }
+                LogicResponse::Progress(stages) => {
+                    self.state.progress = stages;
+                }
+                LogicResponse::Bandwidth(usage) => {
+                    self.state.bandwidth = usage;
                 }
                 LogicResponse::FileDiff(res) => {
                     self.state.diff = res.ok();
                 }
+                LogicResponse::File(res) => {
+                    self.state.raw_file.set(res);
+                }
+                LogicResponse::GrammarInstalled(res) => {
+                    let installed = res.is_ok();
+                    self.state.grammar_install.set(res);
+                    // Diff the file again, now that it can be parsed
+                    if installed {
+                        if let Some(diffed_extension) = self.state.diffed_extension.value.take() {
+                            self.select_file_changed(&diffed_extension);
+                            self.state.diffed_extension.value = Some(diffed_extension);
+                        }
+                    }
+                }
+                // Dropped if another file was selected while they were on their way
+                LogicResponse::FileHistory { path, revisions } => {
+                    if self.state.selected_file.as_deref() == Some(path.as_str()) {
+                        self.state.history.revisions.set(revisions);
+                    }
+                }
+                LogicResponse::RevisionDiff { path, diff } => {
+                    if self.state.selected_file.as_deref() == Some(path.as_str()) {
+                        self.state.history.diff.set(diff);
+                    }
+                }
+                LogicResponse::Blame { path, lines } => {
+                    if self.state.selected_file.as_deref() == Some(path.as_str()) {
+                        self.state.blame.set(lines);
+                    }
+                }
+                LogicResponse::DependencyRisks(res) => {
+                    self.prompt_for_auth(&res);
+                    self.state.dependency_risks.set(res);
+                }
+                LogicResponse::Advisories(res) => {
+                    self.prompt_for_auth(&res);
+                    self.state.advisories.set(res);
+                }
+                LogicResponse::Dependents(res) => {
+                    self.state.dependents.set(res);
+                }
+                LogicResponse::Provenance(res) => {
+                    self.state.provenance.set(res);
+                }
+                LogicResponse::Timings(timings) => {
+                    self.state.timings.set(Ok(timings));
+                }
+                LogicResponse::PrefetchStatus(items) => {
+                    if items
+                        .iter()
+                        .any(|item| item.number == self.state.pull_request_id)
+                    {
+                        self.refresh_overview();
+                    }
+                    self.state.prefetch = items;
+                    self.refresh_review_statuses(false);
+                }
+                LogicResponse::ReviewStatuses(statuses) => {
+                    self.state.review_statuses = statuses;
+                }
+                LogicResponse::ReviewsSaved(res) => {
+                    if let Err(err) = res {
+                        log::warn!("Failed to save reviews: {}", err);
+                    }
+                    self.refresh_review_statuses(true);
+                }
+                LogicResponse::ReviewsLoaded {
+                    pull_request,
+                    reviews,
+                } => {
+                    if pull_request == self.state.reviews_pull_request {
+                        self.restore_reviews(reviews);
+                    }
+                }
+                LogicResponse::AuditLog(entries) => {
+                    self.state.audit_log.set(Ok(entries));
+                }
+                LogicResponse::AuditLogExported(res) => {
+                    self.state.audit_exported.set(res);
+                }
+                LogicResponse::SessionExported(res) => {
+                    self.state.session_exported.set(res);
+                    self.refresh_review_statuses(true);
+                }
+                LogicResponse::CheckRunPublished(res) => {
+                    self.prompt_for_auth(&res);
+                    self.state.check_run.set(res);
+                }
+                LogicResponse::ReviewSubmitted(res) => {
+                    self.prompt_for_auth(&res);
+                    let submitted = std::mem::take(&mut self.state.submitting_comments);
+                    if res.is_ok() {
+                        self.state.review_body.clear();
+                        // Comments queued or edited while submitting stay for the next review
+                        for (id, comments) in submitted {
+                            let Some(review) = self.state.reviews.get_mut(&id) else {
+                                continue;
+                            };
+                            for comment in comments {
+                                if let Some(index) =
+                                    review.comments.iter().position(|queued| *queued == comment)
+                                {
+                                    review.comments.remove(index);
+                                }
+                            }
+                        }
+                    }
+                    self.state.submitted_review.set(res);
+                }
+                LogicResponse::ExtensionApproved(res) => {
+                    self.state.approval.set(res);
+                    self.refresh_review_statuses(true);
+                }
+                LogicResponse::Tools(checks) => {
+                    self.state.onboarding.tools.set(Ok(checks));
+                }
+                LogicResponse::SelfTest(steps) => {
+                    self.state.onboarding.self_test.set(Ok(steps));
+                }
+                LogicResponse::ReviewSynced(res) => {
+                    self.prompt_for_auth(&res);
+                    self.state.team_review.set(res);
+                }
+                LogicResponse::SessionImported(res) => match res {
+                    Ok(session) => self.load_session(session),
+                    Err(err) => self.state.session_imported.set(Err(err)),
+                },
+                LogicResponse::LocalBuild(res) => match res {
+                    Ok(build) => {
+                        if let Some(diffed_extension) = &mut self.state.diffed_extension.value {
+                            diffed_extension.local_build = Some(build);
+                        }
+                        self.state.building.set(Ok(()));
+                    }
+                    Err(err) => self.state.building.set(Err(err)),
+                },
             }
         }
     }
 
+    // Saved progress fills in extensions that haven't been touched since the PR was opened
+    fn restore_reviews(&mut self, saved: BTreeMap<String, ReviewState>) {
+        for (id, saved) in saved {
+            match self.state.reviews.get_mut(&id) {
+                Some(review) => review.reviewed.extend(saved.reviewed),
+                None => {
+                    self.state.reviews.insert(id, saved);
+                }
+            }
+        }
+    }
+
+    // The reviews, if they changed since they were last saved. Counts them as saved from here on.
+    fn unsaved_reviews(&mut self) -> Option<BTreeMap<String, ReviewState>> {
+        if self.state.reviews.is_empty() {
+            return None;
+        }
+        let serialized = serde_json::to_string(&self.state.reviews).ok()?;
+        if serialized == self.state.autosaved {
+            return None;
+        }
+        self.state.autosaved = serialized;
+        Some(self.state.reviews.clone())
+    }
+
+    fn save_reviews(&mut self) {
+        if let Some(reviews) = self.unsaved_reviews() {
+            send(
+                &self.tx,
+                LogicCommand::SaveReviews {
+                    pull_request: self.state.reviews_pull_request,
+                    reviews,
+                },
+            );
+        }
+    }
+
+    // Saves the reviews every few seconds when they've changed
+    fn autosave_reviews(&mut self, ctx: &egui::Context) {
+        let time = ctx.input(|input| input.time);
+        if time - self.state.autosave_time < AUTOSAVE_INTERVAL {
+            return;
+        }
+        self.state.autosave_time = time;
+        self.save_reviews();
+    }
+
+    fn refresh_overview(&self) {
+        if let Some(update) = &self.state.pull_request_update.value {
+            send(
+                &self.tx,
+                LogicCommand::GetOverview {
+                    extensions: update.extensions.clone(),
+                    artifact_url: update.artifact_url.clone(),
+                },
+            );
+        }
+    }
+
+    fn refresh_review_statuses(&mut self, force: bool) {
+        let pull_requests = self
+            .state
+            .prefetch
+            .iter()
+            .map(|item| (item.number, item.extensions.clone()))
+            .collect::<BTreeMap<_, _>>();
+        if pull_requests.is_empty() || (!force && pull_requests == self.state.review_statuses_for) {
+            return;
+        }
+        self.state.review_statuses_for = pull_requests.clone();
+        send(&self.tx, LogicCommand::GetReviewStatuses(pull_requests));
+    }
+
+    // The snapshot is shown as-is, so the review continues against the same commits
+    fn load_session(&mut self, session: ReviewSession) {
+        // Whatever happened since the last autosave would be lost with the reviews replaced
+        self.save_reviews();
+        self.state.pull_request_id = session.pull_request;
+        self.state.pull_request_update.set(Ok(session.update));
+        self.state.selected_extension = session.selected_extension;
+        self.state.diffed_extension.clear();
+        self.state.reviews = session.reviews;
+        self.state.team_review.clear();
+        self.state.check_run.clear();
+        self.state.reviews_pull_request = session.pull_request;
+        self.state.session_imported.set(Ok(()));
+        self.state.overview.clear();
+        self.refresh_overview();
+        crash::set_pull_request(Some(session.pull_request));
+    }
+
+    fn fetch_pull_request(&mut self) {
+        if self.state.reviews_pull_request != self.state.pull_request_id {
+            // Sent first, so it's saved before anything for the next pull request is loaded
+            self.save_reviews();
+            self.state.reviews.clear();
+            self.state.reviews_pull_request = self.state.pull_request_id;
+            self.state.team_review.clear();
+            self.state.check_run.clear();
+            self.state.discarded_extension = None;
+            send(
+                &self.tx,
+                LogicCommand::LoadReviews(self.state.pull_request_id),
+            );
+        }
+        self.state.pull_request_update.clear();
+        crash::set_pull_request(Some(self.state.pull_request_id));
+        send(
+            &self.tx,
+            LogicCommand::GetPullRequest(self.state.pull_request_id),
+        );
+        self.state.pull_request_update.start();
+    }
+
+    fn save_config(&mut self, mut config: Config) {
+        // Favorites may have changed while the settings or setup window was open
+        config.favorites = self.state.config.favorites.clone();
+        let token_changed = config.github_token != self.state.config.github_token;
+        self.state.config = config.clone();
+        if token_changed {
+            // The client reads the token once, so the new thread has to find it saved
+            if let Err(err) = config.save() {
+                log::error!("Failed to save config: {:?}", err);
+            }
+            self.restart_logic_thread();
+        } else {
+            send(&self.tx, LogicCommand::UpdateConfig(config));
+        }
+    }
+
+    // Previewed while the setup wizard is open, so the choice can be seen before finishing
+    fn update_theme(&mut self, ctx: &egui::Context) {
+        let theme = if self.state.onboarding.open {
+            self.state.onboarding.draft.theme
+        } else {
+            self.state.config.theme
+        };
+        let dark = theme == Theme::Dark;
+        if ctx.style().visuals.dark_mode != dark {
+            ctx.set_visuals(visuals(theme));
+        }
+    }
+
+    // Ctrl+scroll here and egui's own Ctrl+plus/minus both end up in the saved config
+    fn update_scale(&mut self, ctx: &egui::Context) {
+        let zoom = ctx.input(|input| input.zoom_delta());
+        let mut scale = ctx.zoom_factor() * zoom;
+        if self.state.config.ui_scale != self.state.applied_scale {
+            // Changed in the settings, or the first frame
+            scale = self.state.config.ui_scale;
+        }
+        let scale = scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+        if scale != self.state.applied_scale {
+            let native = ctx.native_pixels_per_point().unwrap_or(1.0);
+            ctx.set_pixels_per_point(native * scale);
+            self.state.applied_scale = scale;
+        }
+
+        if self.state.config.ui_scale != scale {
+            self.state.config.ui_scale = scale;
+            self.state.scale_unsaved = true;
+        }
+        // Saved once the gesture is over instead of on every frame of it
+        if zoom == 1.0 && self.state.scale_unsaved {
+            self.state.scale_unsaved = false;
+            send(
+                &self.tx,
+                LogicCommand::UpdateConfig(self.state.config.clone()),
+            );
+        }
+    }
+
+    fn draw_prefetch(&mut self, ctx: &egui::Context) {
+        let mut command = None;
+        let mut open = None;
+        egui::Window::new("Prefetch")
+            .open(&mut self.state.show_prefetch)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label("Pull requests are downloaded and diffed in the background, and open instantly once done.");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.state.prefetch_input)
+                            .hint_text("PR numbers, e.g. 123, 456"),
+                    );
+                    let numbers = self
+                        .state
+                        .prefetch_input
+                        .split([',', ' '])
+                        .filter_map(|number| number.trim().trim_start_matches('#').parse().ok())
+                        .collect::<Vec<u64>>();
+                    if ui
+                        .add_enabled(!numbers.is_empty(), egui::Button::new("Queue"))
+                        .clicked()
+                    {
+                        command = Some(LogicCommand::Prefetch(numbers));
+                    }
+                    if ui.button("Queue all open PRs").clicked() {
+                        command = Some(LogicCommand::PrefetchOpen);
+                    }
+                });
+                ui.separator();
+                open = components::draw_prefetch_items(
+                    ui,
+                    &self.state.prefetch,
+                    &self.state.config.favorites,
+                    &self.state.review_statuses,
+                );
+            });
+
+        if let Some(command) = command {
+            self.state.prefetch_input.clear();
+            send(&self.tx, command);
+        }
+        if let Some(number) = open {
+            self.open_pull_request(number);
+        }
+    }
+
+    fn draw_undo_reset(&mut self, ui: &mut egui::Ui) {
+        let Some(discarded) = &self.state.discarded_extension else {
+            return;
+        };
+        // A download in flight would replace whatever gets restored
+        if self.state.diffed_extension.working {
+            return;
+        }
+
+        let id = &discarded.diffed_extension.extension.id;
+        if !ui
+            .button(format!("Undo reset ({})", id))
+            .on_hover_text("Go back to the extension without downloading it again")
+            .clicked()
+        {
+            return;
+        }
+
+        let Some(discarded) = self.state.discarded_extension.take() else {
+            return;
+        };
+        let diffed_extension = discarded.diffed_extension;
+        self.state.selected_extension = Some(diffed_extension.extension.id.clone());
+        self.state.view_type = discarded.view_type;
+        self.state.selected_file = discarded.selected_file;
+        self.state.selected_directory = None;
+        self.select_file_changed(&diffed_extension);
+        self.state.diffed_extension.clear();
+        self.state.diffed_extension.value = Some(diffed_extension);
+    }
+
     fn draw_pr_select(&mut self, ui: &mut egui::Ui) {
+        self.draw_undo_reset(ui);
+
+        let mut fetch = false;
         ui.horizontal(|ui| {
             ui.label("Pull request ID:");
             ui.add(egui::DragValue::new(&mut self.state.pull_request_id));
@@ -53,66 +752,126 @@ impl App {
             let fetch_enabled =
                 self.state.pull_request_id > 0 && !self.state.pull_request_update.working;
 
-            if ui
+            fetch = ui
                 .add_enabled(fetch_enabled, egui::Button::new("Fetch"))
-                .clicked()
-            {
-                self.state.pull_request_update.clear();
-                self.tx
-                    .send(LogicCommand::GetPullRequest(self.state.pull_request_id))
-                    .unwrap();
-                self.state.pull_request_update.start();
-            }
+                .clicked();
 
             if self.state.pull_request_update.working {
-                ui.spinner();
+                components::draw_busy(ui, &self.state.progress);
             }
         });
+        if let Some(err) = &self.state.pull_request_update.error {
+            fetch |= components::draw_error(ui, err);
+        }
+
+        if fetch {
+            self.fetch_pull_request();
+        }
+        self.draw_pull_request_list(ui);
 
         if let Some(update) = &self.state.pull_request_update.value {
+            components::draw_pr_header(ui, update, &mut self.state.markdown_cache.0);
+
+            // Every extension the PR touches, with how far along it is
+            let mut download_all = false;
             ui.horizontal(|ui| {
-                egui::ComboBox::from_label("Extension")
-                    .selected_text(
-                        self.state
-                            .selected_extension
-                            .as_deref()
-                            .unwrap_or("Select an extension"),
+                ui.heading(format!("Extensions ({})", update.extensions.len()));
+                download_all = ui
+                    .button("Download all")
+                    .on_hover_text(
+                        "Downloads in the background, extensions open instantly once done",
                     )
-                    .show_ui(ui, |ui| {
-                        for ext in &update.extensions {
-                            ui.selectable_value(
-                                &mut self.state.selected_extension,
-                                Some(ext.id.clone()),
-                                ext.id.clone(),
-                            );
-                        }
-                    });
+                    .clicked();
+                let prefetch = self
+                    .state
+                    .prefetch
+                    .iter()
+                    .find(|item| item.number == self.state.pull_request_id);
+                if let Some(PrefetchItem {
+                    state: PrefetchState::Failed(err),
+                    ..
+                }) = prefetch
+                {
+                    ui.colored_label(egui::Color32::RED, "Download failed")
+                        .on_hover_text(err);
+                }
+            });
 
-                let download_enabled =
-                    self.state.selected_extension.is_some() && !self.state.diffed_extension.working;
+            let config = &self.state.config;
+            let mut overview = update
+                .extensions
+                .iter()
+                .map(|ext| ExtensionOverview {
+                    id: ext.id.clone(),
+                    state: self
+                        .state
+                        .overview
+                        .iter()
+                        .find(|overview| overview.id == ext.id)
+                        .map(|overview| overview.state.clone())
+                        .unwrap_or(OverviewState::NotDownloaded),
+                })
+                .collect::<Vec<_>>();
+            // Favorites first, otherwise in the order the PR lists them
+            overview.sort_by_key(|ext| !config.is_favorite(&ext.id));
+            let action = components::draw_extension_overview(
+                ui,
+                &overview,
+                &self.state.reviews,
+                &config.favorites,
+                !self.state.diffed_extension.working,
+            );
 
-                if ui
-                    .add_enabled(download_enabled, egui::Button::new("Download"))
-                    .clicked()
-                {
-                    if let Some(ext_id) = &self.state.selected_extension {
-                        if let Some(ext) = update.extensions.iter().find(|ext| &ext.id == ext_id) {
-                            self.state.diffed_extension.clear();
-                            self.tx
-                                .send(LogicCommand::DownloadExtension {
-                                    extension: ext.clone(),
-                                    artifact_url: update.artifact_url.clone(),
-                                })
-                                .unwrap();
-                            self.state.diffed_extension.start();
-                        }
-                    }
+            let mut download = false;
+            match action {
+                Some(OverviewAction::Open(ext_id)) => {
+                    self.state.selected_extension = Some(ext_id);
+                    download = true;
+                }
+                Some(OverviewAction::ToggleFavorite(ext_id)) => {
+                    self.state.config.toggle_favorite(&ext_id);
+                    send(
+                        &self.tx,
+                        LogicCommand::UpdateConfig(self.state.config.clone()),
+                    );
                 }
+                None => {}
+            }
 
-                if self.state.diffed_extension.working {
-                    ui.spinner();
+            if self.state.diffed_extension.working && !self.state.local.active {
+                components::draw_progress(ui, &self.state.progress);
+            }
+            if let Some(err) = &self.state.diffed_extension.error {
+                if !self.state.local.active {
+                    download |= components::draw_error(ui, err);
                 }
-            });
+            }
+
+            if download_all {
+                send(
+                    &self.tx,
+                    LogicCommand::Prefetch(vec![self.state.pull_request_id]),
+                );
+            }
+
+            if download {
+                if let Some(ext_id) = &self.state.selected_extension {
+                    if let Some(ext) = update.extensions.iter().find(|ext| &ext.id == ext_id) {
+                        self.state.diffed_extension.clear();
+                        self.state.local.active = false;
+                        send(
+                            &self.tx,
+                            LogicCommand::DownloadExtension {
+                                extension: ext.clone(),
+                                artifact_url: update.artifact_url.clone(),
+                                pull_request: update.number,
+                                head_sha: update.head_sha.clone(),
+                            },
+                        );
+                        self.state.diffed_extension.start();
+                    }
+                }
+            }
 
             if let Some(ext_id) = &self.state.selected_extension {
                 if let Some(ext) = update.extensions.iter().find(|ext| &ext.id == ext_id) {
@@ -122,90 +881,2177 @@ impl App {
                 }
             }
         }
+
+        ui.separator();
+        self.draw_local_select(ui);
     }
-}
 
-impl eframe::App for App {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let mut delete_diffed_extension = false;
-        if let Some(diffed_extension) = &self.state.diffed_extension.value {
-            let width = ctx.available_rect().width();
-            egui::SidePanel::left("sidebar")
-                .resizable(true)
-                .max_width(width * 0.3)
-                .show(ctx, |ui| {
-                    ui.vertical(|ui| {
-                        if ui.button("Reset").clicked() {
-                            delete_diffed_extension = true;
-                        }
+    fn draw_pull_request_list(&mut self, ui: &mut egui::Ui) {
+        let mut refresh = false;
+        let mut open = None;
+        let pull_requests = &self.state.pull_requests;
+        let shown = egui::CollapsingHeader::new("Open pull requests")
+            .id_salt("open_pull_requests")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    refresh = ui
+                        .add_enabled(!pull_requests.working, egui::Button::new("Refresh"))
+                        .clicked();
+                    if pull_requests.working {
+                        ui.spinner();
+                    }
+                });
+                if let Some(err) = &pull_requests.error {
+                    refresh |= components::draw_error(ui, err);
+                }
+                if let Some(list) = &pull_requests.value {
+                    open = components::draw_pull_request_list(ui, list);
+                }
+            })
+            .body_returned
+            .is_some();
 
-                        ui.horizontal(|ui| {
-                            let source_clicked = ui
-                                .selectable_value(
-                                    &mut self.state.view_type,
-                                    state::ViewType::Source,
-                                    "Source",
-                                )
-                                .clicked();
-                            let asar_clicked = ui
-                                .selectable_value(
-                                    &mut self.state.view_type,
-                                    state::ViewType::Asar,
-                                    ".asar",
-                                )
-                                .clicked();
-                            if source_clicked || asar_clicked {
-                                self.state.selected_file = None;
-                            }
-                        });
+        // Listing takes a few requests, so it waits until someone looks
+        let unloaded = pull_requests.value.is_none()
+            && pull_requests.error.is_none()
+            && !pull_requests.working;
+        if refresh || (shown && unloaded) {
+            send(&self.tx, LogicCommand::ListPullRequests);
+            self.state.pull_requests.start();
+        }
+        if let Some(number) = open {
+            self.open_pull_request(number);
+        }
+    }
+
+    fn draw_local_select(&mut self, ui: &mut egui::Ui) {
+        let mut review = false;
+        egui::CollapsingHeader::new("Local development")
+            .id_salt("local_development")
+            .show(ui, |ui| {
+                ui.label("Compare your working tree and built .asar to the published extension.");
+                let local = &mut self.state.local;
+                egui::Grid::new("local_paths")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Extension ID:");
+                        ui.text_edit_singleline(&mut local.extension_id);
+                        ui.end_row();
+                        ui.label("Working directory:");
+                        ui.text_edit_singleline(&mut local.working_dir);
+                        ui.end_row();
+                        ui.label(".asar or artifact:");
+                        ui.text_edit_singleline(&mut local.asar_path);
+                        ui.end_row();
                     });
 
-                    let diff = if self.state.view_type == ViewType::Source {
-                        &diffed_extension.source_diff
-                    } else {
-                        &diffed_extension.asar_diff
-                    };
-                    let modified = components::draw_diffed_extension_sidebar(
-                        ui,
-                        &mut self.state.selected_file,
-                        diff,
-                    );
-                    if modified {
-                        if let Some(file) = self.state.selected_file.as_deref() {
-                            self.tx
-                                .send(LogicCommand::DiffFile(
-                                    diff.old.join(file),
-                                    diff.new.join(file),
-                                ))
-                                .unwrap();
-                        }
+                ui.horizontal(|ui| {
+                    let review_enabled = !local.extension_id.trim().is_empty()
+                        && !local.working_dir.trim().is_empty()
+                        && !local.asar_path.trim().is_empty()
+                        && !self.state.diffed_extension.working;
+                    review = ui
+                        .add_enabled(review_enabled, egui::Button::new("Review"))
+                        .clicked();
+                    if local.active && self.state.diffed_extension.working {
+                        components::draw_busy(ui, &self.state.progress);
                     }
                 });
-
-            egui::CentralPanel::default().show(ctx, |ui| {
-                egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
-                    if let Some(diff) = &self.state.diff {
-                        components::ansi(ui, diff);
+                if local.active {
+                    if let Some(err) = &self.state.diffed_extension.error {
+                        review |= components::draw_error(ui, err);
                     }
-                });
-            });
-        } else {
-            egui::CentralPanel::default().show(ctx, |ui| {
-                egui::ScrollArea::vertical()
-                    .auto_shrink(false)
-                    .show(ui, |ui| {
-                        self.draw_pr_select(ui);
-                    });
+                }
             });
-        }
 
-        if delete_diffed_extension {
+        if review {
+            let local = &mut self.state.local;
+            let extension_id = local.extension_id.trim().to_string();
+            local.active = true;
+            self.state.selected_extension = Some(extension_id.clone());
             self.state.diffed_extension.clear();
+            send(
+                &self.tx,
+                LogicCommand::ReviewLocal {
+                    extension_id,
+                    working_dir: PathBuf::from(local.working_dir.trim()),
+                    asar_path: PathBuf::from(local.asar_path.trim()),
+                },
+            );
+            self.state.diffed_extension.start();
+        }
+    }
+
+    fn current_diff<'a>(&self, diffed_extension: &'a DiffedExtension) -> &'a Diff {
+        match self.state.view_type {
+            ViewType::Source => &diffed_extension.source_diff,
+            ViewType::Asar => &diffed_extension.asar_diff,
+            ViewType::Build => diffed_extension
+                .local_build
+                .as_ref()
+                .and_then(|build| build.diff.as_ref())
+                .unwrap_or(&diffed_extension.asar_diff),
+            ViewType::Approved => diffed_extension
+                .previous_review
+                .as_ref()
+                .map(|previous| &previous.diff)
+                .unwrap_or(&diffed_extension.source_diff),
+        }
+    }
+
+    // Lands on content right away, keeping the selection when it's still there, e.g. after a redownload
+    fn open_first_file(&mut self, diffed_extension: &DiffedExtension) {
+        if let Some(file) = &self.state.selected_file {
+            if diff::find_file(&self.current_diff(diffed_extension).dir, file).is_some() {
+                self.select_file_changed(diffed_extension);
+                return;
+            }
+        }
+
+        // Modified files before anything else, and source before the .asar
+        type Filter = fn(FileState, &FileStats) -> bool;
+        let modified: Filter = |state, stats| state == FileState::Modified && !stats.build_noise;
+        let any: Filter = |_, _| true;
+        let candidates = [
+            (ViewType::Source, &diffed_extension.source_diff, modified),
+            (ViewType::Asar, &diffed_extension.asar_diff, modified),
+            (ViewType::Source, &diffed_extension.source_diff, any),
+            (ViewType::Asar, &diffed_extension.asar_diff, any),
+        ];
+        let Some((view_type, file)) =
+            candidates
+                .into_iter()
+                .find_map(|(view_type, diff, filter)| {
+                    diff::first_file(&diff.dir, &filter).map(|file| (view_type, file))
+                })
+        else {
+            return;
+        };
+
+        self.state.view_type = view_type;
+        self.state.selected_file = Some(file);
+        self.state.selected_directory = None;
+        self.state.file_tab = FileTab::Diff;
+        self.select_file_changed(diffed_extension);
+    }
+
+    fn select_file_changed(&mut self, diffed_extension: &DiffedExtension) {
+        self.state.page = ExtensionPage::Files;
+        self.state.diff = None;
+        self.state.raw_file.clear();
+        self.state.diff_export.clear();
+        self.state.diff_export_overwrite = None;
+        self.state.history = HistoryState::default();
+        self.state.blame.clear();
+        self.state.provenance.clear();
+        // Files seen before open where they were left, unless a specific line was asked for
+        self.state.restore_scroll = match self.scroll_key() {
+            Some(key) if self.state.scroll_to_line.is_none() => Some(
+                self.state
+                    .scroll_offsets
+                    .get(&key)
+                    .copied()
+                    .unwrap_or_default(),
+            ),
+            _ => None,
+        };
+
+        if self.selected_unchanged(diffed_extension) {
+            self.request_unchanged_file(diffed_extension);
+            return;
+        }
+        if let Some(file) = self.state.selected_file.as_deref() {
+            let diff = self.current_diff(diffed_extension);
+            send(
+                &self.tx,
+                LogicCommand::DiffFile {
+                    old: diff.old.join(file),
+                    new: diff.new.join(file),
+                    pretty: self.state.view_type == ViewType::Asar
+                        && self.state.pretty_print_bundles,
+                },
+            );
+        }
+        self.request_raw_file(diffed_extension);
+    }
+
+    // Unchanged files are only in the full tree, and open read-only since there's no diff
+    fn selected_unchanged(&self, diffed_extension: &DiffedExtension) -> bool {
+        let Some(file) = &self.state.selected_file else {
+            return false;
+        };
+        let tree = &self.current_diff(diffed_extension).full_dir;
+        matches!(diff::find_file(tree, file), Some((FileState::Unchanged, _)))
+    }
+
+    fn request_unchanged_file(&mut self, diffed_extension: &DiffedExtension) {
+        self.state.raw_file.clear();
+        let Some(file) = &self.state.selected_file else {
+            return;
+        };
+        let path = self.current_diff(diffed_extension).new.join(file);
+        send(&self.tx, LogicCommand::GetFile(path));
+        self.state.raw_file.start();
+    }
+
+    fn request_raw_file(&mut self, diffed_extension: &DiffedExtension) {
+        self.state.raw_file.clear();
+        let (Some(side), Some(file)) = (self.state.raw_side, &self.state.selected_file) else {
+            return;
+        };
+        let diff = self.current_diff(diffed_extension);
+        let dir = match side {
+            RawSide::Old => &diff.old,
+            RawSide::New => &diff.new,
+        };
+        send(&self.tx, LogicCommand::GetFile(dir.join(file)));
+        self.state.raw_file.start();
+    }
+
+    fn open_location(
+        &mut self,
+        origin: Origin,
+        path: &str,
+        line: Option<usize>,
+        diffed_extension: &DiffedExtension,
+    ) {
+        self.state.view_type = match origin {
+            Origin::Source => ViewType::Source,
+            Origin::Asar => ViewType::Asar,
+        };
+        self.state.selected_file = Some(path.to_string());
+        self.state.selected_directory = None;
+        self.state.file_tab = FileTab::Diff;
+        if line.is_some() {
+            self.state.diff_display = DiffDisplay::Hunks;
+            self.state.scroll_to_line = line;
+        }
+        self.select_file_changed(diffed_extension);
+    }
+
+    fn open_finding(&mut self, finding: &Finding, diffed_extension: &DiffedExtension) {
+        self.open_location(
+            finding.origin,
+            &finding.path,
+            finding.line,
+            diffed_extension,
+        );
+    }
+
+    fn draw_findings(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        let findings = &diffed_extension.findings;
+        egui::CollapsingHeader::new(format!("Findings ({})", findings.len()))
+            .default_open(!findings.is_empty())
+            .show(ui, |ui| {
+                egui::ScrollArea::both()
+                    .auto_shrink([false, true])
+                    .show(ui, |ui| {
+                        let triage = &mut self.state.review(&diffed_extension.extension.id).triage;
+                        if let Some(i) = components::draw_findings(ui, findings, triage) {
+                            self.open_finding(&findings[i], diffed_extension);
+                        }
+                    });
+            });
+    }
+
+    fn draw_sidebar(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) -> bool {
+        let mut reset = false;
+        let mut rediff = false;
+
+        ui.vertical(|ui| {
+            for downgrade in &diffed_extension.downgrades {
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("⚠ Downgrade: {}", downgrade.describe()),
+                )
+                .on_hover_text("Downgrades can bring back fixed vulnerabilities");
+            }
+            components::draw_consistency(ui, &diffed_extension.consistency);
+            components::draw_manifest_images(ui, &diffed_extension.images);
+            ui.horizontal(|ui| {
+                if ui.button("Reset").clicked() {
+                    reset = true;
+                }
+                if ui.button("Go to file").on_hover_text("Ctrl+P").clicked() {
+                    self.state.quick_open.show();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let source_clicked = ui
+                    .selectable_value(&mut self.state.view_type, ViewType::Source, "Source")
+                    .clicked();
+                let asar_clicked = ui
+                    .selectable_value(&mut self.state.view_type, ViewType::Asar, ".asar")
+                    .clicked();
+                let has_build = diffed_extension
+                    .local_build
+                    .as_ref()
+                    .is_some_and(|build| build.diff.is_some());
+                let build_clicked = has_build
+                    && ui
+                        .selectable_value(&mut self.state.view_type, ViewType::Build, "Build")
+                        .on_hover_text("Your local build compared to the artifact")
+                        .clicked();
+                let approved_clicked = match &diffed_extension.previous_review {
+                    Some(previous) => ui
+                        .selectable_value(
+                            &mut self.state.view_type,
+                            ViewType::Approved,
+                            "Since approval",
+                        )
+                        .on_hover_text(format!(
+                            "The new source compared to {}, which you approved in #{} {}",
+                            &previous.approval.commit[..previous.approval.commit.len().min(8)],
+                            previous.approval.pull_request,
+                            components::format_age(previous.approval.timestamp as i64)
+                        ))
+                        .clicked(),
+                    None => false,
+                };
+                if source_clicked || asar_clicked || build_clicked || approved_clicked {
+                    self.state.selected_file = None;
+                    self.state.selected_directory = None;
+                    self.state.history = HistoryState::default();
+                    self.state.blame.clear();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.state.full_tree, false, "Changes");
+                ui.selectable_value(&mut self.state.full_tree, true, "All files")
+                    .on_hover_text("Unchanged files too, for reading the code around a change");
+            });
+
+            if self.state.view_type == ViewType::Asar {
+                ui.checkbox(&mut self.state.hide_build_noise, "Hide build noise")
+                    .on_hover_text(
+                        "Hide files that only changed by build paths, timestamps, or line endings",
+                    );
+                let pretty = ui
+                    .checkbox(&mut self.state.pretty_print_bundles, "Format minified code")
+                    .on_hover_text(
+                        "Lay minified JavaScript out one statement per line before diffing it. \
+                         Line numbers then refer to the formatted code.",
+                    );
+                if pretty.changed() && self.state.selected_file.is_some() {
+                    rediff = true;
+                }
+                if diffed_extension.asar_reordered {
+                    ui.label("Files in the .asar were stored in a different order.");
+                }
+                if !diffed_extension.renamed_paths.is_empty() {
+                    components::draw_renamed_paths(ui, &diffed_extension.renamed_paths);
+                }
+            }
+
+            if self.state.config.build.enabled {
+                self.draw_build_controls(ui, diffed_extension);
+            }
+        });
+
+        let diff = self.current_diff(diffed_extension);
+        let previous_directory = self.state.selected_directory.clone();
+        let origin = review_origin(self.state.view_type);
+        let extension_id = &diffed_extension.extension.id;
+        let review = self.state.reviews.get(extension_id);
+        let team = self.state.team_review.value.as_ref();
+        let review_marker = |path: &str| {
+            let Some(origin) = origin else {
+                return String::new();
+            };
+            let key = FileKey::new(origin, path);
+            let mut marker = String::new();
+            if review.is_some_and(|review| review.reviewed.contains(&key)) {
+                marker.push_str(" ✔");
+            }
+            let others = team
+                .map(|team| team.reviewed_by_others(extension_id, &key))
+                .unwrap_or_default();
+            if !others.is_empty() {
+                marker.push_str(&format!(" ({})", others.join(", ")));
+            }
+            marker
+        };
+        let modified = components::draw_diffed_extension_sidebar(
+            ui,
+            &mut self.state.selected_file,
+            &mut self.state.selected_directory,
+            diff,
+            self.state.full_tree,
+            self.state.view_type == ViewType::Asar && self.state.hide_build_noise,
+            &review_marker,
+        );
+        if modified || rediff {
+            self.select_file_changed(diffed_extension);
+        } else if self.state.selected_directory != previous_directory {
+            self.state.page = ExtensionPage::Files;
+        }
+
+        reset
+    }
+
+    fn draw_build_controls(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        ui.horizontal(|ui| {
+            let building = &mut self.state.building;
+            let mut button = ui.add_enabled(!building.working, egui::Button::new("Build locally"));
+            if self.state.config.build.container_runtime.trim().is_empty() {
+                button = button.on_hover_text(
+                    "No container runtime is set, so the build runs on this machine and can \
+                     still reach the network.",
+                );
+            }
+            if button.clicked() {
+                let temp_dir = diffed_extension
+                    .repository_dir
+                    .parent()
+                    .unwrap_or(&diffed_extension.repository_dir)
+                    .to_path_buf();
+                building.clear();
+                building.request(
+                    &self.tx,
+                    LogicCommand::BuildLocally {
+                        extension_id: diffed_extension.extension.id.clone(),
+                        source_dir: diffed_extension.source_diff.new.clone(),
+                        artifact_dir: diffed_extension.asar_diff.new.clone(),
+                        temp_dir,
+                    },
+                );
+            }
+
+            if building.working {
+                ui.spinner();
+            } else if let Some(build) = &diffed_extension.local_build {
+                match build.outcome {
+                    BuildOutcome::Succeeded => {
+                        ui.colored_label(egui::Color32::GREEN, "Built");
+                    }
+                    BuildOutcome::Failed => {
+                        ui.colored_label(egui::Color32::RED, "Build failed");
+                    }
+                    BuildOutcome::MissingOutput => {
+                        ui.colored_label(egui::Color32::RED, "No build output")
+                            .on_hover_text(
+                                "The build succeeded, but the output directory is missing",
+                            );
+                    }
+                }
+                ui.toggle_value(&mut self.state.show_build_log, "Log");
+            }
+        });
+        if components::draw_error_banner(ui, &self.state.building) {
+            self.state.building.retry(&self.tx);
+        }
+    }
+
+    fn draw_extension_page(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.state.page, ExtensionPage::Files, "Files");
+            ui.selectable_value(
+                &mut self.state.page,
+                ExtensionPage::Dependencies,
+                format!(
+                    "Dependencies ({})",
+                    diffed_extension.dependencies.changes.len()
+                ),
+            );
+            ui.selectable_value(
+                &mut self.state.page,
+                ExtensionPage::Patches,
+                format!("Patches ({})", diffed_extension.patches.len()),
+            );
+            let dependents = match &self.state.dependents.value {
+                Some(dependents) => format!("Used by ({})", dependents.len()),
+                None => "Used by".to_string(),
+            };
+            ui.selectable_value(&mut self.state.page, ExtensionPage::Dependents, dependents);
+        });
+        ui.separator();
+
+        match self.state.page {
+            ExtensionPage::Files => self.draw_file_view(ui, diffed_extension),
+            ExtensionPage::Dependencies => self.draw_dependencies(ui, diffed_extension),
+            ExtensionPage::Patches => {
+                egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                    if let Some((path, line)) =
+                        components::draw_patch_changes(ui, &diffed_extension.patches)
+                    {
+                        self.open_location(Origin::Source, &path, Some(line), diffed_extension);
+                    }
+                });
+            }
+            ExtensionPage::Dependents => self.draw_dependents(ui, diffed_extension),
+        }
+    }
+
+    // Offered rather than done automatically, since installing runs a C compiler on downloaded code
+    fn draw_grammar_prompt(&mut self, ui: &mut egui::Ui) {
+        let Some(name) = self
+            .state
+            .diff
+            .as_ref()
+            .and_then(|diff| diff.missing_grammar.clone())
+        else {
+            return;
+        };
+
+        let install = &mut self.state.grammar_install;
+        let mut start = false;
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "No {} grammar is installed, so hunks aren't labeled and there's no outline.",
+                name
+            ));
+            start = ui
+                .add_enabled(
+                    !install.working,
+                    egui::Button::new(format!("Download and compile {} grammar", name)),
+                )
+                .on_hover_text(
+                    "Clones the tree-sitter grammar and builds it with your C compiler \
+                     (cc, or CC if set) into RoboJules' cache",
+                )
+                .clicked();
+            if install.working {
+                ui.spinner();
+            }
+        });
+        if let Some(err) = &install.error {
+            start |= components::draw_error(ui, err);
+        }
+
+        if start {
+            install.clear();
+            send(&self.tx, LogicCommand::InstallGrammar(name));
+            install.start();
+        }
+    }
+
+    fn draw_dependents(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        let dependents = &mut self.state.dependents;
+        let mut refresh = ui
+            .horizontal(|ui| {
+                ui.label("Published extensions that depend on this one");
+                let refresh = ui
+                    .add_enabled(!dependents.working, egui::Button::new("Refresh"))
+                    .clicked();
+                if dependents.working {
+                    ui.spinner();
+                }
+                refresh
+            })
+            .inner;
+        if let Some(err) = &dependents.error {
+            refresh |= components::draw_error(ui, err);
+        }
+        if refresh {
+            dependents.clear();
+            send(
+                &self.tx,
+                LogicCommand::GetDependents(diffed_extension.extension.id.clone()),
+            );
+            dependents.start();
+        }
+
+        if let Some(dependents) = &dependents.value {
+            egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                components::draw_dependents(ui, dependents);
+            });
+        }
+    }
+
+    fn draw_dependencies(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        let dependencies = &diffed_extension.dependencies;
+        let Some(lockfile) = &dependencies.lockfile else {
+            ui.label("This extension doesn't have a lockfile.");
+            return;
+        };
+        if ui.button("View lockfile as text").clicked() {
+            self.open_location(Origin::Source, lockfile, None, diffed_extension);
+            return;
+        }
+
+        let added = dependencies
+            .added()
+            .map(|(name, version)| (name.to_string(), version.to_string()))
+            .collect::<Vec<_>>();
+        let introduced = dependencies.introduced();
+        let risks = &mut self.state.dependency_risks;
+        let advisories = &mut self.state.advisories;
+
+        // Advisories are cheap to check, so do it as soon as the page is opened
+        let refresh = ui.horizontal(|ui| {
+            ui.label(format!("Changes in {}", lockfile));
+            if ui
+                .add_enabled(
+                    !added.is_empty() && !risks.working,
+                    egui::Button::new(format!("Check {} added packages", added.len())),
+                )
+                .clicked()
+            {
+                risks.clear();
+                risks.request(&self.tx, LogicCommand::CheckDependencies(added.clone()));
+            }
+            if risks.working {
+                ui.spinner();
+            }
+
+            let refresh = ui
+                .add_enabled(
+                    !introduced.is_empty() && !advisories.working,
+                    egui::Button::new("Refresh advisories"),
+                )
+                .clicked();
+            if advisories.working {
+                ui.spinner();
+            }
+            refresh
+        });
+        let refresh = refresh.inner;
+
+        let should_check = advisories.value.is_none()
+            && advisories.error.is_none()
+            && !advisories.working
+            && !introduced.is_empty();
+        if refresh || should_check {
+            advisories.clear();
+            advisories.request(
+                &self.tx,
+                LogicCommand::CheckAdvisories {
+                    packages: introduced,
+                    refresh,
+                },
+            );
+        }
+        if components::draw_error_banner(ui, advisories) {
+            advisories.retry(&self.tx);
+        }
+        if components::draw_error_banner(ui, risks) {
+            risks.retry(&self.tx);
+        }
+
+        if let Some(advisories) = &advisories.value {
+            components::draw_advisories(ui, advisories);
+        }
+        let advisories = advisories.value.as_deref().unwrap_or_default();
+
+        egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+            if let Some(risks) = &risks.value {
+                ui.heading("Added packages");
+                components::draw_dependency_risks(ui, risks);
+                ui.separator();
+            }
+
+            components::draw_dependency_changes(ui, &dependencies.changes, advisories);
+        });
+    }
+
+    fn draw_file_view(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        if let Some(path) = &self.state.selected_directory {
+            let tree = &self.current_diff(diffed_extension).dir;
+            egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                if let Some(dir) = diff::find_directory(tree, path) {
+                    components::draw_directory_summary(ui, path, &diff::summarize_directory(dir));
+                }
+            });
+            return;
+        }
+
+        // History only makes sense for files in the cloned repository
+        if matches!(self.state.view_type, ViewType::Source | ViewType::Approved)
+            && self.state.selected_file.is_some()
+        {
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.state.file_tab, FileTab::Diff, "Diff");
+                ui.selectable_value(&mut self.state.file_tab, FileTab::History, "History");
+                ui.selectable_value(&mut self.state.file_tab, FileTab::Blame, "Blame");
+            });
+            ui.separator();
+        } else {
+            self.state.file_tab = FileTab::Diff;
+        }
+
+        self.draw_file_review(ui, diffed_extension);
+
+        match self.state.file_tab {
+            FileTab::Diff if self.selected_unchanged(diffed_extension) => {
+                ui.weak("Unchanged in this pull request.");
+                let wrap = self.is_wrapped();
+                let raw_file = &self.state.raw_file;
+                if raw_file.working {
+                    ui.spinner();
+                }
+                let mut retry = false;
+                if let Some(err) = &raw_file.error {
+                    retry = components::draw_error(ui, err);
+                } else if let Some(file) = &raw_file.value {
+                    components::draw_single_file(ui, file, wrap);
+                }
+                if retry {
+                    self.request_unchanged_file(diffed_extension);
+                }
+            }
+            FileTab::Diff => {
+                if let Some(file) = &self.state.selected_file {
+                    let tree = &self.current_diff(diffed_extension).dir;
+                    if let Some((_, stats)) = diff::find_file(tree, file) {
+                        components::draw_mode_change(ui, &stats);
+                    }
+                }
+                // Lockfile diffs are unreadable as text, the dependencies page resolves them
+                if self.state.view_type == ViewType::Source
+                    && self.state.selected_file.is_some()
+                    && self.state.selected_file == diffed_extension.dependencies.lockfile
+                    && ui.button("Show resolved dependencies").clicked()
+                {
+                    self.state.page = ExtensionPage::Dependencies;
+                }
+                if let Some(diff) = &self.state.diff {
+                    components::draw_whitespace_changes(ui, diff);
+                }
+                self.draw_grammar_prompt(ui);
+                if matches!(self.state.view_type, ViewType::Asar | ViewType::Build) {
+                    self.draw_provenance(ui, diffed_extension);
+                }
+
+                ui.horizontal(|ui| {
+                    ui.selectable_value(
+                        &mut self.state.diff_display,
+                        DiffDisplay::Difftastic,
+                        "difftastic",
+                    );
+                    ui.selectable_value(&mut self.state.diff_display, DiffDisplay::Hunks, "Hunks");
+                    if self
+                        .state
+                        .diff
+                        .as_ref()
+                        .is_some_and(|diff| diff.modules.is_some())
+                    {
+                        ui.selectable_value(
+                            &mut self.state.diff_display,
+                            DiffDisplay::Modules,
+                            "Modules",
+                        );
+                    }
+                    ui.selectable_value(
+                        &mut self.state.diff_display,
+                        DiffDisplay::SideBySide,
+                        "Side by side",
+                    );
+                    if self.state.diff_display == DiffDisplay::Hunks {
+                        components::draw_diff_layout(
+                            ui,
+                            "diff_layout",
+                            &mut self.state.diff_layout,
+                        );
+                    }
+                    ui.separator();
+                    // Reading a whole side helps with context the diff leaves out
+                    let raw_side = self.state.raw_side;
+                    for (side, label) in [(RawSide::Old, "Old file"), (RawSide::New, "New file")] {
+                        let mut selected = self.state.raw_side == Some(side);
+                        if ui.toggle_value(&mut selected, label).changed() {
+                            self.state.raw_side = selected.then_some(side);
+                        }
+                    }
+                    if self.state.raw_side != raw_side {
+                        self.request_raw_file(diffed_extension);
+                    }
+                    ui.separator();
+                    ui.toggle_value(&mut self.state.show_outline, "Outline");
+                    if let Some(file) = &self.state.selected_file {
+                        let mut wrap = self.state.wrapped_files.contains(file);
+                        if ui
+                            .toggle_value(&mut wrap, "Wrap")
+                            .on_hover_text("Wrap long lines in this file")
+                            .changed()
+                        {
+                            if wrap {
+                                self.state.wrapped_files.insert(file.clone());
+                            } else {
+                                self.state.wrapped_files.remove(file);
+                            }
+                        }
+                    }
+                    ui.separator();
+                    ui.menu_button("Export", |ui| {
+                        for format in [ExportFormat::Png, ExportFormat::Svg] {
+                            let enabled = !self.state.diff_export.working;
+                            if ui
+                                .add_enabled(
+                                    enabled,
+                                    egui::Button::new(format!("Export as {}", format.name())),
+                                )
+                                .clicked()
+                            {
+                                self.start_diff_export(ui, format, false);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    if ui
+                        .button("Pop out")
+                        .on_hover_text("Open this diff in its own window")
+                        .clicked()
+                    {
+                        self.pop_out_diff(diffed_extension);
+                    }
+                });
+
+                if let Some(rx) = &self.state.diff_export_rx {
+                    let result = match rx.try_recv() {
+                        Ok(result) => Some(result),
+                        Err(flume::TryRecvError::Empty) => None,
+                        Err(flume::TryRecvError::Disconnected) => {
+                            Some(Err(anyhow::anyhow!("Export stopped unexpectedly")))
+                        }
+                    };
+                    if let Some(result) = result {
+                        self.state.diff_export.set(result.map_err(Into::into));
+                        self.state.diff_export_rx = None;
+                    }
+                }
+                if let Some((format, path)) = self.state.diff_export_overwrite.clone() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} already exists.", path.display()));
+                        if ui.button("Overwrite").clicked() {
+                            self.state.diff_export_overwrite = None;
+                            self.start_diff_export(ui, format, true);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.state.diff_export_overwrite = None;
+                        }
+                    });
+                }
+                if self.state.diff_export.working {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Exporting…");
+                    });
+                }
+                if let Some(path) = &self.state.diff_export.value {
+                    ui.label(format!("Saved to {}", path.display()));
+                }
+                if let Some(err) = &self.state.diff_export.error {
+                    components::draw_error(ui, err);
+                }
+
+                if self.state.show_outline {
+                    if let Some(diff) = &self.state.diff {
+                        egui::SidePanel::right("outline")
+                            .resizable(true)
+                            .show_inside(ui, |ui| {
+                                egui::ScrollArea::vertical()
+                                    .auto_shrink(false)
+                                    .show(ui, |ui| {
+                                        if let Some(line) =
+                                            components::draw_outline(ui, &diff.outline)
+                                        {
+                                            // Only the hunk view knows where lines are
+                                            self.state.diff_display = DiffDisplay::Hunks;
+                                            self.state.raw_side = None;
+                                            self.state.scroll_to_line = Some(line);
+                                        }
+                                    });
+                            });
+                    }
+                }
+
+                let wrap = self.is_wrapped();
+                if self.state.raw_side.is_some() {
+                    let raw_file = &self.state.raw_file;
+                    if raw_file.working {
+                        ui.spinner();
+                    }
+                    let mut retry = false;
+                    if let Some(err) = &raw_file.error {
+                        retry = components::draw_error(ui, err);
+                    } else if let Some(file) = &raw_file.value {
+                        components::draw_single_file(ui, file, wrap);
+                    }
+                    if retry {
+                        self.request_raw_file(diffed_extension);
+                    }
+                } else if let Some(diff) = &self.state.diff {
+                    // Comments need a file that's part of the review
+                    let comment_key = review_origin(self.state.view_type)
+                        .zip(self.state.selected_file.as_deref())
+                        .map(|(origin, file)| FileKey::new(origin, file));
+                    let mut comment_target = None;
+                    let offset = components::draw_file_diff(
+                        ui,
+                        diff,
+                        &mut self.state.diff_display,
+                        self.state.diff_layout,
+                        &mut self.state.scroll_to_line,
+                        self.state.restore_scroll.take(),
+                        wrap,
+                        comment_key.is_some().then_some(&mut comment_target),
+                    );
+                    if let (Some(key), Some(target)) = (comment_key, comment_target) {
+                        self.state.pending_comment = Some((key, target));
+                    }
+                    if let Some(key) = self.scroll_key() {
+                        self.state.scroll_offsets.insert(key, offset);
+                    }
+                }
+            }
+            FileTab::History => self.draw_history(ui, diffed_extension),
+            FileTab::Blame => self.draw_blame(ui, diffed_extension),
+        }
+    }
+
+    // Lays out what the diff view currently shows, minus the modules view which is interactive
+    // rather than text, and renders and saves it on another thread since big files take a while
+    fn start_diff_export(&mut self, ui: &egui::Ui, format: ExportFormat, overwrite: bool) {
+        let (job, path) = match self.diff_export_job(ui, format) {
+            Ok(job) => job,
+            Err(err) => {
+                self.state.diff_export.set(Err(err.into()));
+                return;
+            }
+        };
+        if !overwrite && path.exists() {
+            self.state.diff_export_overwrite = Some((format, path));
+            return;
+        }
+
+        let ctx = ui.ctx().clone();
+        let background = ui.visuals().extreme_bg_color;
+        let (tx, rx) = flume::bounded(1);
+        std::thread::spawn(move || {
+            let result = match format {
+                ExportFormat::Png => export::png(&ctx, job, background),
+                ExportFormat::Svg => Ok(export::svg(&job, background).into_bytes()),
+            }
+            .and_then(|data| {
+                std::fs::write(&path, data)
+                    .with_context(|| format!("Failed to write {:?}", path))?;
+                log::debug!("Exported diff to {:?}", path);
+                Ok(path)
+            });
+            let _ = tx.send(result);
+            ctx.request_repaint();
+        });
+        self.state.diff_export.clear();
+        self.state.diff_export.start();
+        self.state.diff_export_rx = Some(rx);
+    }
+
+    fn diff_export_job(
+        &self,
+        ui: &egui::Ui,
+        format: ExportFormat,
+    ) -> anyhow::Result<(egui::text::LayoutJob, PathBuf)> {
+        let diff = self.state.diff.as_ref().context("No diff to export")?;
+        let file = self
+            .state
+            .selected_file
+            .as_deref()
+            .context("No file selected")?;
+        let raw_file = match self.state.raw_side {
+            Some(_) => Some(
+                self.state
+                    .raw_file
+                    .value
+                    .as_ref()
+                    .context("File isn't loaded")?,
+            ),
+            None => None,
+        };
+        let job = match (raw_file, self.state.diff_display) {
+            (Some(raw_file), _) => components::single_file_layout_job(ui, raw_file, false),
+            (None, DiffDisplay::Difftastic) => {
+                components::ansi_layout_job(&diff.rendered, DiffColors::get(ui.ctx()))
+            }
+            (None, DiffDisplay::Hunks | DiffDisplay::Modules) => {
+                widget::DiffView::from_file_diff(diff)
+                    .layout(self.state.diff_layout)
+                    .style(components::diff_style(ui, false))
+                    .layout_job(ui.visuals())
+            }
+            (None, DiffDisplay::SideBySide) => widget::DiffView::new(diff.whole_file.as_slice())
+                .layout(widget::DiffLayout::Split)
+                .style(components::side_by_side_style(ui))
+                .layout_job(ui.visuals()),
+        };
+
+        let name = file.rsplit('/').next().unwrap_or(file);
+        let dir = dirs::download_dir()
+            .or_else(dirs::home_dir)
+            .context("Failed to find a directory to save to")?;
+        let path = dir.join(format!("robojules-{}.{}", name, format.extension()));
+        Ok((job, path))
+    }
+
+    // Pop-outs keep a copy of the diff, so they stay put while the main window moves on
+    fn pop_out_diff(&mut self, diffed_extension: &DiffedExtension) {
+        let (Some(file), Some(diff)) = (&self.state.selected_file, &self.state.diff) else {
+            return;
+        };
+        let title = format!(
+            "{} ({}) - {}",
+            file,
+            self.state.view_type.name(),
+            diffed_extension.extension.id
+        );
+        if self
+            .state
+            .popped_out
+            .iter()
+            .any(|popped| popped.title == title)
+        {
+            return;
+        }
+        self.state.popped_out.push(PoppedOutDiff {
+            id: egui::ViewportId::from_hash_of(&title),
+            title,
+            diff: diff.clone(),
+            display: self.state.diff_display,
+            layout: self.state.diff_layout,
+            scroll_to_line: None,
+            wrap: self.is_wrapped(),
+        });
+    }
+
+    fn draw_popped_out(&mut self, ctx: &egui::Context) {
+        self.state.popped_out.retain_mut(|popped| {
+            let mut open = true;
+            ctx.show_viewport_immediate(
+                popped.id,
+                egui::ViewportBuilder::default()
+                    .with_title(&popped.title)
+                    .with_inner_size([900., 700.]),
+                |ctx, _class| {
+                    egui::TopBottomPanel::top(popped.id.0.with("bar")).show(ctx, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(
+                                &mut popped.display,
+                                DiffDisplay::Difftastic,
+                                "difftastic",
+                            );
+                            ui.selectable_value(&mut popped.display, DiffDisplay::Hunks, "Hunks");
+                            if popped.diff.modules.is_some() {
+                                ui.selectable_value(
+                                    &mut popped.display,
+                                    DiffDisplay::Modules,
+                                    "Modules",
+                                );
+                            }
+                            ui.selectable_value(
+                                &mut popped.display,
+                                DiffDisplay::SideBySide,
+                                "Side by side",
+                            );
+                            if popped.display == DiffDisplay::Hunks {
+                                components::draw_diff_layout(
+                                    ui,
+                                    popped.id.0.with("layout"),
+                                    &mut popped.layout,
+                                );
+                            }
+                            ui.separator();
+                            ui.toggle_value(&mut popped.wrap, "Wrap");
+                        });
+                    });
+                    egui::CentralPanel::default().show(ctx, |ui| {
+                        components::draw_file_diff(
+                            ui,
+                            &popped.diff,
+                            &mut popped.display,
+                            popped.layout,
+                            &mut popped.scroll_to_line,
+                            None,
+                            popped.wrap,
+                            None,
+                        );
+                    });
+                    if ctx.input(|input| input.viewport().close_requested()) {
+                        open = false;
+                    }
+                },
+            );
+            open
+        });
+    }
+
+    fn scroll_key(&self) -> Option<String> {
+        let file = self.state.selected_file.as_ref()?;
+        Some(format!("{}:{}", self.state.view_type.name(), file))
+    }
+
+    fn is_wrapped(&self) -> bool {
+        self.state
+            .selected_file
+            .as_ref()
+            .is_some_and(|file| self.state.wrapped_files.contains(file))
+    }
+
+    fn draw_file_review(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        // Nothing to review in a file the pull request doesn't touch
+        if self.selected_unchanged(diffed_extension) {
+            return;
+        }
+        let (Some(origin), Some(file)) = (
+            review_origin(self.state.view_type),
+            self.state.selected_file.clone(),
+        ) else {
+            return;
+        };
+        let key = FileKey::new(origin, &file);
+        let review = self.state.review(&diffed_extension.extension.id);
+
+        ui.horizontal(|ui| {
+            let mut reviewed = review.reviewed.contains(&key);
+            if ui.checkbox(&mut reviewed, "Reviewed").changed() {
+                if reviewed {
+                    review.reviewed.insert(key.clone());
+                } else {
+                    review.reviewed.remove(&key);
+                }
+            }
+
+            let mut note = review.file_notes.get(&key).cloned().unwrap_or_default();
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut note)
+                    .hint_text("Note")
+                    .desired_width(f32::INFINITY),
+            );
+            if response.changed() {
+                if note.is_empty() {
+                    review.file_notes.remove(&key);
+                } else {
+                    review.file_notes.insert(key.clone(), note);
+                }
+            }
+        });
+
+        // Queued comments on this file, posted when the review is submitted
+        let mut remove = None;
+        for (i, comment) in review.comments.iter().enumerate() {
+            if comment.origin != origin || comment.path != file {
+                continue;
+            }
+            ui.horizontal(|ui| {
+                if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                    remove = Some(i);
+                }
+                ui.label(format!("Line {}: {}", comment.line, comment.body));
+            });
+        }
+        if let Some(i) = remove {
+            review.comments.remove(i);
+        }
+
+        let Some((_, target)) = self
+            .state
+            .pending_comment
+            .clone()
+            .filter(|(pending_key, _)| *pending_key == key)
+        else {
+            return;
+        };
+        let mut queue = false;
+        let mut cancel = false;
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("Comment on line {}", target.line));
+                ui.weak(target.text.trim());
+            });
+            ui.add(
+                egui::TextEdit::multiline(&mut self.state.comment_draft)
+                    .hint_text("Posted with the review")
+                    .desired_rows(2)
+                    .desired_width(f32::INFINITY),
+            );
+            ui.horizontal(|ui| {
+                queue = ui
+                    .add_enabled(
+                        !self.state.comment_draft.trim().is_empty(),
+                        egui::Button::new("Add to review"),
+                    )
+                    .clicked();
+                cancel = ui.button("Cancel").clicked();
+            });
+        });
+
+        if queue {
+            let ext = &diffed_extension.extension;
+            let repository = ext.new_repository.as_ref().unwrap_or(&ext.repository);
+            // Only source files can be linked to, the .asar isn't in the repository
+            let link = (origin == Origin::Source && repository.starts_with("https://github.com/"))
+                .then(|| {
+                    format!(
+                        "{}/blob/{}/{}#L{}",
+                        repository.trim_end_matches('/').trim_end_matches(".git"),
+                        ext.new_commit,
+                        file,
+                        target.line
+                    )
+                });
+            let comment = LineComment {
+                origin,
+                path: file,
+                line: target.line,
+                quote: target.text,
+                link,
+                body: self.state.comment_draft.trim().to_string(),
+            };
+            self.state.review(&ext.id).comments.push(comment);
+        }
+        if queue || cancel {
+            self.state.pending_comment = None;
+            self.state.comment_draft.clear();
+        }
+    }
+
+    // Approving or requesting changes on GitHub, once there's an extension loaded to have reviewed
+    fn draw_review_composer(&mut self, ui: &mut egui::Ui) -> Option<LogicCommand> {
+        ui.heading("Submit review");
+        // The loaded pull request, not whatever number has been typed in since
+        let reviewed = self
+            .state
+            .pull_request_update
+            .value
+            .as_ref()
+            .map(|update| (update.number, update.head_sha.clone()))
+            .filter(|(_, head_sha)| !head_sha.is_empty());
+        let loaded = self.state.diffed_extension.value.is_some() && reviewed.is_some();
+        if !loaded {
+            ui.weak("Download an extension from a pull request to submit a review.");
+        }
+
+        // Files nobody marked as reviewed yet, across every downloaded extension
+        let unreviewed = self
+            .state
+            .reviews
+            .values()
+            .map(|review| review.total_files.saturating_sub(review.reviewed.len()))
+            .sum::<usize>();
+        if loaded && unreviewed > 0 {
+            ui.colored_label(
+                egui::Color32::LIGHT_YELLOW,
+                format!("{} changed files aren't marked as reviewed", unreviewed),
+            );
+        }
+
+        // The extensions repository's policy can hold approvals until some checks pass
+        let missing_checks = self
+            .state
+            .pull_request_update
+            .value
+            .as_ref()
+            .map(|update| update.policy.missing_checks(&update.checks))
+            .unwrap_or_default();
+        if loaded && !missing_checks.is_empty() {
+            ui.colored_label(
+                egui::Color32::LIGHT_YELLOW,
+                format!(
+                    "Required checks haven't passed: {}",
+                    missing_checks.join(", ")
+                ),
+            );
+        }
+
+        let queued = self
+            .state
+            .reviews
+            .values()
+            .map(|review| review.comments.len())
+            .sum::<usize>();
+        if queued > 0 {
+            ui.label(format!(
+                "{} line comments will be posted with the review",
+                queued
+            ));
+        }
+
+        let mut command = None;
+        ui.add_enabled_ui(loaded, |ui| {
+            ui.horizontal(|ui| {
+                for verdict in ReviewVerdict::ALL {
+                    ui.selectable_value(&mut self.state.review_verdict, verdict, verdict.name());
+                }
+            });
+            ui.add(
+                egui::TextEdit::multiline(&mut self.state.review_body)
+                    .hint_text("Comment, optional when approving")
+                    .desired_rows(3)
+                    .desired_width(f32::INFINITY),
+            );
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Insert feedback")
+                    .on_hover_text(
+                        "Every downloaded extension's findings, worded by the review templates",
+                    )
+                    .clicked()
+                {
+                    let feedback = self
+                        .state
+                        .reviews
+                        .iter()
+                        .map(|(id, review)| {
+                            templates::feedback(&self.state.config.review_templates, id, review)
+                        })
+                        .filter(|feedback| !feedback.is_empty())
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    if !self.state.review_body.trim().is_empty() && !feedback.is_empty() {
+                        self.state.review_body.push_str("\n\n");
+                    }
+                    self.state.review_body.push_str(&feedback);
+                }
+                let needs_body = self.state.review_verdict != ReviewVerdict::Approve
+                    && self.state.review_body.trim().is_empty()
+                    && queued == 0;
+                let blocked = self.state.review_verdict == ReviewVerdict::Approve
+                    && !missing_checks.is_empty();
+                if ui
+                    .add_enabled(
+                        !needs_body && !blocked && !self.state.submitted_review.working,
+                        egui::Button::new(format!("Submit: {}", self.state.review_verdict.name())),
+                    )
+                    .on_hover_text("Posted to GitHub as you, for the commit you reviewed")
+                    .clicked()
+                {
+                    let comments = self
+                        .state
+                        .reviews
+                        .iter()
+                        .filter(|(_, review)| !review.comments.is_empty())
+                        .map(|(id, review)| (id.clone(), review.comments.clone()))
+                        .collect::<BTreeMap<_, _>>();
+                    self.state.submitting_comments = comments.clone();
+                    command = reviewed.map(|(pull_request, head_sha)| LogicCommand::SubmitReview {
+                        pull_request,
+                        head_sha,
+                        verdict: self.state.review_verdict,
+                        body: self.state.review_body.trim().to_string(),
+                        comments,
+                    });
+                }
+                if self.state.submitted_review.working {
+                    ui.spinner();
+                }
+                if let Some(review) = &self.state.submitted_review.value {
+                    ui.hyperlink_to("Submitted", &review.html_url);
+                }
+            });
+        });
+        if let Some(err) = &self.state.submitted_review.error {
+            components::draw_error(ui, err);
+        }
+        command
+    }
+
+    fn draw_review(&mut self, ctx: &egui::Context) {
+        let mut command = None;
+        let mut open = None;
+        // Copied out, so the window's contents can borrow all of self
+        let mut show_review = self.state.show_review;
+        // The loaded pull request, not whatever number has been typed in since
+        let pull_request = self
+            .state
+            .pull_request_update
+            .value
+            .as_ref()
+            .map(|update| update.number);
+        egui::Window::new("Review")
+            .open(&mut show_review)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let extension_id = self
+                    .state
+                    .diffed_extension
+                    .value
+                    .as_ref()
+                    .map(|diffed_extension| diffed_extension.extension.id.clone())
+                    .or_else(|| self.state.selected_extension.clone());
+                match extension_id {
+                    Some(extension_id) => {
+                        match pull_request {
+                            Some(pull_request) => ui
+                                .label(format!("Pull request #{}, {}", pull_request, extension_id)),
+                            None => ui.label(&extension_id),
+                        };
+                        ui.separator();
+                        let review = self.state.reviews.entry(extension_id.clone()).or_default();
+                        egui::ScrollArea::vertical()
+                            .max_height(400.)
+                            .show(ui, |ui| {
+                                open = components::draw_review(ui, review);
+                            });
+                        let feedback = templates::feedback(
+                            &self.state.config.review_templates,
+                            &extension_id,
+                            review,
+                        );
+                        if ui
+                            .add_enabled(!feedback.is_empty(), egui::Button::new("Copy feedback"))
+                            .on_hover_text(
+                                "Markdown for the author, worded by the review templates in the \
+                                 settings. False positives are left out.",
+                            )
+                            .clicked()
+                        {
+                            ui.ctx().copy_text(feedback);
+                        }
+
+                        if let Some(diffed_extension) = &self.state.diffed_extension.value {
+                            if let Some(dependents) = self
+                                .state
+                                .dependents
+                                .value
+                                .as_ref()
+                                .filter(|dependents| !dependents.is_empty())
+                            {
+                                ui.colored_label(
+                                    egui::Color32::LIGHT_YELLOW,
+                                    format!(
+                                        "{} published extensions depend on this one",
+                                        dependents.len()
+                                    ),
+                                )
+                                .on_hover_text(
+                                    dependents
+                                        .iter()
+                                        .map(|dependent| dependent.name.as_str())
+                                        .collect::<Vec<_>>()
+                                        .join("\n"),
+                                );
+                            }
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .add_enabled(
+                                        !self.state.approval.working && pull_request.is_some(),
+                                        egui::Button::new("Approve this version"),
+                                    )
+                                    .on_hover_text(
+                                        "Later updates will also be diffed against this commit",
+                                    )
+                                    .clicked()
+                                {
+                                    command = pull_request.map(|pull_request| {
+                                        LogicCommand::ApproveExtension {
+                                            extension: diffed_extension.extension.clone(),
+                                            pull_request,
+                                        }
+                                    });
+                                }
+                                if self.state.approval.working {
+                                    ui.spinner();
+                                } else if let Some(approval) = &self.state.approval.value {
+                                    ui.label(format!(
+                                        "Approved {}",
+                                        &approval.commit[..approval.commit.len().min(8)]
+                                    ));
+                                }
+                            });
+                            if let Some(err) = &self.state.approval.error {
+                                components::draw_error(ui, err);
+                            }
+                        }
+
+                        if !self.state.config.sync.gist_id.trim().is_empty() {
+                            ui.separator();
+                            ui.horizontal(|ui| {
+                                ui.heading("Team");
+                                if ui
+                                    .add_enabled(
+                                        !self.state.team_review.working && pull_request.is_some(),
+                                        egui::Button::new("Sync"),
+                                    )
+                                    .on_hover_text("Share your progress and fetch everyone else's")
+                                    .clicked()
+                                {
+                                    command =
+                                        pull_request.map(|pull_request| LogicCommand::SyncReview {
+                                            pull_request,
+                                            reviews: self.state.reviews.clone(),
+                                        });
+                                }
+                                if self.state.team_review.working {
+                                    ui.spinner();
+                                }
+                            });
+                            if let Some(err) = &self.state.team_review.error {
+                                components::draw_error(ui, err);
+                            }
+                            if let Some(team) = &self.state.team_review.value {
+                                components::draw_team_review(ui, team, &extension_id);
+                            }
+                        }
+                    }
+                    None => {
+                        ui.label("Select an extension to start reviewing it.");
+                    }
+                }
+                ui.separator();
+
+                // Findings are snapshotted into the review as each extension is downloaded
+                let findings = self
+                    .state
+                    .reviews
+                    .iter()
+                    .map(|(id, review)| (id.clone(), review.findings.clone()))
+                    .collect::<BTreeMap<_, _>>();
+                ui.horizontal(|ui| {
+                    let head_sha = self
+                        .state
+                        .pull_request_update
+                        .value
+                        .as_ref()
+                        .map(|update| update.head_sha.clone())
+                        .filter(|head_sha| !head_sha.is_empty());
+                    let enabled =
+                        head_sha.is_some() && !findings.is_empty() && !self.state.check_run.working;
+                    if ui
+                        .add_enabled(enabled, egui::Button::new("Publish findings"))
+                        .on_hover_text(format!(
+                            "Post findings from {} downloaded extensions as a check run on the PR. \
+                             Needs a GITHUB_TOKEN that can write checks.",
+                            findings.len()
+                        ))
+                        .clicked()
+                    {
+                        command = head_sha.map(|head_sha| LogicCommand::PublishCheckRun {
+                            head_sha,
+                            conclusion: CheckConclusion::from_findings(&findings),
+                            findings: findings.clone(),
+                        });
+                    }
+                    if self.state.check_run.working {
+                        ui.spinner();
+                    }
+                    if let Some(check_run) = &self.state.check_run.value {
+                        ui.hyperlink_to("Check run", &check_run.html_url);
+                    }
+                });
+                if let Some(err) = &self.state.check_run.error {
+                    components::draw_error(ui, err);
+                }
+                ui.separator();
+
+                if let Some(submit) = self.draw_review_composer(ui) {
+                    command = Some(submit);
+                }
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.state.session_path);
+                    let path = PathBuf::from(self.state.session_path.trim());
+                    let has_path = !self.state.session_path.trim().is_empty();
+                    let export_enabled = has_path
+                        && self.state.pull_request_update.value.is_some()
+                        && !self.state.session_exported.working;
+                    if ui
+                        .add_enabled(export_enabled, egui::Button::new("Export"))
+                        .clicked()
+                    {
+                        if let Some(update) = &self.state.pull_request_update.value {
+                            command = Some(LogicCommand::ExportSession {
+                                path: path.clone(),
+                                session: ReviewSession::new(
+                                    update.number,
+                                    update.clone(),
+                                    self.state.selected_extension.clone(),
+                                    self.state.reviews.clone(),
+                                    &self.state.config.review_templates,
+                                ),
+                            });
+                        }
+                    }
+                    if ui
+                        .add_enabled(
+                            has_path && !self.state.session_imported.working,
+                            egui::Button::new("Import"),
+                        )
+                        .clicked()
+                    {
+                        command = Some(LogicCommand::ImportSession(path));
+                    }
+                });
+                if let Some(path) = &self.state.session_exported.value {
+                    ui.label(format!("Exported to {}", path.display()));
+                }
+                if self.state.session_imported.value.is_some() {
+                    ui.label("Imported. Download the extension to continue reviewing.");
+                }
+                for err in [
+                    &self.state.session_exported.error,
+                    &self.state.session_imported.error,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    components::draw_error(ui, err);
+                }
+            });
+
+        self.state.show_review = show_review;
+
+        match command {
+            Some(command @ LogicCommand::PublishCheckRun { .. }) => {
+                send(&self.tx, command);
+                self.state.check_run.clear();
+                self.state.check_run.start();
+            }
+            Some(command @ LogicCommand::SubmitReview { .. }) => {
+                send(&self.tx, command);
+                self.state.submitted_review.clear();
+                self.state.submitted_review.start();
+            }
+            Some(command @ LogicCommand::SyncReview { .. }) => {
+                send(&self.tx, command);
+                self.state.team_review.start();
+            }
+            Some(command @ LogicCommand::ApproveExtension { .. }) => {
+                send(&self.tx, command);
+                self.state.approval.clear();
+                self.state.approval.start();
+            }
+            Some(command @ LogicCommand::ExportSession { .. }) => {
+                send(&self.tx, command);
+                self.state.session_exported.clear();
+                self.state.session_exported.start();
+            }
+            Some(command) => {
+                send(&self.tx, command);
+                self.state.session_imported.clear();
+                self.state.session_imported.start();
+            }
+            None => {}
+        }
+
+        if let Some(key) = open {
+            if let Some(diffed_extension) = self.state.diffed_extension.value.take() {
+                self.open_location(key.origin, &key.path, None, &diffed_extension);
+                self.state.diffed_extension.value = Some(diffed_extension);
+            }
+        }
+    }
+
+    fn draw_provenance(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        let Some(file) = self.state.selected_file.clone() else {
+            return;
+        };
+        let provenance = &mut self.state.provenance;
+
+        if provenance.value.is_none() && provenance.error.is_none() && !provenance.working {
+            let dist_dir = match self.state.view_type {
+                ViewType::Source | ViewType::Approved => return,
+                ViewType::Asar | ViewType::Build => diffed_extension.asar_diff.new.clone(),
+            };
+            provenance.request(
+                &self.tx,
+                LogicCommand::GetProvenance {
+                    dist_dir,
+                    source_dir: diffed_extension.source_diff.new.clone(),
+                    path: file,
+                },
+            );
+        }
+
+        if provenance.working {
+            ui.spinner();
+            return;
+        }
+        if components::draw_error_banner(ui, provenance) {
+            provenance.retry(&self.tx);
+            return;
+        }
+
+        let Some(provenance) = &provenance.value else {
+            return;
+        };
+        let mut clicked = None;
+        egui::CollapsingHeader::new(format!("Provenance: {}", provenance.summary()))
+            .id_salt("provenance")
+            .show(ui, |ui| {
+                clicked = components::draw_provenance(ui, provenance);
+            });
+        if let Some(path) = clicked {
+            self.open_location(Origin::Source, &path, None, diffed_extension);
+        }
+    }
+
+    fn draw_blame(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        let Some(file) = self.state.selected_file.clone() else {
+            return;
+        };
+        let blame = &mut self.state.blame;
+
+        if blame.value.is_none() && blame.error.is_none() && !blame.working {
+            let ext = &diffed_extension.extension;
+            blame.request(
+                &self.tx,
+                LogicCommand::BlameFile {
+                    repository_dir: diffed_extension.repository_dir.clone(),
+                    old_rev: ext.old_commit.clone(),
+                    new_rev: ext.new_commit.clone(),
+                    path: file,
+                },
+            );
+        }
+
+        if blame.working {
+            ui.spinner();
+            return;
+        }
+        if components::draw_error_banner(ui, blame) {
+            blame.retry(&self.tx);
+            return;
+        }
+
+        if let Some(lines) = &blame.value {
+            egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                components::draw_blame(ui, lines);
+            });
+        }
+    }
+
+    fn draw_history(&mut self, ui: &mut egui::Ui, diffed_extension: &DiffedExtension) {
+        let Some(file) = self.state.selected_file.clone() else {
+            return;
+        };
+        let wrap = self.is_wrapped();
+        let history = &mut self.state.history;
+
+        if history.revisions.value.is_none()
+            && history.revisions.error.is_none()
+            && !history.revisions.working
+        {
+            history.revisions.request(
+                &self.tx,
+                LogicCommand::GetFileHistory {
+                    repository_dir: diffed_extension.repository_dir.clone(),
+                    rev: diffed_extension.extension.new_commit.clone(),
+                    path: file.clone(),
+                },
+            );
+        }
+
+        if history.revisions.working {
+            ui.spinner();
+            return;
+        }
+        if components::draw_error_banner(ui, &history.revisions) {
+            history.revisions.retry(&self.tx);
+            return;
+        }
+
+        let Some(revisions) = &history.revisions.value else {
+            return;
+        };
+
+        egui::ScrollArea::vertical()
+            .id_salt("history_revisions")
+            .max_height(ui.available_height() * 0.4)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                egui::Grid::new("history_grid")
+                    .striped(true)
+                    .num_columns(6)
+                    .show(ui, |ui| {
+                        ui.strong("Old");
+                        ui.strong("New");
+                        ui.strong("Commit");
+                        ui.strong("Author");
+                        ui.strong("Date");
+                        ui.strong("Summary");
+                        ui.end_row();
+
+                        for (i, revision) in revisions.iter().enumerate() {
+                            ui.radio_value(&mut history.old, Some(i), "");
+                            ui.radio_value(&mut history.new, Some(i), "");
+                            ui.monospace(&revision.hash[..revision.hash.len().min(8)]);
+                            ui.label(&revision.author);
+                            ui.label(components::format_age(revision.timestamp));
+                            ui.label(&revision.summary);
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        let selected = match (history.old, history.new) {
+            (Some(old), Some(new)) if old != new => Some((old, new)),
+            _ => None,
+        };
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    selected.is_some() && !history.diff.working,
+                    egui::Button::new("Diff selected revisions"),
+                )
+                .clicked()
+            {
+                if let Some((old, new)) = selected {
+                    history.diff.clear();
+                    history.diff.request(
+                        &self.tx,
+                        LogicCommand::DiffRevisions {
+                            repository_dir: diffed_extension.repository_dir.clone(),
+                            old: revisions[old].clone(),
+                            new: revisions[new].clone(),
+                            path: file.clone(),
+                        },
+                    );
+                }
+            }
+
+            if history.diff.working {
+                ui.spinner();
+            }
+        });
+        if components::draw_error_banner(ui, &history.diff) {
+            history.diff.retry(&self.tx);
+        }
+        ui.separator();
+
+        egui::ScrollArea::new([!wrap, true])
+            .id_salt("history_diff")
+            .auto_shrink(false)
+            .show(ui, |ui| {
+                if let Some(diff) = &history.diff.value {
+                    components::ansi(ui, diff, wrap);
+                }
+            });
+    }
+}
+
+// Only source and .asar files can be marked as reviewed, build output is compared against the artifact
+fn review_origin(view_type: ViewType) -> Option<Origin> {
+    match view_type {
+        // Both sides are source, so the new files are the same ones
+        ViewType::Source | ViewType::Approved => Some(Origin::Source),
+        ViewType::Asar => Some(Origin::Asar),
+        ViewType::Build => None,
+    }
+}
+
+impl eframe::App for App {
+    // The logic thread may not get to a save sent now, so the reviews are written before exiting
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        let Some(reviews) = self.unsaved_reviews() else {
+            return;
+        };
+        let pull_request = self.state.reviews_pull_request;
+        let res = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| runtime.block_on(session::save_reviews(pull_request, reviews)));
+        if let Err(err) = res {
+            log::error!("Failed to save reviews: {:?}", err);
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.check_logic_thread();
+        self.update_scale(ctx);
+        DiffColors::new(self.state.config.palette).store(ctx);
+        self.update_theme(ctx);
+        self.autosave_reviews(ctx);
+        if let Some(reason) = &self.state.backend_error {
+            let mut restart = false;
+            egui::TopBottomPanel::top("backend_error").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(egui::Color32::RED, reason);
+                    restart = ui.button("Restart backend").clicked();
+                });
+            });
+            if restart {
+                self.restart_logic_thread();
+            }
+        }
+
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Settings").clicked() {
+                    self.state.settings.show(&self.state.config);
+                }
+                if ui.button("Setup").clicked() {
+                    self.state.onboarding.show(&self.state.config);
+                    self.state
+                        .onboarding
+                        .tools
+                        .request(&self.tx, LogicCommand::CheckTools);
+                }
+                if ui
+                    .toggle_value(&mut self.state.show_diagnostics, "Diagnostics")
+                    .clicked()
+                    && self.state.show_diagnostics
+                {
+                    send(&self.tx, LogicCommand::GetTimings);
+                    self.state.timings.start();
+                }
+                ui.toggle_value(&mut self.state.show_prefetch, "Prefetch");
+                ui.toggle_value(&mut self.state.show_review, "Review");
+                if ui
+                    .toggle_value(&mut self.state.show_audit_log, "Audit log")
+                    .clicked()
+                    && self.state.show_audit_log
+                {
+                    send(&self.tx, LogicCommand::GetAuditLog);
+                    self.state.audit_log.start();
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    components::draw_auth_status(ui, &self.state.auth);
+                });
+            });
+        });
+
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            components::draw_bandwidth(ui, &self.state.bandwidth);
+        });
+
+        // Taken out of the state while drawing so the draw functions can borrow self mutably
+        if let Some(diffed_extension) = self.state.diffed_extension.value.take() {
+            let width = ctx.available_rect().width();
+            let mut reset = false;
+            egui::SidePanel::left("sidebar")
+                .resizable(true)
+                .max_width(width * 0.3)
+                .show(ctx, |ui| {
+                    reset = self.draw_sidebar(ui, &diffed_extension);
+                });
+
+            egui::TopBottomPanel::bottom("findings")
+                .resizable(true)
+                .show(ctx, |ui| {
+                    self.draw_findings(ui, &diffed_extension);
+                });
+
+            egui::CentralPanel::default().show(ctx, |ui| {
+                self.draw_extension_page(ui, &diffed_extension);
+            });
+
+            if let Some((origin, path)) =
+                quick_open::draw_quick_open(ctx, &mut self.state.quick_open, &diffed_extension)
+            {
+                self.open_location(origin, &path, None, &diffed_extension);
+            }
+
+            if reset {
+                self.state.diffed_extension.clear();
+                self.state.discarded_extension = Some(DiscardedExtension {
+                    diffed_extension,
+                    view_type: self.state.view_type,
+                    selected_file: self.state.selected_file.clone(),
+                });
+            } else {
+                self.state.diffed_extension.value = Some(diffed_extension);
+            }
+        } else {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .auto_shrink(false)
+                    .show(ui, |ui| {
+                        self.draw_pr_select(ui);
+                    });
+            });
+        }
+
+        if self.state.show_build_log {
+            let log = self
+                .state
+                .diffed_extension
+                .value
+                .as_ref()
+                .and_then(|diffed_extension| diffed_extension.local_build.as_ref())
+                .map(|build| build.log.as_str());
+            if let Some(log) = log {
+                egui::Window::new("Build log")
+                    .open(&mut self.state.show_build_log)
+                    .resizable(true)
+                    .show(ctx, |ui| {
+                        egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
+                            ui.monospace(log);
+                        });
+                    });
+            }
+        }
+
+        if self.state.show_diagnostics {
+            let mut command = None;
+            egui::Window::new("Diagnostics")
+                .open(&mut self.state.show_diagnostics)
+                .resizable(true)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Refresh").clicked() {
+                            command = Some(LogicCommand::GetTimings);
+                        }
+                        if ui.button("Clear").clicked() {
+                            command = Some(LogicCommand::ClearTimings);
+                        }
+                        if self.state.timings.working {
+                            ui.spinner();
+                        }
+                    });
+                    ui.separator();
+                    if let Some(timings) = &self.state.timings.value {
+                        components::draw_timings(ui, timings);
+                    }
+                });
+            if let Some(command) = command {
+                send(&self.tx, command);
+                self.state.timings.start();
+            }
+        }
+
+        if self.state.show_prefetch {
+            self.draw_prefetch(ctx);
+        }
+
+        if self.state.show_review {
+            self.draw_review(ctx);
+        }
+
+        self.draw_auth_prompt(ctx);
+
+        if self.state.show_audit_log {
+            let mut command = None;
+            egui::Window::new("Audit log")
+                .open(&mut self.state.show_audit_log)
+                .resizable(true)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Refresh").clicked() {
+                            command = Some(LogicCommand::GetAuditLog);
+                        }
+                        if self.state.audit_log.working {
+                            ui.spinner();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut self.state.audit_export_path);
+                        let export_enabled = !self.state.audit_export_path.trim().is_empty()
+                            && !self.state.audit_exported.working;
+                        if ui
+                            .add_enabled(export_enabled, egui::Button::new("Export JSON"))
+                            .clicked()
+                        {
+                            command = Some(LogicCommand::ExportAuditLog(PathBuf::from(
+                                self.state.audit_export_path.trim(),
+                            )));
+                        }
+                    });
+                    if let Some(path) = &self.state.audit_exported.value {
+                        ui.label(format!("Exported to {}", path.display()));
+                    }
+                    if let Some(err) = &self.state.audit_exported.error {
+                        components::draw_error(ui, err);
+                    }
+                    ui.separator();
+                    if let Some(entries) = &self.state.audit_log.value {
+                        components::draw_audit_log(ui, entries);
+                    }
+                });
+            match command {
+                Some(LogicCommand::GetAuditLog) => {
+                    send(&self.tx, LogicCommand::GetAuditLog);
+                    self.state.audit_log.start();
+                }
+                Some(command) => {
+                    send(&self.tx, command);
+                    self.state.audit_exported.clear();
+                    self.state.audit_exported.start();
+                }
+                None => {}
+            }
+        }
+
+        if self.state.crash.is_none() {
+            self.state.crash = crash::take_pending();
+        }
+        if let Some(report) = &self.state.crash {
+            let mut dismissed = false;
+            egui::Window::new("RoboJules crashed")
+                .collapsible(false)
+                .resizable(true)
+                .show(ctx, |ui| {
+                    ui.label("A crash report was written to:");
+                    ui.monospace(report.path.display().to_string());
+                    ui.label("It includes recent log lines, which can mention PRs and extensions you were reviewing.");
+                    ui.separator();
+                    egui::ScrollArea::vertical()
+                        .max_height(300.)
+                        .show(ui, |ui| {
+                            ui.monospace(&report.text);
+                        });
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        if ui.button("Report issue").clicked() {
+                            ctx.open_url(egui::OpenUrl::new_tab(report.issue_url()));
+                        }
+                        if ui.button("Copy report").clicked() {
+                            ctx.copy_text(report.text.clone());
+                        }
+                        if ui.button("Dismiss").clicked() {
+                            dismissed = true;
+                        }
+                    });
+                });
+            if dismissed {
+                self.state.crash = None;
+            }
+        }
+
+        self.draw_popped_out(ctx);
+
+        #[cfg(feature = "tray")]
+        let tray_error = self.tray.as_ref().and_then(|tray| tray.as_ref().err());
+        #[cfg(not(feature = "tray"))]
+        let tray_error = None;
+        if let Some(config) = settings::draw_settings(ctx, &mut self.state.settings, tray_error) {
+            self.save_config(config);
+        }
+
+        match onboarding::draw_onboarding(ctx, &mut self.state.onboarding, &self.state.auth) {
+            Some(OnboardingAction::CheckTools) => {
+                self.state
+                    .onboarding
+                    .tools
+                    .request(&self.tx, LogicCommand::CheckTools);
+            }
+            Some(OnboardingAction::SignIn(token)) => {
+                let mut config = self.state.config.clone();
+                config.github_token = token;
+                if let Err(err) = config.save() {
+                    log::error!("Failed to save config: {:?}", err);
+                }
+                self.state.config = config;
+                // Restarted even when the token didn't change, to pick up a new gh CLI login
+                self.restart_logic_thread();
+            }
+            Some(OnboardingAction::SelfTest) => {
+                self.state
+                    .onboarding
+                    .self_test
+                    .request(&self.tx, LogicCommand::SelfTest);
+            }
+            // Saved even when nothing changed, so the wizard doesn't come back on the next start.
+            // Only what the wizard asks about is taken from the draft, so anything changed in the
+            // settings while it was open stays.
+            Some(OnboardingAction::Finish(draft)) => {
+                let mut config = self.state.config.clone();
+                config.theme = draft.theme;
+                config.cache_dir = draft.cache_dir;
+                config.github_token = draft.github_token;
+                self.save_config(config);
+            }
+            None => {}
         }
 
         // Since we're receiving messages on the UI thread, we need to be
         // repainting at least sometimes so the UI can update
         self.handle_messages();
+        self.handle_instance_requests();
+        #[cfg(feature = "tray")]
+        self.update_tray(ctx);
         ctx.request_repaint_after(Duration::from_millis(100));
     }
 }
@@ -0,0 +1,255 @@
+use super::{components, settings, state::AsyncState};
+use crate::logic::{
+    config::{Config, Secret},
+    onboarding::{SelfTestStep, ToolCheck},
+    AuthStatus,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnboardingStep {
+    #[default]
+    Tools,
+    GitHub,
+    Preferences,
+    SelfTest,
+}
+
+impl OnboardingStep {
+    const ALL: [OnboardingStep; 4] = [
+        OnboardingStep::Tools,
+        OnboardingStep::GitHub,
+        OnboardingStep::Preferences,
+        OnboardingStep::SelfTest,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            OnboardingStep::Tools => "Tools",
+            OnboardingStep::GitHub => "GitHub",
+            OnboardingStep::Preferences => "Preferences",
+            OnboardingStep::SelfTest => "Self-test",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|step| *step == self).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OnboardingState {
+    pub open: bool,
+    pub step: OnboardingStep,
+    pub draft: Config,
+    pub tools: AsyncState<Vec<ToolCheck>>,
+    pub self_test: AsyncState<Vec<SelfTestStep>>,
+}
+
+impl OnboardingState {
+    pub fn show(&mut self, config: &Config) {
+        self.open = true;
+        self.step = OnboardingStep::default();
+        self.draft = config.clone();
+        self.self_test.clear();
+    }
+}
+
+pub enum OnboardingAction {
+    CheckTools,
+    // The client reads the token once, so signing in restarts the logic thread
+    SignIn(Secret),
+    SelfTest,
+    Finish(Config),
+}
+
+fn install_url(tool: &str) -> Option<&'static str> {
+    match tool {
+        "git" => Some("https://git-scm.com/downloads"),
+        "difft" => Some("https://difftastic.wilfred.me.uk/installation.html"),
+        _ => None,
+    }
+}
+
+fn draw_tools(ui: &mut egui::Ui, onboarding: &OnboardingState) -> Option<OnboardingAction> {
+    ui.label(
+        "RoboJules clones extensions with git and diffs them with difftastic, \
+         so both need to be on the PATH.",
+    );
+    if onboarding.tools.working {
+        ui.spinner();
+    }
+    if let Some(checks) = &onboarding.tools.value {
+        egui::Grid::new("onboarding_tools").show(ui, |ui| {
+            for check in checks {
+                ui.monospace(&check.name);
+                match &check.version {
+                    Ok(version) => {
+                        ui.colored_label(egui::Color32::GREEN, format!("✔ {}", version));
+                    }
+                    Err(err) => {
+                        ui.colored_label(egui::Color32::RED, "✖ Not found")
+                            .on_hover_text(err);
+                        if let Some(url) = install_url(&check.name) {
+                            ui.hyperlink_to("Install", url);
+                        }
+                    }
+                }
+                ui.end_row();
+            }
+        });
+    }
+    let clicked = ui
+        .add_enabled(!onboarding.tools.working, egui::Button::new("Check again"))
+        .on_hover_text("After installing a tool; a new PATH may need a restart to be picked up")
+        .clicked();
+    clicked.then_some(OnboardingAction::CheckTools)
+}
+
+fn draw_github(
+    ui: &mut egui::Ui,
+    onboarding: &mut OnboardingState,
+    auth: &AsyncState<AuthStatus>,
+) -> Option<OnboardingAction> {
+    ui.label(
+        "Signing in raises GitHub's rate limit and lets CI artifacts be downloaded directly. \
+         Reviews can be submitted from here too. It's optional, public pull requests can be \
+         reviewed without it.",
+    );
+    ui.horizontal(|ui| {
+        ui.label("Currently:");
+        components::draw_auth_status(ui, auth);
+    });
+    ui.label(
+        "Log in with the GitHub CLI (`gh auth login`), set GITHUB_TOKEN, or paste a token here. \
+         It's stored in plain text.",
+    );
+    ui.horizontal(|ui| {
+        ui.label("Token");
+        ui.add(
+            egui::TextEdit::singleline(&mut onboarding.draft.github_token.0)
+                .password(true)
+                .desired_width(300.0),
+        );
+        if ui.button("Create a token").clicked() {
+            ui.ctx().open_url(egui::OpenUrl::new_tab(
+                "https://github.com/settings/tokens/new?description=RoboJules",
+            ));
+        }
+    });
+    let clicked = ui
+        .add_enabled(!auth.working, egui::Button::new("Sign in"))
+        .on_hover_text("Picks up the token above, or the gh CLI's login")
+        .clicked();
+    clicked.then(|| OnboardingAction::SignIn(onboarding.draft.github_token.clone()))
+}
+
+fn draw_preferences(ui: &mut egui::Ui, onboarding: &mut OnboardingState) {
+    settings::draw_theme(ui, &mut onboarding.draft.theme);
+    ui.separator();
+    settings::draw_cache_dir(ui, &mut onboarding.draft.cache_dir);
+    ui.separator();
+    ui.weak("Everything else is in the settings.");
+}
+
+fn draw_self_test(ui: &mut egui::Ui, onboarding: &OnboardingState) -> Option<OnboardingAction> {
+    ui.label(
+        "Goes through a review on a recently merged pull request: reading it from GitHub, \
+         reaching the extension's repository and diffing a file. Nothing is written to the cache.",
+    );
+    let clicked = ui
+        .add_enabled(
+            !onboarding.self_test.working,
+            egui::Button::new("Run self-test"),
+        )
+        .clicked();
+    if onboarding.self_test.working {
+        ui.spinner();
+    }
+    if let Some(steps) = &onboarding.self_test.value {
+        egui::Grid::new("onboarding_self_test").show(ui, |ui| {
+            for step in steps {
+                match &step.result {
+                    Ok(found) => {
+                        ui.colored_label(egui::Color32::GREEN, "✔");
+                        ui.label(&step.name);
+                        ui.weak(found);
+                    }
+                    Err(err) => {
+                        ui.colored_label(egui::Color32::RED, "✖");
+                        ui.label(&step.name);
+                        ui.colored_label(egui::Color32::RED, err);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        if steps.iter().all(|step| step.result.is_ok()) {
+            ui.label("Everything works. You're ready to review.");
+        }
+    }
+    clicked.then_some(OnboardingAction::SelfTest)
+}
+
+pub fn draw_onboarding(
+    ctx: &egui::Context,
+    onboarding: &mut OnboardingState,
+    auth: &AsyncState<AuthStatus>,
+) -> Option<OnboardingAction> {
+    if !onboarding.open {
+        return None;
+    }
+
+    let mut action = None;
+    egui::Window::new("Welcome to RoboJules")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for step in OnboardingStep::ALL {
+                    ui.selectable_value(&mut onboarding.step, step, step.name());
+                }
+            });
+            ui.separator();
+
+            action = match onboarding.step {
+                OnboardingStep::Tools => draw_tools(ui, onboarding),
+                OnboardingStep::GitHub => draw_github(ui, onboarding, auth),
+                OnboardingStep::Preferences => {
+                    draw_preferences(ui, onboarding);
+                    None
+                }
+                OnboardingStep::SelfTest => draw_self_test(ui, onboarding),
+            };
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                let index = onboarding.step.index();
+                if ui
+                    .add_enabled(index > 0, egui::Button::new("Back"))
+                    .clicked()
+                {
+                    onboarding.step = OnboardingStep::ALL[index - 1];
+                }
+                let finished = match OnboardingStep::ALL.get(index + 1) {
+                    Some(next) => {
+                        if ui.button("Next").clicked() {
+                            onboarding.step = *next;
+                        }
+                        ui.button("Skip setup")
+                            .on_hover_text("It can be reopened from the menu")
+                            .clicked()
+                    }
+                    None => ui.button("Finish").clicked(),
+                };
+                if finished {
+                    action = Some(OnboardingAction::Finish(onboarding.draft.clone()));
+                }
+            });
+        });
+
+    if matches!(action, Some(OnboardingAction::Finish(_))) {
+        onboarding.open = false;
+    }
+    action
+}
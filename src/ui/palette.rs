@@ -0,0 +1,48 @@
+use crate::logic::config::Palette;
+use egui::Color32;
+
+// Colors for added, removed, and modified content, in the palette picked in the settings
+#[derive(Debug, Clone, Copy)]
+pub struct DiffColors {
+    pub added: Color32,
+    pub removed: Color32,
+    pub modified: Color32,
+}
+
+impl DiffColors {
+    pub fn new(palette: Palette) -> Self {
+        match palette {
+            Palette::RedGreen => Self {
+                added: Color32::GREEN,
+                removed: Color32::RED,
+                modified: Color32::LIGHT_YELLOW,
+            },
+            // Tells apart for red-green color blindness, the most common kind
+            Palette::BlueOrange => Self {
+                added: Color32::from_rgb(86, 180, 233),
+                removed: Color32::from_rgb(230, 159, 0),
+                modified: Color32::from_rgb(204, 121, 167),
+            },
+            // For blue-yellow color blindness
+            Palette::MagentaTeal => Self {
+                added: Color32::from_rgb(0, 200, 190),
+                removed: Color32::from_rgb(230, 60, 160),
+                modified: Color32::LIGHT_GRAY,
+            },
+        }
+    }
+
+    fn id() -> egui::Id {
+        egui::Id::new("diff_colors")
+    }
+
+    // Kept in egui's memory for the frame, so components deep in the tree don't need it passed down
+    pub fn store(self, ctx: &egui::Context) {
+        ctx.data_mut(|data| data.insert_temp(Self::id(), self));
+    }
+
+    pub fn get(ctx: &egui::Context) -> Self {
+        ctx.data(|data| data.get_temp(Self::id()))
+            .unwrap_or_else(|| Self::new(Palette::default()))
+    }
+}
@@ -0,0 +1,153 @@
+// Ctrl+P file search across both trees
+use crate::logic::{
+    diff::{self, DiffedExtension},
+    scan::Origin,
+};
+
+const MAX_RESULTS: usize = 50;
+
+#[derive(Debug, Default)]
+pub struct QuickOpenState {
+    pub open: bool,
+    pub query: String,
+    pub selected: usize,
+}
+
+impl QuickOpenState {
+    pub fn show(&mut self) {
+        self.open = true;
+        self.query.clear();
+        self.selected = 0;
+    }
+}
+
+// Higher is better. Every query character has to show up in order; runs of them, and ones
+// starting a path segment, count for more.
+fn fuzzy_score(query: &str, path: &str) -> Option<i64> {
+    let path = path.to_lowercase();
+    let mut score = 0;
+    // Byte offsets of the last matched character and the one after it
+    let mut last_match: Option<(usize, usize)> = None;
+    let mut chars = path.char_indices();
+    for wanted in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let (index, c) = chars.find(|(_, c)| *c == wanted)?;
+        score += 1;
+        if last_match.is_some_and(|(_, end)| end == index) {
+            score += 5;
+        }
+        if index == 0 || path[..index].ends_with(['/', '.', '-', '_']) {
+            score += 3;
+        }
+        last_match = Some((index, index + c.len_utf8()));
+    }
+    // Matches in the file name beat matches in directories
+    let name_start = path.rfind('/').map_or(0, |i| i + 1);
+    if last_match.is_some_and(|(last, _)| last >= name_start) {
+        score += 10;
+    }
+    Some(score * 100 - path.len() as i64)
+}
+
+fn matches(query: &str, diffed_extension: &DiffedExtension) -> Vec<(Origin, String)> {
+    let files = [
+        (Origin::Source, &diffed_extension.source_diff),
+        (Origin::Asar, &diffed_extension.asar_diff),
+    ]
+    .into_iter()
+    .flat_map(|(origin, diff)| {
+        diff::flatten_tree(&diff.dir)
+            .into_iter()
+            .map(move |(path, _)| (origin, path))
+    });
+
+    let mut scored = files
+        .filter_map(|(origin, path)| Some((fuzzy_score(query, &path)?, origin, path)))
+        .collect::<Vec<_>>();
+    // Stable, so equal scores keep sidebar order with source first
+    scored.sort_by_key(|(score, ..)| std::cmp::Reverse(*score));
+    scored
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|(_, origin, path)| (origin, path))
+        .collect()
+}
+
+// Returns the file to open
+pub fn draw_quick_open(
+    ctx: &egui::Context,
+    state: &mut QuickOpenState,
+    diffed_extension: &DiffedExtension,
+) -> Option<(Origin, String)> {
+    let shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::P);
+    if ctx.input_mut(|input| input.consume_shortcut(&shortcut)) {
+        state.show();
+    }
+    if !state.open {
+        return None;
+    }
+
+    let results = matches(&state.query, diffed_extension);
+    let (up, down, enter, escape) = ctx.input_mut(|input| {
+        (
+            input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+            input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+            input.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+            input.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+        )
+    });
+    if up {
+        state.selected = state.selected.saturating_sub(1);
+    }
+    if down {
+        state.selected += 1;
+    }
+    state.selected = state.selected.min(results.len().saturating_sub(1));
+
+    let mut chosen = enter
+        .then(|| results.get(state.selected).cloned())
+        .flatten();
+    egui::Window::new("Go to file")
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut state.query)
+                    .hint_text("Search source and .asar files")
+                    .desired_width(400.0),
+            );
+            response.request_focus();
+            if response.changed() {
+                state.selected = 0;
+            }
+
+            if results.is_empty() {
+                ui.weak("No matching files.");
+            }
+            egui::ScrollArea::vertical()
+                .max_height(400.0)
+                .show(ui, |ui| {
+                    for (i, (origin, path)) in results.iter().enumerate() {
+                        let origin_text = match origin {
+                            Origin::Source => "source",
+                            Origin::Asar => ".asar",
+                        };
+                        let response = ui.selectable_label(
+                            i == state.selected,
+                            format!("{}  ({})", path, origin_text),
+                        );
+                        if i == state.selected && (up || down) {
+                            response.scroll_to_me(None);
+                        }
+                        if response.clicked() {
+                            chosen = Some((*origin, path.clone()));
+                        }
+                    }
+                });
+        });
+
+    if chosen.is_some() || escape {
+        state.open = false;
+    }
+    chosen
+}
@@ -0,0 +1,338 @@
+use crate::logic::{
+    config::{
+        self, ArtifactConfig, ConcurrencyConfig, Config, Palette, Renderer, Theme, MAX_UI_SCALE,
+        MIN_UI_SCALE,
+    },
+    scan::FindingCategory,
+    syntax, templates,
+};
+
+#[derive(Debug, Default)]
+pub struct SettingsState {
+    pub open: bool,
+    pub draft: Config,
+    pub allowed_domains: String,
+    pub artifact_headers: String,
+    pub languages: String,
+}
+
+impl SettingsState {
+    pub fn show(&mut self, config: &Config) {
+        self.open = true;
+        self.draft = config.clone();
+        self.allowed_domains = config.allowed_domains.join("\n");
+        self.artifact_headers = config
+            .artifacts
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.languages = config
+            .languages
+            .iter()
+            .map(|(ext, name)| format!("{}: {}", ext, name))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    fn apply(&mut self) -> Config {
+        self.draft.allowed_domains = self
+            .allowed_domains
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+        self.draft.artifacts.headers = self
+            .artifact_headers
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        self.draft.languages = self
+            .languages
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(ext, name)| (syntax::normalize_ext(ext), name.trim().to_string()))
+            .filter(|(ext, name)| !ext.is_empty() && !name.is_empty())
+            .collect();
+        self.draft.clone()
+    }
+}
+
+// Shared with the setup wizard
+pub fn draw_theme(ui: &mut egui::Ui, theme: &mut Theme) {
+    egui::ComboBox::from_label("Theme")
+        .selected_text(theme.name())
+        .show_ui(ui, |ui| {
+            for option in Theme::ALL {
+                ui.selectable_value(theme, option, option.name());
+            }
+        });
+}
+
+pub fn draw_cache_dir(ui: &mut egui::Ui, cache_dir: &mut String) {
+    ui.label(
+        "Where grammars, published .asars and advisories are kept. Leave it empty for the default. \
+         Nothing is moved over when it changes.",
+    );
+    ui.horizontal(|ui| {
+        ui.label("Cache directory");
+        ui.add(
+            egui::TextEdit::singleline(cache_dir)
+                .hint_text(config::default_cache_dir().display().to_string())
+                .desired_width(f32::INFINITY),
+        );
+    });
+}
+
+// Returns the new config when the user saves
+pub fn draw_settings(
+    ctx: &egui::Context,
+    settings: &mut SettingsState,
+    tray_error: Option<&String>,
+) -> Option<Config> {
+    let mut saved = None;
+    let mut open = settings.open;
+
+    egui::Window::new("Settings")
+        .open(&mut open)
+        .resizable(true)
+        .show(ctx, |ui| {
+            ui.heading("Allowed domains");
+            ui.label(
+                "Hosts that won't be reported when they show up in added code, one per line. \
+                 Subdomains are allowed too.",
+            );
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    ui.add(
+                        egui::TextEdit::multiline(&mut settings.allowed_domains)
+                            .code_editor()
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+
+            ui.separator();
+            ui.heading("Local builds");
+            let build = &mut settings.draft.build;
+            ui.checkbox(&mut build.enabled, "Allow building extensions locally");
+            ui.label(
+                "Builds run code from the pull request. Use a container runtime to isolate them; \
+                 without one, they run on your system with only a temporary HOME, and can still \
+                 reach the network past the proxy variables.",
+            );
+            egui::Grid::new("build_settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Container runtime");
+                    ui.text_edit_singleline(&mut build.container_runtime);
+                    ui.end_row();
+                    ui.label("Image");
+                    ui.text_edit_singleline(&mut build.image);
+                    ui.end_row();
+                    ui.label("Install command");
+                    ui.text_edit_singleline(&mut build.install_command);
+                    ui.end_row();
+                    ui.label("Build command");
+                    ui.text_edit_singleline(&mut build.build_command);
+                    ui.end_row();
+                    ui.label("Output directory");
+                    ui.text_edit_singleline(&mut build.output_dir);
+                    ui.end_row();
+                });
+
+            ui.separator();
+            ui.heading("GitHub");
+            ui.label(
+                "A personal access token raises the rate limit and lets artifacts be downloaded \
+                 from GitHub directly. Stored in plain text. GITHUB_TOKEN overrides it, and the \
+                 gh CLI's login is used when both are empty.",
+            );
+            ui.horizontal(|ui| {
+                ui.label("Token");
+                ui.add(
+                    egui::TextEdit::singleline(&mut settings.draft.github_token.0)
+                        .password(true)
+                        .desired_width(f32::INFINITY),
+                );
+            });
+
+            ui.separator();
+            ui.heading("Artifact mirror");
+            ui.label(
+                "GitHub only serves CI artifacts to logged in users, so they're downloaded \
+                 through a mirror. {run_id} and {artifact} are replaced with the workflow run \
+                 and artifact name.",
+            );
+            let artifacts = &mut settings.draft.artifacts;
+            ui.horizontal(|ui| {
+                ui.label("URL template");
+                ui.add(
+                    egui::TextEdit::singleline(&mut artifacts.url_template)
+                        .desired_width(f32::INFINITY),
+                );
+            });
+            ui.label(
+                "Headers sent to the mirror, one \"Name: value\" per line. Stored in plain text.",
+            );
+            ui.add(
+                egui::TextEdit::multiline(&mut settings.artifact_headers)
+                    .code_editor()
+                    .desired_rows(2)
+                    .desired_width(f32::INFINITY),
+            );
+            ui.checkbox(&mut artifacts.direct, "Download from GitHub when logged in")
+                .on_hover_text("The mirror is only used without a token");
+            if ui.button("Use nightly.link").clicked() {
+                *artifacts = ArtifactConfig::default();
+                settings.artifact_headers.clear();
+            }
+
+            ui.separator();
+            ui.heading("Performance");
+            ui.label("Lower these on slow machines or metered connections.");
+            let concurrency = &mut settings.draft.concurrency;
+            egui::Grid::new("concurrency_settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Parallel downloads");
+                    ui.add(egui::DragValue::new(&mut concurrency.downloads).range(1..=32));
+                    ui.end_row();
+                    ui.label("Hashing threads");
+                    ui.add(egui::DragValue::new(&mut concurrency.hashing_threads).range(1..=64));
+                    ui.end_row();
+                    ui.label("Diff workers");
+                    ui.add(egui::DragValue::new(&mut concurrency.diff_workers).range(1..=64));
+                    ui.end_row();
+                    ui.label("Diff timeout (seconds)");
+                    ui.add(egui::DragValue::new(&mut concurrency.diff_timeout_secs).range(1..=600))
+                        .on_hover_text(
+                            "Files that take longer are shown as a plain text diff, \
+                             and their line counts in the file tree are approximate",
+                        );
+                    ui.end_row();
+                    ui.label("Refresh favorites every (minutes)");
+                    ui.add(
+                        egui::DragValue::new(&mut concurrency.dist_refresh_minutes)
+                            .range(0..=1440),
+                    )
+                    .on_hover_text(
+                        "Keeps the published .asars of favorites downloaded, so reviews start faster. 0 turns it off.",
+                    );
+                    ui.end_row();
+                });
+            if ui.button("Reset to defaults").clicked() {
+                *concurrency = ConcurrencyConfig::default();
+            }
+
+            ui.separator();
+            ui.heading("Team review");
+            ui.label(
+                "Reviewed files and notes are shared through a gist, one file per pull request. \
+                 Syncing needs a GITHUB_TOKEN with the gist scope.",
+            );
+            ui.horizontal(|ui| {
+                ui.label("Gist ID");
+                ui.text_edit_singleline(&mut settings.draft.sync.gist_id);
+            });
+
+            ui.separator();
+            ui.heading("Review templates");
+            ui.label(format!(
+                "Wording for each kind of finding, used by \"Copy feedback\", exported reviews \
+                 and published check runs. {} are filled in. Leave one empty to skip that kind.",
+                templates::PLACEHOLDERS
+            ));
+            for category in FindingCategory::ALL {
+                ui.collapsing(category.id(), |ui| {
+                    let template = settings
+                        .draft
+                        .review_templates
+                        .entry(category.id().to_string())
+                        .or_insert_with(|| templates::default_template(*category).to_string());
+                    ui.add(
+                        egui::TextEdit::multiline(template)
+                            .desired_rows(2)
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+            }
+            if ui.button("Reset templates").clicked() {
+                settings.draft.review_templates = templates::default_templates();
+            }
+
+            ui.separator();
+            ui.heading("Languages");
+            ui.label(format!(
+                "File extensions to treat as another language, one \"extension: language\" per \
+                 line. These win over the built-in detection. {} are parsed for hunks, outlines \
+                 and findings; anything else is only passed to difftastic.",
+                syntax::LANGUAGES.join(", ")
+            ));
+            ui.add(
+                egui::TextEdit::multiline(&mut settings.languages)
+                    .code_editor()
+                    .desired_rows(3)
+                    .desired_width(f32::INFINITY),
+            );
+
+            ui.separator();
+            ui.heading("Cache");
+            draw_cache_dir(ui, &mut settings.draft.cache_dir);
+
+            ui.separator();
+            ui.heading("Display");
+            ui.add(
+                egui::Slider::new(&mut settings.draft.ui_scale, MIN_UI_SCALE..=MAX_UI_SCALE)
+                    .text("UI scale"),
+            )
+            .on_hover_text("Ctrl+scroll and Ctrl+plus/minus change this too");
+            egui::ComboBox::from_label("Diff colors")
+                .selected_text(settings.draft.palette.name())
+                .show_ui(ui, |ui| {
+                    for palette in Palette::ALL {
+                        ui.selectable_value(&mut settings.draft.palette, palette, palette.name());
+                    }
+                });
+            draw_theme(ui, &mut settings.draft.theme);
+            ui.label(
+                "Applies on restart. If the renderer fails to start, the others are tried in turn.",
+            );
+            egui::ComboBox::from_label("Renderer")
+                .selected_text(settings.draft.renderer.id())
+                .show_ui(ui, |ui| {
+                    for renderer in Renderer::ALL {
+                        ui.selectable_value(&mut settings.draft.renderer, renderer, renderer.id());
+                    }
+                });
+            if cfg!(feature = "tray") {
+                ui.checkbox(
+                    &mut settings.draft.tray,
+                    "Keep running in the tray when the window is closed",
+                )
+                .on_hover_text(
+                    "Queued prefetches keep going, and the tray menu reopens the window",
+                );
+                if let Some(err) = tray_error {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!(
+                            "Couldn't create the tray icon, so closing the window quits: {}",
+                            err
+                        ),
+                    );
+                }
+            }
+
+            ui.separator();
+            if ui.button("Save").clicked() {
+                saved = Some(settings.apply());
+            }
+        });
+
+    settings.open = open && saved.is_none();
+    saved
+}
@@ -1,13 +1,51 @@
+use super::{
+    export::ExportFormat, onboarding::OnboardingState, quick_open::QuickOpenState,
+    settings::SettingsState,
+};
+use crate::crash::CrashReport;
 use crate::logic::{
-    diff::{DiffedExtension, PullRequestUpdate},
-    LogicError, LogicResult,
+    approvals::Approval,
+    audit::AuditEntry,
+    bandwidth::BandwidthUsage,
+    checks::CheckRun,
+    config::Config,
+    dependents::Dependent,
+    deps::{Advisory, DependencyRisk},
+    diff::{DiffedExtension, FileDiff, PullRequestUpdate, SingleFile},
+    git::{BlameLine, Revision},
+    overview::ExtensionOverview,
+    pr::PullRequestSummary,
+    prefetch::PrefetchItem,
+    provenance::Provenance,
+    review::{LineComment, ReviewVerdict, SubmittedReview},
+    session::{FileKey, ReviewState, ReviewStatus},
+    sync::TeamReview,
+    timing::{StageProgress, Timing},
+    AuthStatus, LogicCommand, LogicError, LogicResult,
+};
+use crate::widget::{CommentTarget, DiffLayout};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
 };
 
+// egui_commonmark's cache holds loaded images and link hooks, none of it worth printing
+#[derive(Default)]
+pub struct MarkdownCache(pub egui_commonmark::CommonMarkCache);
+
+impl std::fmt::Debug for MarkdownCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MarkdownCache")
+    }
+}
+
 #[derive(Debug)]
 pub struct AsyncState<T> {
     pub value: Option<T>,
     pub working: bool,
     pub error: Option<LogicError>,
+    // What was sent for the value, so a failure can be retried
+    pub command: Option<LogicCommand>,
 }
 
 impl<T> Default for AsyncState<T> {
@@ -16,6 +54,7 @@ impl<T> Default for AsyncState<T> {
             value: None,
             working: false,
             error: None,
+            command: None,
         }
     }
 }
@@ -40,29 +79,272 @@ impl<T> AsyncState<T> {
         self.working = true;
     }
 
+    // Sends the command and remembers it for `retry`
+    pub fn request(&mut self, tx: &flume::Sender<LogicCommand>, command: LogicCommand) {
+        super::send(tx, command.clone());
+        self.command = Some(command);
+        self.working = true;
+        self.error = None;
+    }
+
+    pub fn retry(&mut self, tx: &flume::Sender<LogicCommand>) {
+        if let Some(command) = self.command.clone() {
+            self.request(tx, command);
+        }
+    }
+
     pub fn clear(&mut self) {
         self.value = None;
         self.working = false;
         self.error = None;
+        self.command = None;
+    }
+
+    // For requests the logic thread died before answering
+    pub fn interrupt(&mut self) {
+        if self.working {
+            self.working = false;
+            self.error = Some("The backend stopped before answering".to_string().into());
+        }
     }
 }
 
+// What Reset threw away; its temp dirs stay on disk for the session, so it can be restored as is
+#[derive(Debug)]
+pub struct DiscardedExtension {
+    pub diffed_extension: DiffedExtension,
+    pub view_type: ViewType,
+    pub selected_file: Option<String>,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ViewType {
     #[default]
     Source,
     Asar,
+    // Local build output against the artifact
+    Build,
+    // The new source against the last version the user approved
+    Approved,
+}
+
+impl ViewType {
+    pub fn name(self) -> &'static str {
+        match self {
+            ViewType::Source => "source",
+            ViewType::Asar => ".asar",
+            ViewType::Build => "build",
+            ViewType::Approved => "approved",
+        }
+    }
+}
+
+// A file diff shown in its own OS window
+#[derive(Debug)]
+pub struct PoppedOutDiff {
+    pub id: egui::ViewportId,
+    pub title: String,
+    pub diff: FileDiff,
+    pub display: DiffDisplay,
+    pub layout: DiffLayout,
+    pub scroll_to_line: Option<usize>,
+    pub wrap: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExtensionPage {
+    #[default]
+    Files,
+    Dependencies,
+    Patches,
+    Dependents,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileTab {
+    #[default]
+    Diff,
+    History,
+    Blame,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffDisplay {
+    #[default]
+    Difftastic,
+    Hunks,
+    Modules,
+    // Both files in full, in two columns
+    SideBySide,
+}
+
+// Which side of the diff is shown in full instead of the diff itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawSide {
+    Old,
+    New,
+}
+
+// Paths for reviewing an extension before its PR exists
+#[derive(Debug, Default)]
+pub struct LocalState {
+    pub extension_id: String,
+    pub working_dir: String,
+    pub asar_path: String,
+    // Whether the extension being downloaded came from here rather than a PR
+    pub active: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct HistoryState {
+    pub revisions: AsyncState<Vec<Revision>>,
+    pub old: Option<usize>,
+    pub new: Option<usize>,
+    pub diff: AsyncState<String>,
 }
 
 #[derive(Debug, Default)]
 pub struct AppState {
+    pub config: Config,
+    // The UI scale last applied to the context, to notice changes from either side
+    pub applied_scale: f32,
+    pub scale_unsaved: bool,
+    pub settings: SettingsState,
+    pub quick_open: QuickOpenState,
+    pub onboarding: OnboardingState,
+
     pub pull_request_id: u64,
     pub pull_request_update: AsyncState<PullRequestUpdate>,
+    pub pull_requests: AsyncState<Vec<PullRequestSummary>>,
+    pub markdown_cache: MarkdownCache,
 
     pub selected_extension: Option<String>,
     pub diffed_extension: AsyncState<DiffedExtension>,
+    pub discarded_extension: Option<DiscardedExtension>,
+
+    pub page: ExtensionPage,
+    pub dependency_risks: AsyncState<Vec<DependencyRisk>>,
+    pub advisories: AsyncState<Vec<Advisory>>,
+    pub dependents: AsyncState<Vec<Dependent>>,
+    // The language whose grammar is being installed
+    pub grammar_install: AsyncState<String>,
+
+    pub building: AsyncState<()>,
+    pub show_build_log: bool,
 
     pub view_type: ViewType,
+    pub hide_build_noise: bool,
+    // Format minified JavaScript in the .asar before diffing it
+    pub pretty_print_bundles: bool,
+    // Unchanged files too, for context
+    pub full_tree: bool,
     pub selected_file: Option<String>,
-    pub diff: Option<String>,
+    pub selected_directory: Option<String>,
+    pub file_tab: FileTab,
+    pub diff_display: DiffDisplay,
+    // Only for the hunk view
+    pub diff_layout: DiffLayout,
+    pub diff: Option<FileDiff>,
+    pub show_outline: bool,
+    pub raw_side: Option<RawSide>,
+    pub raw_file: AsyncState<SingleFile>,
+    // Files shown with long lines wrapped, by path
+    pub wrapped_files: HashSet<String>,
+    pub scroll_to_line: Option<usize>,
+    // Where each file's diff was left, by view type and path
+    pub scroll_offsets: HashMap<String, egui::Vec2>,
+    pub restore_scroll: Option<egui::Vec2>,
+    pub diff_export: AsyncState<PathBuf>,
+    // Rendering happens on its own thread, which reports back here
+    pub diff_export_rx: Option<flume::Receiver<anyhow::Result<PathBuf>>>,
+    // Asked before replacing an earlier export of the same file
+    pub diff_export_overwrite: Option<(ExportFormat, PathBuf)>,
+    pub popped_out: Vec<PoppedOutDiff>,
+    pub history: HistoryState,
+    pub blame: AsyncState<Vec<BlameLine>>,
+    pub provenance: AsyncState<Provenance>,
+
+    // What the logic thread is in the middle of, for busy indicators
+    pub progress: Vec<StageProgress>,
+    pub bandwidth: BandwidthUsage,
+
+    pub show_diagnostics: bool,
+    pub timings: AsyncState<Vec<Timing>>,
+
+    pub show_prefetch: bool,
+    pub prefetch_input: String,
+    pub prefetch: Vec<PrefetchItem>,
+    // Each extension in the fetched pull request, as of the last `GetOverview`
+    pub overview: Vec<ExtensionOverview>,
+    pub review_statuses: BTreeMap<u64, ReviewStatus>,
+    // What the statuses were last requested for, so progress updates don't re-request them
+    pub review_statuses_for: BTreeMap<u64, Vec<String>>,
+
+    // By extension ID, for `reviews_pull_request`
+    pub reviews: BTreeMap<String, ReviewState>,
+    pub reviews_pull_request: u64,
+    // The reviews as last autosaved, and when they were last checked for changes
+    pub autosaved: String,
+    pub autosave_time: f64,
+    pub show_review: bool,
+    pub session_path: String,
+    pub local: LocalState,
+    pub session_exported: AsyncState<PathBuf>,
+    pub session_imported: AsyncState<()>,
+    pub team_review: AsyncState<TeamReview>,
+    pub check_run: AsyncState<CheckRun>,
+    pub review_verdict: ReviewVerdict,
+    pub review_body: String,
+    pub submitted_review: AsyncState<SubmittedReview>,
+    // The line picked to comment on, with the file it's in
+    pub pending_comment: Option<(FileKey, CommentTarget)>,
+    pub comment_draft: String,
+    // Each extension's comments in the review being submitted
+    pub submitting_comments: BTreeMap<String, Vec<LineComment>>,
+    pub approval: AsyncState<Approval>,
+
+    pub show_audit_log: bool,
+    pub audit_log: AsyncState<Vec<AuditEntry>>,
+    pub audit_export_path: String,
+    pub audit_exported: AsyncState<PathBuf>,
+
+    pub crash: Option<CrashReport>,
+    pub backend_error: Option<String>,
+    // The request GitHub refused, shown in a dialog explaining how to log in
+    pub auth_prompt: Option<LogicError>,
+    pub auth: AsyncState<AuthStatus>,
+}
+
+impl AppState {
+    pub fn interrupt_requests(&mut self) {
+        self.pull_request_update.interrupt();
+        self.pull_requests.interrupt();
+        self.auth.interrupt();
+        self.diffed_extension.interrupt();
+        self.dependency_risks.interrupt();
+        self.advisories.interrupt();
+        self.dependents.interrupt();
+        self.grammar_install.interrupt();
+        self.raw_file.interrupt();
+        self.building.interrupt();
+        self.history.revisions.interrupt();
+        self.history.diff.interrupt();
+        self.blame.interrupt();
+        self.provenance.interrupt();
+        self.timings.interrupt();
+        self.audit_log.interrupt();
+        self.audit_exported.interrupt();
+        self.session_exported.interrupt();
+        self.session_imported.interrupt();
+        self.team_review.interrupt();
+        self.check_run.interrupt();
+        self.submitted_review.interrupt();
+        self.approval.interrupt();
+        self.onboarding.tools.interrupt();
+        self.onboarding.self_test.interrupt();
+    }
+
+    pub fn review(&mut self, extension_id: &str) -> &mut ReviewState {
+        self.reviews.entry(extension_id.to_string()).or_default()
+    }
 }
@@ -0,0 +1,184 @@
+// Tray icon that keeps the app reachable while its window is hidden, so prefetches keep going
+use anyhow::Context;
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayCommand {
+    Show,
+    // The first prefetched pull request that hasn't been finished yet
+    OpenReady,
+    Quit,
+}
+
+const SHOW_ID: &str = "show";
+const OPEN_READY_ID: &str = "open_ready";
+const QUIT_ID: &str = "quit";
+
+// A filled circle, since there's no icon file to ship
+fn icon() -> anyhow::Result<Icon> {
+    const SIZE: u32 = 32;
+    let mut rgba = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+    let center = SIZE as f32 / 2.0 - 0.5;
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let distance = ((x as f32 - center).powi(2) + (y as f32 - center).powi(2)).sqrt();
+            let alpha = if distance <= center { 255 } else { 0 };
+            rgba.extend_from_slice(&[120, 90, 220, alpha]);
+        }
+    }
+    Icon::from_rgba(rgba, SIZE, SIZE).context("Failed to create tray icon")
+}
+
+fn build() -> anyhow::Result<TrayIcon> {
+    let menu = Menu::new();
+    menu.append_items(&[
+        &MenuItem::with_id(SHOW_ID, "Open RoboJules", true, None),
+        &MenuItem::with_id(OPEN_READY_ID, "Open next ready review", true, None),
+        &PredefinedMenuItem::separator(),
+        &MenuItem::with_id(QUIT_ID, "Quit", true, None),
+    ])
+    .context("Failed to create tray menu")?;
+    TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip("RoboJules")
+        .with_icon(icon()?)
+        .build()
+        .context("Failed to create tray icon")
+}
+
+// GTK can only be initialized on one thread, so the icon lives on one that's never stopped and
+// is hidden instead of dropped
+#[cfg(target_os = "linux")]
+fn linux_tray() -> anyhow::Result<flume::Sender<bool>> {
+    use std::sync::OnceLock;
+    static VISIBLE: OnceLock<Result<flume::Sender<bool>, String>> = OnceLock::new();
+
+    VISIBLE
+        .get_or_init(|| {
+            let (visible_tx, visible_rx) = flume::unbounded::<bool>();
+            let (ready_tx, ready_rx) = flume::bounded(1);
+            std::thread::spawn(move || {
+                let icon = gtk::init()
+                    .context("Failed to initialize GTK")
+                    .and_then(|()| build());
+                let icon = match icon {
+                    Ok(icon) => {
+                        let _ = ready_tx.send(Ok(()));
+                        icon
+                    }
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(format!("{:?}", err)));
+                        return;
+                    }
+                };
+                loop {
+                    while gtk::events_pending() {
+                        gtk::main_iteration_do(false);
+                    }
+                    match visible_rx.recv_timeout(std::time::Duration::from_millis(50)) {
+                        Ok(visible) => {
+                            if let Err(err) = icon.set_visible(visible) {
+                                log::warn!("Failed to change tray icon visibility: {:?}", err);
+                            }
+                        }
+                        Err(flume::RecvTimeoutError::Timeout) => {}
+                        Err(flume::RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+            });
+            match ready_rx.recv() {
+                Ok(Ok(())) => Ok(visible_tx),
+                Ok(Err(err)) => Err(err),
+                Err(_) => Err("Tray thread stopped".to_string()),
+            }
+        })
+        .clone()
+        .map_err(|err| anyhow::anyhow!("{}", err))
+}
+
+pub struct Tray {
+    #[cfg(target_os = "linux")]
+    visible: flume::Sender<bool>,
+    #[cfg(not(target_os = "linux"))]
+    _icon: TrayIcon,
+    rx: flume::Receiver<TrayCommand>,
+}
+
+impl std::fmt::Debug for Tray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Tray")
+    }
+}
+
+impl Tray {
+    // Has to be called from the UI thread, which owns the event loop on Windows and macOS
+    pub fn new(ctx: &egui::Context) -> anyhow::Result<Self> {
+        #[cfg(target_os = "linux")]
+        let visible = {
+            let visible = linux_tray()?;
+            let _ = visible.send(true);
+            visible
+        };
+        #[cfg(not(target_os = "linux"))]
+        let icon = build()?;
+
+        // The window may be hidden, when the UI doesn't update, so it's shown from the handlers
+        let (tx, rx) = flume::unbounded();
+        let send = {
+            let ctx = ctx.clone();
+            move |command: TrayCommand| {
+                if command != TrayCommand::Quit {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                }
+                let _ = tx.send(command);
+                ctx.request_repaint();
+            }
+        };
+        let send_click = send.clone();
+        MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+            let command = match event.id.as_ref() {
+                SHOW_ID => TrayCommand::Show,
+                OPEN_READY_ID => TrayCommand::OpenReady,
+                QUIT_ID => TrayCommand::Quit,
+                _ => return,
+            };
+            send(command);
+        }));
+        // Linux doesn't report clicks on the icon itself, only the menu
+        TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                send_click(TrayCommand::Show);
+            }
+        }));
+
+        Ok(Self {
+            #[cfg(target_os = "linux")]
+            visible,
+            #[cfg(not(target_os = "linux"))]
+            _icon: icon,
+            rx,
+        })
+    }
+
+    pub fn try_recv(&self) -> Option<TrayCommand> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Drop for Tray {
+    fn drop(&mut self) {
+        MenuEvent::set_event_handler(None::<fn(MenuEvent)>);
+        TrayIconEvent::set_event_handler(None::<fn(TrayIconEvent)>);
+        #[cfg(target_os = "linux")]
+        let _ = self.visible.send(false);
+    }
+}
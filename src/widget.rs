@@ -0,0 +1,614 @@
+// A standalone hunk diff viewer, for embedding RoboJules' diff view in other egui tools
+use egui::{
+    text::{LayoutJob, LayoutSection},
+    Color32, FontId, Galley,
+};
+use regex::Regex;
+use std::{ops::Range, path::Path, sync::LazyLock};
+
+pub use crate::logic::diff::{DiffLine, FileDiff, Hunk, LineKind};
+
+// Builds hunks for two versions of a file. The paths are only used to pick a grammar for hunk headers.
+pub fn diff_text(old_path: &Path, old: &str, new_path: &Path, new: &str) -> Vec<Hunk> {
+    crate::logic::diff::calculate_hunks(old_path, old, new_path, new)
+}
+
+static URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\b(?:https?|wss?)://[^\s"'`<>()\[\]{}\\]+"#).unwrap());
+
+// Byte ranges of URL literals, minus punctuation from the surrounding code
+fn url_ranges(text: &str) -> Vec<Range<usize>> {
+    URL_REGEX
+        .find_iter(text)
+        .map(|url| {
+            let trimmed = url
+                .as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?']);
+            url.start()..url.start() + trimmed.len()
+        })
+        .collect()
+}
+
+// Splits sections around URL literals so they can be underlined like links
+pub(crate) fn underline_urls(job: &mut LayoutJob) {
+    let urls = url_ranges(&job.text);
+    if urls.is_empty() {
+        return;
+    }
+
+    let mut sections = Vec::with_capacity(job.sections.len() + urls.len() * 2);
+    for section in std::mem::take(&mut job.sections) {
+        let range = section.byte_range.clone();
+        let mut leading_space = section.leading_space;
+        let mut push = |byte_range: Range<usize>, format: egui::TextFormat| {
+            sections.push(LayoutSection {
+                leading_space: std::mem::take(&mut leading_space),
+                byte_range,
+                format,
+            });
+        };
+
+        let mut start = range.start;
+        for url in urls
+            .iter()
+            .filter(|url| url.start < range.end && url.end > range.start)
+        {
+            let url_start = url.start.max(start);
+            let url_end = url.end.min(range.end);
+            if url_start > start {
+                push(start..url_start, section.format.clone());
+            }
+            let mut format = section.format.clone();
+            format.underline = egui::Stroke::new(1.0, format.color);
+            push(url_start..url_end, format);
+            start = url_end;
+        }
+        if start < range.end {
+            push(start..range.end, section.format.clone());
+        }
+    }
+    job.sections = sections;
+}
+
+// Shows the full URL under the pointer and opens it on click. Only the hovered line is searched.
+pub(crate) fn link_urls(ui: &egui::Ui, response: &egui::Response, galley: &Galley) {
+    let Some(pos) = response.hover_pos() else {
+        return;
+    };
+    let cursor = galley.cursor_from_pos(pos - response.rect.min);
+    let text = galley.job.text.as_str();
+    let Some((byte, _)) = text.char_indices().nth(cursor.ccursor.index) else {
+        return;
+    };
+
+    let line_start = text[..byte].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[byte..].find('\n').map_or(text.len(), |i| byte + i);
+    let Some(url) = url_ranges(&text[line_start..line_end])
+        .into_iter()
+        .map(|url| line_start + url.start..line_start + url.end)
+        .find(|url| url.contains(&byte))
+    else {
+        return;
+    };
+
+    let url = &text[url];
+    ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+    egui::show_tooltip_at_pointer(ui.ctx(), ui.layer_id(), response.id.with("url"), |ui| {
+        ui.label(url);
+    });
+    if response.clicked() {
+        ui.ctx().open_url(egui::OpenUrl::new_tab(url));
+    }
+}
+
+// The hunks as a unified diff, for the clipboard
+pub fn unified_text(hunks: &[Hunk]) -> String {
+    let mut text = String::new();
+    for hunk in hunks {
+        text.push_str(&hunk.header());
+        text.push('\n');
+        for line in &hunk.lines {
+            let prefix = match line.kind {
+                LineKind::Context => ' ',
+                LineKind::Added => '+',
+                LineKind::Removed => '-',
+            };
+            text.push(prefix);
+            text.push_str(&line.text);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+// Context and added lines without prefixes, i.e. the new file's side of the hunks
+pub fn new_text(hunks: &[Hunk]) -> String {
+    let mut text = String::new();
+    for line in hunks.iter().flat_map(|hunk| &hunk.lines) {
+        if line.kind != LineKind::Removed {
+            text.push_str(&line.text);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+// A GitHub review suggestion replacing the hunk's lines in the new file with their new text.
+// The fence is longer than any run of backticks in the code, so those don't close it early.
+pub fn suggestion_text(hunk: &Hunk) -> String {
+    let text = new_text(std::slice::from_ref(hunk));
+    let longest_run = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    format!("{}suggestion\n{}{}\n", fence, text, fence)
+}
+
+// Chars in `text`, only counting what was appended since the last call
+fn count_chars(text: &str, counted: &mut (usize, usize)) -> usize {
+    counted.1 += text[counted.0..].chars().count();
+    counted.0 = text.len();
+    counted.1
+}
+
+// Which sides of the hunks are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffLayout {
+    #[default]
+    Unified,
+    Old,
+    New,
+    // Old and new lines next to each other
+    Split,
+}
+
+impl DiffLayout {
+    pub const ALL: [Self; 4] = [Self::Unified, Self::Old, Self::New, Self::Split];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unified => "Unified",
+            Self::Old => "Old only",
+            Self::New => "New only",
+            Self::Split => "Split",
+        }
+    }
+}
+
+// Split rows are padded with spaces, which tabs would throw off
+const TAB: &str = "    ";
+
+// Changed parts of the line get a tinted background, so a small edit stands out from the rest of it
+fn append_line(
+    job: &mut LayoutJob,
+    line: &DiffLine,
+    font_id: &FontId,
+    color: Color32,
+    expand_tabs: bool,
+) {
+    let expand = |text: &str| {
+        if expand_tabs {
+            text.replace('\t', TAB)
+        } else {
+            text.to_string()
+        }
+    };
+    let format = egui::TextFormat::simple(font_id.clone(), color);
+    let changed = egui::TextFormat {
+        background: color.gamma_multiply(0.3),
+        ..format.clone()
+    };
+
+    let text = line.text.as_str();
+    let mut end = 0;
+    for range in &line.changes {
+        let (Some(before), Some(inside)) = (text.get(end..range.start), text.get(range.clone()))
+        else {
+            break;
+        };
+        job.append(&expand(before), 0., format.clone());
+        job.append(&expand(inside), 0., changed.clone());
+        end = range.end;
+    }
+    job.append(&expand(&text[end..]), 0., format);
+}
+
+// The lines shown on each row. Only split rows have a right side, where removed lines sit next to the
+// added lines replacing them.
+fn rows(hunk: &Hunk, layout: DiffLayout) -> Vec<(Option<&DiffLine>, Option<&DiffLine>)> {
+    fn pair<'a>(
+        rows: &mut Vec<(Option<&'a DiffLine>, Option<&'a DiffLine>)>,
+        removed: &mut Vec<&'a DiffLine>,
+        added: &mut Vec<&'a DiffLine>,
+    ) {
+        for i in 0..removed.len().max(added.len()) {
+            rows.push((removed.get(i).copied(), added.get(i).copied()));
+        }
+        removed.clear();
+        added.clear();
+    }
+
+    let hidden = match layout {
+        DiffLayout::Unified => None,
+        DiffLayout::Old => Some(LineKind::Added),
+        DiffLayout::New => Some(LineKind::Removed),
+        DiffLayout::Split => {
+            let mut rows = Vec::new();
+            let (mut removed, mut added) = (Vec::new(), Vec::new());
+            for line in &hunk.lines {
+                match line.kind {
+                    LineKind::Removed => {
+                        if !added.is_empty() {
+                            pair(&mut rows, &mut removed, &mut added);
+                        }
+                        removed.push(line);
+                    }
+                    LineKind::Added => added.push(line),
+                    LineKind::Context => {
+                        pair(&mut rows, &mut removed, &mut added);
+                        rows.push((Some(line), Some(line)));
+                    }
+                }
+            }
+            pair(&mut rows, &mut removed, &mut added);
+            return rows;
+        }
+    };
+    hunk.lines
+        .iter()
+        .filter(|line| Some(line.kind) != hidden)
+        .map(|line| (Some(line), None))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffStyle {
+    pub font_size: f32,
+    pub show_line_numbers: bool,
+    pub show_headers: bool,
+    // Wrap long lines at the available width instead of scrolling horizontally
+    pub wrap: bool,
+    pub added_color: Color32,
+    pub removed_color: Color32,
+    // Defaults to the UI's text/weak text colors
+    pub context_color: Option<Color32>,
+    pub dim_color: Option<Color32>,
+}
+
+impl Default for DiffStyle {
+    fn default() -> Self {
+        Self {
+            font_size: 14.,
+            show_line_numbers: true,
+            show_headers: true,
+            wrap: false,
+            added_color: Color32::GREEN,
+            removed_color: Color32::RED,
+            context_color: None,
+            dim_color: None,
+        }
+    }
+}
+
+// A line picked from the context menu to comment on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommentTarget {
+    // 1-indexed, in the new file
+    pub line: usize,
+    pub text: String,
+}
+
+#[must_use = "You should put this widget in a ui with `ui.add(widget);`"]
+pub struct DiffView<'a> {
+    hunks: &'a [Hunk],
+    style: DiffStyle,
+    layout: DiffLayout,
+    scroll_to_line: Option<usize>,
+    empty_text: &'a str,
+    comment_target: Option<&'a mut Option<CommentTarget>>,
+}
+
+struct Built<'a> {
+    job: LayoutJob,
+    // Where the line to scroll to starts, in chars
+    scroll_char: Option<usize>,
+    // Where each hunk starts, in chars
+    hunk_chars: Vec<usize>,
+    // Where each row starts in bytes, with its line in the new file
+    rows: Vec<(usize, Option<usize>, &'a str)>,
+}
+
+impl<'a> DiffView<'a> {
+    pub fn new(hunks: &'a [Hunk]) -> Self {
+        Self {
+            hunks,
+            style: DiffStyle::default(),
+            layout: DiffLayout::default(),
+            scroll_to_line: None,
+            empty_text: "No text changes to show.",
+            comment_target: None,
+        }
+    }
+
+    pub fn from_file_diff(diff: &'a FileDiff) -> Self {
+        Self::new(&diff.hunks)
+    }
+
+    pub fn style(mut self, style: DiffStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    // Split rows never wrap, so they need a horizontally scrolling parent
+    pub fn layout(mut self, layout: DiffLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    // Scrolls the surrounding ScrollArea to a (1-indexed) line in the new file
+    pub fn scroll_to_line(mut self, line: Option<usize>) -> Self {
+        self.scroll_to_line = line;
+        self
+    }
+
+    pub fn empty_text(mut self, text: &'a str) -> Self {
+        self.empty_text = text;
+        self
+    }
+
+    // Offers commenting on a line in the context menu, writing the line here when picked
+    pub fn comment_target(mut self, target: &'a mut Option<CommentTarget>) -> Self {
+        self.comment_target = Some(target);
+        self
+    }
+}
+
+impl<'a> DiffView<'a> {
+    // The unwrapped text the widget shows, e.g. for exporting it
+    pub fn layout_job(&self, visuals: &egui::Visuals) -> LayoutJob {
+        self.build(visuals).job
+    }
+
+    fn build(&self, visuals: &egui::Visuals) -> Built<'a> {
+        let style = &self.style;
+        let font_id = FontId::monospace(style.font_size);
+        let dim = style.dim_color.unwrap_or_else(|| visuals.weak_text_color());
+        let text_color = style.context_color.unwrap_or_else(|| visuals.text_color());
+
+        let line_format = |kind: LineKind| match kind {
+            LineKind::Context => (" ", text_color),
+            LineKind::Added => ("+", style.added_color),
+            LineKind::Removed => ("-", style.removed_color),
+        };
+        // The old side of split rows is padded to its longest line, so the new side lines up
+        let old_width = match self.layout {
+            DiffLayout::Split => self
+                .hunks
+                .iter()
+                .flat_map(|hunk| &hunk.lines)
+                .filter(|line| line.kind != LineKind::Added)
+                .map(|line| line.text.replace('\t', TAB).chars().count() + 1)
+                .max()
+                .unwrap_or_default(),
+            _ => 0,
+        };
+
+        let mut layout_job = LayoutJob::default();
+        // Where the line we want to scroll to (or the next one after it) starts, in chars
+        let mut scroll_char = None;
+        let mut hunk_chars = Vec::with_capacity(self.hunks.len());
+        // Bytes and chars of the text counted so far, so it isn't recounted from the start
+        let mut counted = (0, 0);
+        let mut row_starts = Vec::new();
+        let hunks: &'a [Hunk] = self.hunks;
+        for hunk in hunks {
+            hunk_chars.push(count_chars(&layout_job.text, &mut counted));
+            if style.show_headers {
+                layout_job.append(
+                    &format!("{}\n", hunk.header()),
+                    0.,
+                    egui::TextFormat::simple(font_id.clone(), dim),
+                );
+            }
+
+            for (left, right) in rows(hunk, self.layout) {
+                let scroll_line = [left, right]
+                    .into_iter()
+                    .flatten()
+                    .find_map(|line| line.new_line);
+                if let (Some(target), Some(new_line), None) =
+                    (self.scroll_to_line, scroll_line, scroll_char)
+                {
+                    if new_line >= target {
+                        scroll_char = Some(count_chars(&layout_job.text, &mut counted));
+                    }
+                }
+
+                let commented = right.or(left);
+                row_starts.push((
+                    layout_job.text.len(),
+                    commented.and_then(|line| line.new_line),
+                    commented.map(|line| line.text.as_str()).unwrap_or_default(),
+                ));
+
+                if self.layout == DiffLayout::Split {
+                    let cell = |job: &mut LayoutJob,
+                                line: Option<&DiffLine>,
+                                number: fn(&DiffLine) -> Option<usize>,
+                                width: usize| {
+                        if style.show_line_numbers {
+                            let number = line.and_then(number).map(|n| n.to_string());
+                            job.append(
+                                &format!("{:>5} ", number.unwrap_or_default()),
+                                0.,
+                                egui::TextFormat::simple(font_id.clone(), dim),
+                            );
+                        }
+                        let len = match line {
+                            Some(line) => {
+                                let (prefix, color) = line_format(line.kind);
+                                job.append(
+                                    prefix,
+                                    0.,
+                                    egui::TextFormat::simple(font_id.clone(), color),
+                                );
+                                append_line(job, line, &font_id, color, true);
+                                line.text.replace('\t', TAB).chars().count() + 1
+                            }
+                            None => 0,
+                        };
+                        job.append(
+                            &" ".repeat(width.saturating_sub(len)),
+                            0.,
+                            egui::TextFormat::simple(font_id.clone(), text_color),
+                        );
+                    };
+                    cell(&mut layout_job, left, |line| line.old_line, old_width);
+                    layout_job.append(" │ ", 0., egui::TextFormat::simple(font_id.clone(), dim));
+                    cell(&mut layout_job, right, |line| line.new_line, 0);
+                    layout_job.append("\n", 0., egui::TextFormat::simple(font_id.clone(), dim));
+                    continue;
+                }
+
+                let Some(line) = left else {
+                    continue;
+                };
+                let (prefix, color) = line_format(line.kind);
+                if style.show_line_numbers {
+                    let old_line = line.old_line.map(|n| n.to_string()).unwrap_or_default();
+                    let new_line = line.new_line.map(|n| n.to_string()).unwrap_or_default();
+                    let numbers = match self.layout {
+                        DiffLayout::Old => format!("{:>5} ", old_line),
+                        DiffLayout::New => format!("{:>5} ", new_line),
+                        _ => format!("{:>5} {:>5} ", old_line, new_line),
+                    };
+                    layout_job.append(&numbers, 0., egui::TextFormat::simple(font_id.clone(), dim));
+                }
+                let format = egui::TextFormat::simple(font_id.clone(), color);
+                layout_job.append(prefix, 0., format.clone());
+                append_line(&mut layout_job, line, &font_id, color, false);
+                layout_job.append("\n", 0., format);
+            }
+        }
+        Built {
+            job: layout_job,
+            scroll_char,
+            hunk_chars,
+            rows: row_starts,
+        }
+    }
+}
+
+impl egui::Widget for DiffView<'_> {
+    fn ui(mut self, ui: &mut egui::Ui) -> egui::Response {
+        if self.hunks.is_empty() {
+            return ui.label(self.empty_text);
+        }
+
+        let Built {
+            job: mut layout_job,
+            scroll_char,
+            hunk_chars,
+            rows: row_starts,
+        } = self.build(ui.visuals());
+        underline_urls(&mut layout_job);
+        // Laid out here so wrapped rows can still be found when scrolling
+        layout_job.wrap.max_width = if self.style.wrap && self.layout != DiffLayout::Split {
+            ui.available_width()
+        } else {
+            f32::INFINITY
+        };
+        let chars = layout_job.text.chars().count();
+        let galley = ui.fonts(|fonts| fonts.layout_job(layout_job));
+        let response = ui.add(egui::Label::new(galley.clone()).selectable(true));
+        link_urls(ui, &response, &galley);
+
+        // The hunk and row that were right-clicked, remembered while the context menu is open
+        let hunk_id = response.id.with("context_hunk");
+        let row_id = response.id.with("context_row");
+        if response.secondary_clicked() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                let char = galley
+                    .cursor_from_pos(pos - response.rect.min)
+                    .ccursor
+                    .index;
+                let hunk = hunk_chars
+                    .partition_point(|start| *start <= char)
+                    .saturating_sub(1);
+                let byte = galley
+                    .job
+                    .text
+                    .char_indices()
+                    .nth(char)
+                    .map_or(galley.job.text.len(), |(byte, _)| byte);
+                let row = row_starts
+                    .partition_point(|(start, _, _)| *start <= byte)
+                    .saturating_sub(1);
+                ui.data_mut(|data| {
+                    data.insert_temp(hunk_id, hunk);
+                    data.insert_temp(row_id, row);
+                });
+            }
+        }
+        let clicked_hunk = ui
+            .data(|data| data.get_temp::<usize>(hunk_id))
+            .and_then(|hunk| self.hunks.get(hunk));
+        let clicked_line = ui
+            .data(|data| data.get_temp::<usize>(row_id))
+            .and_then(|row| row_starts.get(row))
+            .and_then(|(_, line, text)| Some(((*line)?, *text)));
+
+        // Selections include line numbers, so offer clean copies too
+        response.context_menu(|ui| {
+            if ui.button("Copy as unified diff").clicked() {
+                ui.ctx().copy_text(unified_text(self.hunks));
+                ui.close_menu();
+            }
+            if ui.button("Copy new text").clicked() {
+                ui.ctx().copy_text(new_text(self.hunks));
+                ui.close_menu();
+            }
+            // Pure removals have no new lines to comment on
+            if let Some(hunk) = clicked_hunk.filter(|hunk| hunk.new_len > 0) {
+                ui.separator();
+                let last = hunk.new_start + hunk.new_len - 1;
+                if ui
+                    .button(format!(
+                        "Copy hunk as suggestion (lines {}-{})",
+                        hunk.new_start, last
+                    ))
+                    .on_hover_text(
+                        "Comment on these lines of the new file in the pull request, then paste",
+                    )
+                    .clicked()
+                {
+                    ui.ctx().copy_text(suggestion_text(hunk));
+                    ui.close_menu();
+                }
+            }
+            if let (Some(target), Some((line, text))) =
+                (self.comment_target.as_deref_mut(), clicked_line)
+            {
+                if ui.button(format!("Comment on line {}", line)).clicked() {
+                    *target = Some(CommentTarget {
+                        line,
+                        text: text.to_string(),
+                    });
+                    ui.close_menu();
+                }
+            }
+        });
+
+        // Past the last line means the end of the file
+        if let Some(char) = scroll_char.or(self.scroll_to_line.map(|_| chars.saturating_sub(1))) {
+            let rect = galley
+                .pos_from_ccursor(egui::text::CCursor::new(char))
+                .translate(response.rect.min.to_vec2());
+            ui.scroll_to_rect(rect, Some(egui::Align::TOP));
+        }
+
+        response
+    }
+}